@@ -0,0 +1,80 @@
+// Time per frame of the per frame work: checking a frame as read from the device, unpacking
+// its distances, scalar and vectorized, projecting them to a point cloud and each of the
+// built in filters with its defaults. Run with cargo bench, or cargo bench -- <name> for the
+// benchmarks whose name contains it.
+use rusty_lidar_viewer::cloud::Projection;
+use rusty_lidar_viewer::depth::{DepthFrame, HEIGHT_3D, PAYLOAD_3D_HEADER, WIDTH_3D};
+use rusty_lidar_viewer::filters;
+use rusty_lidar_viewer::frame::{new, parse_frame_into};
+use rusty_lidar_viewer::unpack::{unpack_12bit, unpack_12bit_scalar};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUN_FOR : Duration = Duration::from_secs(2);
+
+fn bench(name : &str, mut run : impl FnMut())
+{
+    if std::env::args().skip(1).filter(|arg| !arg.starts_with('-')).any(|filter| !name.contains(&filter))
+    {
+        return
+    }
+    // warm up, then as many runs as fit in RUN_FOR
+    for _ in 0..10
+    {
+        run();
+    }
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while start.elapsed() < RUN_FOR
+    {
+        run();
+        runs += 1;
+    }
+    println!("{:<18} {:>10.2?} per frame, {} runs", name, start.elapsed() / runs, runs);
+}
+
+// a wall 1 to 3 meters away, sloping left to right, with a few unmeasured pixels
+fn scene() -> DepthFrame
+{
+    let data = (0..WIDTH_3D * HEIGHT_3D).map(|index|
+    {
+        let (column, row) = (index % WIDTH_3D, index / WIDTH_3D);
+        if index % 97 == 0 { 0 } else { (1000 + column * 12 + row * 3 + index * 7919 % 13) as u16 }
+    }).collect();
+    DepthFrame { width : WIDTH_3D, height : HEIGHT_3D, data }
+}
+
+fn main()
+{
+    let depth = scene();
+    let payload = depth.to_payload();
+    let bytes = new(payload.clone()).as_bytes().unwrap();
+
+    let mut frame = new(Vec::new());
+    bench("checksum", || { parse_frame_into(black_box(&bytes), &mut frame).unwrap(); });
+
+    let packed = &payload[1..];
+    let mut scalar = vec![0u16; WIDTH_3D * HEIGHT_3D];
+    let mut vector = vec![0u16; WIDTH_3D * HEIGHT_3D];
+    unpack_12bit_scalar(packed, &mut scalar);
+    unpack_12bit(packed, &mut vector);
+    assert_eq!(scalar, vector);
+    assert_eq!(payload[0], PAYLOAD_3D_HEADER);
+    bench("unpack/scalar", || unpack_12bit_scalar(black_box(packed), black_box(&mut scalar)));
+    bench("unpack", || unpack_12bit(black_box(packed), black_box(&mut vector)));
+
+    let projection = Projection::default();
+    bench("project", || { black_box(projection.project(black_box(&depth))); });
+
+    for name in filters::NAMES
+    {
+        let mut filter = filters::open(name).unwrap();
+        let mut filtered = depth.clone();
+        bench(&format!("filter/{}", name), ||
+        {
+            filtered.data.copy_from_slice(&depth.data);
+            filter.apply(black_box(&mut filtered));
+        });
+    }
+}
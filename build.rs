@@ -0,0 +1,8 @@
+fn main()
+{
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("No vendored protoc for this host");
+    prost_build::Config::new()
+    .protoc_executable(protoc)
+    .compile_protos(&["proto/rusty_lidar_viewer.proto"], &["proto"])
+    .expect("Failed to compile protos");
+}
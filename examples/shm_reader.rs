@@ -0,0 +1,32 @@
+// Prints the nearest valid distance of every new frame published with
+// --publish shm://<name>, e.g. cargo run --example shm_reader -- rusty_lidar_viewer
+use rusty_lidar_viewer::depth::is_valid;
+use rusty_lidar_viewer::shm::ShmReader;
+
+use std::thread;
+use std::time::Duration;
+
+fn main()
+{
+    let name = std::env::args().nth(1).unwrap_or("rusty_lidar_viewer".to_string());
+    let reader = match ShmReader::open(&name)
+    {
+        Ok(reader) => reader,
+        Err(msg) => { println!("Error opening {}!, {}", name, msg); return ; },
+    };
+    let mut depth = Vec::new();
+    let mut last = None;
+    loop
+    {
+        if let Some((sequence, timestamp_ms)) = reader.latest(&mut depth)
+        {
+            if last != Some(sequence)
+            {
+                last = Some(sequence);
+                let nearest = depth.iter().copied().filter(|distance| is_valid(*distance)).min();
+                println!("Frame {} at {} ms, nearest {:?} mm", sequence, timestamp_ms, nearest);
+            }
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
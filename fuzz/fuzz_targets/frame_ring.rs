@@ -0,0 +1,47 @@
+// Any bytes through a FrameRing, written in pieces of a size the first byte picks and
+// looking for payloads of the 2D and 3D sizes and one the second byte picks. Every frame
+// taken out has to be where it was in the stream, after the one before, start with the
+// header and be as long as its size, one of those asked for, says; one that checks out
+// has to be what a Frame of its payload serializes to. The ring holds what it was made
+// for and no more, whatever comes.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_lidar_viewer::depth::{PAYLOAD_2D_SIZE, PAYLOAD_3D_SIZE};
+use rusty_lidar_viewer::frame::{new, parse_frame, FrameRing, HEADER};
+
+fuzz_target!(|data : &[u8]|
+{
+    let [piece, size, stream @ ..] = data else { return };
+    let sizes = [PAYLOAD_2D_SIZE, PAYLOAD_3D_SIZE, *size as u16];
+    let capacity = 2 * (PAYLOAD_3D_SIZE as usize + 6);
+    let piece = *piece as usize % 64 + 1;
+    let mut ring = FrameRing::new(capacity);
+    let mut frame = Vec::new();
+    let (mut written, mut taken) = (0, 0);
+    while written < stream.len()
+    {
+        let [first, second] = ring.free_mut();
+        let count = piece.min(first.len() + second.len()).min(stream.len() - written);
+        assert!(count > 0, "ring full without a frame to take out");
+        let into_first = count.min(first.len());
+        first[..into_first].copy_from_slice(&stream[written..written + into_first]);
+        second[..count - into_first].copy_from_slice(&stream[written + into_first..written + count]);
+        ring.filled(count);
+        written += count;
+        assert!(ring.len() <= capacity);
+        while let Some(position) = ring.next_frame(&sizes, &mut frame)
+        {
+            assert!(position >= taken && position + frame.len() <= written);
+            assert_eq!(frame, stream[position..position + frame.len()]);
+            assert_eq!(frame[..3], HEADER);
+            let size = u16::from_le_bytes([frame[3], frame[4]]);
+            assert!(sizes.contains(&size) && frame.len() == size as usize + 6);
+            if let Ok(parsed) = parse_frame(&frame)
+            {
+                assert_eq!(new(parsed.payload).as_bytes().unwrap(), frame);
+            }
+            taken = position + frame.len();
+        }
+    }
+});
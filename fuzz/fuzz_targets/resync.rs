@@ -0,0 +1,40 @@
+// Any bytes as garbage before three copies of a frame with an empty payload of a size the
+// first byte picks, written to a FrameRing in pieces of a size the second byte picks. A
+// header in the garbage can take bytes of the copies as its own, but no more than a
+// frame's worth, so the ring has to be back in sync for the last copy and take it out.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_lidar_viewer::frame::{new, parse_frame, FrameRing};
+
+fuzz_target!(|data : &[u8]|
+{
+    let [size, piece, garbage @ ..] = data else { return };
+    // zeros hold no header, so the copies only have one at their start
+    let valid = new(vec![0; *size as usize]).as_bytes().unwrap();
+    let stream = [garbage, &valid, &valid, &valid].concat();
+    let sizes = [*size as u16];
+    let piece = *piece as usize + 1;
+    let mut ring = FrameRing::new(2 * valid.len());
+    let mut frame = Vec::new();
+    let (mut written, mut last) = (0, None);
+    while written < stream.len()
+    {
+        let [first, second] = ring.free_mut();
+        let count = piece.min(first.len() + second.len()).min(stream.len() - written);
+        assert!(count > 0, "ring full without a frame to take out");
+        let into_first = count.min(first.len());
+        first[..into_first].copy_from_slice(&stream[written..written + into_first]);
+        second[..count - into_first].copy_from_slice(&stream[written + into_first..written + count]);
+        ring.filled(count);
+        written += count;
+        while let Some(position) = ring.next_frame(&sizes, &mut frame)
+        {
+            last = Some(position);
+        }
+    }
+    assert_eq!(last, Some(stream.len() - valid.len()));
+    assert_eq!(frame, valid);
+    assert!(parse_frame(&frame).is_ok());
+    assert!(ring.is_empty());
+});
@@ -0,0 +1,24 @@
+// Any bytes as packed 12 bit distances, unpacked into as many distances as the first two
+// bytes say: the vector and scalar unpacking have to agree, on the distances and on the
+// part of out they leave as it was. Any bytes as a payload make a DepthFrame whose size
+// matches its data, and packed again they make the same frame.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_lidar_viewer::depth::DepthFrame;
+use rusty_lidar_viewer::unpack::{unpack_12bit, unpack_12bit_scalar};
+
+fuzz_target!(|data : &[u8]|
+{
+    let [low, high, packed @ ..] = data else { return };
+    let count = u16::from_le_bytes([*low, *high]) as usize;
+    let (mut vector, mut scalar) = (vec![0xffff; count], vec![0xffff; count]);
+    unpack_12bit(packed, &mut vector);
+    unpack_12bit_scalar(packed, &mut scalar);
+    assert_eq!(vector, scalar);
+
+    let depth = DepthFrame::from_payload(data);
+    assert_eq!(depth.data.len(), depth.width * depth.height);
+    let again = DepthFrame::from_payload(&depth.to_payload());
+    assert_eq!((again.width, again.height, again.data), (depth.width, depth.height, depth.data));
+});
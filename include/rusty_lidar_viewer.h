@@ -0,0 +1,46 @@
+/* Functions returning int give 0 on success and -1 on failure. */
+
+#ifndef RUSTY_LIDAR_VIEWER_H
+#define RUSTY_LIDAR_VIEWER_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct lidar_t lidar_t;
+
+// `data` points at width * height distances in millimeters, row major, and stays valid
+// until the next `lidar_next_frame` or `lidar_close` on the same handle.
+typedef struct lidar_frame_t {
+  const uint16_t *data;
+  uint32_t width;
+  uint32_t height;
+  uint64_t sequence;
+} lidar_frame_t;
+
+// Opens the port, runs the handshake and starts 3D streaming. Returns NULL on failure.
+//
+// # Safety
+//
+// `port` must be NULL or a valid NUL terminated string.
+struct lidar_t *lidar_open(const char *port, uint32_t baud_rate);
+
+// Blocks until the next frame has been read and fills `frame` with it. Frames that fail
+// their checksum are skipped.
+//
+// # Safety
+//
+// `lidar` must come from `lidar_open` and not be closed, `frame` must be writable.
+int lidar_next_frame(struct lidar_t *lidar, struct lidar_frame_t *frame);
+
+// Stops streaming and releases the handle. NULL is ignored.
+//
+// # Safety
+//
+// `lidar` must come from `lidar_open` and not be used afterwards.
+void lidar_close(struct lidar_t *lidar);
+
+#endif  /* RUSTY_LIDAR_VIEWER_H */
@@ -0,0 +1,35 @@
+/* ABI for filter plugins loaded from the plugins directory, see src/plugin.rs.
+ *
+ * A plugin is a shared library (.so, .dylib) exporting lidar_filter_plugin(), which
+ * returns a table that has to stay valid while the library is loaded. The filter is then
+ * used by its name in the config:
+ *
+ *   [[pipeline.stage]]
+ *   type = "my_filter"
+ *   strength = 2
+ */
+
+#ifndef RUSTY_LIDAR_VIEWER_PLUGIN_H
+#define RUSTY_LIDAR_VIEWER_PLUGIN_H
+
+#include <stdint.h>
+
+#define LIDAR_PLUGIN_ABI_VERSION 1
+
+typedef struct lidar_filter_plugin_t {
+  /* LIDAR_PLUGIN_ABI_VERSION the plugin was built against */
+  uint32_t abi_version;
+  /* type of the stage, must not be one of the built in filters */
+  const char *name;
+  /* the stage's options as name=value pairs joined with '&', e.g. "strength=2",
+   * returns the filter's state or NULL to reject the options */
+  void *(*create)(const char *options);
+  /* filters width * height distances in millimeters in place, row major, 0 or 4080
+   * and above is invalid */
+  void (*apply)(void *state, uint16_t *data, uint32_t width, uint32_t height);
+  void (*destroy)(void *state);
+} lidar_filter_plugin_t;
+
+const lidar_filter_plugin_t *lidar_filter_plugin(void);
+
+#endif  /* RUSTY_LIDAR_VIEWER_PLUGIN_H */
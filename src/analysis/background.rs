@@ -0,0 +1,134 @@
+// Per pixel background model: the mean valid distance over the first learn=<frames>
+// frames, then kept up to date with rate=<weight of a new frame> wherever the pixel is
+// background. A pixel is foreground when it is threshold_mm=<mm> nearer than the
+// background, or valid where the background never was.
+use crate::analysis::{Analysis, Analyzer};
+use crate::depth::{is_valid, DepthFrame};
+use crate::options::Options;
+
+use serde::Serialize;
+
+const DEFAULT_LEARN : u32 = 30;
+const DEFAULT_RATE : f32 = 0.01;
+const DEFAULT_THRESHOLD_MM : f32 = 100.0;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Foreground
+{
+    pub width : usize,
+    pub height : usize,
+    pub foreground_pixels : usize,
+    // one bit per pixel, row major, least significant bit first
+    pub mask : Vec<u8>,
+}
+
+impl Foreground
+{
+
+pub fn is_foreground(&self, pixel : usize) -> bool
+{
+    self.mask[pixel / 8] & (1 << (pixel % 8)) != 0
+}
+
+}
+
+pub struct BackgroundModel
+{
+    learn : u32,
+    rate : f32,
+    threshold_mm : f32,
+    frames : u32,
+    // mean and valid sample count while learning
+    background : Vec<(f32, u32)>,
+}
+
+impl BackgroundModel
+{
+
+pub fn new(learn : u32, rate : f32, threshold_mm : f32) -> BackgroundModel
+{
+    BackgroundModel { learn, rate, threshold_mm, frames : 0, background : Vec::new() }
+}
+
+// the foreground of this frame, None while the model is still learning
+pub fn update(&mut self, depth : &DepthFrame) -> Option<Foreground>
+{
+    if self.background.len() != depth.data.len()
+    {
+        self.background = vec![(0.0, 0); depth.data.len()];
+        self.frames = 0;
+    }
+    if self.frames < self.learn
+    {
+        self.frames += 1;
+        for (distance, (mean, count)) in depth.data.iter().zip(self.background.iter_mut())
+        {
+            if is_valid(*distance)
+            {
+                *count += 1;
+                *mean += (*distance as f32 - *mean) / *count as f32;
+            }
+        }
+        return None
+    }
+
+    let mut foreground = Foreground { width : depth.width, height : depth.height, foreground_pixels : 0, mask : vec![0; depth.data.len().div_ceil(8)] };
+    for (pixel, (distance, (mean, count))) in depth.data.iter().zip(self.background.iter_mut()).enumerate()
+    {
+        if !is_valid(*distance)
+        {
+            continue;
+        }
+        let distance = *distance as f32;
+        if *count == 0 || *mean - distance > self.threshold_mm
+        {
+            foreground.mask[pixel / 8] |= 1 << (pixel % 8);
+            foreground.foreground_pixels += 1;
+        }
+        else
+        {
+            *mean += self.rate * (distance - *mean);
+        }
+    }
+    Some(foreground)
+}
+
+}
+
+pub struct Background
+{
+    model : BackgroundModel,
+}
+
+impl Background
+{
+
+pub fn new(options : &Options) -> Result<Background, String>
+{
+    let mut learn = DEFAULT_LEARN;
+    let mut rate = DEFAULT_RATE;
+    let mut threshold_mm = DEFAULT_THRESHOLD_MM;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "learn" => learn = value.parse().map_err(|_| format!("invalid value for learn {}", value))?,
+            "rate" => rate = value.parse().map_err(|_| format!("invalid value for rate {}", value))?,
+            "threshold_mm" => threshold_mm = value.parse().map_err(|_| format!("invalid value for threshold_mm {}", value))?,
+            _ => return Err(format!("background analyzer has no option {}", name)),
+        }
+    }
+    Ok(Background { model : BackgroundModel::new(learn, rate, threshold_mm) })
+}
+
+}
+
+impl Analyzer for Background
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    self.model.update(depth).map(Analysis::Foreground)
+}
+
+}
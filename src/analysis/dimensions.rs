@@ -0,0 +1,205 @@
+// Parcel dimensioning: finds the supporting plane, a table or the floor, as the largest
+// plane in view, whichever way the sensor looks at it, clusters what stands more than min_height=<meters> above it and measures
+// the largest cluster as a box standing on the plane. Length and width are the sides of
+// the smallest rectangle around the cluster seen from above, length the longer one, plus
+// one pixel spacing at the box's distance since the outermost samples lie half a pixel
+// inside the edges; height is the top surface's height above the plane. The tolerance is
+// what the sensor can resolve there: a pixel spacing for length and width, the spread of
+// the top surface plus the plane threshold=<meters> for height.
+use crate::analysis::obstacles::cluster_obstacles;
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::{PointCloud, Projection, FOV_H_DEG, FOV_V_DEG};
+use crate::depth::{DepthFrame, HEIGHT_3D, WIDTH_3D};
+use crate::options::Options;
+use crate::planes;
+
+use serde::Serialize;
+
+const DEFAULT_THRESHOLD : f32 = 0.01;
+const DEFAULT_MIN_HEIGHT : f32 = 0.02;
+const DEFAULT_TOLERANCE : f32 = 0.1;
+const DEFAULT_MIN_POINTS : usize = 50;
+// the top surface is the highest points, from this quantile of heights on
+const TOP_QUANTILE : f32 = 0.9;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BoxDimensions
+{
+    // meters
+    pub length : f32,
+    pub width : f32,
+    pub height : f32,
+    // plus or minus, meters, for length, width and height
+    pub tolerance : [f32; 3],
+    // center of the footprint on the plane, in the coordinates of cloud.rs
+    pub center : [f32; 3],
+    pub point_count : usize,
+}
+
+pub struct Dimensions
+{
+    projection : Projection,
+    threshold : f32,
+    min_height : f32,
+    tolerance : f32,
+    min_points : usize,
+}
+
+impl Dimensions
+{
+
+pub fn new(options : &Options) -> Result<Dimensions, String>
+{
+    let mut threshold = DEFAULT_THRESHOLD;
+    let mut min_height = DEFAULT_MIN_HEIGHT;
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut min_points = DEFAULT_MIN_POINTS;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "threshold" => threshold = value.parse().map_err(|_| format!("invalid value for threshold {}", value))?,
+            "min_height" => min_height = value.parse().map_err(|_| format!("invalid value for min_height {}", value))?,
+            "tolerance" => tolerance = value.parse().map_err(|_| format!("invalid value for tolerance {}", value))?,
+            "min_points" => min_points = value.parse().map_err(|_| format!("invalid value for min_points {}", value))?,
+            _ => return Err(format!("dimensions analyzer has no option {}", name)),
+        }
+    }
+    Ok(Dimensions { projection : Projection::default(), threshold, min_height, tolerance, min_points })
+}
+
+}
+
+fn dot(a : [f32; 3], b : [f32; 3]) -> f32
+{
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a : [f32; 3], b : [f32; 3]) -> [f32; 3]
+{
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalized(a : [f32; 3]) -> [f32; 3]
+{
+    let length = dot(a, a).sqrt();
+    [a[0] / length, a[1] / length, a[2] / length]
+}
+
+// Andrew's monotone chain, counter clockwise
+fn convex_hull(mut points : Vec<[f32; 2]>) -> Vec<[f32; 2]>
+{
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3
+    {
+        return points
+    }
+    let turn = |o : [f32; 2], a : [f32; 2], b : [f32; 2]| (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]);
+    let mut hull : Vec<[f32; 2]> = Vec::with_capacity(points.len() * 2);
+    for pass in 0..2
+    {
+        let start = hull.len();
+        let ordered : Box<dyn Iterator<Item = &[f32; 2]>> = if pass == 0 { Box::new(points.iter()) } else { Box::new(points.iter().rev()) };
+        for point in ordered
+        {
+            while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], *point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        // the last point starts the other half
+        hull.pop();
+    }
+    hull
+}
+
+// the smallest area rectangle has a side along a hull edge: (long side, short side, center)
+fn min_area_rectangle(hull : &[[f32; 2]]) -> Option<(f32, f32, [f32; 2])>
+{
+    let mut best : Option<(f32, f32, f32, [f32; 2])> = None;
+    for index in 0..hull.len()
+    {
+        let (a, b) = (hull[index], hull[(index + 1) % hull.len()]);
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        if length == 0.0
+        {
+            continue;
+        }
+        let axis = [(b[0] - a[0]) / length, (b[1] - a[1]) / length];
+        let (mut min_u, mut max_u, mut min_v, mut max_v) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+        for point in hull
+        {
+            let u = point[0] * axis[0] + point[1] * axis[1];
+            let v = -point[0] * axis[1] + point[1] * axis[0];
+            (min_u, max_u, min_v, max_v) = (min_u.min(u), max_u.max(u), min_v.min(v), max_v.max(v));
+        }
+        let area = (max_u - min_u) * (max_v - min_v);
+        if best.is_none_or(|(best, _, _, _)| area < best)
+        {
+            let (u, v) = ((min_u + max_u) / 2.0, (min_v + max_v) / 2.0);
+            let center = [u * axis[0] - v * axis[1], u * axis[1] + v * axis[0]];
+            let (side_u, side_v) = (max_u - min_u, max_v - min_v);
+            best = Some((area, side_u.max(side_v), side_u.min(side_v), center));
+        }
+    }
+    best.map(|(_, length, width, center)| (length, width, center))
+}
+
+impl Analyzer for Dimensions
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let cloud = self.projection.project_pooled(depth);
+    let mut plane = planes::segment_planes(&cloud, 1, self.threshold, planes::DEFAULT_ITERATIONS, self.min_points).pop()?;
+    // up is towards the sensor
+    if plane.d < 0.0
+    {
+        plane.normal = plane.normal.map(|value| -value);
+        plane.d = -plane.d;
+    }
+    let mut above = PointCloud::default();
+    for point in cloud.points.iter()
+    {
+        if plane.distance(*point) > self.min_height
+        {
+            above.points.push(*point);
+        }
+    }
+    let largest = cluster_obstacles(&above, self.tolerance, self.min_points, usize::MAX).into_iter().max_by_key(|cluster| cluster.point_count)?;
+
+    // plane coordinates: u and v along the plane, the normal up
+    let up = plane.normal;
+    let u = normalized(cross(up, if up[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 0.0, 1.0] }));
+    let v = cross(up, u);
+    let box_points : Vec<[f32; 3]> = above.points.iter().copied()
+        .filter(|point| (0..3).all(|axis| point[axis] >= largest.bbox[0][axis] && point[axis] <= largest.bbox[1][axis])).collect();
+    let hull = convex_hull(box_points.iter().map(|point| [dot(*point, u), dot(*point, v)]).collect());
+    let (length, width, footprint) = min_area_rectangle(&hull)?;
+
+    let mut heights : Vec<f32> = box_points.iter().map(|point| plane.distance(*point)).collect();
+    heights.sort_unstable_by(f32::total_cmp);
+    let top = &heights[((heights.len() - 1) as f32 * TOP_QUANTILE) as usize..];
+    let height = top.iter().sum::<f32>() / top.len() as f32;
+    let spread = (top.iter().map(|value| (value - height).powi(2)).sum::<f32>() / top.len() as f32).sqrt();
+
+    // on the plane below the footprint center
+    let offset = -plane.d;
+    let center = [0, 1, 2].map(|axis| footprint[0] * u[axis] + footprint[1] * v[axis] + offset * up[axis]);
+    // the coarser of the horizontal and vertical pixel pitch
+    let pitch = (FOV_H_DEG.to_radians() / WIDTH_3D as f32).max(FOV_V_DEG.to_radians() / HEIGHT_3D as f32);
+    let spacing = dot(largest.centroid, largest.centroid).sqrt() * pitch;
+    Some(Analysis::Dimensions(BoxDimensions
+    {
+        length : length + spacing,
+        width : width + spacing,
+        height,
+        tolerance : [spacing, spacing, spread + self.threshold],
+        center,
+        point_count : box_points.len(),
+    }))
+}
+
+}
@@ -0,0 +1,166 @@
+// Fall detection for care settings: foreground clusters are tracked like for people
+// counting and their centroid's height above the floor followed. A track that drops from
+// at least standing_height=<meters> to at most fallen_height=<meters> within
+// fall_frames=<frames> and stays down for hold=<frames> frames is reported once as a fall.
+// The floor is found with RANSAC in the first frames the model learns the background from.
+// learn, rate, threshold_mm, tolerance, min_points, max_points and max_jump are as for
+// the people analyzer.
+use crate::analysis::background::BackgroundModel;
+use crate::analysis::tracks::{foreground_clusters, Tracker};
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+use crate::options::Options;
+use crate::planes::{self, Plane};
+
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+const DEFAULT_STANDING_HEIGHT : f32 = 0.6;
+const DEFAULT_FALLEN_HEIGHT : f32 = 0.3;
+const DEFAULT_FALL_FRAMES : u64 = 10;
+const DEFAULT_HOLD : u64 = 30;
+const DEFAULT_LEARN : u32 = 30;
+const DEFAULT_RATE : f32 = 0.01;
+const DEFAULT_THRESHOLD_MM : f32 = 100.0;
+const DEFAULT_TOLERANCE : f32 = 0.1;
+const DEFAULT_MIN_POINTS : usize = 50;
+const DEFAULT_MAX_POINTS : usize = 5000;
+const DEFAULT_MAX_JUMP : f32 = 0.5;
+const FLOOR_MAX_TILT_DEG : f32 = 15.0;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Fall
+{
+    pub track : u64,
+    // centroid in meters, in the coordinates of cloud.rs
+    pub position : [f32; 3],
+    // of the centroid above the floor, meters
+    pub height : f32,
+    // frames from the last time the track stood to it going down
+    pub fall_frames : u64,
+}
+
+#[derive(Default)]
+struct History
+{
+    standing : Option<u64>,
+    down : Option<(u64, u64)>,
+    reported : bool,
+}
+
+pub struct Falls
+{
+    model : BackgroundModel,
+    projection : Projection,
+    floor : Option<Plane>,
+    standing_height : f32,
+    fallen_height : f32,
+    fall_frames : u64,
+    hold : u64,
+    tolerance : f32,
+    min_points : usize,
+    max_points : usize,
+    tracker : Tracker,
+    histories : HashMap<u64, History>,
+    frame : u64,
+}
+
+impl Falls
+{
+
+pub fn new(options : &Options) -> Result<Falls, String>
+{
+    let mut standing_height = DEFAULT_STANDING_HEIGHT;
+    let mut fallen_height = DEFAULT_FALLEN_HEIGHT;
+    let mut fall_frames = DEFAULT_FALL_FRAMES;
+    let mut hold = DEFAULT_HOLD;
+    let mut learn = DEFAULT_LEARN;
+    let mut rate = DEFAULT_RATE;
+    let mut threshold_mm = DEFAULT_THRESHOLD_MM;
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut min_points = DEFAULT_MIN_POINTS;
+    let mut max_points = DEFAULT_MAX_POINTS;
+    let mut max_jump = DEFAULT_MAX_JUMP;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "standing_height" => standing_height = value.parse().map_err(|_| format!("invalid value for standing_height {}", value))?,
+            "fallen_height" => fallen_height = value.parse().map_err(|_| format!("invalid value for fallen_height {}", value))?,
+            "fall_frames" => fall_frames = value.parse().map_err(|_| format!("invalid value for fall_frames {}", value))?,
+            "hold" => hold = value.parse().map_err(|_| format!("invalid value for hold {}", value))?,
+            "learn" => learn = value.parse().map_err(|_| format!("invalid value for learn {}", value))?,
+            "rate" => rate = value.parse().map_err(|_| format!("invalid value for rate {}", value))?,
+            "threshold_mm" => threshold_mm = value.parse().map_err(|_| format!("invalid value for threshold_mm {}", value))?,
+            "tolerance" => tolerance = value.parse().map_err(|_| format!("invalid value for tolerance {}", value))?,
+            "min_points" => min_points = value.parse().map_err(|_| format!("invalid value for min_points {}", value))?,
+            "max_points" => max_points = value.parse().map_err(|_| format!("invalid value for max_points {}", value))?,
+            "max_jump" => max_jump = value.parse().map_err(|_| format!("invalid value for max_jump {}", value))?,
+            _ => return Err(format!("falls analyzer has no option {}", name)),
+        }
+    }
+    if fallen_height >= standing_height
+    {
+        return Err("fallen_height has to be below standing_height".to_string())
+    }
+    Ok(Falls
+    {
+        model : BackgroundModel::new(learn, rate, threshold_mm),
+        projection : Projection::default(),
+        floor : None,
+        standing_height, fallen_height, fall_frames, hold, tolerance, min_points, max_points,
+        tracker : Tracker::new(max_jump),
+        histories : HashMap::new(),
+        frame : 0,
+    })
+}
+
+}
+
+impl Analyzer for Falls
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    self.frame += 1;
+    if self.floor.is_none()
+    {
+        self.floor = planes::find_ground(&self.projection.project_pooled(depth), planes::DEFAULT_THRESHOLD, planes::DEFAULT_ITERATIONS, FLOOR_MAX_TILT_DEG);
+    }
+    let clusters = foreground_clusters(&mut self.model, &self.projection, depth, self.tolerance, self.min_points, self.max_points)?;
+    let floor = self.floor.as_ref()?;
+    let centroids : Vec<[f32; 3]> = clusters.iter().map(|cluster| cluster.centroid).collect();
+    let mut fall = None;
+    let tracks = self.tracker.update(&centroids);
+    self.histories.retain(|id, _| tracks.iter().any(|track| track.id == *id));
+    for track in tracks.iter().filter(|track| track.missed == 0)
+    {
+        let height = floor.distance(track.position);
+        let history = self.histories.entry(track.id).or_default();
+        if height >= self.standing_height
+        {
+            *history = History { standing : Some(self.frame), ..History::default() };
+        }
+        else if height > self.fallen_height
+        {
+            history.down = None;
+        }
+        else if let Some((down, fall_frames)) = history.down
+        {
+            if !history.reported && self.frame - down >= self.hold
+            {
+                history.reported = true;
+                fall = Some(Fall { track : track.id, position : track.position, height, fall_frames });
+            }
+        }
+        else if let Some(standing) = history.standing.filter(|standing| self.frame - standing <= self.fall_frames)
+        {
+            history.down = Some((self.frame, self.frame - standing));
+        }
+    }
+    fall.map(Analysis::Fall)
+}
+
+}
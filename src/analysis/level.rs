@@ -0,0 +1,81 @@
+// Fill level of a tank or bin seen from above: the distance to the surface is the median
+// valid distance in roi=<x0,y0,x1,y1> (pixels, x1 and y1 exclusive, the whole frame by
+// default), and the level is where that lies between empty_mm=<mm> (distance to the
+// bottom) and full_mm=<mm> (distance to the surface when full), in percent. The median
+// keeps the walls and a few stray pixels at the edge of the roi from moving it.
+use crate::analysis::{Analysis, Analyzer};
+use crate::depth::{is_valid, DepthFrame};
+use crate::options::{self, Options};
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FillLevel
+{
+    // 0 empty to 100 full, clamped
+    pub percent : f32,
+    pub distance_mm : u16,
+    // valid pixels in the roi
+    pub pixels : usize,
+}
+
+pub struct Level
+{
+    roi : Option<[usize; 4]>,
+    empty_mm : f32,
+    full_mm : f32,
+    samples : Vec<u16>,
+}
+
+impl Level
+{
+
+pub fn new(options : &Options) -> Result<Level, String>
+{
+    let mut roi = None;
+    let mut empty_mm = None;
+    let mut full_mm = None;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "roi" => roi = Some(options::list(value).filter(|[x0, y0, x1, y1]| x0 < x1 && y0 < y1).ok_or(format!("invalid roi {}, expected x0,y0,x1,y1", value))?),
+            "empty_mm" => empty_mm = Some(value.parse().map_err(|_| format!("invalid value for empty_mm {}", value))?),
+            "full_mm" => full_mm = Some(value.parse().map_err(|_| format!("invalid value for full_mm {}", value))?),
+            _ => return Err(format!("level analyzer has no option {}", name)),
+        }
+    }
+    let (empty_mm, full_mm) = match (empty_mm, full_mm)
+    {
+        (Some(empty_mm), Some(full_mm)) if empty_mm > full_mm => (empty_mm, full_mm),
+        (Some(_), Some(_)) => return Err("empty_mm has to be farther than full_mm".to_string()),
+        _ => return Err("level needs empty_mm and full_mm".to_string()),
+    };
+    Ok(Level { roi, empty_mm, full_mm, samples : Vec::new() })
+}
+
+}
+
+impl Analyzer for Level
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let [x0, y0, x1, y1] = self.roi.unwrap_or([0, 0, depth.width, depth.height]);
+    self.samples.clear();
+    for y in y0..y1.min(depth.height)
+    {
+        let row = &depth.data[y * depth.width..(y + 1) * depth.width];
+        self.samples.extend(row[x0.min(depth.width)..x1.min(depth.width)].iter().copied().filter(|distance| is_valid(*distance)));
+    }
+    if self.samples.is_empty()
+    {
+        return None
+    }
+    let middle = self.samples.len() / 2;
+    let distance_mm = *self.samples.select_nth_unstable(middle).1;
+    let percent = ((self.empty_mm - distance_mm as f32) / (self.empty_mm - self.full_mm) * 100.0).clamp(0.0, 100.0);
+    Some(Analysis::Level(FillLevel { percent, distance_mm, pixels : self.samples.len() }))
+}
+
+}
@@ -0,0 +1,119 @@
+// Analyzers look at every depth frame after the filters ran and report what they found,
+// e.g. the obstacles in view. Their results go to the publishers next to the frame.
+use crate::depth::DepthFrame;
+use crate::options;
+
+use serde::Serialize;
+
+pub mod background;
+pub mod dimensions;
+pub mod falls;
+pub mod level;
+pub mod motion;
+pub mod obstacles;
+pub mod odometry;
+pub mod people;
+pub mod sectors;
+pub mod tracks;
+pub mod zones;
+
+use background::Foreground;
+use dimensions::BoxDimensions;
+use falls::Fall;
+use level::FillLevel;
+use motion::MotionEvent;
+use obstacles::Obstacle;
+use odometry::Odometry;
+use people::PeopleCount;
+use zones::ZoneReport;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Analysis
+{
+    Obstacles(Vec<Obstacle>),
+    // nearest distance in mm by sector, left to right
+    Sectors(Vec<Option<u16>>),
+    Foreground(Foreground),
+    Odometry(Odometry),
+    Motion(MotionEvent),
+    // outputs of a --script, see script.rs
+    Script(serde_json::Value),
+    Zones(Vec<ZoneReport>),
+    People(PeopleCount),
+    Fall(Fall),
+    Dimensions(BoxDimensions),
+    Level(FillLevel),
+}
+
+impl Analysis
+{
+
+// short name, e.g. the mqtt topic it is published on
+pub fn name(&self) -> &'static str
+{
+    match self
+    {
+        Analysis::Obstacles(_) => "obstacles",
+        Analysis::Sectors(_) => "sectors",
+        Analysis::Foreground(_) => "foreground",
+        Analysis::Odometry(_) => "odometry",
+        Analysis::Motion(_) => "motion",
+        Analysis::Script(_) => "script",
+        Analysis::Zones(_) => "zones",
+        Analysis::People(_) => "people",
+        Analysis::Fall(_) => "fall",
+        Analysis::Dimensions(_) => "dimensions",
+        Analysis::Level(_) => "level",
+    }
+}
+
+// what is worth a notification, e.g. on a webhook: motion events, falls, the zones that
+// were entered or left and people crossing the counting line, None for everything else
+pub fn events(&self) -> Option<Analysis>
+{
+    match self
+    {
+        Analysis::Motion(_) | Analysis::Fall(_) => Some(self.clone()),
+        Analysis::People(count) if count.crossed_in + count.crossed_out > 0 => Some(self.clone()),
+        Analysis::Zones(reports) =>
+        {
+            let changed : Vec<ZoneReport> = reports.iter().filter(|report| report.event.is_some()).cloned().collect();
+            if changed.is_empty() { None } else { Some(Analysis::Zones(changed)) }
+        },
+        _ => None,
+    }
+}
+
+// events someone has to see, sent reliably and kept where the transport can
+pub fn is_urgent(&self) -> bool
+{
+    matches!(self, Analysis::Fall(_))
+}
+
+}
+
+pub trait Analyzer
+{
+    fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>;
+}
+
+// analyzers are given as name[?option=value&...], e.g. obstacles?tolerance=0.1
+pub fn open(spec : &str) -> Result<Box<dyn Analyzer>, String>
+{
+    let (name, options) = options::split(spec)?;
+    let analyzer : Box<dyn Analyzer> = match name
+    {
+        "background" => Box::new(background::Background::new(&options)?),
+        "dimensions" => Box::new(dimensions::Dimensions::new(&options)?),
+        "falls" => Box::new(falls::Falls::new(&options)?),
+        "level" => Box::new(level::Level::new(&options)?),
+        "motion" => Box::new(motion::Motion::new(&options)?),
+        "obstacles" => Box::new(obstacles::Obstacles::new(&options)?),
+        "people" => Box::new(people::People::new(&options)?),
+        "odometry" => Box::new(odometry::OdometryEstimator::new(&options)?),
+        "sectors" => Box::new(sectors::Sectors::new(&options)?),
+        _ => return Err(format!("unknown analyzer {}", name)),
+    };
+    Ok(analyzer)
+}
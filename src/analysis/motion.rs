@@ -0,0 +1,159 @@
+// Motion detection on top of the background model: motion starts once at least
+// min_pixels=<pixels> are foreground and stops after hold=<frames> frames with fewer.
+// Only the transitions are reported, so downstream gets one event each way instead of a
+// mask every frame. learn, rate and threshold_mm go to the background model.
+use crate::analysis::background::{BackgroundModel, Foreground};
+use crate::analysis::{Analysis, Analyzer};
+use crate::depth::DepthFrame;
+use crate::options::Options;
+
+use serde::Serialize;
+
+const DEFAULT_LEARN : u32 = 30;
+const DEFAULT_RATE : f32 = 0.01;
+const DEFAULT_THRESHOLD_MM : f32 = 100.0;
+const DEFAULT_MIN_PIXELS : usize = 50;
+const DEFAULT_HOLD : u32 = 10;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionKind
+{
+    MotionStarted,
+    MotionStopped,
+}
+
+// bounding box in pixels, inclusive
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region
+{
+    pub column_min : usize,
+    pub row_min : usize,
+    pub column_max : usize,
+    pub row_max : usize,
+}
+
+impl Region
+{
+
+fn merge(&self, other : &Region) -> Region
+{
+    Region
+    {
+        column_min : self.column_min.min(other.column_min),
+        row_min : self.row_min.min(other.row_min),
+        column_max : self.column_max.max(other.column_max),
+        row_max : self.row_max.max(other.row_max),
+    }
+}
+
+}
+
+// started carries the frame that started the motion, stopped the whole motion: the
+// region everything moved in and the largest magnitude seen
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct MotionEvent
+{
+    pub kind : MotionKind,
+    pub region : Region,
+    // fraction of the pixels that are foreground, 0 to 1
+    pub magnitude : f32,
+    // frames the motion lasted, 0 when it just started
+    pub frames : u32,
+}
+
+fn region(foreground : &Foreground) -> Option<Region>
+{
+    let mut region : Option<Region> = None;
+    for pixel in (0..foreground.width * foreground.height).filter(|pixel| foreground.is_foreground(*pixel))
+    {
+        let (column, row) = (pixel % foreground.width, pixel / foreground.width);
+        let this = Region { column_min : column, row_min : row, column_max : column, row_max : row };
+        region = Some(region.map_or(this, |region| region.merge(&this)));
+    }
+    region
+}
+
+struct Moving
+{
+    region : Region,
+    magnitude : f32,
+    frames : u32,
+    quiet : u32,
+}
+
+pub struct Motion
+{
+    model : BackgroundModel,
+    min_pixels : usize,
+    hold : u32,
+    moving : Option<Moving>,
+}
+
+impl Motion
+{
+
+pub fn new(options : &Options) -> Result<Motion, String>
+{
+    let mut learn = DEFAULT_LEARN;
+    let mut rate = DEFAULT_RATE;
+    let mut threshold_mm = DEFAULT_THRESHOLD_MM;
+    let mut min_pixels = DEFAULT_MIN_PIXELS;
+    let mut hold = DEFAULT_HOLD;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "learn" => learn = value.parse().map_err(|_| format!("invalid value for learn {}", value))?,
+            "rate" => rate = value.parse().map_err(|_| format!("invalid value for rate {}", value))?,
+            "threshold_mm" => threshold_mm = value.parse().map_err(|_| format!("invalid value for threshold_mm {}", value))?,
+            "min_pixels" => min_pixels = value.parse().ok().filter(|min_pixels| *min_pixels > 0).ok_or(format!("invalid value for min_pixels {}", value))?,
+            "hold" => hold = value.parse().map_err(|_| format!("invalid value for hold {}", value))?,
+            _ => return Err(format!("motion analyzer has no option {}", name)),
+        }
+    }
+    Ok(Motion { model : BackgroundModel::new(learn, rate, threshold_mm), min_pixels, hold, moving : None })
+}
+
+}
+
+impl Analyzer for Motion
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let foreground = self.model.update(depth)?;
+    let magnitude = foreground.foreground_pixels as f32 / (foreground.width * foreground.height).max(1) as f32;
+    let active = if foreground.foreground_pixels >= self.min_pixels { region(&foreground) } else { None };
+    match (&mut self.moving, active)
+    {
+        (None, Some(region)) =>
+        {
+            self.moving = Some(Moving { region, magnitude, frames : 0, quiet : 0 });
+            Some(Analysis::Motion(MotionEvent { kind : MotionKind::MotionStarted, region, magnitude, frames : 0 }))
+        },
+        (Some(moving), Some(region)) =>
+        {
+            moving.region = moving.region.merge(&region);
+            moving.magnitude = moving.magnitude.max(magnitude);
+            moving.frames += 1;
+            moving.quiet = 0;
+            None
+        },
+        (Some(moving), None) =>
+        {
+            moving.frames += 1;
+            moving.quiet += 1;
+            if moving.quiet <= self.hold
+            {
+                return None
+            }
+            let event = MotionEvent { kind : MotionKind::MotionStopped, region : moving.region, magnitude : moving.magnitude, frames : moving.frames };
+            self.moving = None;
+            Some(Analysis::Motion(event))
+        },
+        (None, None) => None,
+    }
+}
+
+}
@@ -0,0 +1,121 @@
+// Euclidean clustering of the projected cloud: points closer than tolerance=<meters> to
+// each other belong to the same obstacle, clusters with fewer than min_points or more
+// than max_points points are dropped. Put the ground filter first so the floor doesn't
+// join everything into one cluster.
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::{PointCloud, Projection};
+use crate::depth::DepthFrame;
+use crate::options::Options;
+
+use serde::Serialize;
+
+const DEFAULT_TOLERANCE : f32 = 0.1;
+const DEFAULT_MIN_POINTS : usize = 20;
+const DEFAULT_MAX_POINTS : usize = 5000;
+
+// in meters, in the coordinates of cloud.rs
+#[derive(Serialize, Clone, Debug)]
+pub struct Obstacle
+{
+    pub centroid : [f32; 3],
+    // min and max corner
+    pub bbox : [[f32; 3]; 2],
+    pub point_count : usize,
+}
+
+pub fn cluster_obstacles(cloud : &PointCloud, tolerance : f32, min_points : usize, max_points : usize) -> Vec<Obstacle>
+{
+    let tree = cloud.kd_tree();
+    let mut visited = vec![false; cloud.len()];
+    let mut neighbours = Vec::new();
+    let mut cluster = Vec::new();
+    let mut obstacles = Vec::new();
+    for seed in 0..cloud.len()
+    {
+        if visited[seed]
+        {
+            continue;
+        }
+        visited[seed] = true;
+        cluster.clear();
+        cluster.push(seed);
+        let mut next = 0;
+        while next < cluster.len()
+        {
+            tree.within(cloud.points[cluster[next]], tolerance, &mut neighbours);
+            for neighbour in neighbours.iter()
+            {
+                if !visited[*neighbour]
+                {
+                    visited[*neighbour] = true;
+                    cluster.push(*neighbour);
+                }
+            }
+            next += 1;
+        }
+        if cluster.len() >= min_points && cluster.len() <= max_points
+        {
+            obstacles.push(obstacle(cloud, &cluster));
+        }
+    }
+    obstacles
+}
+
+fn obstacle(cloud : &PointCloud, cluster : &[usize]) -> Obstacle
+{
+    let mut sum = [0.0; 3];
+    let mut bbox = [[f32::MAX; 3], [f32::MIN; 3]];
+    for point in cluster.iter().map(|index| cloud.points[*index])
+    {
+        for axis in 0..3
+        {
+            sum[axis] += point[axis];
+            bbox[0][axis] = bbox[0][axis].min(point[axis]);
+            bbox[1][axis] = bbox[1][axis].max(point[axis]);
+        }
+    }
+    let count = cluster.len() as f32;
+    Obstacle { centroid : sum.map(|sum| sum / count), bbox, point_count : cluster.len() }
+}
+
+pub struct Obstacles
+{
+    tolerance : f32,
+    min_points : usize,
+    max_points : usize,
+    projection : Projection,
+}
+
+impl Obstacles
+{
+
+pub fn new(options : &Options) -> Result<Obstacles, String>
+{
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut min_points = DEFAULT_MIN_POINTS;
+    let mut max_points = DEFAULT_MAX_POINTS;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "tolerance" => tolerance = value.parse().map_err(|_| format!("invalid value for tolerance {}", value))?,
+            "min_points" => min_points = value.parse().map_err(|_| format!("invalid value for min_points {}", value))?,
+            "max_points" => max_points = value.parse().map_err(|_| format!("invalid value for max_points {}", value))?,
+            _ => return Err(format!("obstacles analyzer has no option {}", name)),
+        }
+    }
+    Ok(Obstacles { tolerance, min_points, max_points, projection : Projection::default() })
+}
+
+}
+
+impl Analyzer for Obstacles
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let cloud = self.projection.project_pooled(depth);
+    Some(Analysis::Obstacles(cluster_obstacles(&cloud, self.tolerance, self.min_points, self.max_points)))
+}
+
+}
@@ -0,0 +1,70 @@
+// Ego motion from ICP between consecutive frames (leaf=<voxel meters>, max_distance=<pair
+// distance meters>, iterations=<count>, see icp.rs). Reports the motion since the previous
+// frame and the pose relative to the first frame; when a frame can't be aligned the
+// estimate keeps the last pose and starts again from that frame.
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::{PointCloud, Projection};
+use crate::depth::DepthFrame;
+use crate::icp::{align, Transform, DEFAULT_ITERATIONS, DEFAULT_LEAF, DEFAULT_MAX_DISTANCE};
+use crate::options::Options;
+use crate::pool::Pooled;
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Odometry
+{
+    // takes points of this frame into the first one
+    pub pose : Transform,
+    // takes points of this frame into the previous one
+    pub step : Transform,
+    pub rmse : f32,
+    pub pairs : usize,
+}
+
+pub struct OdometryEstimator
+{
+    leaf : f32,
+    max_distance : f32,
+    iterations : usize,
+    pose : Transform,
+    previous : Option<Pooled<PointCloud>>,
+    projection : Projection,
+}
+
+impl OdometryEstimator
+{
+
+pub fn new(options : &Options) -> Result<OdometryEstimator, String>
+{
+    let mut leaf = DEFAULT_LEAF;
+    let mut max_distance = DEFAULT_MAX_DISTANCE;
+    let mut iterations = DEFAULT_ITERATIONS;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "leaf" => leaf = value.parse().ok().filter(|leaf| *leaf > 0.0).ok_or(format!("invalid value for leaf {}", value))?,
+            "max_distance" => max_distance = value.parse().map_err(|_| format!("invalid value for max_distance {}", value))?,
+            "iterations" => iterations = value.parse().map_err(|_| format!("invalid value for iterations {}", value))?,
+            _ => return Err(format!("odometry analyzer has no option {}", name)),
+        }
+    }
+    Ok(OdometryEstimator { leaf, max_distance, iterations, pose : Transform::default(), previous : None, projection : Projection::default() })
+}
+
+}
+
+impl Analyzer for OdometryEstimator
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let cloud = self.projection.project_pooled(depth);
+    let previous = self.previous.replace(cloud);
+    let alignment = align(previous.as_ref()?, self.previous.as_ref()?, self.leaf, self.max_distance, self.iterations)?;
+    self.pose = self.pose.then(&alignment.transform);
+    Some(Analysis::Odometry(Odometry { pose : self.pose, step : alignment.transform, rmse : alignment.rmse, pairs : alignment.pairs }))
+}
+
+}
@@ -0,0 +1,141 @@
+// Counts people crossing a line on the ground. Foreground pixels of the background model
+// are projected and clustered like obstacles, each cluster is followed from frame to frame
+// by its centroid, and a track that moves across line=<x1,z1,x2,z2> (meters, in the x z
+// ground plane of cloud.rs) counts as in when it crosses to the left, seen from the first
+// end of the line towards the second, and as out when it crosses to the right.
+// learn, rate and threshold_mm go to the background model, tolerance, min_points and
+// max_points to the clustering; tracks follow centroids up to max_jump=<meters> per frame.
+use crate::analysis::background::BackgroundModel;
+use crate::analysis::tracks::{foreground_clusters, Tracker};
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+use crate::options::{self, Options};
+
+use serde::Serialize;
+
+const DEFAULT_LINE : [f32; 4] = [-2.0, 1.5, 2.0, 1.5];
+const DEFAULT_LEARN : u32 = 30;
+const DEFAULT_RATE : f32 = 0.01;
+const DEFAULT_THRESHOLD_MM : f32 = 100.0;
+const DEFAULT_TOLERANCE : f32 = 0.1;
+const DEFAULT_MIN_POINTS : usize = 50;
+const DEFAULT_MAX_POINTS : usize = 5000;
+const DEFAULT_MAX_JUMP : f32 = 0.5;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PeopleCount
+{
+    pub tracked : usize,
+    pub in_total : u64,
+    pub out_total : u64,
+    // crossings in this frame
+    pub crossed_in : u32,
+    pub crossed_out : u32,
+}
+
+pub struct People
+{
+    model : BackgroundModel,
+    projection : Projection,
+    line : [f32; 4],
+    tolerance : f32,
+    min_points : usize,
+    max_points : usize,
+    tracker : Tracker,
+    in_total : u64,
+    out_total : u64,
+}
+
+impl People
+{
+
+pub fn new(options : &Options) -> Result<People, String>
+{
+    let mut line = DEFAULT_LINE;
+    let mut learn = DEFAULT_LEARN;
+    let mut rate = DEFAULT_RATE;
+    let mut threshold_mm = DEFAULT_THRESHOLD_MM;
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut min_points = DEFAULT_MIN_POINTS;
+    let mut max_points = DEFAULT_MAX_POINTS;
+    let mut max_jump = DEFAULT_MAX_JUMP;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "line" => line = parse_line(value).ok_or(format!("invalid line {}, expected x1,z1,x2,z2", value))?,
+            "learn" => learn = value.parse().map_err(|_| format!("invalid value for learn {}", value))?,
+            "rate" => rate = value.parse().map_err(|_| format!("invalid value for rate {}", value))?,
+            "threshold_mm" => threshold_mm = value.parse().map_err(|_| format!("invalid value for threshold_mm {}", value))?,
+            "tolerance" => tolerance = value.parse().map_err(|_| format!("invalid value for tolerance {}", value))?,
+            "min_points" => min_points = value.parse().map_err(|_| format!("invalid value for min_points {}", value))?,
+            "max_points" => max_points = value.parse().map_err(|_| format!("invalid value for max_points {}", value))?,
+            "max_jump" => max_jump = value.parse().map_err(|_| format!("invalid value for max_jump {}", value))?,
+            _ => return Err(format!("people analyzer has no option {}", name)),
+        }
+    }
+    Ok(People
+    {
+        model : BackgroundModel::new(learn, rate, threshold_mm),
+        projection : Projection::default(),
+        line, tolerance, min_points, max_points,
+        tracker : Tracker::new(max_jump),
+        in_total : 0,
+        out_total : 0,
+    })
+}
+
+}
+
+// positive left of the line, negative right of it, None past its ends
+fn side(line : [f32; 4], from : [f32; 3], to : [f32; 3]) -> Option<(f32, f32)>
+{
+    let [x1, z1, x2, z2] = line;
+    let (dx, dz) = (x2 - x1, z2 - z1);
+    let cross = |point : [f32; 3]| dx * (point[2] - z1) - dz * (point[0] - x1);
+    let (before, after) = (cross(from), cross(to));
+    if before == after
+    {
+        return None
+    }
+    // where the step meets the line, as a fraction along it
+    let t = before / (before - after);
+    let (x, z) = (from[0] + t * (to[0] - from[0]), from[2] + t * (to[2] - from[2]));
+    let along = ((x - x1) * dx + (z - z1) * dz) / (dx * dx + dz * dz);
+    if (0.0..=1.0).contains(&along) { Some((before, after)) } else { None }
+}
+
+fn parse_line(value : &str) -> Option<[f32; 4]>
+{
+    let line : [f32; 4] = options::list(value)?;
+    if line[0] == line[2] && line[1] == line[3] { None } else { Some(line) }
+}
+
+impl Analyzer for People
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let clusters = foreground_clusters(&mut self.model, &self.projection, depth, self.tolerance, self.min_points, self.max_points)?;
+    let centroids : Vec<[f32; 3]> = clusters.iter().map(|cluster| cluster.centroid).collect();
+    let mut count = PeopleCount { tracked : 0, in_total : 0, out_total : 0, crossed_in : 0, crossed_out : 0 };
+    for track in self.tracker.update(&centroids)
+    {
+        if track.missed == 0
+        {
+            count.tracked += 1;
+        }
+        match track.previous.and_then(|previous| side(self.line, previous, track.position))
+        {
+            Some((before, after)) if before > 0.0 && after <= 0.0 => { count.crossed_out += 1; self.out_total += 1; },
+            Some((before, after)) if before <= 0.0 && after > 0.0 => { count.crossed_in += 1; self.in_total += 1; },
+            _ => (),
+        }
+    }
+    count.in_total = self.in_total;
+    count.out_total = self.out_total;
+    Some(Analysis::People(count))
+}
+
+}
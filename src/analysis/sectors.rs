@@ -0,0 +1,64 @@
+// Splits the horizontal field of view into count=<sectors> equal sectors, left to right,
+// and reports the nearest valid distance in each, in mm, as a simple collision avoidance
+// signal. Columns are spread evenly over the azimuth, so sectors are column ranges.
+use crate::analysis::{Analysis, Analyzer};
+use crate::depth::{is_valid, DepthFrame};
+use crate::options::Options;
+
+const DEFAULT_COUNT : usize = 8;
+
+pub struct Sectors
+{
+    count : usize,
+}
+
+impl Sectors
+{
+
+pub fn new(options : &Options) -> Result<Sectors, String>
+{
+    let mut count = DEFAULT_COUNT;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "count" => count = value.parse().map_err(|_| format!("invalid value for count {}", value))?,
+            _ => return Err(format!("sectors analyzer has no option {}", name)),
+        }
+    }
+    if count == 0
+    {
+        return Err("count has to be at least 1".to_string())
+    }
+    Ok(Sectors { count })
+}
+
+}
+
+// None for sectors without a valid distance
+pub fn nearest_by_sector(depth : &DepthFrame, count : usize) -> Vec<Option<u16>>
+{
+    let mut nearest : Vec<Option<u16>> = vec![None; count];
+    for row in depth.data.chunks_exact(depth.width.max(1))
+    {
+        for (x, distance) in row.iter().enumerate()
+        {
+            if is_valid(*distance)
+            {
+                let sector = &mut nearest[x * count / depth.width];
+                *sector = Some(sector.map_or(*distance, |nearest| nearest.min(*distance)));
+            }
+        }
+    }
+    nearest
+}
+
+impl Analyzer for Sectors
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    Some(Analysis::Sectors(nearest_by_sector(depth, self.count)))
+}
+
+}
@@ -0,0 +1,75 @@
+// Following foreground clusters from frame to frame, for the people and falls analyzers.
+// Each track takes the nearest free cluster within max_jump meters in the ground plane
+// (x and z of cloud.rs), clusters left over start new tracks and tracks without a cluster
+// for more than MAX_MISSED frames end.
+use crate::analysis::background::BackgroundModel;
+use crate::analysis::obstacles::{cluster_obstacles, Obstacle};
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+use crate::pool::DEPTH_FRAMES;
+
+const MAX_MISSED : u32 = 5;
+
+pub struct Track
+{
+    pub id : u64,
+    pub position : [f32; 3],
+    // where it was the frame before, None when it is new or had no cluster this frame
+    pub previous : Option<[f32; 3]>,
+    pub missed : u32,
+}
+
+pub struct Tracker
+{
+    max_jump : f32,
+    next_id : u64,
+    tracks : Vec<Track>,
+}
+
+impl Tracker
+{
+
+pub fn new(max_jump : f32) -> Tracker
+{
+    Tracker { max_jump, next_id : 0, tracks : Vec::new() }
+}
+
+pub fn update(&mut self, positions : &[[f32; 3]]) -> &[Track]
+{
+    let mut free : Vec<Option<[f32; 3]>> = positions.iter().copied().map(Some).collect();
+    for track in self.tracks.iter_mut()
+    {
+        let position = track.position;
+        let nearest = free.iter().enumerate()
+            .filter_map(|(index, next)| next.map(|next| (index, (next[0] - position[0]).hypot(next[2] - position[2]))))
+            .filter(|(_, distance)| *distance <= self.max_jump)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        match nearest.and_then(|(index, _)| free[index].take())
+        {
+            Some(next) => { track.previous = Some(position); track.position = next; track.missed = 0; },
+            None => { track.previous = None; track.missed += 1; },
+        }
+    }
+    self.tracks.retain(|track| track.missed <= MAX_MISSED);
+    for position in free.into_iter().flatten()
+    {
+        self.tracks.push(Track { id : self.next_id, position, previous : None, missed : 0 });
+        self.next_id += 1;
+    }
+    &self.tracks
+}
+
+}
+
+// the clusters of the foreground, None while the background is still being learned
+pub fn foreground_clusters(model : &mut BackgroundModel, projection : &Projection, depth : &DepthFrame,
+    tolerance : f32, min_points : usize, max_points : usize) -> Option<Vec<Obstacle>>
+{
+    let foreground = model.update(depth)?;
+    let mut masked = DEPTH_FRAMES.get();
+    masked.width = depth.width;
+    masked.height = depth.height;
+    masked.data.clear();
+    masked.data.extend(depth.data.iter().enumerate().map(|(pixel, distance)| if foreground.is_foreground(pixel) { *distance } else { 0 }));
+    Some(cluster_obstacles(&projection.project_pooled(&masked), tolerance, min_points, max_points))
+}
@@ -0,0 +1,149 @@
+// Named zones in the sensor's frame (meters, x right, y down, z forward, see cloud.rs),
+// given in the config as [[zone]] tables. A zone is either a box, min = [x, y, z] and
+// max = [x, y, z], or a polygon = [[x, z], ...] outline on the ground with an optional
+// min_height and max_height above the sensor (-y). Every frame reports how many points
+// are in each zone and how near the nearest is; a zone is occupied from min_points points
+// on and reports entered or left on the frame that changes.
+use crate::analysis::{Analysis, Analyzer};
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MIN_POINTS : usize = 10;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Zone
+{
+    pub name : String,
+    pub min : Option<[f32; 3]>,
+    pub max : Option<[f32; 3]>,
+    pub polygon : Option<Vec<[f32; 2]>>,
+    pub min_height : Option<f32>,
+    pub max_height : Option<f32>,
+    pub min_points : Option<usize>,
+}
+
+enum Shape
+{
+    Box([f32; 3], [f32; 3]),
+    Polygon(Vec<[f32; 2]>, f32, f32),
+}
+
+impl Shape
+{
+
+fn contains(&self, point : [f32; 3]) -> bool
+{
+    match self
+    {
+        Shape::Box(min, max) => (0..3).all(|axis| point[axis] >= min[axis] && point[axis] <= max[axis]),
+        Shape::Polygon(outline, min_height, max_height) => -point[1] >= *min_height && -point[1] <= *max_height && inside(outline, point[0], point[2]),
+    }
+}
+
+}
+
+// even odd rule
+fn inside(outline : &[[f32; 2]], x : f32, z : f32) -> bool
+{
+    let mut inside = false;
+    let mut previous = outline[outline.len() - 1];
+    for corner in outline
+    {
+        if (corner[1] > z) != (previous[1] > z) && x < (previous[0] - corner[0]) * (z - corner[1]) / (previous[1] - corner[1]) + corner[0]
+        {
+            inside = !inside;
+        }
+        previous = *corner;
+    }
+    inside
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneEvent
+{
+    Entered,
+    Left,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ZoneReport
+{
+    pub name : String,
+    pub points : usize,
+    // meters from the sensor to the nearest point in the zone
+    pub min_distance : Option<f32>,
+    pub occupied : bool,
+    // set on the frame the zone became occupied or empty
+    pub event : Option<ZoneEvent>,
+}
+
+pub struct Zones
+{
+    zones : Vec<(String, Shape, usize)>,
+    occupied : Vec<bool>,
+    projection : Projection,
+}
+
+impl Zones
+{
+
+pub fn new(zones : &[Zone]) -> Result<Zones, String>
+{
+    let mut shapes = Vec::new();
+    for zone in zones
+    {
+        let shape = match (zone.min, zone.max, &zone.polygon)
+        {
+            (Some(min), Some(max), None) if zone.min_height.is_none() && zone.max_height.is_none() => Shape::Box(min, max),
+            (None, None, Some(polygon)) if polygon.len() >= 3 =>
+                Shape::Polygon(polygon.clone(), zone.min_height.unwrap_or(f32::MIN), zone.max_height.unwrap_or(f32::MAX)),
+            (None, None, Some(_)) => return Err(format!("zone {} needs at least 3 corners", zone.name)),
+            _ => return Err(format!("zone {} needs either min and max or a polygon with optional heights", zone.name)),
+        };
+        if shapes.iter().any(|(name, _, _)| *name == zone.name)
+        {
+            return Err(format!("zone {} is given twice", zone.name))
+        }
+        shapes.push((zone.name.clone(), shape, zone.min_points.unwrap_or(DEFAULT_MIN_POINTS).max(1)));
+    }
+    Ok(Zones { occupied : vec![false; shapes.len()], zones : shapes, projection : Projection::default() })
+}
+
+}
+
+impl Analyzer for Zones
+{
+
+fn analyze(&mut self, depth : &DepthFrame) -> Option<Analysis>
+{
+    let cloud = self.projection.project_pooled(depth);
+    let mut reports : Vec<ZoneReport> = self.zones.iter().map(|(name, _, _)| ZoneReport { name : name.clone(), points : 0, min_distance : None, occupied : false, event : None }).collect();
+    for point in cloud.points.iter()
+    {
+        let distance = (point[0] * point[0] + point[1] * point[1] + point[2] * point[2]).sqrt();
+        for ((_, shape, _), report) in self.zones.iter().zip(reports.iter_mut())
+        {
+            if shape.contains(*point)
+            {
+                report.points += 1;
+                report.min_distance = Some(report.min_distance.map_or(distance, |nearest| nearest.min(distance)));
+            }
+        }
+    }
+    for (((_, _, min_points), report), occupied) in self.zones.iter().zip(reports.iter_mut()).zip(self.occupied.iter_mut())
+    {
+        report.occupied = report.points >= *min_points;
+        if report.occupied != *occupied
+        {
+            report.event = Some(if report.occupied { ZoneEvent::Entered } else { ZoneEvent::Left });
+            *occupied = report.occupied;
+        }
+    }
+    Some(Analysis::Zones(reports))
+}
+
+}
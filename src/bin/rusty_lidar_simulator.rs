@@ -0,0 +1,143 @@
+// Plays the lidar for the viewer, see simulator.rs: on a pseudo terminal, printed and
+// optionally linked to a path for --port, or for the hosts connecting to --listen, one
+// at a time. Runs until Ctrl-C or SIGTERM.
+use clap::{Arg, ArgMatches, Command as ClapCommand};
+use log::{error, info, warn, LevelFilter};
+
+use std::fs;
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use rusty_lidar_viewer::logging;
+use rusty_lidar_viewer::signals;
+use rusty_lidar_viewer::simulator::{Pty, Scene, Settings, Simulator};
+
+fn value(name : &'static str, value_name : &'static str, help : &'static str) -> Arg
+{
+    Arg::new(name).long(name).value_name(value_name).help(help)
+}
+
+fn fraction(value : &str) -> Result<f32, String>
+{
+    value.parse().ok().filter(|value : &f32| (0.0..=1.0).contains(value)).ok_or(format!("expected a number from 0 to 1, got {}", value))
+}
+
+fn non_negative(value : &str) -> Result<f32, String>
+{
+    value.parse().ok().filter(|value : &f32| *value >= 0.0).ok_or(format!("expected a number from 0 up, got {}", value))
+}
+
+fn version(value : &str) -> Result<[u8; 3], String>
+{
+    let parts : Vec<u8> = value.split('.').map(|part| part.parse()).collect::<Result<_, _>>().map_err(|_| format!("invalid version {}, expected e.g. 1.2.3", value))?;
+    parts.try_into().map_err(|_| format!("invalid version {}, expected e.g. 1.2.3", value))
+}
+
+fn cli() -> ClapCommand
+{
+    ClapCommand::new("rusty_lidar_simulator")
+    .about("the device side of the lidar protocol, for the viewer to read from without a lidar")
+    .arg(value("log-level", "LEVEL", "off, error, warn, info or debug, info by default").value_parser(["off", "error", "warn", "info", "debug"]))
+    .arg(value("link", "PATH", "symlink to the pseudo terminal, e.g. /tmp/lidar for --port /tmp/lidar").conflicts_with("listen"))
+    .arg(value("listen", "ADDRESS", "serve tcp connections instead of a pseudo terminal, e.g. 0.0.0.0:7000"))
+    .arg(value("scene", "SCENE", "wall, room or person walking through the room, person by default").value_parser(Scene::parse))
+    .arg(value("distance", "M", "meters to the wall ahead, 3 by default").value_parser(non_negative))
+    .arg(value("noise", "MM", "standard deviation of the distances, 10 by default").value_parser(non_negative))
+    .arg(value("dropout", "FRACTION", "of the pixels left unmeasured, 0.01 by default").value_parser(fraction))
+    .arg(value("corrupt", "FRACTION", "of the frames sent with a wrong byte, 0 by default").value_parser(fraction))
+    .arg(value("fps", "FPS", "frames a second, 30 by default").value_parser(|value : &str| value.parse().ok().filter(|fps : &f64| *fps > 0.0).ok_or("expected a positive number")))
+    .arg(value("firmware", "VERSION", "firmware version sent as device info, 1.0.0 by default").value_parser(version))
+    .arg(value("hardware", "VERSION", "hardware version sent as device info, 1.0.0 by default").value_parser(version))
+    .arg(value("seed", "N", "for the noise, runs with the same seed send the same frames").value_parser(clap::value_parser!(u64)))
+}
+
+fn settings(args : &ArgMatches) -> Settings
+{
+    let mut settings = Settings::default();
+    settings.scene = args.get_one::<Scene>("scene").copied().unwrap_or(settings.scene);
+    settings.distance = args.get_one::<f32>("distance").copied().unwrap_or(settings.distance);
+    settings.noise = args.get_one::<f32>("noise").copied().unwrap_or(settings.noise);
+    settings.dropout = args.get_one::<f32>("dropout").copied().unwrap_or(settings.dropout);
+    settings.corrupt = args.get_one::<f32>("corrupt").copied().unwrap_or(settings.corrupt);
+    settings.fps = args.get_one::<f64>("fps").copied().unwrap_or(settings.fps);
+    settings.firmware = args.get_one::<[u8; 3]>("firmware").copied().unwrap_or(settings.firmware);
+    settings.hardware = args.get_one::<[u8; 3]>("hardware").copied().unwrap_or(settings.hardware);
+    settings.seed = args.get_one::<u64>("seed").copied().unwrap_or(settings.seed);
+    settings
+}
+
+fn serve_pty(simulator : &mut Simulator, link : Option<&String>) -> Result<(), String>
+{
+    let mut pty = Pty::open().map_err(|msg| format!("Error opening a pseudo terminal!, {}", msg))?;
+    if let Some(link) = link
+    {
+        // a link left by an earlier run is replaced, anything else stays
+        if fs::symlink_metadata(link).is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            let _ = fs::remove_file(link);
+        }
+        symlink(&pty.path, link).map_err(|msg| format!("Error linking {} to {}!, {}", link, pty.path.display(), msg))?;
+    }
+    println!("{}", link.map_or(pty.path.display().to_string(), |link| link.clone()));
+    info!("Serving on {}", pty.path.display());
+    let served = simulator.serve(&mut pty.master, &signals::RUNNING).map_err(|msg| format!("Error serving {}!, {}", pty.path.display(), msg));
+    if let Some(link) = link.filter(|link| fs::read_link(link).is_ok_and(|target| target == pty.path))
+    {
+        let _ = fs::remove_file(Path::new(link));
+    }
+    served
+}
+
+fn serve_tcp(simulator : &mut Simulator, address : &str) -> Result<(), String>
+{
+    let listener = TcpListener::bind(address).map_err(|msg| format!("Error listening on {}!, {}", address, msg))?;
+    listener.set_nonblocking(true).map_err(|msg| msg.to_string())?;
+    println!("{}", listener.local_addr().map_or(address.to_string(), |address| address.to_string()));
+    info!("Listening on {}", address);
+    while signals::RUNNING.load(Ordering::SeqCst)
+    {
+        let (mut stream, peer) = match listener.accept()
+        {
+            Ok(accepted) => accepted,
+            Err(msg) if msg.kind() == ErrorKind::WouldBlock => { thread::sleep(Duration::from_millis(100)); continue },
+            Err(msg) => { warn!("Error accepting a connection!, {}", msg); continue },
+        };
+        info!("Serving {}", peer);
+        let _ = stream.set_nodelay(true);
+        match stream.set_nonblocking(true).and_then(|_| simulator.serve(&mut stream, &signals::RUNNING))
+        {
+            Ok(()) => (),
+            Err(msg) if matches!(msg.kind(), ErrorKind::ConnectionReset | ErrorKind::BrokenPipe) => info!("{} went away", peer),
+            Err(msg) => warn!("Error serving {}!, {}", peer, msg),
+        }
+    }
+    Ok(())
+}
+
+fn main()
+{
+    let args = cli().get_matches();
+    let level = args.get_one::<String>("log-level").and_then(|level| level.parse().ok()).unwrap_or(LevelFilter::Info);
+    let _ = logging::init(level, logging::Format::Text);
+    ctrlc::set_handler(|| signals::RUNNING.store(false, Ordering::SeqCst)).expect("Error setting Ctrl-C handler");
+    if let Err(msg) = signals::install()
+    {
+        warn!("Failed to handle SIGTERM, {}", msg);
+    }
+    let mut simulator = Simulator::new(settings(&args));
+    let served = match args.get_one::<String>("listen")
+    {
+        Some(address) => serve_tcp(&mut simulator, address),
+        None => serve_pty(&mut simulator, args.get_one::<String>("link")),
+    };
+    if let Err(msg) = served
+    {
+        error!("{}", msg);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::frame::Frame;
+use crate::depth::{is_valid, DepthFrame, WIDTH_3D};
+use crate::publish::{Options, Publisher};
+
+use log::{info, warn};
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Fixed size packets for game engines, see docs/bridge.md for the layout
+pub const VERSION : u8 = 1;
+pub const HEADER_SIZE : usize = 32;
+pub const ROWS_PER_PACKET : usize = 2;
+pub const PACKET_SIZE : usize = HEADER_SIZE + ROWS_PER_PACKET * WIDTH_3D * 4;
+const HEARTBEAT_INTERVAL : Duration = Duration::from_secs(1);
+
+const TYPE_HEARTBEAT : u8 = 0;
+const TYPE_ROWS : u8 = 1;
+const FLAG_BIG_ENDIAN : u8 = 1;
+
+#[derive(Clone, Copy)]
+struct Writer
+{
+    big_endian : bool,
+}
+
+impl Writer
+{
+
+fn u16(&self, packet : &mut [u8], offset : usize, value : u16)
+{
+    packet[offset..offset + 2].copy_from_slice(&if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+}
+
+fn u32(&self, packet : &mut [u8], offset : usize, value : u32)
+{
+    packet[offset..offset + 4].copy_from_slice(&if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+}
+
+fn f32(&self, packet : &mut [u8], offset : usize, value : f32)
+{
+    packet[offset..offset + 4].copy_from_slice(&if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+}
+
+fn header(&self, packet : &mut [u8], kind : u8, sequence : u32)
+{
+    packet.fill(0);
+    packet[0..4].copy_from_slice(b"RLVB");
+    packet[4] = VERSION;
+    packet[5] = kind;
+    packet[6] = if self.big_endian { FLAG_BIG_ENDIAN } else { 0 };
+    self.u32(packet, 8, sequence);
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u32).unwrap_or(0);
+    self.u32(packet, 20, timestamp_ms);
+}
+
+}
+
+pub struct BridgePublisher
+{
+    socket : UdpSocket,
+    target : SocketAddr,
+    writer : Writer,
+    // last frame sequence, also reported by the heartbeat
+    sequence : Arc<AtomicU32>,
+    packet : Vec<u8>,
+}
+
+impl BridgePublisher
+{
+
+// options: endian=little|big
+pub fn new(address : &str, options : &Options) -> Result<BridgePublisher, String>
+{
+    let mut writer = Writer { big_endian : false };
+    for (name, value) in options
+    {
+        match (*name, *value)
+        {
+            ("endian", "little") => writer.big_endian = false,
+            ("endian", "big") => writer.big_endian = true,
+            _ => return Err(format!("bridge has no option {}={}", name, value)),
+        }
+    }
+    let target = match address.to_socket_addrs().map_err(|msg| msg.to_string())?.next()
+    {
+        Some(target) => target,
+        None => return Err(format!("{} did not resolve", address)),
+    };
+    let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local).map_err(|msg| msg.to_string())?;
+    let sequence = Arc::new(AtomicU32::new(0));
+
+    let heartbeat_socket = socket.try_clone().map_err(|msg| msg.to_string())?;
+    let heartbeat_sequence = sequence.clone();
+    thread::spawn(move ||
+    {
+        let mut packet = vec![0u8; PACKET_SIZE];
+        loop
+        {
+            writer.header(&mut packet, TYPE_HEARTBEAT, heartbeat_sequence.load(Ordering::Relaxed));
+            let _ = heartbeat_socket.send_to(&packet, target);
+            thread::sleep(HEARTBEAT_INTERVAL);
+        }
+    });
+    info!("Bridging frames to udp://{}", target);
+    Ok(BridgePublisher { socket, target, writer, sequence, packet : vec![0u8; PACKET_SIZE] })
+}
+
+}
+
+impl Publisher for BridgePublisher
+{
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    if depth.width != WIDTH_3D
+    {
+        warn!("Bridge packets are laid out for {} wide frames, got {}", WIDTH_3D, depth.width);
+        return Err(())
+    }
+    let sequence = self.sequence.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    for first_row in (0..depth.height).step_by(ROWS_PER_PACKET)
+    {
+        let rows = ROWS_PER_PACKET.min(depth.height - first_row);
+        let writer = self.writer;
+        let packet = &mut self.packet;
+        writer.header(packet, TYPE_ROWS, sequence);
+        writer.u16(packet, 12, depth.width as u16);
+        writer.u16(packet, 14, depth.height as u16);
+        writer.u16(packet, 16, first_row as u16);
+        writer.u16(packet, 18, rows as u16);
+        let points = &depth.data[first_row * depth.width..(first_row + rows) * depth.width];
+        for (index, distance) in points.iter().enumerate()
+        {
+            let meters = if is_valid(*distance) { *distance as f32 / 1000.0 } else { 0.0 };
+            writer.f32(packet, HEADER_SIZE + index * 4, meters);
+        }
+        if let Err(msg) = self.socket.send_to(packet, self.target)
+        {
+            warn!("Failed to send bridge packet to {}, {}", self.target, msg);
+            return Err(())
+        }
+    }
+    Ok(())
+}
+
+}
@@ -0,0 +1,69 @@
+// Wall clock time in UTC broken into its fields, for timestamps in logs and file names,
+// without a date library. Days are turned into dates with the civil_from_days algorithm
+// from Howard Hinnant's date library, no time zones or leap seconds.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utc
+{
+    pub year : i64,
+    pub month : u32,
+    pub day : u32,
+    pub hour : u32,
+    pub minute : u32,
+    pub second : u32,
+    pub millisecond : u32,
+}
+
+impl Utc
+{
+
+pub fn now() -> Utc
+{
+    Utc::at(SystemTime::now())
+}
+
+// times before 1970 are taken as 1970
+pub fn at(time : SystemTime) -> Utc
+{
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since.as_secs() as i64;
+    let (days, of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let of_era = shifted.rem_euclid(146097);
+    let year_of_era = (of_era - of_era / 1460 + of_era / 36524 - of_era / 146096) / 365;
+    let day_of_year = of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    Utc
+    {
+        year : year_of_era + era * 400 + if month <= 2 { 1 } else { 0 },
+        month : month as u32,
+        day : (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32,
+        hour : (of_day / 3600) as u32,
+        minute : (of_day / 60 % 60) as u32,
+        second : (of_day % 60) as u32,
+        millisecond : since.subsec_millis(),
+    }
+}
+
+// e.g. 2026-10-14T09:30:05.123Z
+pub fn rfc3339(&self) -> String
+{
+    format!("{}T{:02}:{:02}:{:02}.{:03}Z", self.date(), self.hour, self.minute, self.second, self.millisecond)
+}
+
+// e.g. 2026-10-14
+pub fn date(&self) -> String
+{
+    format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+}
+
+// e.g. 09-30-05, for file names, which can't have colons everywhere
+pub fn time(&self) -> String
+{
+    format!("{:02}-{:02}-{:02}", self.hour, self.minute, self.second)
+}
+
+}
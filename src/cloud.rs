@@ -0,0 +1,270 @@
+// Point clouds projected from depth frames. Points are in meters with x to the right,
+// y down and z along the optical axis of the sensor; every point remembers the pixel it
+// came from so results can be mapped back onto the depth frame. Clouds straight from a
+// projection are organized: width is the width of the frame, 0 for merged or
+// downsampled clouds.
+use crate::depth::{is_valid, DepthFrame, HEIGHT_3D, WIDTH_3D};
+use crate::kdtree::KdTree;
+use crate::pool::{Pooled, CLOUDS};
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// field of view of the 3D mode
+pub const FOV_H_DEG : f32 = 120.0;
+pub const FOV_V_DEG : f32 = 65.0;
+
+#[derive(Clone, Debug, Default)]
+pub struct PointCloud
+{
+    pub points : Vec<[f32; 3]>,
+    pub pixels : Vec<usize>,
+    pub width : usize,
+}
+
+// the direction of every pixel, distances are measured along it
+pub struct Projection
+{
+    width : usize,
+    height : usize,
+    rays : Vec<[f32; 3]>,
+    // field of view in radians
+    fov_h : f32,
+    fov_v : f32,
+}
+
+impl Default for Projection
+{
+    fn default() -> Self
+    {
+        Projection::new(WIDTH_3D, HEIGHT_3D, FOV_H_DEG, FOV_V_DEG)
+    }
+}
+
+impl Projection
+{
+
+pub fn new(width : usize, height : usize, fov_h_deg : f32, fov_v_deg : f32) -> Projection
+{
+    let mut rays = Vec::with_capacity(width * height);
+    for y in 0..height
+    {
+        let elevation = ((y as f32 + 0.5) / height as f32 - 0.5) * fov_v_deg.to_radians();
+        for x in 0..width
+        {
+            let azimuth = ((x as f32 + 0.5) / width as f32 - 0.5) * fov_h_deg.to_radians();
+            rays.push([azimuth.sin() * elevation.cos(), elevation.sin(), azimuth.cos() * elevation.cos()]);
+        }
+    }
+    Projection { width, height, rays, fov_h : fov_h_deg.to_radians(), fov_v : fov_v_deg.to_radians() }
+}
+
+// unit length, a row after the other
+pub fn rays(&self) -> &[[f32; 3]]
+{
+    &self.rays
+}
+
+// steradians a pixel sees, smaller towards the top and bottom rows
+pub fn solid_angle(&self, pixel : usize) -> f32
+{
+    let elevation_cos = (1.0 - self.rays[pixel][1] * self.rays[pixel][1]).max(0.0).sqrt();
+    self.fov_h / self.width as f32 * self.fov_v / self.height as f32 * elevation_cos
+}
+
+// valid pixels only, frames of another size give an empty cloud
+pub fn project(&self, depth : &DepthFrame) -> PointCloud
+{
+    let mut cloud = PointCloud::default();
+    self.project_into(depth, &mut cloud);
+    cloud
+}
+
+// project into a cloud from the pool, for clouds that live no longer than a frame or two
+pub fn project_pooled(&self, depth : &DepthFrame) -> Pooled<PointCloud>
+{
+    let mut cloud = CLOUDS.get();
+    self.project_into(depth, &mut cloud);
+    cloud
+}
+
+// project into cloud, reusing its buffers
+pub fn project_into(&self, depth : &DepthFrame, cloud : &mut PointCloud)
+{
+    cloud.width = depth.width;
+    cloud.points.clear();
+    cloud.pixels.clear();
+    if depth.width != self.width || depth.height != self.height
+    {
+        return
+    }
+    for (pixel, (distance, ray)) in depth.data.iter().zip(&self.rays).enumerate()
+    {
+        if is_valid(*distance)
+        {
+            let meters = *distance as f32 / 1000.0;
+            cloud.points.push([ray[0] * meters, ray[1] * meters, ray[2] * meters]);
+            cloud.pixels.push(pixel);
+        }
+    }
+}
+
+}
+
+impl PointCloud
+{
+
+pub fn len(&self) -> usize
+{
+    self.points.len()
+}
+
+pub fn is_empty(&self) -> bool
+{
+    self.points.is_empty()
+}
+
+// for radius and k nearest neighbour queries, indices found are into points and pixels
+pub fn kd_tree(&self) -> KdTree<'_>
+{
+    KdTree::new(&self.points)
+}
+
+// Normals of an organized cloud from the cross product of the vectors to the neighbours
+// right (or left) and below (or above) in the image, facing the sensor. [0, 0, 0] for
+// points without such neighbours and for all points of unorganized clouds.
+pub fn normals(&self) -> Vec<[f32; 3]>
+{
+    let mut normals = vec![[0.0; 3]; self.len()];
+    if self.width == 0 || self.is_empty()
+    {
+        return normals
+    }
+    let pixels = self.pixels.iter().max().map_or(0, |last| last + 1);
+    let mut points = vec![None; pixels];
+    for (index, pixel) in self.pixels.iter().enumerate()
+    {
+        points[*pixel] = Some(self.points[index]);
+    }
+    let at = |x : usize, y : usize| if x < self.width { points.get(y * self.width + x).copied().flatten() } else { None };
+    for (normal, (point, pixel)) in normals.iter_mut().zip(self.points.iter().zip(&self.pixels))
+    {
+        let (x, y) = (pixel % self.width, pixel / self.width);
+        let horizontal = match (at(x + 1, y), x.checked_sub(1).and_then(|left| at(left, y)))
+        {
+            (Some(right), _) => difference(right, *point),
+            (None, Some(left)) => difference(*point, left),
+            _ => continue,
+        };
+        let vertical = match (at(x, y + 1), y.checked_sub(1).and_then(|up| at(x, up)))
+        {
+            (Some(down), _) => difference(down, *point),
+            (None, Some(up)) => difference(*point, up),
+            _ => continue,
+        };
+        let cross =
+        [
+            horizontal[1] * vertical[2] - horizontal[2] * vertical[1],
+            horizontal[2] * vertical[0] - horizontal[0] * vertical[2],
+            horizontal[0] * vertical[1] - horizontal[1] * vertical[0],
+        ];
+        let length = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+        if length > 0.0
+        {
+            let facing = if cross[0] * point[0] + cross[1] * point[1] + cross[2] * point[2] > 0.0 { -length } else { length };
+            *normal = cross.map(|value| value / facing);
+        }
+    }
+    normals
+}
+
+// binary little endian PLY with float x, y, z vertices
+pub fn write_ply<W : Write>(&self, mut writer : W) -> io::Result<()>
+{
+    write!(writer, "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\nend_header\n", self.len())?;
+    for point in self.points.iter()
+    {
+        for value in point
+        {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writer.flush()
+}
+
+// keeps the points whose entry in keep is true
+pub fn retain(&mut self, keep : &[bool])
+{
+    let mut kept = 0;
+    for (index, keep) in keep.iter().enumerate().take(self.points.len())
+    {
+        if *keep
+        {
+            self.points[kept] = self.points[index];
+            self.pixels[kept] = self.pixels[index];
+            kept += 1;
+        }
+    }
+    self.points.truncate(kept);
+    self.pixels.truncate(kept);
+}
+
+}
+
+fn difference(a : [f32; 3], b : [f32; 3]) -> [f32; 3]
+{
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+// Statistical outlier removal: a point is an outlier when its mean distance to its k
+// nearest neighbours is more than std_ratio standard deviations above the mean of that
+// over the whole cloud. Returns true for the points to keep.
+pub fn statistical_outliers(cloud : &PointCloud, k : usize, std_ratio : f32) -> Vec<bool>
+{
+    if cloud.len() <= k
+    {
+        return vec![true; cloud.len()]
+    }
+    let tree = cloud.kd_tree();
+    let mut found = Vec::with_capacity(k + 1);
+    let mean_distances : Vec<f32> = cloud.points.iter().map(|point|
+    {
+        // the nearest is the point itself
+        tree.nearest(*point, k + 1, &mut found);
+        found[1..].iter().map(|(distance, _)| distance.sqrt()).sum::<f32>() / k as f32
+    }).collect();
+    let mean = mean_distances.iter().sum::<f32>() / mean_distances.len() as f32;
+    let variance = mean_distances.iter().map(|distance| (distance - mean).powi(2)).sum::<f32>() / mean_distances.len() as f32;
+    let threshold = mean + std_ratio * variance.sqrt();
+    mean_distances.iter().map(|distance| *distance <= threshold).collect()
+}
+
+// Voxel grid downsampling: all points within one cube of leaf meters are replaced by their
+// centroid, keeping the pixel of the first of them. For clouds merged from many frames.
+pub fn voxel_downsample(cloud : &PointCloud, leaf : f32) -> PointCloud
+{
+    let mut voxels : HashMap<[i32; 3], usize> = HashMap::new();
+    let mut sums : Vec<([f32; 3], u32)> = Vec::new();
+    let mut downsampled = PointCloud::default();
+    for (point, pixel) in cloud.points.iter().zip(&cloud.pixels)
+    {
+        let voxel = [(point[0] / leaf).floor() as i32, (point[1] / leaf).floor() as i32, (point[2] / leaf).floor() as i32];
+        let index = *voxels.entry(voxel).or_insert_with(||
+        {
+            sums.push(([0.0; 3], 0));
+            downsampled.pixels.push(*pixel);
+            sums.len() - 1
+        });
+        let (sum, count) = &mut sums[index];
+        sum[0] += point[0];
+        sum[1] += point[1];
+        sum[2] += point[2];
+        *count += 1;
+    }
+    downsampled.points = sums.iter().map(|(sum, count)|
+    {
+        let count = *count as f32;
+        [sum[0] / count, sum[1] / count, sum[2] / count]
+    }).collect();
+    downsampled
+}
@@ -0,0 +1,23 @@
+use crate::depth::{is_valid, DepthFrame};
+
+pub const DEFAULT_MIN_MM : u16 = 200;
+pub const DEFAULT_MAX_MM : u16 = 3000;
+
+// jet style colormap, near is blue and far is red, invalid pixels are black
+pub fn colorize(depth : &DepthFrame, min_mm : u16, max_mm : u16) -> Vec<u8>
+{
+    let span = max_mm.saturating_sub(min_mm).max(1) as f32;
+    let mut rgb = Vec::with_capacity(depth.data.len() * 3);
+    for distance in depth.data.iter().copied()
+    {
+        if !is_valid(distance)
+        {
+            rgb.extend_from_slice(&[0, 0, 0]);
+            continue;
+        }
+        let t = (distance.saturating_sub(min_mm) as f32 / span).min(1.0);
+        let channel = |offset : f32| ((1.5 - (4.0 * t - offset).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+        rgb.extend_from_slice(&[channel(3.0), channel(2.0), channel(1.0)]);
+    }
+    rgb
+}
@@ -0,0 +1,315 @@
+// Settings read from a TOML file given with --config. The filter pipeline is a list of
+// stages, each a table with the filter as type and its options next to it, run in order:
+//
+//   [[pipeline.stage]]
+//   type = "median"
+//   kernel = 3
+//
+//   [[pipeline.stage]]
+//   type = "temporal"
+//   mode = "ema"
+//   alpha = 0.3
+//
+// [[zone]] tables define zones for the zones analyzer, see analysis/zones.rs:
+//
+//   [[zone]]
+//   name = "door"
+//   min = [-0.5, -1.0, 1.0]
+//   max = [0.5, 1.0, 2.0]
+//
+// Stages are built by filters::create like --filter specs, so every filter and option
+// works in both places. A type that isn't a built in filter is looked up in the plugins
+// found in [plugins] directory ("plugins" by default), see plugin.rs.
+//
+// [device] takes the port and the options of --device, see device::Settings:
+//
+//   [device]
+//   port = "/dev/ttyUSB0"
+//   cpu = 2
+//   priority = 50
+//
+// publish lists publishers like --publish does, before the tables as TOML wants it:
+//
+//   publish = ["ws://0.0.0.0:9000", "record://captures/{date}/{seq}.rlv"]
+//
+// All but [device] and [plugins] can change while reading, see reload.rs. validate finds
+// everything wrong with a file, with the line and column it is at, without starting
+// anything; schema.rs has the options it checks publishers against.
+use crate::analysis::zones::{Zone, Zones};
+use crate::analysis::Analyzer;
+use crate::device::{Settings, DEFAULT_PORT};
+use crate::filters::{self, Filter};
+use crate::publish::{self, Output};
+use crate::schema;
+#[cfg(unix)]
+use crate::plugin::Plugins;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use std::collections::BTreeMap;
+use std::fs;
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config
+{
+    #[serde(default)]
+    pub pipeline : Pipeline,
+    #[serde(default)]
+    pub plugins : PluginsConfig,
+    #[serde(default)]
+    pub zone : Vec<Zone>,
+    pub device : Option<toml::Table>,
+    #[serde(default)]
+    pub publish : Vec<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline
+{
+    #[serde(default)]
+    pub stage : Vec<toml::Table>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PluginsConfig
+{
+    pub directory : String,
+}
+
+impl Default for PluginsConfig
+{
+    fn default() -> Self
+    {
+        PluginsConfig { directory : "plugins".to_string() }
+    }
+}
+
+impl Config
+{
+
+pub fn load(path : &str) -> Result<Config, String>
+{
+    let text = fs::read_to_string(path).map_err(|msg| format!("failed to read {}, {}", path, msg))?;
+    toml::from_str(&text).map_err(|msg| format!("invalid config {}, {}", path, msg))
+}
+
+pub fn device(&self) -> Result<Option<Settings>, String>
+{
+    let device = match &self.device
+    {
+        Some(device) => device,
+        None => return Ok(None),
+    };
+    let port = match device.get("port")
+    {
+        Some(toml::Value::String(port)) => port.as_str(),
+        Some(_) => return Err("device port has to be a string".to_string()),
+        None => DEFAULT_PORT,
+    };
+    let values = table_options(device, "port").map_err(|msg| format!("device, {}", msg))?;
+    let options = values.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    Settings::from_options(port, &options, &Settings::default()).map(Some)
+}
+
+pub fn analyzers(&self) -> Result<Vec<Box<dyn Analyzer>>, String>
+{
+    let mut analyzers : Vec<Box<dyn Analyzer>> = Vec::new();
+    if !self.zone.is_empty()
+    {
+        analyzers.push(Box::new(Zones::new(&self.zone)?));
+    }
+    Ok(analyzers)
+}
+
+pub fn publishers(&self) -> Result<Vec<Output>, String>
+{
+    self.publish.iter().map(|url| publish::open(url).map_err(|msg| format!("publisher {}, {}", url, msg))).collect()
+}
+
+pub fn filters(&self) -> Result<Vec<Box<dyn Filter>>, String>
+{
+    let mut stages = Stages::new(&self.plugins.directory);
+    let mut filters = Vec::new();
+    for (index, stage) in self.pipeline.stage.iter().enumerate()
+    {
+        filters.push(stages.filter(stage).map_err(|msg| format!("stage {}, {}", index + 1, msg))?);
+    }
+    Ok(filters)
+}
+
+}
+
+// builds the filters of stages, plugins are only loaded when a stage needs one
+struct Stages<'a>
+{
+    #[cfg_attr(not(unix), allow(dead_code))]
+    directory : &'a str,
+    #[cfg(unix)]
+    plugins : Option<Plugins>,
+}
+
+impl Stages<'_>
+{
+
+fn new(directory : &str) -> Stages<'_>
+{
+    Stages { directory, #[cfg(unix)] plugins : None }
+}
+
+fn filter(&mut self, stage : &toml::Table) -> Result<Box<dyn Filter>, String>
+{
+    let (name, values) = stage_options(stage)?;
+    let options = values.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    if filters::NAMES.contains(&name)
+    {
+        return filters::create(name, &options)
+    }
+    #[cfg(unix)]
+    {
+        if self.plugins.is_none()
+        {
+            self.plugins = Some(Plugins::load(self.directory)?);
+        }
+        self.plugins.as_ref().unwrap().create(name, &options)
+    }
+    #[cfg(not(unix))]
+    Err(format!("unknown filter {}, plugins need a unix system", name))
+}
+
+}
+
+// something wrong in a config file, lines and columns from 1
+pub struct Problem
+{
+    pub line : usize,
+    pub column : usize,
+    pub message : String,
+}
+
+// a table with where its keys and values are in the text
+type Located = Spanned<BTreeMap<Spanned<String>, Spanned<toml::Value>>>;
+
+// the parts of a config that are checked, with where they are
+#[derive(Deserialize, Default)]
+struct Spans
+{
+    #[serde(default)]
+    pipeline : StageSpans,
+    #[serde(default)]
+    zone : Vec<Located>,
+    device : Option<Located>,
+    #[serde(default)]
+    publish : Vec<Spanned<String>>,
+}
+
+#[derive(Deserialize, Default)]
+struct StageSpans
+{
+    #[serde(default)]
+    stage : Vec<Located>,
+}
+
+// Everything wrong with the file at path, none when it loads. A file that isn't valid
+// TOML, or has keys that don't belong, is reported where the parser stopped. Otherwise
+// every stage, zone and publisher is checked and the device settings are, at the key a
+// problem is with when it names one. Err when the file can't be read
+pub fn validate(path : &str) -> Result<Vec<Problem>, String>
+{
+    let text = fs::read_to_string(path).map_err(|msg| format!("failed to read {}, {}", path, msg))?;
+    let problem = |at : usize, message : String| Problem { line : line(&text, at), column : column(&text, at), message };
+    let (config, spans) = match toml::from_str::<Config>(&text).and_then(|config| Ok((config, toml::from_str::<Spans>(&text)?)))
+    {
+        Ok(parsed) => parsed,
+        Err(msg) => return Ok(vec![problem(msg.span().map_or(0, |span| span.start), msg.message().trim_end().to_string())]),
+    };
+    let mut problems = Vec::new();
+    let mut stages = Stages::new(&config.plugins.directory);
+    for (index, (stage, located)) in config.pipeline.stage.iter().zip(spans.pipeline.stage.iter()).enumerate()
+    {
+        if let Err(msg) = stages.filter(stage)
+        {
+            problems.push(problem(position(located, &msg), format!("stage {}, {}", index + 1, msg)));
+        }
+    }
+    for (index, (zone, located)) in config.zone.iter().zip(spans.zone.iter()).enumerate()
+    {
+        let name = located.get_ref().keys().find(|key| key.get_ref() == "name").map_or(located.span().start, |key| key.span().start);
+        match Zones::new(std::slice::from_ref(zone))
+        {
+            Err(msg) => problems.push(problem(position(located, &msg), msg)),
+            Ok(_) if config.zone[..index].iter().any(|before| before.name == zone.name) =>
+                problems.push(problem(name, format!("zone {} is given twice", zone.name))),
+            Ok(_) => (),
+        }
+    }
+    // an option at a time, so each wrong one is reported
+    for (key, value) in spans.device.iter().flat_map(|located| located.get_ref().iter())
+    {
+        let device = toml::Table::from_iter([(key.get_ref().clone(), value.get_ref().clone())]);
+        if let Err(msg) = (Config { device : Some(device), ..Config::default() }).device()
+        {
+            problems.push(problem(key.span().start, format!("device, {}", msg)));
+        }
+    }
+    for url in spans.publish.iter()
+    {
+        if let Err((at, msg)) = schema::check_url(url.get_ref())
+        {
+            // past the opening quote
+            problems.push(problem(url.span().start + 1 + at, format!("publisher {}, {}", url.get_ref(), msg)));
+        }
+    }
+    Ok(problems)
+}
+
+// the key a message names, or the key whose value it names, else the table
+fn position(table : &Located, message : &str) -> usize
+{
+    let words : Vec<&str> = message.split(|c : char| !c.is_alphanumeric() && c != '_' && c != '.' && c != '-').collect();
+    let named = |text : &str| words.contains(&text);
+    let entries = table.get_ref();
+    entries.keys().find(|key| named(key.get_ref()))
+        .map(|key| key.span().start)
+        .or_else(|| entries.iter().find(|(_, value)| named(value.get_ref().to_string().trim_matches('"'))).map(|(key, _)| key.span().start))
+        .unwrap_or(table.span().start)
+}
+
+fn line(text : &str, at : usize) -> usize
+{
+    text[..at.min(text.len())].matches('\n').count() + 1
+}
+
+fn column(text : &str, at : usize) -> usize
+{
+    let before = &text[..at.min(text.len())];
+    before.chars().rev().take_while(|c| *c != '\n').count() + 1
+}
+
+// the type and the other keys as options
+type StageOptions<'a> = (&'a str, Vec<(&'a str, String)>);
+
+fn stage_options(stage : &toml::Table) -> Result<StageOptions<'_>, String>
+{
+    let name = match stage.get("type")
+    {
+        Some(toml::Value::String(name)) => name.as_str(),
+        Some(_) => return Err("type has to be a string".to_string()),
+        None => return Err("missing type".to_string()),
+    };
+    Ok((name, table_options(stage, "type")?))
+}
+
+// the keys other than skip as options
+fn table_options<'a>(table : &'a toml::Table, skip : &str) -> Result<Vec<(&'a str, String)>, String>
+{
+    table.iter().filter(|(key, _)| *key != skip).map(|(key, value)| match value
+    {
+        toml::Value::String(value) => Ok((key.as_str(), value.clone())),
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => Ok((key.as_str(), value.to_string())),
+        _ => Err(format!("option {} has to be a string, number or boolean", key)),
+    }).collect()
+}
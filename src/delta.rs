@@ -0,0 +1,133 @@
+// Inter frame delta compression for low bandwidth links. Each message is
+//   width u16, height u16, sequence u32 (little endian), kind u8, zstd compressed body
+// A keyframe body holds the distances, a delta body the zigzag coded difference of every
+// distance to the one in the previous frame, both as u16 little endian. Distances are sent
+// as read, invalid ones included, so the decoder reconstructs frames exactly. A keyframe
+// goes out every keyframe_interval frames so clients joining late or losing a datagram
+// pick up the stream again.
+use crate::depth::{DepthFrame, MAX_PIXELS};
+
+use log::error;
+
+pub const DEFAULT_KEYFRAME_INTERVAL : u32 = 30;
+pub const DEFAULT_LEVEL : i32 = 3;
+
+const HEADER : usize = 9;
+const KEYFRAME : u8 = 0;
+const DELTA : u8 = 1;
+
+pub struct Encoder
+{
+    keyframe_interval : u32,
+    level : i32,
+    sequence : u32,
+    previous : Option<Vec<u16>>,
+}
+
+impl Encoder
+{
+
+pub fn new(keyframe_interval : u32, level : i32) -> Encoder
+{
+    Encoder { keyframe_interval : keyframe_interval.max(1), level, sequence : 0, previous : None }
+}
+
+pub fn frame(&mut self, depth : &DepthFrame) -> Result<Vec<u8>, ()>
+{
+    let keyframe = self.sequence.is_multiple_of(self.keyframe_interval)
+        || self.previous.as_ref().is_none_or(|previous| previous.len() != depth.data.len());
+    let body : Vec<u8> = match (&self.previous, keyframe)
+    {
+        (Some(previous), false) => depth.data.iter().zip(previous).flat_map(|(current, previous)|
+        {
+            zigzag(current.wrapping_sub(*previous) as i16).to_le_bytes()
+        }).collect(),
+        _ => depth.data.iter().flat_map(|distance| distance.to_le_bytes()).collect(),
+    };
+    let compressed = match zstd::bulk::compress(&body, self.level)
+    {
+        Ok(compressed) => compressed,
+        Err(msg) => { error!("Failed to compress frame, {}", msg); return Err(()) }
+    };
+
+    let mut message = Vec::with_capacity(HEADER + compressed.len());
+    message.extend_from_slice(&(depth.width as u16).to_le_bytes());
+    message.extend_from_slice(&(depth.height as u16).to_le_bytes());
+    message.extend_from_slice(&self.sequence.to_le_bytes());
+    message.push(if keyframe { KEYFRAME } else { DELTA });
+    message.extend_from_slice(&compressed);
+
+    self.sequence = self.sequence.wrapping_add(1);
+    self.previous = Some(depth.data.clone());
+    Ok(message)
+}
+
+}
+
+// Keeps the last frame to apply deltas to. Deltas that don't follow the previous frame
+// (lost or reordered messages, joining mid stream) are skipped until the next keyframe.
+#[derive(Default)]
+pub struct Decoder
+{
+    previous : Option<(u32, DepthFrame)>,
+}
+
+impl Decoder
+{
+
+pub fn new() -> Decoder
+{
+    Decoder::default()
+}
+
+pub fn decode(&mut self, message : &[u8]) -> Option<(u32, &DepthFrame)>
+{
+    if message.len() < HEADER
+    {
+        return None
+    }
+    let width = u16::from_le_bytes([message[0], message[1]]) as usize;
+    let height = u16::from_le_bytes([message[2], message[3]]) as usize;
+    let sequence = u32::from_le_bytes(message[4..8].try_into().unwrap());
+    let pixels = width * height;
+    // the body is decompressed into as much room as the header asks for
+    if pixels > MAX_PIXELS
+    {
+        return None
+    }
+    let body = zstd::bulk::decompress(&message[HEADER..], pixels * 2).ok()?;
+    if body.len() != pixels * 2
+    {
+        return None
+    }
+    let values = body.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+
+    let data : Vec<u16> = match message[8]
+    {
+        KEYFRAME => values.collect(),
+        DELTA =>
+        {
+            let previous = match &self.previous
+            {
+                Some((last, previous)) if last.wrapping_add(1) == sequence && previous.data.len() == pixels => previous,
+                _ => return None,
+            };
+            values.zip(&previous.data).map(|(delta, previous)| previous.wrapping_add(unzigzag(delta) as u16)).collect()
+        },
+        _ => return None,
+    };
+    self.previous = Some((sequence, DepthFrame { width, height, data }));
+    self.previous.as_ref().map(|(sequence, depth)| (*sequence, depth))
+}
+
+}
+
+fn zigzag(value : i16) -> u16
+{
+    ((value << 1) ^ (value >> 15)) as u16
+}
+
+fn unzigzag(value : u16) -> i16
+{
+    ((value >> 1) as i16) ^ -((value & 1) as i16)
+}
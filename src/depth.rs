@@ -0,0 +1,167 @@
+use crate::unpack::unpack_12bit;
+
+pub const WIDTH_3D : usize = 160;
+pub const HEIGHT_3D : usize = 60;
+// payload header followed by 12 bit distances
+pub const PAYLOAD_3D_SIZE : u16 = (1 + WIDTH_3D * HEIGHT_3D * 3 / 2) as u16;
+pub const PAYLOAD_3D_HEADER : u8 = 0x08;
+// 2D scans are a single row, payload header followed by 16 bit little endian distances
+pub const WIDTH_2D : usize = 161;
+pub const PAYLOAD_2D_SIZE : u16 = (1 + WIDTH_2D * 2) as u16;
+pub const PAYLOAD_2D_HEADER : u8 = 0x01;
+// the most distances a frame from the device holds, for sizes that come from elsewhere
+pub const MAX_PIXELS : usize = WIDTH_3D * HEIGHT_3D;
+
+// distances are in millimeters, values from here up are codes for pixels the sensor
+// couldn't measure (low amplitude, saturation, ...)
+pub const INVALID_DEPTH : u16 = 4080;
+
+pub fn is_valid(depth : u16) -> bool
+{
+    depth != 0 && depth < INVALID_DEPTH
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary
+{
+    pub valid_points : usize,
+    pub min_mm : Option<u16>,
+    pub max_mm : Option<u16>,
+    pub mean_mm : Option<f32>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DepthFrame
+{
+    pub width : usize,
+    pub height : usize,
+    pub data : Vec<u16>,
+}
+
+impl DepthFrame
+{
+
+// payload[0] is the payload header, after it every 3 bytes carry two 12 bit distances, or
+// for 2D scans every 2 bytes one
+pub fn from_payload(payload : &[u8]) -> DepthFrame
+{
+    let mut depth = DepthFrame::default();
+    depth.unpack(payload);
+    depth
+}
+
+// from_payload into this frame, reusing its data
+pub fn unpack(&mut self, payload : &[u8])
+{
+    if payload.first() == Some(&PAYLOAD_2D_HEADER)
+    {
+        self.width = WIDTH_2D;
+        self.height = 1;
+        self.data.clear();
+        self.data.extend(payload[1..].chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])));
+        self.data.resize(WIDTH_2D, 0);
+        return
+    }
+    self.width = WIDTH_3D;
+    self.height = HEIGHT_3D;
+    self.data.clear();
+    self.data.resize(WIDTH_3D * HEIGHT_3D, 0);
+    unpack_12bit(payload.get(1..).unwrap_or(&[]), &mut self.data);
+}
+
+// the inverse of from_payload, for frames that didn't come from the device
+pub fn to_payload(&self) -> Vec<u8>
+{
+    let mut payload = Vec::with_capacity(PAYLOAD_3D_SIZE as usize);
+    self.pack(&mut payload);
+    payload
+}
+
+// to_payload into payload, reusing it
+pub fn pack(&self, payload : &mut Vec<u8>)
+{
+    payload.clear();
+    if self.height == 1
+    {
+        payload.push(PAYLOAD_2D_HEADER);
+        payload.extend(self.data.iter().flat_map(|distance| distance.to_le_bytes()));
+        return
+    }
+    payload.push(PAYLOAD_3D_HEADER);
+    for points in self.data.chunks(2)
+    {
+        let p0 = points[0].min(0xfff);
+        let p1 = points.get(1).map_or(0, |p1| (*p1).min(0xfff));
+        payload.extend_from_slice(&[p0 as u8, (p0 >> 8) as u8 | (p1 << 4) as u8, (p1 >> 4) as u8]);
+    }
+}
+
+// 16 bit grayscale, values in millimeters
+pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError>
+{
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    let data : Vec<u8> = self.data.iter().flat_map(|distance| distance.to_be_bytes()).collect();
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+pub fn valid(&self) -> impl Iterator<Item = u16> + '_
+{
+    self.data.iter().copied().filter(|depth| is_valid(*depth))
+}
+
+pub fn summary(&self) -> Summary
+{
+    let mut summary = Summary::default();
+    let mut sum : u64 = 0;
+    for distance in self.valid()
+    {
+        summary.valid_points += 1;
+        sum += distance as u64;
+        summary.min_mm = Some(summary.min_mm.map_or(distance, |min| min.min(distance)));
+        summary.max_mm = Some(summary.max_mm.map_or(distance, |max| max.max(distance)));
+    }
+    if summary.valid_points > 0
+    {
+        summary.mean_mm = Some(sum as f32 / summary.valid_points as f32);
+    }
+    summary
+}
+
+// mean valid distance of each block of a cols x rows grid, row major, None for blocks
+// without valid pixels
+pub fn downsample(&self, cols : usize, rows : usize) -> Vec<Option<f32>>
+{
+    let mut sums = vec![(0u64, 0u32); cols * rows];
+    for y in 0..self.height
+    {
+        let row = y * rows / self.height;
+        for x in 0..self.width
+        {
+            let distance = self.data[y * self.width + x];
+            if is_valid(distance)
+            {
+                let block = &mut sums[row * cols + x * cols / self.width];
+                block.0 += distance as u64;
+                block.1 += 1;
+            }
+        }
+    }
+    sums.iter().map(|(sum, count)| if *count > 0 { Some(*sum as f32 / *count as f32) } else { None }).collect()
+}
+
+// downsample as a frame of width x height into preview, 0 for blocks without valid pixels
+pub fn preview_into(&self, width : usize, height : usize, preview : &mut DepthFrame)
+{
+    preview.width = width;
+    preview.height = height;
+    preview.data.clear();
+    preview.data.extend(self.downsample(width, height).iter().map(|mean| mean.map_or(0, |mean| mean.round() as u16)));
+}
+
+}
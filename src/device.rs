@@ -0,0 +1,491 @@
+use crate::depth::{PAYLOAD_2D_SIZE, PAYLOAD_3D_SIZE};
+use crate::frame::{new, parse_frame, Frame, HEADER};
+use crate::options::{self, Options};
+use crate::transport::Transport;
+
+use log::{error, info, warn};
+use serde::Serialize;
+use serialport::{DataBits, FlowControl, Parity, SerialPortType, StopBits, TTYPort};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::ffi::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort};
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+
+pub const DEFAULT_PORT : &str = "/dev/ttyUSB0";
+pub const DEFAULT_BAUD_RATE : u32 = 3000000;
+pub const DEFAULT_TIMEOUT : Duration = Duration::from_secs(1);
+// how long a probed port has to answer the info request
+pub const PROBE_WAIT : Duration = Duration::from_millis(500);
+const INFO_PAYLOAD_SIZE : u16 = 7;
+
+// What the device streams: 2D scans, 3D depth frames, or both taking turns
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode
+{
+    Scan,
+    Depth,
+    Dual,
+}
+
+impl Mode
+{
+
+pub fn parse(value : &str) -> Result<Mode, String>
+{
+    match value
+    {
+        "2d" => Ok(Mode::Scan),
+        "3d" => Ok(Mode::Depth),
+        "dual" => Ok(Mode::Dual),
+        _ => Err(format!("invalid mode {}, expected 2d, 3d or dual", value)),
+    }
+}
+
+// the sizes of the payloads the device sends in this mode
+pub fn payload_sizes(&self) -> &'static [u16]
+{
+    match self
+    {
+        Mode::Scan => &[PAYLOAD_2D_SIZE],
+        Mode::Depth => &[PAYLOAD_3D_SIZE],
+        Mode::Dual => &[PAYLOAD_2D_SIZE, PAYLOAD_3D_SIZE],
+    }
+}
+
+// the byte of the start request
+pub(crate) fn command(&self) -> u8
+{
+    match self
+    {
+        Mode::Scan => 0x01,
+        Mode::Depth => 0x08,
+        Mode::Dual => 0x07,
+    }
+}
+
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DeviceInfo
+{
+    pub firmware : String,
+    pub hardware : String,
+}
+
+impl DeviceInfo
+{
+
+// payload[0] is the payload header, followed by firmware and hardware versions, 3 bytes each
+pub fn from_payload(payload : &[u8]) -> Option<DeviceInfo>
+{
+    if payload.len() < 7
+    {
+        return None
+    }
+    let version = |bytes : &[u8]| format!("{}.{}.{}", bytes[0], bytes[1], bytes[2]);
+    Some(DeviceInfo { firmware : version(&payload[1..4]), hardware : version(&payload[4..7]) })
+}
+
+}
+
+// How the port is opened, given as path[?option=value&...], e.g.
+// /dev/ttyUSB0?low_latency=true&latency_timer=1. USB serial adapters hold received bytes
+// for a while before passing them on, up to 16 ms on FTDI chips by default; low_latency
+// asks the driver not to, latency_timer sets the FTDI timer in milliseconds. chunk reads
+// frames that many bytes at a time, exclusive=false lets other programs open the port too.
+// cpu pins the thread reading the port to a core and priority runs it SCHED_FIFO at that
+// priority, 1 to 99, which needs CAP_SYS_NICE; without it reading goes on as before.
+// mode picks what the device streams, timeout is how long it may stay quiet before that
+// is reported. watchdog restarts the stream when no valid frame came for that long.
+#[derive(Clone, Debug)]
+pub struct Settings
+{
+    pub path : String,
+    pub baud_rate : u32,
+    pub low_latency : bool,
+    pub latency_timer : Option<u8>,
+    // whole frames at a time when None
+    pub chunk : Option<usize>,
+    pub exclusive : bool,
+    pub cpu : Option<usize>,
+    pub priority : Option<i32>,
+    pub mode : Mode,
+    pub timeout : Duration,
+    pub watchdog : Option<Duration>,
+}
+
+impl Default for Settings
+{
+    fn default() -> Self
+    {
+        Settings { path : DEFAULT_PORT.to_string(), baud_rate : DEFAULT_BAUD_RATE, low_latency : false, latency_timer : None, chunk : None, exclusive : true, cpu : None, priority : None, mode : Mode::Depth, timeout : DEFAULT_TIMEOUT, watchdog : None }
+    }
+}
+
+impl Settings
+{
+
+// the spec's port and options over base, a spec of only options, e.g. ?low_latency=true,
+// keeps the port of base
+pub fn from_spec(spec : &str, base : &Settings) -> Result<Settings, String>
+{
+    let (path, options) = options::split(spec)?;
+    Settings::from_options(if path.is_empty() { &base.path } else { path }, &options, base)
+}
+
+// options: baud=<baud rate>, low_latency=true|false, latency_timer=<1 to 255 ms>,
+// chunk=<bytes per read>, exclusive=true|false, cpu=<core>, priority=<1 to 99>,
+// mode=2d|3d|dual, timeout=<ms>, watchdog=<ms>, over base
+pub fn from_options(path : &str, options : &Options, base : &Settings) -> Result<Settings, String>
+{
+    let mut settings = Settings { path : path.to_string(), ..base.clone() };
+    for (name, value) in options
+    {
+        match *name
+        {
+            "baud" => settings.baud_rate = value.parse().map_err(|_| format!("invalid baud rate {}", value))?,
+            "low_latency" => settings.low_latency = value.parse().map_err(|_| format!("invalid value for low_latency {}", value))?,
+            "latency_timer" => settings.latency_timer = Some(value.parse().ok().filter(|timer| *timer > 0).ok_or(format!("invalid latency timer {}", value))?),
+            "chunk" => settings.chunk = Some(value.parse().ok().filter(|chunk| *chunk > 0).ok_or(format!("invalid chunk size {}", value))?),
+            "exclusive" => settings.exclusive = value.parse().map_err(|_| format!("invalid value for exclusive {}", value))?,
+            "cpu" => settings.cpu = Some(value.parse().ok().filter(|cpu| *cpu < CPU_SET_SIZE).ok_or(format!("invalid cpu {}", value))?),
+            "priority" => settings.priority = Some(value.parse().ok().filter(|priority| (1..=99).contains(priority)).ok_or(format!("invalid priority {}", value))?),
+            "mode" => settings.mode = Mode::parse(value)?,
+            "timeout" => settings.timeout = Duration::from_millis(value.parse().ok().filter(|timeout| *timeout > 0).ok_or(format!("invalid timeout {}", value))?),
+            "watchdog" => settings.watchdog = Some(Duration::from_millis(value.parse().ok().filter(|watchdog| *watchdog > 0).ok_or(format!("invalid watchdog {}", value))?)),
+            _ => return Err(format!("device has no option {}", name)),
+        }
+    }
+    Ok(settings)
+}
+
+// opens the port and applies the latency settings, failing to apply them is only reported
+pub fn open(&self) -> Result<TTYPort, String>
+{
+    if !Path::new(&self.path).exists()
+    {
+        return Err(format!("{} doesn't exist, {}", self.path, found_ports()))
+    }
+    let mut serial_port = open(&self.path, self.baud_rate).map_err(|msg| format!("{}, {}", self.path, msg))?;
+    serial_port.set_exclusive(self.exclusive).map_err(|msg| format!("failed to set exclusive access, {}", msg))?;
+    if self.low_latency
+    {
+        if let Err(msg) = set_low_latency(&serial_port)
+        {
+            warn!("Failed to set low latency mode on {}, {}", self.path, msg);
+        }
+    }
+    if let Some(timer) = self.latency_timer
+    {
+        if let Err(msg) = set_latency_timer(&self.path, timer)
+        {
+            warn!("Failed to set the latency timer of {}, {}", self.path, msg);
+        }
+    }
+    Ok(serial_port)
+}
+
+// for the thread reading the port, failing is only reported
+pub fn apply_to_thread(&self)
+{
+    if let Some(cpu) = self.cpu
+    {
+        match pin_thread(cpu)
+        {
+            Ok(()) => info!("Pinned the reading thread to cpu {}", cpu),
+            Err(msg) => warn!("Failed to pin the reading thread to cpu {}, {}", cpu, msg),
+        }
+    }
+    if let Some(priority) = self.priority
+    {
+        match set_realtime(priority)
+        {
+            Ok(()) => info!("Reading with SCHED_FIFO priority {}", priority),
+            Err(msg) => warn!("Failed to set SCHED_FIFO priority {}, reading with the default scheduler, {}", priority, msg),
+        }
+    }
+}
+
+}
+
+// cpus in a cpu_set_t
+const CPU_SET_SIZE : usize = 1024;
+
+#[cfg(target_os = "linux")]
+extern "C"
+{
+    fn sched_setaffinity(pid : c_int, size : usize, mask : *const u64) -> c_int;
+    fn sched_setscheduler(pid : c_int, policy : c_int, param : *const c_int) -> c_int;
+}
+
+// pid 0 is the calling thread for both
+#[cfg(target_os = "linux")]
+fn pin_thread(cpu : usize) -> io::Result<()>
+{
+    let mut mask = [0u64; CPU_SET_SIZE / 64];
+    mask[cpu / 64] |= 1 << (cpu % 64);
+    // SAFETY: mask is a cpu_set_t of the given size
+    if unsafe { sched_setaffinity(0, mem::size_of_val(&mask), mask.as_ptr()) } != 0
+    {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime(priority : i32) -> io::Result<()>
+{
+    const SCHED_FIFO : c_int = 1;
+    // struct sched_param is only sched_priority
+    let param : c_int = priority;
+    // SAFETY: param outlives the call
+    if unsafe { sched_setscheduler(0, SCHED_FIFO, &param) } != 0
+    {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_thread(_cpu : usize) -> io::Result<()>
+{
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime(_priority : i32) -> io::Result<()>
+{
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on linux"))
+}
+
+// the FTDI driver's timer, in sysfs under the name of the tty the path leads to
+fn set_latency_timer(path : &str, timer : u8) -> io::Result<()>
+{
+    let device = fs::canonicalize(path)?;
+    let name = device.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    fs::write(format!("/sys/bus/usb-serial/devices/{}/latency_timer", name), timer.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn set_low_latency(serial_port : &TTYPort) -> io::Result<()>
+{
+    // struct serial_struct of linux/serial.h
+    #[repr(C)]
+    struct SerialStruct
+    {
+        kind : c_int,
+        line : c_int,
+        port : c_uint,
+        irq : c_int,
+        flags : c_int,
+        xmit_fifo_size : c_int,
+        custom_divisor : c_int,
+        baud_base : c_int,
+        close_delay : c_ushort,
+        io_type : c_char,
+        reserved_char : c_char,
+        hub6 : c_int,
+        closing_wait : c_ushort,
+        closing_wait2 : c_ushort,
+        iomem_base : *mut c_uchar,
+        iomem_reg_shift : c_ushort,
+        port_high : c_uint,
+        iomap_base : c_ulong,
+    }
+    const TIOCGSERIAL : c_ulong = 0x541e;
+    const TIOCSSERIAL : c_ulong = 0x541f;
+    const ASYNC_LOW_LATENCY : c_int = 1 << 13;
+    extern "C"
+    {
+        fn ioctl(fd : c_int, request : c_ulong, ...) -> c_int;
+    }
+
+    // SAFETY: serial_struct is plain data, the kernel fills it in and reads it back
+    unsafe
+    {
+        let mut serial : SerialStruct = mem::zeroed();
+        if ioctl(serial_port.as_raw_fd(), TIOCGSERIAL, &mut serial) != 0
+        {
+            return Err(io::Error::last_os_error())
+        }
+        serial.flags |= ASYNC_LOW_LATENCY;
+        if ioctl(serial_port.as_raw_fd(), TIOCSSERIAL, &serial) != 0
+        {
+            return Err(io::Error::last_os_error())
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_low_latency(_serial_port : &TTYPort) -> io::Result<()>
+{
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on linux"))
+}
+
+// the serial ports there are, for when the one asked for isn't
+pub fn found_ports() -> String
+{
+    match serialport::available_ports()
+    {
+        Ok(ports) if !ports.is_empty() => format!("serial ports found: {}", ports.iter().map(|port| port.port_name.as_str()).collect::<Vec<_>>().join(", ")),
+        Ok(_) => "no serial ports found".to_string(),
+        Err(msg) => format!("failed to list serial ports, {}", msg),
+    }
+}
+
+pub fn open(path : &str, baud_rate : u32) -> serialport::Result<TTYPort>
+{
+    serialport::new(path, baud_rate)
+    .data_bits(DataBits::Eight)
+    .parity(Parity::None)
+    .stop_bits(StopBits::One)
+    .flow_control(FlowControl::None)
+    .open_native()
+}
+
+fn send(serial_port : &mut dyn Transport, payload : Vec<u8>, what : &str) -> Result<(), ()>
+{
+    let frame = new(payload);
+    match serial_port.write_all(&frame.as_bytes()?)
+    {
+        Ok(_) => Ok(()),
+        Err(msg) => { error!("Error writing {}!, {}", what, msg); Err(()) },
+    }
+}
+
+// drops whatever the device sent before, so the next read starts at the answer to the
+// command sent after
+fn clear_input(serial_port : &mut dyn Transport) -> Result<(), ()>
+{
+    match serial_port.clear_input()
+    {
+        Ok(_) => Ok(()),
+        Err(msg) => { error!("Error clearing serial port!, {}", msg); Err(()) },
+    }
+}
+
+// sets the device baud rate and asks for its info, returns the info frame. Waiting up to
+// wait for that frame is all the waiting the handshake needs
+pub fn handshake(serial_port : &mut dyn Transport, wait : Duration) -> Result<Frame, String>
+{
+    let timeout = serial_port.timeout();
+    serial_port.set_timeout(Duration::from_millis(20)).map_err(|msg| msg.to_string())?;
+    let frame = request_info(serial_port).map_err(|_| "failed to send the info request".to_string()).and_then(|_| wait_for_info_frame(serial_port, wait));
+    let _ = serial_port.set_timeout(timeout);
+    frame?.ok_or(format!("no answer from device within {:?}", wait))
+}
+
+fn request_info(serial_port : &mut dyn Transport) -> Result<(), ()>
+{
+    clear_input(serial_port)?;
+    send(serial_port, vec![0x12, 0x55], "baud info")?;
+    send(serial_port, vec![0x10, 0x00], "dev info request")?;
+    if let Err(msg) = serial_port.flush()
+    {
+        error!("Error flushing serial port!, {}", msg);
+        return Err(())
+    }
+    Ok(())
+}
+
+// the handshake giving up after wait, for finding the device among the serial ports.
+// Whatever else the port sends is skipped
+pub fn probe(path : &str, baud_rate : u32, wait : Duration) -> Result<Option<DeviceInfo>, String>
+{
+    let mut serial_port = open(path, baud_rate).map_err(|msg| msg.to_string())?;
+    serial_port.set_timeout(Duration::from_millis(20)).map_err(|msg| msg.to_string())?;
+    request_info(&mut serial_port).map_err(|_| "failed to send the info request".to_string())?;
+    Ok(wait_for_info_frame(&mut serial_port, wait)?.and_then(|frame| DeviceInfo::from_payload(&frame.payload)))
+}
+
+// stop, the handshake and start again, for a device that stopped sending frames. None
+// when it didn't answer the info request within wait, it isn't started then
+pub fn restart(serial_port : &mut dyn Transport, mode : Mode, wait : Duration) -> Result<Option<DeviceInfo>, String>
+{
+    stop(serial_port).map_err(|_| "failed to send the stop request".to_string())?;
+    let timeout = serial_port.timeout();
+    serial_port.set_timeout(Duration::from_millis(20)).map_err(|msg| msg.to_string())?;
+    let info = request_info(serial_port).map_err(|_| "failed to send the info request".to_string()).and_then(|_| wait_for_info_frame(serial_port, wait))
+        .map(|frame| frame.and_then(|frame| DeviceInfo::from_payload(&frame.payload)));
+    let _ = serial_port.set_timeout(timeout);
+    if info.as_ref().is_ok_and(|info| info.is_some())
+    {
+        start(serial_port, mode).map_err(|_| "failed to send the start request".to_string())?;
+    }
+    info
+}
+
+// the info frame among whatever comes in the next wait, reading with the port's timeout
+fn wait_for_info_frame(serial_port : &mut dyn Transport, wait : Duration) -> Result<Option<Frame>, String>
+{
+    let size = INFO_PAYLOAD_SIZE.to_le_bytes();
+    let expected = [HEADER[0], HEADER[1], HEADER[2], size[0], size[1]];
+    let length = INFO_PAYLOAD_SIZE as usize + 6;
+    let mut bytes = Vec::new();
+    let mut read = [0u8; 256];
+    let started = Instant::now();
+    while started.elapsed() < wait
+    {
+        match serial_port.read(&mut read)
+        {
+            Ok(0) => return Err("end of file".to_string()),
+            Ok(count) => bytes.extend_from_slice(&read[..count]),
+            Err(msg) if msg.kind() == io::ErrorKind::TimedOut || msg.kind() == io::ErrorKind::Interrupted => continue,
+            Err(msg) => return Err(msg.to_string()),
+        }
+        let start = match bytes.windows(expected.len()).position(|window| window == expected)
+        {
+            Some(start) if bytes.len() >= start + length => start,
+            _ => continue,
+        };
+        match parse_frame(&bytes[start..start + length])
+        {
+            Ok(frame) => return Ok(Some(frame)),
+            Err(()) => { bytes.drain(..start + 1); },
+        }
+    }
+    Ok(None)
+}
+
+// how a port is attached, with the USB ids and names for USB ones
+pub fn describe(port_type : &SerialPortType) -> String
+{
+    match port_type
+    {
+        SerialPortType::UsbPort(usb) =>
+        {
+            let mut description = format!("usb {:04x}:{:04x}", usb.vid, usb.pid);
+            for (label, value) in [("", &usb.manufacturer), ("", &usb.product), ("serial ", &usb.serial_number)]
+            {
+                if let Some(value) = value
+                {
+                    description += &format!(", {}{}", label, value);
+                }
+            }
+            description
+        },
+        SerialPortType::PciPort => "pci".to_string(),
+        SerialPortType::BluetoothPort => "bluetooth".to_string(),
+        SerialPortType::Unknown => "unknown".to_string(),
+    }
+}
+
+// the device answers with the stream itself, the first read blocks until it comes
+pub fn start(serial_port : &mut dyn Transport, mode : Mode) -> Result<(), ()>
+{
+    clear_input(serial_port)?;
+    send(serial_port, vec![mode.command(), 0x00], "start request")
+}
+
+pub fn stop(serial_port : &mut dyn Transport) -> Result<(), ()>
+{
+    send(serial_port, vec![0x02, 0x00, 0x00], "stop request")
+}
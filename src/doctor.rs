@@ -0,0 +1,187 @@
+// Checks what has to work for frames to come off the lidar, in the order it has to work
+// in, for the doctor subcommand: the port is there, it may be read and written, it opens,
+// the lidar answers the info request at some baud rate, and frames come after the start
+// request. A check failing says why and what to do about it, and the checks needing it
+// are left out, so the first failure is the one to fix.
+use crate::device::{self, Settings};
+use crate::frame::{new, parse_frame_into};
+use crate::reader::{Command, SerialReader};
+
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::ffi::{c_char, c_int, CString};
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::MetadataExt;
+
+// tried after the configured one, fastest first
+pub const BAUD_RATES : [u32; 8] = [3000000, 2000000, 1500000, 1000000, 921600, 460800, 230400, 115200];
+pub const FRAMES : usize = 10;
+pub const FRAME_WAIT : Duration = Duration::from_secs(3);
+
+pub struct Check
+{
+    pub name : &'static str,
+    pub passed : bool,
+    pub detail : String,
+}
+
+fn check(name : &'static str, result : Result<String, String>) -> Check
+{
+    match result
+    {
+        Ok(detail) => Check { name, passed : true, detail },
+        Err(detail) => Check { name, passed : false, detail },
+    }
+}
+
+// the checks run, up to the first failing
+pub fn diagnose(settings : &Settings) -> Vec<Check>
+{
+    let mut checks = Vec::new();
+    let mut run = |name, result : Result<String, String>| { checks.push(check(name, result)); checks.last().is_some_and(|check| check.passed) };
+    let path = settings.path.as_str();
+    let _ = run("port", exists(path))
+        && run("access", readable_and_writable(path))
+        && run("open", device::open(path, settings.baud_rate).map(|_| format!("opened at {} baud", settings.baud_rate)).map_err(|msg| msg.to_string()))
+        && match answering_baud(settings)
+        {
+            Ok((baud_rate, detail)) => run("handshake", Ok(detail)) && run("frames", frames(&Settings { baud_rate, ..settings.clone() })),
+            Err(detail) => run("handshake", Err(detail)),
+        };
+    checks
+}
+
+fn exists(path : &str) -> Result<String, String>
+{
+    if !Path::new(path).exists()
+    {
+        return Err(format!("{} doesn't exist, {}; is the lidar plugged in, or is it at another --port?", path, device::found_ports()))
+    }
+    match Path::new(path).canonicalize()
+    {
+        Ok(target) if target != Path::new(path) => Ok(format!("{} is there, a link to {}", path, target.display())),
+        _ => Ok(format!("{} is there", path)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+extern "C"
+{
+    fn access(path : *const c_char, mode : c_int) -> c_int;
+}
+
+// with the group owning the port, which is what the user lacks when they may not use it
+#[cfg(target_os = "linux")]
+fn readable_and_writable(path : &str) -> Result<String, String>
+{
+    const READ_WRITE : c_int = 4 | 2;
+    let gid = fs::metadata(path).map_err(|msg| msg.to_string())?.gid();
+    let group = group_name(gid).unwrap_or(gid.to_string());
+    let name = CString::new(path).map_err(|msg| msg.to_string())?;
+    // SAFETY: name is a nul terminated string outliving the call
+    if unsafe { access(name.as_ptr(), READ_WRITE) } == 0
+    {
+        return Ok(format!("may be read and written, owned by group {}", group))
+    }
+    if !own_groups().contains(&gid)
+    {
+        return Err(format!("may not be read and written, it is owned by group {} which you aren't in; add yourself with sudo usermod -aG {} $USER and log in again", group, group))
+    }
+    Err(format!("may not be read and written, though you are in group {} owning it; check its mode and udev rules", group))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn readable_and_writable(_path : &str) -> Result<String, String>
+{
+    Ok("not checked on this platform, see open".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn group_name(gid : u32) -> Option<String>
+{
+    let groups = fs::read_to_string("/etc/group").ok()?;
+    groups.lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[2].parse() == Ok(gid))
+        .map(|fields| fields[0].to_string())
+}
+
+// the primary and supplementary groups of this process, as the kernel sees them
+#[cfg(target_os = "linux")]
+fn own_groups() -> Vec<u32>
+{
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let field = |name : &str| status.lines().find_map(|line| line.strip_prefix(name)).unwrap_or_default().to_string();
+    let mut groups : Vec<u32> = field("Groups:").split_whitespace().filter_map(|gid| gid.parse().ok()).collect();
+    groups.extend(field("Gid:").split_whitespace().next().and_then(|gid| gid.parse::<u32>().ok()));
+    groups
+}
+
+// the configured baud rate first, a lidar answering at another one means --baud is wrong
+fn answering_baud(settings : &Settings) -> Result<(u32, String), String>
+{
+    let mut tried = vec![settings.baud_rate];
+    tried.extend(BAUD_RATES.iter().filter(|baud_rate| **baud_rate != settings.baud_rate));
+    for baud_rate in tried.iter().copied()
+    {
+        let info = match device::probe(&settings.path, baud_rate, device::PROBE_WAIT)
+        {
+            Ok(Some(info)) => info,
+            Ok(None) => continue,
+            Err(msg) => return Err(format!("failed to probe at {} baud, {}", baud_rate, msg)),
+        };
+        let answer = format!("the lidar answers at {} baud, firmware {}, hardware {}", baud_rate, info.firmware, info.hardware);
+        if baud_rate != settings.baud_rate
+        {
+            return Err(format!("{}, not at the {} configured; pass --baud {}", answer, settings.baud_rate, baud_rate))
+        }
+        return Ok((baud_rate, answer))
+    }
+    let tried = tried.iter().map(|baud_rate| baud_rate.to_string()).collect::<Vec<_>>().join(", ");
+    Err(format!("no answer to the info request at {} baud; check the cable, the power and that nothing else has the port open", tried))
+}
+
+// FRAMES frames, or what came within FRAME_WAIT
+fn frames(settings : &Settings) -> Result<String, String>
+{
+    // the probe before sent the info request, the handshake would wait for an answer forever
+    let mut serial_port = settings.open()?;
+    device::start(&mut serial_port, settings.mode).map_err(|_| "failed to send the start request".to_string())?;
+    let (mut reader, control) = SerialReader::new(&serial_port, settings.timeout).map_err(|msg| msg.to_string())?;
+    let (done, finished) = channel::<()>();
+    let started = Instant::now();
+    let (mut valid, mut invalid) = (0, 0);
+    thread::scope(|scope|
+    {
+        scope.spawn(move ||
+        {
+            let _ = finished.recv_timeout(FRAME_WAIT);
+            let _ = control.send(Command::Stop);
+        });
+        let mut bytes = Vec::new();
+        let mut frame = new(Vec::new());
+        while valid + invalid < FRAMES
+        {
+            match reader.read_frame(&mut serial_port, settings.mode.payload_sizes(), usize::MAX, &mut bytes)
+            {
+                Ok(Some(_)) => if parse_frame_into(&bytes, &mut frame).is_ok() { valid += 1 } else { invalid += 1 },
+                Ok(None) | Err(()) => break,
+            }
+        }
+        drop(done);
+    });
+    let elapsed = started.elapsed();
+    let _ = device::stop(&mut serial_port);
+    match (valid, invalid)
+    {
+        (0, 0) => Err(format!("no frames within {:?} of the start request in {:?} mode; check --mode", FRAME_WAIT, settings.mode)),
+        (_, 0) if valid < FRAMES => Err(format!("only {} of {} frames within {:?}", valid, FRAMES, FRAME_WAIT)),
+        (_, 0) => Ok(format!("read {} frames in {:.2?}, {:.1} frames/s", valid, elapsed, valid as f64 / elapsed.as_secs_f64())),
+        _ => Err(format!("{} of {} frames failed their checksum; check the cable, or try a lower --baud", invalid, valid + invalid)),
+    }
+}
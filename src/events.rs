@@ -0,0 +1,77 @@
+// Appends events, see Analysis::events, to a log, one json object per line with the time
+// it happened, e.g.
+// {"timestamp_ms":1700000000000,"motion":{"kind":"motion_started","region":{...},...}}
+use crate::analysis::Analysis;
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::{Options, Publisher};
+use crate::sinks::Backpressure;
+
+use log::{error, info};
+use serde::Serialize;
+
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+struct Entry<'a>
+{
+    timestamp_ms : u128,
+    #[serde(flatten)]
+    event : &'a Analysis,
+}
+
+pub struct EventsPublisher
+{
+    path : String,
+    log : LineWriter<File>,
+}
+
+impl EventsPublisher
+{
+
+pub fn new(path : &str, options : &Options) -> Result<EventsPublisher, String>
+{
+    if let Some((name, _)) = options.first()
+    {
+        return Err(format!("events publisher has no option {}", name))
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path).map_err(|msg| format!("failed to open events log {}, {}", path, msg))?;
+    info!("Logging events to {}", path);
+    Ok(EventsPublisher { path : path.to_string(), log : LineWriter::new(file) })
+}
+
+}
+
+impl Publisher for EventsPublisher
+{
+
+// events missed are not seen again
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    let event = match analysis.events()
+    {
+        Some(event) => event,
+        None => return,
+    };
+    let entry = Entry { timestamp_ms : SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0), event : &event };
+    let written = serde_json::to_string(&entry).map_err(|msg| msg.to_string())
+        .and_then(|json| writeln!(self.log, "{}", json).map_err(|msg| msg.to_string()));
+    if let Err(msg) = written
+    {
+        error!("Failed to log event to {}, {}", self.path, msg);
+    }
+}
+
+fn publish(&mut self, _frame : &Frame, _depth : &DepthFrame) -> Result<(), ()>
+{
+    Ok(())
+}
+
+}
@@ -0,0 +1,127 @@
+// Writes every frame to a file of its own in a directory, frame_000000.png and on: a 16
+// bit grayscale png of the distances in mm, a ply of the frame's point cloud, or a csv of
+// the distances in mm, a line per row. export://<directory>[?format=png|ply|csv] writes
+// frames as they come, the export subcommand writes all of a recording's.
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::{Options, Publisher};
+use crate::sinks::Backpressure;
+use crate::template;
+
+use log::{error, info};
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format
+{
+    Png,
+    Ply,
+    Csv,
+}
+
+impl Format
+{
+
+pub fn parse(value : &str) -> Result<Format, String>
+{
+    match value
+    {
+        "png" => Ok(Format::Png),
+        "ply" => Ok(Format::Ply),
+        "csv" => Ok(Format::Csv),
+        _ => Err(format!("invalid format {}, expected png, ply or csv", value)),
+    }
+}
+
+fn extension(&self) -> &'static str
+{
+    match self
+    {
+        Format::Png => "png",
+        Format::Ply => "ply",
+        Format::Csv => "csv",
+    }
+}
+
+}
+
+pub struct ExportPublisher
+{
+    directory : PathBuf,
+    format : Format,
+    projection : Projection,
+    frames : u64,
+}
+
+impl ExportPublisher
+{
+
+// options: format=png|ply|csv, png by default
+pub fn new(directory : &str, options : &Options) -> Result<ExportPublisher, String>
+{
+    let mut format = Format::Png;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "format" => format = Format::parse(value)?,
+            _ => return Err(format!("export publisher has no option {}", name)),
+        }
+    }
+    let directory = template::expand(directory, "")?;
+    fs::create_dir_all(&directory).map_err(|msg| format!("failed to create {}, {}", directory, msg))?;
+    info!("Exporting frames to {} as {}", directory, format.extension());
+    Ok(ExportPublisher { directory : PathBuf::from(directory), format, projection : Projection::default(), frames : 0 })
+}
+
+fn write(&self, path : &Path, depth : &DepthFrame) -> Result<(), String>
+{
+    match self.format
+    {
+        Format::Png => fs::write(path, depth.to_png().map_err(|msg| msg.to_string())?).map_err(|msg| msg.to_string()),
+        Format::Ply =>
+        {
+            let file = File::create(path).map_err(|msg| msg.to_string())?;
+            self.projection.project(depth).write_ply(BufWriter::new(file)).map_err(|msg| msg.to_string())
+        },
+        Format::Csv =>
+        {
+            let mut file = BufWriter::new(File::create(path).map_err(|msg| msg.to_string())?);
+            for row in depth.data.chunks(depth.width.max(1))
+            {
+                let line = row.iter().map(|distance| distance.to_string()).collect::<Vec<_>>().join(",");
+                writeln!(file, "{}", line).map_err(|msg| msg.to_string())?;
+            }
+            file.flush().map_err(|msg| msg.to_string())
+        },
+    }
+}
+
+}
+
+impl Publisher for ExportPublisher
+{
+
+// a frame missing from an export is a file missing
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let path = self.directory.join(format!("frame_{:06}.{}", self.frames, self.format.extension()));
+    self.frames += 1;
+    if let Err(msg) = self.write(&path, depth)
+    {
+        error!("Failed to export {}, {}", path.display(), msg);
+        return Err(())
+    }
+    Ok(())
+}
+
+}
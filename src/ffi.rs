@@ -0,0 +1,140 @@
+// C interface to the driver, see include/rusty_lidar_viewer.h (regenerate it with
+// `cbindgen --config cbindgen.toml --output include/rusty_lidar_viewer.h`).
+// Functions return 0 on success and -1 on failure; errors are logged through the log
+// crate, on stderr unless the program has a logger of its own.
+use crate::depth::{DepthFrame, PAYLOAD_3D_SIZE};
+use crate::device::{self, Mode};
+use crate::frame::{new, parse_frame_into, Frame};
+use crate::logging;
+use crate::reader::{Control, SerialReader};
+use crate::stats::{Stats, STATS};
+
+use log::{error, LevelFilter};
+use serialport::TTYPort;
+
+use std::ffi::{c_char, c_int, CStr};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+pub struct Lidar
+{
+    serial_port : TTYPort,
+    // frames found again after lost bytes, as when reading for the viewer. Nothing is sent
+    // over control, it is kept for the reader's wake up pipe to stay open
+    reader : SerialReader,
+    _control : Control,
+    buffer : Vec<u8>,
+    frame : Frame,
+    depth : Option<DepthFrame>,
+    sequence : u64,
+}
+
+/// `data` points at width * height distances in millimeters, row major, and stays valid
+/// until the next `lidar_next_frame` or `lidar_close` on the same handle.
+#[repr(C)]
+pub struct LidarFrame
+{
+    pub data : *const u16,
+    pub width : u32,
+    pub height : u32,
+    pub sequence : u64,
+}
+
+/// Opens the port, runs the handshake and starts 3D streaming. Returns NULL on failure.
+///
+/// # Safety
+///
+/// `port` must be NULL or a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lidar_open(port : *const c_char, baud_rate : u32) -> *mut Lidar
+{
+    // errors go to stderr as before unless the program has a logger of its own
+    let _ = logging::init(LevelFilter::Warn, logging::Format::Text);
+    if port.is_null()
+    {
+        return ptr::null_mut()
+    }
+    let path = match CStr::from_ptr(port).to_str()
+    {
+        Ok(path) => path,
+        Err(_) => return ptr::null_mut(),
+    };
+    let mut serial_port = match device::open(path, baud_rate)
+    {
+        Ok(serial_port) => serial_port,
+        Err(msg) => { error!("Error opening port!, {}", msg); return ptr::null_mut() },
+    };
+    if let Err(msg) = device::handshake(&mut serial_port, device::DEFAULT_TIMEOUT)
+    {
+        error!("Failed to read device info on {}!, {}", path, msg);
+        return ptr::null_mut()
+    }
+    if device::start(&mut serial_port, Mode::Depth).is_err()
+    {
+        return ptr::null_mut()
+    }
+    let (reader, _control) = match SerialReader::new(&serial_port, device::DEFAULT_TIMEOUT)
+    {
+        Ok(reader) => reader,
+        Err(msg) => { error!("Error waiting on port!, {}", msg); return ptr::null_mut() },
+    };
+    Box::into_raw(Box::new(Lidar { serial_port, reader, _control, buffer : Vec::new(), frame : new(Vec::new()), depth : None, sequence : 0 }))
+}
+
+/// Blocks until the next frame has been read and fills `frame` with it. Frames that fail
+/// their checksum are skipped.
+///
+/// # Safety
+///
+/// `lidar` must come from `lidar_open` and not be closed, `frame` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn lidar_next_frame(lidar : *mut Lidar, frame : *mut LidarFrame) -> c_int
+{
+    let (lidar, frame) = match (lidar.as_mut(), frame.as_mut())
+    {
+        (Some(lidar), Some(frame)) => (lidar, frame),
+        _ => return -1,
+    };
+    loop
+    {
+        match lidar.reader.read_frame(&mut lidar.serial_port, &[PAYLOAD_3D_SIZE], usize::MAX, &mut lidar.buffer)
+        {
+            Ok(Some(_)) => (),
+            _ => return -1,
+        }
+        // counted and reported where it failed
+        if parse_frame_into(&lidar.buffer, &mut lidar.frame).is_ok()
+        {
+            break;
+        }
+    }
+    Stats::count(&STATS.frames);
+    STATS.bytes_read.fetch_add(lidar.buffer.len() as u64, Ordering::Relaxed);
+    let depth = lidar.depth.get_or_insert_with(DepthFrame::default);
+    depth.unpack(&lidar.frame.payload);
+    *frame = LidarFrame
+    {
+        data : depth.data.as_ptr(),
+        width : depth.width as u32,
+        height : depth.height as u32,
+        sequence : lidar.sequence,
+    };
+    lidar.sequence += 1;
+    0
+}
+
+/// Stops streaming and releases the handle. NULL is ignored.
+///
+/// # Safety
+///
+/// `lidar` must come from `lidar_open` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn lidar_close(lidar : *mut Lidar)
+{
+    if lidar.is_null()
+    {
+        return
+    }
+    let mut lidar = Box::from_raw(lidar);
+    let _ = device::stop(&mut lidar.serial_port);
+}
@@ -0,0 +1,84 @@
+// Flying pixels: along object edges a ToF pixel sees foreground and background at once
+// and reports a depth somewhere in between, which shows up as streaks of points trailing
+// off silhouettes. A valid pixel whose depth differs by more than threshold_mm=<mm> from
+// more than fraction=<0..1> of its valid 8 neighbours becomes invalid. Pixels on a surface
+// agree with most of their neighbours, even right at an edge, so only the in between ones go.
+use crate::depth::{is_valid, DepthFrame};
+use crate::filters::Filter;
+use crate::options::Options;
+
+const DEFAULT_THRESHOLD_MM : u16 = 100;
+const DEFAULT_FRACTION : f32 = 0.5;
+
+pub struct Flying
+{
+    threshold_mm : u16,
+    fraction : f32,
+    input : Vec<u16>,
+}
+
+impl Flying
+{
+
+pub fn new(options : &Options) -> Result<Flying, String>
+{
+    let mut threshold_mm = DEFAULT_THRESHOLD_MM;
+    let mut fraction = DEFAULT_FRACTION;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "threshold_mm" => threshold_mm = value.parse().map_err(|_| format!("invalid value for threshold_mm {}", value))?,
+            "fraction" => fraction = value.parse().ok().filter(|fraction| (0.0..=1.0).contains(fraction)).ok_or(format!("invalid value for fraction {}", value))?,
+            _ => return Err(format!("flying filter has no option {}", name)),
+        }
+    }
+    Ok(Flying { threshold_mm, fraction, input : Vec::new() })
+}
+
+}
+
+impl Filter for Flying
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    self.input.clear();
+    self.input.extend_from_slice(&depth.data);
+    for y in 0..depth.height
+    {
+        for x in 0..depth.width
+        {
+            let pixel = y * depth.width + x;
+            let distance = self.input[pixel];
+            if !is_valid(distance)
+            {
+                continue;
+            }
+            let mut neighbours = 0;
+            let mut differing = 0;
+            for ny in y.saturating_sub(1)..(y + 2).min(depth.height)
+            {
+                for nx in x.saturating_sub(1)..(x + 2).min(depth.width)
+                {
+                    let neighbour = self.input[ny * depth.width + nx];
+                    if (nx, ny) == (x, y) || !is_valid(neighbour)
+                    {
+                        continue;
+                    }
+                    neighbours += 1;
+                    if distance.abs_diff(neighbour) > self.threshold_mm
+                    {
+                        differing += 1;
+                    }
+                }
+            }
+            if neighbours > 0 && differing as f32 > self.fraction * neighbours as f32
+            {
+                depth.data[pixel] = 0;
+            }
+        }
+    }
+}
+
+}
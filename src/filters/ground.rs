@@ -0,0 +1,61 @@
+// Finds the ground plane in every frame and invalidates the pixels on it or below it, up
+// to tolerance=<meters> above it, so what's left are obstacles. The ground is the largest
+// plane tilted no more than max_tilt=<degrees> from level, see planes.rs.
+use crate::cloud::Projection;
+use crate::depth::DepthFrame;
+use crate::filters::Filter;
+use crate::options::Options;
+use crate::planes::{find_ground, DEFAULT_ITERATIONS, DEFAULT_THRESHOLD};
+
+const DEFAULT_TOLERANCE : f32 = 0.05;
+const DEFAULT_MAX_TILT : f32 = 15.0;
+
+pub struct Ground
+{
+    tolerance : f32,
+    max_tilt : f32,
+    projection : Projection,
+}
+
+impl Ground
+{
+
+pub fn new(options : &Options) -> Result<Ground, String>
+{
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut max_tilt = DEFAULT_MAX_TILT;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "tolerance" => tolerance = value.parse().map_err(|_| format!("invalid value for tolerance {}", value))?,
+            "max_tilt" => max_tilt = value.parse().map_err(|_| format!("invalid value for max_tilt {}", value))?,
+            _ => return Err(format!("ground filter has no option {}", name)),
+        }
+    }
+    Ok(Ground { tolerance, max_tilt, projection : Projection::default() })
+}
+
+}
+
+impl Filter for Ground
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    let cloud = self.projection.project_pooled(depth);
+    let ground = match find_ground(&cloud, DEFAULT_THRESHOLD, DEFAULT_ITERATIONS, self.max_tilt)
+    {
+        Some(ground) => ground,
+        None => return,
+    };
+    for (point, pixel) in cloud.points.iter().zip(&cloud.pixels)
+    {
+        if ground.distance(*point) <= self.tolerance
+        {
+            depth.data[*pixel] = 0;
+        }
+    }
+}
+
+}
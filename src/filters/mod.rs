@@ -0,0 +1,43 @@
+// Filters run on every depth frame, in the order given on the command line, before it
+// goes to the publishers. They work in place and leave pixels they have nothing to say
+// about untouched.
+use crate::depth::DepthFrame;
+use crate::options::{self, Options};
+
+pub mod flying;
+pub mod ground;
+pub mod outliers;
+pub mod range;
+pub mod spatial;
+pub mod temporal;
+
+// the built in filters, see create
+pub const NAMES : &[&str] = &["bilateral", "flying", "ground", "median", "outliers", "range", "temporal"];
+
+pub trait Filter
+{
+    fn apply(&mut self, depth : &mut DepthFrame);
+}
+
+// filters are given as name[?option=value&...], e.g. temporal?mode=median&frames=5
+pub fn open(spec : &str) -> Result<Box<dyn Filter>, String>
+{
+    let (name, options) = options::split(spec)?;
+    create(name, &options)
+}
+
+pub fn create(name : &str, options : &Options) -> Result<Box<dyn Filter>, String>
+{
+    let filter : Box<dyn Filter> = match name
+    {
+        "bilateral" => Box::new(spatial::Bilateral::new(options)?),
+        "flying" => Box::new(flying::Flying::new(options)?),
+        "outliers" => Box::new(outliers::Outliers::new(options)?),
+        "ground" => Box::new(ground::Ground::new(options)?),
+        "range" => Box::new(range::Range::new(options)?),
+        "median" => Box::new(spatial::Median::new(options)?),
+        "temporal" => Box::new(temporal::Temporal::new(options)?),
+        _ => return Err(format!("unknown filter {}", name)),
+    };
+    Ok(filter)
+}
@@ -0,0 +1,58 @@
+// Statistical outlier removal on the projected cloud (k=<neighbours>, std_ratio=<allowed
+// standard deviations>), pixels of the removed points become invalid. See cloud.rs.
+use crate::cloud::{statistical_outliers, Projection};
+use crate::depth::DepthFrame;
+use crate::filters::Filter;
+use crate::options::Options;
+
+const DEFAULT_K : usize = 8;
+const DEFAULT_STD_RATIO : f32 = 1.0;
+
+pub struct Outliers
+{
+    k : usize,
+    std_ratio : f32,
+    projection : Projection,
+}
+
+impl Outliers
+{
+
+pub fn new(options : &Options) -> Result<Outliers, String>
+{
+    let mut k = DEFAULT_K;
+    let mut std_ratio = DEFAULT_STD_RATIO;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "k" => k = value.parse().map_err(|_| format!("invalid value for k {}", value))?,
+            "std_ratio" => std_ratio = value.parse().map_err(|_| format!("invalid value for std_ratio {}", value))?,
+            _ => return Err(format!("outliers filter has no option {}", name)),
+        }
+    }
+    if k == 0
+    {
+        return Err("k has to be at least 1".to_string())
+    }
+    Ok(Outliers { k, std_ratio, projection : Projection::default() })
+}
+
+}
+
+impl Filter for Outliers
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    let cloud = self.projection.project_pooled(depth);
+    for (keep, pixel) in statistical_outliers(&cloud, self.k, self.std_ratio).iter().zip(&cloud.pixels)
+    {
+        if !keep
+        {
+            depth.data[*pixel] = 0;
+        }
+    }
+}
+
+}
@@ -0,0 +1,48 @@
+// Invalidates distances below min_mm or above max_mm, the sensor is unreliable at the
+// very near and far ends of its range.
+use crate::depth::DepthFrame;
+use crate::filters::Filter;
+use crate::options::Options;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Range
+{
+    pub min_mm : u16,
+    pub max_mm : u16,
+}
+
+impl Range
+{
+
+pub fn new(options : &Options) -> Result<Range, String>
+{
+    let mut range = Range { min_mm : 0, max_mm : u16::MAX };
+    for (name, value) in options
+    {
+        match *name
+        {
+            "min_mm" => range.min_mm = value.parse().map_err(|_| format!("invalid value for min_mm {}", value))?,
+            "max_mm" => range.max_mm = value.parse().map_err(|_| format!("invalid value for max_mm {}", value))?,
+            _ => return Err(format!("range filter has no option {}", name)),
+        }
+    }
+    Ok(range)
+}
+
+}
+
+impl Filter for Range
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    for distance in depth.data.iter_mut()
+    {
+        if *distance < self.min_mm || *distance > self.max_mm
+        {
+            *distance = 0;
+        }
+    }
+}
+
+}
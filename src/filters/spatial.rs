@@ -0,0 +1,186 @@
+// Filters over the neighbourhood of each pixel. Only valid pixels are changed and only
+// valid pixels contribute.
+//   median     median of the size x size window (size=3|5), so single noisy pixels don't
+//              survive; a valid pixel without any valid neighbour is a speckle and becomes
+//              invalid
+//   bilateral  mean of the window weighted by distance in the image (spatial_sigma, in
+//              pixels) and in depth (range_sigma, in mm), smoothing surfaces but not
+//              across object boundaries
+use crate::depth::{is_valid, DepthFrame, WIDTH_3D};
+use crate::filters::Filter;
+use crate::options::Options;
+
+const DEFAULT_SIZE : usize = 3;
+const DEFAULT_SPATIAL_SIGMA : f32 = 1.5;
+const DEFAULT_RANGE_SIGMA : f32 = 30.0;
+// windows no wider than a frame, and weights for differences within the 12 bit distances
+const MAX_SPATIAL_SIGMA : f32 = WIDTH_3D as f32 / 4.0;
+const MAX_RANGE_SIGMA : f32 = 4096.0 / 3.0;
+
+pub struct Median
+{
+    radius : usize,
+    samples : Vec<u16>,
+    input : Vec<u16>,
+}
+
+impl Median
+{
+
+pub fn new(options : &Options) -> Result<Median, String>
+{
+    let mut size = DEFAULT_SIZE;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "size" | "kernel" => size = match *value
+            {
+                "3" => 3,
+                "5" => 5,
+                _ => return Err(format!("median size has to be 3 or 5, not {}", value)),
+            },
+            _ => return Err(format!("median filter has no option {}", name)),
+        }
+    }
+    Ok(Median { radius : size / 2, samples : Vec::with_capacity(size * size), input : Vec::new() })
+}
+
+}
+
+impl Filter for Median
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    self.input.clear();
+    self.input.extend_from_slice(&depth.data);
+    for y in 0..depth.height
+    {
+        for x in 0..depth.width
+        {
+            let pixel = y * depth.width + x;
+            if !is_valid(self.input[pixel])
+            {
+                continue;
+            }
+            self.samples.clear();
+            for wy in y.saturating_sub(self.radius)..(y + self.radius + 1).min(depth.height)
+            {
+                let row = &self.input[wy * depth.width..(wy + 1) * depth.width];
+                self.samples.extend(row[x.saturating_sub(self.radius)..(x + self.radius + 1).min(depth.width)]
+                    .iter().copied().filter(|distance| is_valid(*distance)));
+            }
+            depth.data[pixel] = if self.samples.len() > 1
+            {
+                self.samples.sort_unstable();
+                self.samples[self.samples.len() / 2]
+            }
+            else
+            {
+                0
+            };
+        }
+    }
+}
+
+}
+
+pub struct Bilateral
+{
+    radius : usize,
+    // by window offset, row major
+    spatial_weights : Vec<f32>,
+    // by depth difference in mm, differences past the end weigh nothing
+    range_weights : Vec<f32>,
+    input : Vec<u16>,
+}
+
+impl Bilateral
+{
+
+pub fn new(options : &Options) -> Result<Bilateral, String>
+{
+    let mut spatial_sigma = DEFAULT_SPATIAL_SIGMA;
+    let mut range_sigma = DEFAULT_RANGE_SIGMA;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "spatial_sigma" => spatial_sigma = value.parse().map_err(|_| format!("invalid value for spatial_sigma {}", value))?,
+            "range_sigma" => range_sigma = value.parse().map_err(|_| format!("invalid value for range_sigma {}", value))?,
+            _ => return Err(format!("bilateral filter has no option {}", name)),
+        }
+    }
+    if !(spatial_sigma > 0.0 && spatial_sigma <= MAX_SPATIAL_SIGMA)
+    {
+        return Err(format!("spatial_sigma has to be above 0 and at most {}, got {}", MAX_SPATIAL_SIGMA, spatial_sigma))
+    }
+    if !(range_sigma > 0.0 && range_sigma <= MAX_RANGE_SIGMA)
+    {
+        return Err(format!("range_sigma has to be above 0 and at most {}, got {}", MAX_RANGE_SIGMA, range_sigma))
+    }
+    let radius = (2.0 * spatial_sigma).ceil() as usize;
+    let size = 2 * radius + 1;
+    let spatial_weights = (0..size * size).map(|offset|
+    {
+        let dx = (offset % size) as f32 - radius as f32;
+        let dy = (offset / size) as f32 - radius as f32;
+        (-(dx * dx + dy * dy) / (2.0 * spatial_sigma * spatial_sigma)).exp()
+    }).collect();
+    let range_weights = (0..(3.0 * range_sigma).ceil() as usize).map(|difference|
+    {
+        let difference = difference as f32;
+        (-(difference * difference) / (2.0 * range_sigma * range_sigma)).exp()
+    }).collect();
+    Ok(Bilateral { radius, spatial_weights, range_weights, input : Vec::new() })
+}
+
+}
+
+impl Filter for Bilateral
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    self.input.clear();
+    self.input.extend_from_slice(&depth.data);
+    let size = 2 * self.radius + 1;
+    for y in 0..depth.height
+    {
+        for x in 0..depth.width
+        {
+            let pixel = y * depth.width + x;
+            let center = self.input[pixel];
+            if !is_valid(center)
+            {
+                continue;
+            }
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for wy in y.saturating_sub(self.radius)..(y + self.radius + 1).min(depth.height)
+            {
+                for wx in x.saturating_sub(self.radius)..(x + self.radius + 1).min(depth.width)
+                {
+                    let distance = self.input[wy * depth.width + wx];
+                    if !is_valid(distance)
+                    {
+                        continue;
+                    }
+                    let range_weight = match self.range_weights.get(distance.abs_diff(center) as usize)
+                    {
+                        Some(weight) => *weight,
+                        None => continue,
+                    };
+                    let weight = self.spatial_weights[(wy + self.radius - y) * size + wx + self.radius - x] * range_weight;
+                    sum += weight * distance as f32;
+                    weights += weight;
+                }
+            }
+            // the center pixel always contributes with weight 1
+            depth.data[pixel] = (sum / weights).round() as u16;
+        }
+    }
+}
+
+}
@@ -0,0 +1,110 @@
+// Smooths every pixel over time against the frame to frame noise of the sensor, either
+// with an exponential moving average (mode=ema, alpha=<weight of the new frame>) or with
+// the median of the last frames (mode=median, frames=<count>). Only valid distances go
+// into the average or median, pixels invalid in the current frame stay invalid.
+use crate::depth::{is_valid, DepthFrame};
+use crate::filters::Filter;
+use crate::options::Options;
+
+use std::collections::VecDeque;
+
+const DEFAULT_ALPHA : f32 = 0.5;
+const DEFAULT_FRAMES : usize = 5;
+
+pub enum Temporal
+{
+    Ema { alpha : f32, average : Vec<Option<f32>> },
+    Median { frames : usize, history : VecDeque<Vec<u16>>, samples : Vec<u16> },
+}
+
+impl Temporal
+{
+
+pub fn new(options : &Options) -> Result<Temporal, String>
+{
+    let mut median = false;
+    let mut alpha = DEFAULT_ALPHA;
+    let mut frames = DEFAULT_FRAMES;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "mode" => median = match *value
+            {
+                "ema" => false,
+                "median" => true,
+                _ => return Err(format!("unknown temporal filter mode {}", value)),
+            },
+            "alpha" => alpha = value.parse().map_err(|_| format!("invalid value for alpha {}", value))?,
+            "frames" => frames = value.parse().map_err(|_| format!("invalid value for frames {}", value))?,
+            _ => return Err(format!("temporal filter has no option {}", name)),
+        }
+    }
+    if !(alpha > 0.0 && alpha <= 1.0)
+    {
+        return Err(format!("alpha has to be in (0, 1], not {}", alpha))
+    }
+    if frames == 0
+    {
+        return Err("frames has to be at least 1".to_string())
+    }
+    Ok(if median
+    {
+        Temporal::Median { frames, history : VecDeque::with_capacity(frames), samples : Vec::with_capacity(frames) }
+    }
+    else
+    {
+        Temporal::Ema { alpha, average : Vec::new() }
+    })
+}
+
+}
+
+impl Filter for Temporal
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    match self
+    {
+        Temporal::Ema { alpha, average } =>
+        {
+            if average.len() != depth.data.len()
+            {
+                *average = vec![None; depth.data.len()];
+            }
+            for (distance, average) in depth.data.iter_mut().zip(average.iter_mut())
+            {
+                if is_valid(*distance)
+                {
+                    let smoothed = average.map_or(*distance as f32, |average| average + *alpha * (*distance as f32 - average));
+                    *average = Some(smoothed);
+                    *distance = smoothed.round() as u16;
+                }
+            }
+        },
+        Temporal::Median { frames, history, samples } =>
+        {
+            if history.front().is_some_and(|last| last.len() != depth.data.len())
+            {
+                history.clear();
+            }
+            // the oldest frame's buffer takes the newest frame
+            let mut newest = if history.len() == *frames { history.pop_front().unwrap_or_default() } else { Vec::new() };
+            newest.clone_from(&depth.data);
+            history.push_back(newest);
+            for (pixel, distance) in depth.data.iter_mut().enumerate()
+            {
+                if is_valid(*distance)
+                {
+                    samples.clear();
+                    samples.extend(history.iter().map(|frame| frame[pixel]).filter(|sample| is_valid(*sample)));
+                    samples.sort_unstable();
+                    *distance = samples[samples.len() / 2];
+                }
+            }
+        },
+    }
+}
+
+}
@@ -0,0 +1,215 @@
+use crate::stats::{Stats, STATS};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Frame
+{
+    pub header : [u8; 3],
+    pub size : u16,
+    pub payload : Vec<u8>,
+    pub checksum : u8,
+}
+
+pub const HEADER : [u8; 3] = [0x5a, 0x77, 0xff];
+
+pub fn new(payload: Vec<u8>) -> Frame {
+    let mut frame = Frame {
+        header : HEADER,
+        size : 0,
+        payload,
+        checksum : 0,
+    };
+    frame.update();
+    frame
+}
+
+impl Default for Frame
+{
+    fn default() -> Self
+    {
+        new(Vec::new())
+    }
+}
+
+// xor of the size and payload bytes, as the device computes it
+fn checksum(size : u16, payload : &[u8]) -> u8
+{
+    payload.iter().fold((size as u8) ^ (size >> 8) as u8, |sum, byte| sum ^ byte)
+}
+
+impl Frame
+{
+
+// size and checksum again after the payload changed
+pub fn update(&mut self)
+{
+    self.size = self.payload.len() as u16;
+    self.checksum = checksum(self.size, &self.payload);
+}
+
+// header, size, payload and checksum as the device sends them. A size that isn't the
+// payload's, e.g. for a payload too long for one, is an error
+pub fn as_bytes(&self) -> Result<Vec<u8>, ()>
+{
+    if self.size as usize != self.payload.len()
+    {
+        error!("Failed to serialize frame, size {} for a payload of {} bytes", self.size, self.payload.len());
+        return Err(())
+    }
+    let mut bytes = Vec::with_capacity(self.payload.len() + 6);
+    bytes.extend_from_slice(&self.header);
+    bytes.extend_from_slice(&self.size.to_le_bytes());
+    bytes.extend_from_slice(&self.payload);
+    bytes.push(self.checksum);
+    Ok(bytes)
+}
+
+}
+
+// checks header, size and checksum of a complete frame as sent by the device
+pub fn parse_frame(frame : &[u8]) -> Result<Frame, ()>
+{
+    let mut frame_obj = new(Vec::new());
+    parse_frame_into(frame, &mut frame_obj)?;
+    Ok(frame_obj)
+}
+
+// parse_frame into an existing frame, reusing its payload buffer
+pub fn parse_frame_into(frame : &[u8], frame_obj : &mut Frame) -> Result<(), ()>
+{
+    if frame.len() < 6
+    {
+        Stats::count(&STATS.size_errors);
+        warn!("Failed to deserialize frame, only {} bytes", frame.len()); return Err(())
+    }
+    if frame_obj.header != frame[0..3]
+    {
+        Stats::count(&STATS.header_errors);
+        warn!("Failed to deserialize frame header"); return Err(())
+    }
+    let payload = &frame[5..frame.len()-1];
+    let size = u16::from_le_bytes([frame[3], frame[4]]);
+    if size as usize != payload.len()
+    {
+        Stats::count(&STATS.size_errors);
+        warn!("Failed to deserialize size of frame frame, size is not as expected ( {} )", payload.len()); return Err(())
+    }
+    let checksum = frame[frame.len()-1];
+    if self::checksum(size, payload) != checksum
+    {
+        Stats::count(&STATS.checksum_errors);
+        warn!("Failed to deserialize checksum, expected ( {} )", checksum); return Err(())
+    }
+    frame_obj.payload.clear();
+    frame_obj.payload.extend_from_slice(payload);
+    frame_obj.size = size;
+    frame_obj.checksum = checksum;
+    Ok(())
+}
+
+// Bytes as read from the device, in whatever pieces they come, with frames taken out as
+// they complete. Positions count up from the first byte ever written, wrapping around the
+// buffer. Bytes that aren't the header and size of the frame expected are skipped one by
+// one, so a reader that started mid frame or lost bytes finds the next frame again
+pub struct FrameRing
+{
+    bytes : Vec<u8>,
+    // read from start, written up to end
+    start : usize,
+    end : usize,
+    resyncing : bool,
+}
+
+impl FrameRing
+{
+
+pub fn new(capacity : usize) -> FrameRing
+{
+    FrameRing { bytes : vec![0; capacity], start : 0, end : 0, resyncing : false }
+}
+
+pub fn len(&self) -> usize
+{
+    self.end - self.start
+}
+
+pub fn is_empty(&self) -> bool
+{
+    self.len() == 0
+}
+
+// where the next byte written goes
+pub fn end(&self) -> usize
+{
+    self.end
+}
+
+// the free space, as the slices before and after the wrap, for a vectored read
+pub fn free_mut(&mut self) -> [&mut [u8]; 2]
+{
+    let capacity = self.bytes.len();
+    let free = capacity - self.len();
+    let head = self.end % capacity;
+    let first = free.min(capacity - head);
+    let (before, after) = self.bytes.split_at_mut(head);
+    [&mut after[..first], &mut before[..free - first]]
+}
+
+// count bytes were read into free_mut
+pub fn filled(&mut self, count : usize)
+{
+    self.end += count;
+}
+
+// drops what wasn't taken out, e.g. the rest of a stream the device was stopped in
+pub fn clear(&mut self)
+{
+    self.start = self.end;
+    self.resyncing = false;
+}
+
+fn byte(&self, position : usize) -> u8
+{
+    self.bytes[position % self.bytes.len()]
+}
+
+// the next complete frame with a payload of one of payload_sizes bytes copied into frame,
+// returning where it started, None until more bytes are in
+pub fn next_frame(&mut self, payload_sizes : &[u16], frame : &mut Vec<u8>) -> Option<usize>
+{
+    loop
+    {
+        let header = (0..self.len().min(HEADER.len())).all(|index| self.byte(self.start + index) == HEADER[index]);
+        let size = (self.len() >= HEADER.len() + 2).then(|| u16::from_le_bytes([self.byte(self.start + 3), self.byte(self.start + 4)]));
+        if !header || size.is_some_and(|size| !payload_sizes.contains(&size))
+        {
+            if !self.resyncing
+            {
+                Stats::count(&STATS.resyncs);
+                warn!("Lost frame sync, skipping to the next header");
+                self.resyncing = true;
+            }
+            self.start += 1;
+            continue;
+        }
+        let length = size? as usize + 6;
+        if self.len() < length || length > self.bytes.len()
+        {
+            return None
+        }
+        let capacity = self.bytes.len();
+        let head = self.start % capacity;
+        let first = length.min(capacity - head);
+        frame.clear();
+        frame.extend_from_slice(&self.bytes[head..head + first]);
+        frame.extend_from_slice(&self.bytes[..length - first]);
+        let position = self.start;
+        self.start += length;
+        self.resyncing = false;
+        return Some(position)
+    }
+}
+
+}
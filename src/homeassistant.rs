@@ -0,0 +1,111 @@
+use crate::depth::Summary;
+use crate::device::DeviceInfo;
+
+use serde::Serialize;
+
+// Home Assistant MQTT discovery: a distance sensor for the nearest valid point and a
+// presence binary sensor that is on while something is closer than presence_mm, both
+// read from one json state topic and tied to the publisher's status topic.
+pub const DEFAULT_DISCOVERY_PREFIX : &str = "homeassistant";
+pub const DEFAULT_NODE_ID : &str = "rusty_lidar_viewer";
+pub const DEFAULT_PRESENCE_MM : u16 = 1500;
+
+pub struct HomeAssistant
+{
+    pub discovery_prefix : String,
+    pub node_id : String,
+    pub presence_mm : u16,
+}
+
+#[derive(Serialize)]
+struct Device<'a>
+{
+    identifiers : [&'a str; 1],
+    name : &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sw_version : Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hw_version : Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Entity<'a>
+{
+    name : &'a str,
+    unique_id : String,
+    state_topic : &'a str,
+    value_template : &'a str,
+    availability_topic : &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class : Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_of_measurement : Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_class : Option<&'a str>,
+    device : Device<'a>,
+}
+
+#[derive(Serialize, PartialEq)]
+pub struct State
+{
+    pub nearest_mm : Option<u16>,
+    pub presence : &'static str,
+}
+
+impl HomeAssistant
+{
+
+pub fn state_topic(&self, prefix : &str) -> String
+{
+    format!("{}/state", prefix)
+}
+
+// retained discovery messages as (topic, payload)
+pub fn configs(&self, prefix : &str, info : Option<&DeviceInfo>) -> Result<Vec<(String, Vec<u8>)>, serde_json::Error>
+{
+    let state_topic = self.state_topic(prefix);
+    let availability_topic = format!("{}/status", prefix);
+    let device = || Device
+    {
+        identifiers : [&self.node_id],
+        name : &self.node_id,
+        sw_version : info.map(|info| info.firmware.as_str()),
+        hw_version : info.map(|info| info.hardware.as_str()),
+    };
+    let nearest = Entity
+    {
+        name : "Nearest object",
+        unique_id : format!("{}_nearest", self.node_id),
+        state_topic : &state_topic,
+        value_template : "{{ value_json.nearest_mm }}",
+        availability_topic : &availability_topic,
+        device_class : Some("distance"),
+        unit_of_measurement : Some("mm"),
+        state_class : Some("measurement"),
+        device : device(),
+    };
+    let presence = Entity
+    {
+        name : "Presence",
+        unique_id : format!("{}_presence", self.node_id),
+        state_topic : &state_topic,
+        value_template : "{{ value_json.presence }}",
+        availability_topic : &availability_topic,
+        device_class : Some("presence"),
+        unit_of_measurement : None,
+        state_class : None,
+        device : device(),
+    };
+    Ok(vec![
+        (format!("{}/sensor/{}/nearest/config", self.discovery_prefix, self.node_id), serde_json::to_vec(&nearest)?),
+        (format!("{}/binary_sensor/{}/presence/config", self.discovery_prefix, self.node_id), serde_json::to_vec(&presence)?),
+    ])
+}
+
+pub fn state(&self, summary : &Summary) -> State
+{
+    let present = summary.min_mm.is_some_and(|nearest| nearest < self.presence_mm);
+    State { nearest_mm : summary.min_mm, presence : if present { "ON" } else { "OFF" } }
+}
+
+}
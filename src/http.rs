@@ -0,0 +1,358 @@
+use crate::analysis::Analysis;
+use crate::frame::Frame;
+use crate::colormap;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::latest::{latest, LatestReader, LatestWriter};
+use crate::publish::{Options, Publisher};
+use crate::stats::STATS;
+
+use log::{error, info};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Cursor, Read};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// GET /                   a page showing the mjpeg stream, for the view subcommand
+// GET /status             device info and counters as json
+// GET /stats              link counters as json, see stats::Link
+// GET /frame/latest.json  latest depth frame as json, distances in millimeters
+// GET /frame/latest.png   latest depth frame as a 16 bit grayscale png, values in millimeters
+// GET /frame/stream.mjpeg  colorized depth frames as a multipart jpeg stream
+// GET /metrics             counters and latencies in the prometheus text format
+// GET /analysis            latest result of every analyzer as json, by name
+// GET /analysis/<name>     latest result of one analyzer, e.g. /analysis/people
+const MJPEG_BOUNDARY : &str = "frame";
+const MJPEG_QUALITY : u8 = 90;
+const MJPEG_QUEUE_DEPTH : usize = 2;
+const VIEWER : &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rusty_lidar_viewer</title>
+<style>
+  body { background: #111; color: #ddd; font-family: sans-serif; }
+  img { width: 960px; image-rendering: pixelated; background: #000; }
+</style>
+</head>
+<body>
+<img src="/frame/stream.mjpeg">
+<p id="status"></p>
+<script>
+setInterval(async () => {
+  const status = await (await fetch("/status")).json();
+  const link = await (await fetch("/stats")).json();
+  document.getElementById("status").textContent = `${status.frames} frames, ${link.checksum_errors} checksum errors, ${link.resyncs} resyncs, ${link.short_reads} short reads, ${link.timeouts} timeouts`;
+}, 1000);
+</script>
+</body>
+</html>
+"#;
+
+#[derive(Default)]
+struct Latest
+{
+    sequence : u64,
+    timestamp_ms : u128,
+    depth : DepthFrame,
+}
+
+// the latest frame is handed to the server without a lock, see latest.rs, so serving it
+// never holds up publishing
+struct State
+{
+    device : Option<DeviceInfo>,
+    started : Instant,
+    mjpeg_clients : Vec<SyncSender<Arc<Vec<u8>>>>,
+    analyses : BTreeMap<&'static str, Analysis>,
+}
+
+#[derive(Serialize)]
+struct Status<'a>
+{
+    device : &'a Option<DeviceInfo>,
+    uptime_s : u64,
+    frames : u64,
+    last_frame_ms : Option<u128>,
+}
+
+#[derive(Serialize)]
+struct FrameJson<'a>
+{
+    sequence : u64,
+    timestamp_ms : u128,
+    width : usize,
+    height : usize,
+    depth_mm : &'a [u16],
+}
+
+pub struct HttpPublisher
+{
+    server : Arc<Server>,
+    serving : Option<JoinHandle<()>>,
+    state : Arc<Mutex<State>>,
+    latest : LatestWriter<Option<Latest>>,
+    frames : u64,
+    min_mm : u16,
+    max_mm : u16,
+}
+
+impl HttpPublisher
+{
+
+// options: min_mm=<near end of the colormap>, max_mm=<far end of the colormap>
+pub fn new(address : &str, options : &Options) -> Result<HttpPublisher, String>
+{
+    let mut min_mm = colormap::DEFAULT_MIN_MM;
+    let mut max_mm = colormap::DEFAULT_MAX_MM;
+    for (name, value) in options
+    {
+        let value = value.parse().map_err(|_| format!("invalid value for {} {}", name, value));
+        match *name
+        {
+            "min_mm" => min_mm = value?,
+            "max_mm" => max_mm = value?,
+            _ => return Err(format!("http publisher has no option {}", name)),
+        }
+    }
+    let server = Arc::new(Server::http(address).map_err(|msg| msg.to_string())?);
+    info!("Serving frames on http://{}", address);
+    let state = Arc::new(Mutex::new(State { device : None, started : Instant::now(), mjpeg_clients : Vec::new(), analyses : BTreeMap::new() }));
+    let (latest, reader) = latest();
+    let (served, serving) = (state.clone(), server.clone());
+    let serving = Some(thread::spawn(move || serve(&serving, served, reader)));
+    Ok(HttpPublisher { server, serving, state, latest, frames : 0, min_mm, max_mm })
+}
+
+}
+
+// the server stops once serve let go of it, mjpeg streams end with their senders
+impl Drop for HttpPublisher
+{
+    fn drop(&mut self)
+    {
+        self.state.lock().unwrap().mjpeg_clients.clear();
+        self.server.unblock();
+        if let Some(serving) = self.serving.take()
+        {
+            let _ = serving.join();
+        }
+    }
+}
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+fn serve(server : &Server, state : Arc<Mutex<State>>, mut latest : LatestReader<Option<Latest>>)
+{
+    for request in server.incoming_requests()
+    {
+        if *request.method() != Method::Get
+        {
+            let _ = request.respond(Response::from_string("method not allowed").with_status_code(405));
+            continue;
+        }
+        if request.url() == "/frame/stream.mjpeg"
+        {
+            let (sender, receiver) = sync_channel(MJPEG_QUEUE_DEPTH);
+            state.lock().unwrap().mjpeg_clients.push(sender);
+            thread::spawn(move || stream_mjpeg(request, receiver));
+            continue;
+        }
+        if request.url() == "/metrics"
+        {
+            let _ = request.respond(with_content_type(Response::from_string(STATS.prometheus()), "text/plain; version=0.0.4"));
+            continue;
+        }
+        let response = match request.url()
+        {
+            "/" => with_content_type(Response::from_string(VIEWER), "text/html; charset=utf-8"),
+            "/status" => status(&state.lock().unwrap(), latest.read()),
+            "/stats" => json(&STATS.link()),
+            "/frame/latest.json" => latest_json(latest.read()),
+            "/frame/latest.png" => latest_png(latest.read()),
+            "/analysis" => analyses(&state.lock().unwrap(), None),
+            url if url.starts_with("/analysis/") => analyses(&state.lock().unwrap(), Some(&url["/analysis/".len()..])),
+            _ => Response::from_string("not found").with_status_code(404),
+        };
+        let _ = request.respond(response);
+    }
+}
+
+fn with_content_type(response : HttpResponse, content_type : &str) -> HttpResponse
+{
+    response.with_header(Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap())
+}
+
+fn json<S : Serialize>(value : &S) -> HttpResponse
+{
+    match serde_json::to_vec(value)
+    {
+        Ok(bytes) => with_content_type(Response::from_data(bytes), "application/json"),
+        Err(msg) => Response::from_string(msg.to_string()).with_status_code(500),
+    }
+}
+
+fn no_frame() -> HttpResponse
+{
+    Response::from_string("no frame received yet").with_status_code(503)
+}
+
+fn status(state : &State, latest : &Option<Latest>) -> HttpResponse
+{
+    json(&Status
+    {
+        device : &state.device,
+        uptime_s : state.started.elapsed().as_secs(),
+        frames : latest.as_ref().map_or(0, |latest| latest.sequence + 1),
+        last_frame_ms : latest.as_ref().map(|latest| latest.timestamp_ms),
+    })
+}
+
+// results serialize as {"name": result}, merged into one object for all of them
+fn analyses(state : &State, name : Option<&str>) -> HttpResponse
+{
+    let mut merged = serde_json::Map::new();
+    for (_, analysis) in state.analyses.iter().filter(|(analysis, _)| name.is_none_or(|name| **analysis == name))
+    {
+        if let Ok(serde_json::Value::Object(result)) = serde_json::to_value(analysis)
+        {
+            merged.extend(result);
+        }
+    }
+    match name
+    {
+        None => json(&merged),
+        Some(name) => match merged.remove(name)
+        {
+            Some(result) => json(&result),
+            None => Response::from_string("no such analysis yet").with_status_code(404),
+        },
+    }
+}
+
+fn latest_json(latest : &Option<Latest>) -> HttpResponse
+{
+    match latest
+    {
+        Some(latest) => json(&FrameJson
+        {
+            sequence : latest.sequence,
+            timestamp_ms : latest.timestamp_ms,
+            width : latest.depth.width,
+            height : latest.depth.height,
+            depth_mm : &latest.depth.data,
+        }),
+        None => no_frame(),
+    }
+}
+
+fn latest_png(latest : &Option<Latest>) -> HttpResponse
+{
+    match latest
+    {
+        Some(latest) => match latest.depth.to_png()
+        {
+            Ok(bytes) => with_content_type(Response::from_data(bytes), "image/png"),
+            Err(msg) => Response::from_string(msg.to_string()).with_status_code(500),
+        },
+        None => no_frame(),
+    }
+}
+
+// Blocks on the next jpeg whenever the previous part has been fully read, ends the
+// response when the publisher drops this client
+struct MjpegStream
+{
+    receiver : Receiver<Arc<Vec<u8>>>,
+    part : Vec<u8>,
+    offset : usize,
+}
+
+impl Read for MjpegStream
+{
+
+fn read(&mut self, buffer : &mut [u8]) -> io::Result<usize>
+{
+    if self.offset == self.part.len()
+    {
+        let jpeg = match self.receiver.recv()
+        {
+            Ok(jpeg) => jpeg,
+            Err(_) => return Ok(0),
+        };
+        self.part.clear();
+        self.part.extend_from_slice(format!("--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", MJPEG_BOUNDARY, jpeg.len()).as_bytes());
+        self.part.extend_from_slice(&jpeg);
+        self.part.extend_from_slice(b"\r\n");
+        self.offset = 0;
+    }
+    let count = buffer.len().min(self.part.len() - self.offset);
+    buffer[..count].copy_from_slice(&self.part[self.offset..self.offset + count]);
+    self.offset += count;
+    Ok(count)
+}
+
+}
+
+fn stream_mjpeg(request : Request, receiver : Receiver<Arc<Vec<u8>>>)
+{
+    let content_type = format!("multipart/x-mixed-replace; boundary={}", MJPEG_BOUNDARY);
+    let headers = vec![Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap()];
+    let stream = MjpegStream { receiver, part : Vec::new(), offset : 0 };
+    let _ = request.respond(Response::new(StatusCode(200), headers, stream, None, None));
+}
+
+fn encode_jpeg(depth : &DepthFrame, min_mm : u16, max_mm : u16) -> Result<Vec<u8>, jpeg_encoder::EncodingError>
+{
+    let rgb = colormap::colorize(depth, min_mm, max_mm);
+    let mut bytes = Vec::new();
+    let encoder = jpeg_encoder::Encoder::new(&mut bytes, MJPEG_QUALITY);
+    encoder.encode(&rgb, depth.width as u16, depth.height as u16, jpeg_encoder::ColorType::Rgb)?;
+    Ok(bytes)
+}
+
+impl Publisher for HttpPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    self.state.lock().unwrap().device = Some(info.clone());
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    self.state.lock().unwrap().analyses.insert(analysis.name(), analysis.clone());
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    // the slot holds an older frame, its buffer is reused
+    let latest = self.latest.slot().get_or_insert_with(Latest::default);
+    latest.sequence = self.frames;
+    latest.timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0);
+    latest.depth.width = depth.width;
+    latest.depth.height = depth.height;
+    latest.depth.data.clone_from(&depth.data);
+    self.latest.publish();
+    self.frames += 1;
+
+    if !self.state.lock().unwrap().mjpeg_clients.is_empty()
+    {
+        let jpeg = match encode_jpeg(depth, self.min_mm, self.max_mm)
+        {
+            Ok(jpeg) => Arc::new(jpeg),
+            Err(msg) => { error!("Failed to encode jpeg {}", msg); return Err(()) }
+        };
+        // a client that is still busy with the previous jpegs just skips this one
+        self.state.lock().unwrap().mjpeg_clients.retain(|client| !matches!(client.try_send(jpeg.clone()), Err(TrySendError::Disconnected(_))));
+    }
+    Ok(())
+}
+
+}
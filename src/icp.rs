@@ -0,0 +1,198 @@
+// Point to plane ICP between consecutive clouds. The previous cloud has to be organized,
+// for its normals, the current one is voxel downsampled. Every iteration pairs each
+// current point with its nearest previous point and solves the linearized point to plane
+// problem for a small rotation and translation.
+use crate::cloud::{voxel_downsample, PointCloud};
+
+use serde::Serialize;
+
+pub const DEFAULT_LEAF : f32 = 0.05;
+pub const DEFAULT_MAX_DISTANCE : f32 = 0.2;
+pub const DEFAULT_ITERATIONS : usize = 20;
+
+const MIN_PAIRS : usize = 6;
+
+// maps points p to rotation * p + translation, rotation is row major
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Transform
+{
+    pub rotation : [[f32; 3]; 3],
+    pub translation : [f32; 3],
+}
+
+impl Default for Transform
+{
+    fn default() -> Self
+    {
+        Transform { rotation : [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], translation : [0.0; 3] }
+    }
+}
+
+impl Transform
+{
+
+pub fn apply(&self, point : [f32; 3]) -> [f32; 3]
+{
+    let r = &self.rotation;
+    [0, 1, 2].map(|row| r[row][0] * point[0] + r[row][1] * point[1] + r[row][2] * point[2] + self.translation[row])
+}
+
+pub fn from_quaternion(translation : [f32; 3], [x, y, z, w] : [f32; 4]) -> Transform
+{
+    let length = (x * x + y * y + z * z + w * w).sqrt();
+    let (x, y, z, w) = (x / length, y / length, z / length, w / length);
+    let rotation =
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+        [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+        [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+    ];
+    Transform { rotation, translation }
+}
+
+// self after other
+pub fn then(&self, other : &Transform) -> Transform
+{
+    let mut rotation = [[0.0; 3]; 3];
+    for (row, rotation) in rotation.iter_mut().enumerate()
+    {
+        for (column, value) in rotation.iter_mut().enumerate()
+        {
+            *value = (0..3).map(|k| self.rotation[row][k] * other.rotation[k][column]).sum();
+        }
+    }
+    Transform { rotation, translation : self.apply(other.translation) }
+}
+
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Alignment
+{
+    // takes points of the current cloud into the previous one
+    pub transform : Transform,
+    // root mean square point to plane distance of the final pairs, meters
+    pub rmse : f32,
+    pub pairs : usize,
+}
+
+// None when the clouds don't overlap enough to pair up points
+pub fn align(previous : &PointCloud, current : &PointCloud, leaf : f32, max_distance : f32, iterations : usize) -> Option<Alignment>
+{
+    let source = voxel_downsample(current, leaf);
+    let tree = previous.kd_tree();
+    let normals = previous.normals();
+
+    let mut transform = Transform::default();
+    let mut found = Vec::with_capacity(1);
+    let mut rmse = 0.0;
+    let mut pairs = 0;
+    for _ in 0..iterations
+    {
+        let mut a = [[0.0f64; 6]; 6];
+        let mut b = [0.0f64; 6];
+        let mut squared_error = 0.0;
+        pairs = 0;
+        for point in source.points.iter()
+        {
+            let p = transform.apply(*point);
+            tree.nearest(p, 1, &mut found);
+            let index = match found.first()
+            {
+                Some((distance, index)) if *distance <= max_distance * max_distance => *index,
+                _ => continue,
+            };
+            let (q, n) = (previous.points[index], normals[index]);
+            if n == [0.0; 3]
+            {
+                continue;
+            }
+            let residual = ((p[0] - q[0]) * n[0] + (p[1] - q[1]) * n[1] + (p[2] - q[2]) * n[2]) as f64;
+            let cross = [p[1] * n[2] - p[2] * n[1], p[2] * n[0] - p[0] * n[2], p[0] * n[1] - p[1] * n[0]];
+            let jacobian = [cross[0], cross[1], cross[2], n[0], n[1], n[2]].map(|value| value as f64);
+            for row in 0..6
+            {
+                for column in 0..6
+                {
+                    a[row][column] += jacobian[row] * jacobian[column];
+                }
+                b[row] -= jacobian[row] * residual;
+            }
+            squared_error += residual * residual;
+            pairs += 1;
+        }
+        if pairs < MIN_PAIRS
+        {
+            return None
+        }
+        rmse = (squared_error / pairs as f64).sqrt() as f32;
+        let step = solve(a, b)?;
+        transform = small_motion(step).then(&transform);
+        if step.iter().map(|value| value * value).sum::<f64>() < 1e-12
+        {
+            break;
+        }
+    }
+    Some(Alignment { transform, rmse, pairs })
+}
+
+// rotation by small angles about x, y and z, then translation
+fn small_motion(step : [f64; 6]) -> Transform
+{
+    let angle = (step[0] * step[0] + step[1] * step[1] + step[2] * step[2]).sqrt();
+    let mut rotation = Transform::default().rotation;
+    if angle > 0.0
+    {
+        // Rodrigues
+        let axis = [step[0] / angle, step[1] / angle, step[2] / angle];
+        let (sin, cos) = angle.sin_cos();
+        for (row, rotation) in rotation.iter_mut().enumerate()
+        {
+            for (column, value) in rotation.iter_mut().enumerate()
+            {
+                let identity = if row == column { 1.0 } else { 0.0 };
+                let skew = match (row, column)
+                {
+                    (0, 1) => -axis[2], (0, 2) => axis[1],
+                    (1, 0) => axis[2], (1, 2) => -axis[0],
+                    (2, 0) => -axis[1], (2, 1) => axis[0],
+                    _ => 0.0,
+                };
+                *value = (cos * identity + sin * skew + (1.0 - cos) * axis[row] * axis[column]) as f32;
+            }
+        }
+    }
+    Transform { rotation, translation : [step[3] as f32, step[4] as f32, step[5] as f32] }
+}
+
+// Gaussian elimination with partial pivoting, None for a singular system
+fn solve(mut a : [[f64; 6]; 6], mut b : [f64; 6]) -> Option<[f64; 6]>
+{
+    for column in 0..6
+    {
+        let pivot = (column..6).max_by(|x, y| a[*x][column].abs().total_cmp(&a[*y][column].abs()))?;
+        if a[pivot][column].abs() < 1e-12
+        {
+            return None
+        }
+        a.swap(column, pivot);
+        b.swap(column, pivot);
+        for row in column + 1..6
+        {
+            let factor = a[row][column] / a[column][column];
+            let pivot_row = a[column];
+            for (value, pivot) in a[row][column..].iter_mut().zip(&pivot_row[column..])
+            {
+                *value -= factor * pivot;
+            }
+            b[row] -= factor * b[column];
+        }
+    }
+    let mut x = [0.0; 6];
+    for row in (0..6).rev()
+    {
+        let sum : f64 = (row + 1..6).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
@@ -0,0 +1,128 @@
+// k-d tree over the points of a cloud for nearest neighbour queries, see
+// PointCloud::kd_tree. The tree is an array of point indices, each subarray split at its
+// median on the axis of its depth. Queries fill a vector of indices into the points that
+// is passed in, so repeated queries don't allocate.
+pub struct KdTree<'a>
+{
+    points : &'a [[f32; 3]],
+    nodes : Vec<usize>,
+}
+
+impl<'a> KdTree<'a>
+{
+
+pub fn new(points : &'a [[f32; 3]]) -> KdTree<'a>
+{
+    let mut nodes : Vec<usize> = (0..points.len()).collect();
+    build(points, &mut nodes, 0);
+    KdTree { points, nodes }
+}
+
+pub fn points(&self) -> &'a [[f32; 3]]
+{
+    self.points
+}
+
+pub fn len(&self) -> usize
+{
+    self.points.len()
+}
+
+pub fn is_empty(&self) -> bool
+{
+    self.points.is_empty()
+}
+
+// the k points nearest to query as (squared distance, index), nearest first
+pub fn nearest(&self, query : [f32; 3], k : usize, found : &mut Vec<(f32, usize)>)
+{
+    found.clear();
+    if k > 0
+    {
+        self.search(&self.nodes, 0, query, k, found);
+    }
+}
+
+// indices of all points within radius of query, in no particular order
+pub fn within(&self, query : [f32; 3], radius : f32, found : &mut Vec<usize>)
+{
+    found.clear();
+    self.search_within(&self.nodes, 0, query, radius * radius, found);
+}
+
+fn search_within(&self, nodes : &[usize], depth : usize, query : [f32; 3], squared_radius : f32, found : &mut Vec<usize>)
+{
+    if nodes.is_empty()
+    {
+        return
+    }
+    let axis = depth % 3;
+    let middle = nodes.len() / 2;
+    let index = nodes[middle];
+    let point = self.points[index];
+    if squared_distance(point, query) <= squared_radius
+    {
+        found.push(index);
+    }
+    let offset = query[axis] - point[axis];
+    if offset <= 0.0 || offset * offset <= squared_radius
+    {
+        self.search_within(&nodes[..middle], depth + 1, query, squared_radius, found);
+    }
+    if offset >= 0.0 || offset * offset <= squared_radius
+    {
+        self.search_within(&nodes[middle + 1..], depth + 1, query, squared_radius, found);
+    }
+}
+
+fn search(&self, nodes : &[usize], depth : usize, query : [f32; 3], k : usize, found : &mut Vec<(f32, usize)>)
+{
+    if nodes.is_empty()
+    {
+        return
+    }
+    let axis = depth % 3;
+    let middle = nodes.len() / 2;
+    let index = nodes[middle];
+    let point = self.points[index];
+
+    let distance = squared_distance(point, query);
+    if found.len() < k || distance < found[found.len() - 1].0
+    {
+        if found.len() == k
+        {
+            found.pop();
+        }
+        let at = found.partition_point(|(found, _)| *found <= distance);
+        found.insert(at, (distance, index));
+    }
+
+    let offset = query[axis] - point[axis];
+    let (near, far) = if offset < 0.0 { (&nodes[..middle], &nodes[middle + 1..]) } else { (&nodes[middle + 1..], &nodes[..middle]) };
+    self.search(near, depth + 1, query, k, found);
+    if found.len() < k || offset * offset < found[found.len() - 1].0
+    {
+        self.search(far, depth + 1, query, k, found);
+    }
+}
+
+}
+
+fn build(points : &[[f32; 3]], nodes : &mut [usize], depth : usize)
+{
+    if nodes.len() <= 1
+    {
+        return
+    }
+    let axis = depth % 3;
+    let middle = nodes.len() / 2;
+    nodes.select_nth_unstable_by(middle, |a, b| points[*a][axis].total_cmp(&points[*b][axis]));
+    let (left, right) = nodes.split_at_mut(middle);
+    build(points, left, depth + 1);
+    build(points, &mut right[1..], depth + 1);
+}
+
+pub fn squared_distance(a : [f32; 3], b : [f32; 3]) -> f32
+{
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
@@ -0,0 +1,73 @@
+// Triple buffer handing the newest value from one writer to one reader without either of
+// them locking or waiting. Of the three slots the writer owns one, the reader another and
+// the third sits in the middle; publishing swaps the writer's slot with the middle one and
+// marks it fresh, reading takes the middle slot in exchange for the reader's if it is
+// fresh. The reader always sees the newest complete value, values it was too slow for are
+// overwritten.
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// set in middle while the slot there hasn't been read
+const FRESH : usize = 4;
+
+struct Slots<T>
+{
+    slots : [UnsafeCell<T>; 3],
+    middle : AtomicUsize,
+}
+
+// SAFETY: every slot is only ever reachable from one of writer, reader or middle, and
+// moving it between them goes through the atomic swap of middle
+unsafe impl<T : Send> Sync for Slots<T> {}
+
+pub struct LatestWriter<T>
+{
+    slots : Arc<Slots<T>>,
+    back : usize,
+}
+
+pub struct LatestReader<T>
+{
+    slots : Arc<Slots<T>>,
+    front : usize,
+}
+
+pub fn latest<T : Default>() -> (LatestWriter<T>, LatestReader<T>)
+{
+    let slots = Arc::new(Slots { slots : [(); 3].map(|_| UnsafeCell::new(T::default())), middle : AtomicUsize::new(1) });
+    (LatestWriter { slots : slots.clone(), back : 0 }, LatestReader { slots, front : 2 })
+}
+
+impl<T> LatestWriter<T>
+{
+
+// the slot to fill before publish, it holds whatever was published some time before
+pub fn slot(&mut self) -> &mut T
+{
+    // SAFETY: back is the writer's own slot
+    unsafe { &mut *self.slots.slots[self.back].get() }
+}
+
+pub fn publish(&mut self)
+{
+    self.back = self.slots.middle.swap(self.back | FRESH, Ordering::AcqRel) & !FRESH;
+}
+
+}
+
+impl<T> LatestReader<T>
+{
+
+// the newest published value, or the one returned last if nothing was published since
+pub fn read(&mut self) -> &T
+{
+    if self.slots.middle.load(Ordering::Relaxed) & FRESH != 0
+    {
+        self.front = self.slots.middle.swap(self.front, Ordering::AcqRel) & !FRESH;
+    }
+    // SAFETY: front is the reader's own slot
+    unsafe { &*self.slots.slots[self.front].get() }
+}
+
+}
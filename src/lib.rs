@@ -0,0 +1,106 @@
+// errors are logged where they happen, see logging.rs, and passed up as Err(())
+#![allow(clippy::result_unit_err)]
+
+pub mod analysis;
+pub mod cloud;
+pub mod colormap;
+pub mod depth;
+pub mod filters;
+pub mod frame;
+pub mod icp;
+pub mod kdtree;
+pub mod latest;
+pub mod mesh;
+pub mod octree;
+pub mod options;
+pub mod planes;
+pub mod pool;
+pub mod rvl;
+pub mod stats;
+pub mod unpack;
+
+// everything touching the serial port or the network is native only, the parser and
+// colormap above also build for wasm32
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bridge;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod delta;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod device;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod doctor;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod export;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod homeassistant;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logging;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mqtt;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod occupancy;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod osc;
+#[cfg(unix)]
+pub mod plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod proto;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod publish;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reader;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod recording;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reload;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scan;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod schema;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod script;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod shm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod signals;
+#[cfg(unix)]
+pub mod simulator;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sinks;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshot;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod systemd;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tcp;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod template;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transport;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod udp;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod volume;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod webhook;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ws;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
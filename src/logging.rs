@@ -0,0 +1,133 @@
+// The library reports through the log crate: errors that end what they happen in at
+// error, protocol errors and frames dropped at warn, what is opened and started at info,
+// every frame at trace. This logger writes what is at or above the level given to init to
+// stderr, so what a subcommand prints on stdout stays apart from it. As text a line is
+// seconds since starting, level, module, message and the fields logged with it as
+// key=value; as json it is an object with timestamp, level, target, event and fields, one
+// a line, for journald or ELK. Lines can be sent elsewhere instead with redirect, e.g. to
+// the screen of the tui subcommand.
+use crate::clock::Utc;
+use crate::progress;
+
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Map};
+
+use std::io::{self, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+pub const DEFAULT_LEVEL : LevelFilter = LevelFilter::Info;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format
+{
+    Text,
+    Json,
+}
+
+impl Format
+{
+
+pub fn parse(value : &str) -> Result<Format, String>
+{
+    match value
+    {
+        "text" => Ok(Format::Text),
+        "json" => Ok(Format::Json),
+        _ => Err(format!("invalid log format {}, expected text or json", value)),
+    }
+}
+
+}
+
+struct Logger
+{
+    started : Instant,
+    format : Format,
+}
+
+static LOGGER : OnceLock<Logger> = OnceLock::new();
+// takes every line written, see redirect
+pub type Redirect = Box<dyn Fn(&str) + Send>;
+
+static REDIRECT : Mutex<Option<Redirect>> = Mutex::new(None);
+
+// the fields of a record, numbers and booleans as they are in json
+#[derive(Default)]
+struct Fields
+{
+    json : Map<String, serde_json::Value>,
+    text : String,
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields
+{
+    fn visit_pair(&mut self, key : Key<'kvs>, value : Value<'kvs>) -> Result<(), kv::Error>
+    {
+        self.text += &format!(" {}={}", key, value);
+        let value = if let Some(value) = value.to_bool() { json!(value) }
+            else if let Some(value) = value.to_u64() { json!(value) }
+            else if let Some(value) = value.to_i64() { json!(value) }
+            else if let Some(value) = value.to_f64() { json!(value) }
+            else { json!(value.to_string()) };
+        self.json.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+impl Log for Logger
+{
+    fn enabled(&self, metadata : &Metadata) -> bool
+    {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record : &Record)
+    {
+        if !self.enabled(record.metadata())
+        {
+            return
+        }
+        let target = record.target().strip_prefix("rusty_lidar_viewer::").unwrap_or(record.target());
+        let mut fields = Fields::default();
+        let _ = record.key_values().visit(&mut fields);
+        let line = match self.format
+        {
+            Format::Text => format!("{:9.3} {:<5} {}: {}{}", self.started.elapsed().as_secs_f64(), record.level(), target, record.args(), fields.text),
+            Format::Json => json!({
+                "timestamp" : Utc::now().rfc3339(),
+                "level" : record.level().as_str().to_lowercase(),
+                "target" : target,
+                "event" : record.args().to_string(),
+                "fields" : fields.json,
+            }).to_string(),
+        };
+        if let Some(to) = REDIRECT.lock().unwrap().as_ref()
+        {
+            return to(&line)
+        }
+        progress::clear();
+        let _ = writeln!(io::stderr().lock(), "{}", line);
+    }
+
+    fn flush(&self)
+    {
+        let _ = io::stderr().flush();
+    }
+}
+
+// fails when a logger was set before, e.g. by a program using the library
+pub fn init(level : LevelFilter, format : Format) -> Result<(), String>
+{
+    let logger = LOGGER.get_or_init(|| Logger { started : Instant::now(), format });
+    log::set_logger(logger).map_err(|msg| msg.to_string())?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+// every line to to instead of stderr, None for stderr again
+pub fn redirect(to : Option<Redirect>)
+{
+    *REDIRECT.lock().unwrap() = to;
+}
@@ -1,220 +1,1145 @@
-use serialport;
-use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits, TTYPort};
-
-use serde::{Deserialize, Serialize};
+use clap::{Arg, ArgAction, ArgMatches, Command as ClapCommand};
+use log::{debug, error, info, trace, warn, LevelFilter};
+use serialport::{SerialPort, SerialPortInfo, SerialPortType, TTYPort};
 
+use std::mem;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{thread};
-use std::io::{Read, Write};
-use std::io::ErrorKind::TimedOut;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Frame
+use rusty_lidar_viewer::analysis;
+use rusty_lidar_viewer::analysis::{Analysis, Analyzer};
+use rusty_lidar_viewer::config::{self, Config};
+use rusty_lidar_viewer::depth::DepthFrame;
+use rusty_lidar_viewer::device;
+use rusty_lidar_viewer::device::{DeviceInfo, Mode};
+use rusty_lidar_viewer::doctor;
+use rusty_lidar_viewer::filters;
+use rusty_lidar_viewer::filters::Filter;
+use rusty_lidar_viewer::filters::range::Range;
+use rusty_lidar_viewer::frame::{new, parse_frame_into, Frame};
+use rusty_lidar_viewer::logging;
+use rusty_lidar_viewer::options;
+use rusty_lidar_viewer::pool::{Pooled, BYTES, DEPTH_FRAMES, FRAMES};
+use rusty_lidar_viewer::progress::Progress;
+use rusty_lidar_viewer::publish;
+use rusty_lidar_viewer::publish::Output;
+use rusty_lidar_viewer::reader::{Command, SerialReader};
+use rusty_lidar_viewer::recording::Playback;
+use rusty_lidar_viewer::reload::Watcher;
+use rusty_lidar_viewer::remote::{Received, RemoteSource};
+use rusty_lidar_viewer::schema;
+use rusty_lidar_viewer::script::Script;
+use rusty_lidar_viewer::signals;
+use rusty_lidar_viewer::sinks::{Published, Sinks, MIN_FPS};
+use rusty_lidar_viewer::snapshot::{self, SnapshotPublisher};
+use rusty_lidar_viewer::stats::{Link, Milestone, Stage, Stats, STATS};
+use rusty_lidar_viewer::systemd::Notifier;
+use rusty_lidar_viewer::template;
+use rusty_lidar_viewer::tui::{Control, Screen};
+use rusty_lidar_viewer::volume::VolumePublisher;
+
+fn value(name : &'static str, value_name : &'static str, help : &'static str) -> Arg
+{
+    Arg::new(name).long(name).value_name(value_name).help(help)
+}
+
+fn many(name : &'static str, value_name : &'static str, help : &'static str) -> Arg
+{
+    value(name, value_name, help).action(ArgAction::Append)
+}
+
+// for the subcommands reading from the device
+fn device_args() -> [Arg; 6]
+{
+    [
+        value("device", "PORT[?OPTIONS]", "serial port with options, see README").env("RLV_DEVICE"),
+        value("port", "PATH", "serial port, /dev/ttyUSB0 by default").env("RLV_PORT"),
+        value("baud", "BAUD", "baud rate, 3000000 by default").env("RLV_BAUD").value_parser(clap::value_parser!(u32).range(1..)),
+        value("mode", "MODE", "what the device streams, 2d, 3d or dual, 3d by default").env("RLV_MODE").value_parser(Mode::parse),
+        value("timeout", "MS", "how long the device may stay quiet before that is reported").env("RLV_TIMEOUT").value_parser(clap::value_parser!(u64).range(1..)),
+        value("watchdog", "MS", "restart the stream when no valid frame came for this long").env("RLV_WATCHDOG").value_parser(clap::value_parser!(u64).range(1..)),
+    ]
+}
+
+fn config_arg() -> Arg
+{
+    value("config", "FILE", "filters, analyzers and device from a TOML file").env("RLV_CONFIG")
+}
+
+// for the subcommands reading the device for as long as it runs
+fn daemon_arg() -> Arg
+{
+    Arg::new("daemon").long("daemon").help("run as a systemd service: no printing frames, READY and WATCHDOG notifications, exit status 1 when reading fails")
+    .action(ArgAction::SetTrue)
+}
+
+// for the subcommands handing frames to the pipeline
+fn pipeline_args() -> [Arg; 13]
+{
+    [
+        many("publish", "URL", "publish frames, e.g. udp://host:port"),
+        many("bridge", "HOST:PORT", "send UDP packets for game engines"),
+        many("filter", "FILTER[?OPTIONS]", "filter frames, e.g. temporal?mode=ema"),
+        many("analyze", "ANALYZER[?OPTIONS]", "analyze frames, e.g. obstacles"),
+        many("script", "COMMAND", "run frames through a script, e.g. \"python3 alerts.py\""),
+        config_arg(),
+        value("decimate", "N", "every Nth frame to the publishers").env("RLV_DECIMATE").value_parser(clap::value_parser!(u64).range(1..)),
+        value("min-range", "MM", "drop distances below").value_parser(clap::value_parser!(u16)),
+        value("max-range", "MM", "drop distances above").value_parser(clap::value_parser!(u16)),
+        Arg::new("latency").long("latency").help("print where the time between reading and publishing goes").action(ArgAction::SetTrue),
+        value("stats-interval", "DURATION", "log the link counters this often, e.g. 10s").env("RLV_STATS_INTERVAL").value_parser(duration),
+        value("frames", "N", "stop after this many frames").value_parser(clap::value_parser!(u64).range(1..)),
+        value("duration", "DURATION", "stop after this long, e.g. 30s, 500ms or 5m").value_parser(duration),
+    ]
+}
+
+// every workflow is a subcommand with its own options, without one frames are streamed
+// as with stream
+fn cli() -> ClapCommand
+{
+    let averaged = || value("frames", "N", "frames to average, 50 by default").value_parser(clap::value_parser!(u64).range(1..));
+    let output = || value("output", "PATH", "where to write the result");
+    let limits = |arg : &Arg| arg.get_id() != "frames" && arg.get_id() != "duration";
+    let tool = |command : ClapCommand| command.args(device_args()).args(pipeline_args().into_iter().filter(limits));
+    ClapCommand::new("rusty_lidar_viewer")
+    .about("Reads depth frames off the lidar and hands them to filters, analyzers and publishers")
+    .args_conflicts_with_subcommands(true)
+    .arg(value("log-level", "LEVEL", "off, error, warn, info, debug for analyses or trace for every frame, info by default")
+        .env("RLV_LOG_LEVEL").value_parser(|value : &str| value.parse::<LevelFilter>().map_err(|_| format!("invalid log level {}", value))).global(true))
+    .arg(value("log-format", "FORMAT", "text or json lines, text by default").env("RLV_LOG_FORMAT").value_parser(logging::Format::parse).global(true))
+    .arg(Arg::new("verbose").short('v').long("verbose").help("log a level more, -vv two").action(ArgAction::Count).global(true))
+    .arg(Arg::new("quiet").short('q').long("quiet").help("log a level less, warnings only and no progress, -qq two").action(ArgAction::Count).global(true))
+    .args(device_args())
+    .args(pipeline_args())
+    .arg(daemon_arg())
+    .subcommand(ClapCommand::new("info").about("print what the device says about itself")
+        .args(device_args())
+        .arg(config_arg()))
+    .subcommand(ClapCommand::new("doctor").about("check the port, its permissions, the baud rate and the frames, step by step")
+        .args(device_args())
+        .arg(config_arg()))
+    .subcommand(ClapCommand::new("config").about("check a --config file, or print what it may have").subcommand_required(true)
+        .subcommand(ClapCommand::new("validate").about("report everything wrong with the file, with its line and column")
+            .arg(Arg::new("path").value_name("FILE").required(true)))
+        .subcommand(ClapCommand::new("schema").about("print the JSON Schema of the file, for editors")))
+    .subcommand(ClapCommand::new("list-ports").about("list the serial ports, marking the ones the lidar answers on")
+        .arg(Arg::new("paths").value_name("PATH").num_args(1..).help("ports to probe besides the ones found, e.g. udev symlinks"))
+        .args(device_args().into_iter().filter(|arg| arg.get_id() == "baud"))
+        .arg(Arg::new("no-probe").long("no-probe").help("only list the ports, without sending them anything").action(ArgAction::SetTrue)))
+    .subcommand(ClapCommand::new("stream").about("print frames from the device and hand them to the publishers")
+        .args(device_args())
+        .args(pipeline_args())
+        .arg(daemon_arg()))
+    .subcommand(ClapCommand::new("record").about("record frames from the device, see play")
+        .arg(Arg::new("recording").value_name("PATH").required(true))
+        .args(device_args())
+        .args(pipeline_args())
+        .arg(daemon_arg()))
+    .subcommand(ClapCommand::new("play").about("play a recording at a fixed rate")
+        .arg(Arg::new("recording").value_name("PATH").required(true))
+        .arg(value("start", "FRAME", "first frame to play").value_parser(clap::value_parser!(usize)))
+        .arg(value("fps", "FPS", "frames a second, 30 by default").value_parser(fps))
+        .args(pipeline_args()))
+    .subcommand(ClapCommand::new("export").about("write every frame of a recording to a file of its own")
+        .arg(Arg::new("recording").value_name("PATH").required(true))
+        .arg(value("output", "DIRECTORY", "where to write the frames").required(true))
+        .arg(value("format", "FORMAT", "png, ply or csv, png by default").value_parser(["png", "ply", "csv"]))
+        .args(pipeline_args()))
+    .subcommand(ClapCommand::new("view").about("show frames from the device in a browser")
+        .arg(value("listen", "ADDRESS", "where to serve the page, 127.0.0.1:8080 by default").env("RLV_LISTEN"))
+        .args(device_args())
+        .args(pipeline_args())
+        .arg(daemon_arg()))
+    .subcommand(ClapCommand::new("tui").about("show stats, zones and alerts in the terminal, with keys to record and change the range")
+        .arg(value("output", "PATH", "where recordings go, recording_{date}_{time}_{seq}.rlv by default"))
+        .args(device_args())
+        .args(pipeline_args()))
+    .subcommand(ClapCommand::new("connect").about("read frames from the tcp:// publisher of another instance")
+        .arg(Arg::new("url").value_name("URL").required(true))
+        .args(pipeline_args()))
+    .subcommand(ClapCommand::new("bench").about("run a recording through the pipeline as fast as it goes")
+        .arg(Arg::new("recording").value_name("PATH").required(true))
+        .args(pipeline_args().into_iter().filter(limits)))
+    .subcommand(tool(ClapCommand::new("snapshot").about("average frames into a snapshot of the scene"))
+        .arg(averaged())
+        .arg(output()))
+    .subcommand(tool(ClapCommand::new("volume").about("measure the volume of what is in front of an empty scene"))
+        .arg(value("reference", "PATH", "snapshot json of the empty scene").required(true))
+        .arg(value("roi", "X0,Y0,X1,Y1", "region to measure in").value_parser(|value : &str| options::list::<usize, 4>(value).ok_or("expected x0,y0,x1,y1")))
+        .arg(averaged())
+        .arg(output()))
+}
+
+fn positive(value : &str) -> Result<f64, String>
+{
+    value.parse().ok().filter(|value : &f64| *value > 0.0).ok_or(format!("expected a positive number, got {}", value))
+}
+
+fn fps(value : &str) -> Result<f64, String>
 {
-    header : [u8; 3],
-    size : u16,
-    payload : Vec<u8>,
-    checksum : u8,
+    value.parse().ok().filter(|value : &f64| *value >= MIN_FPS).ok_or(format!("expected at least {} frames a second, got {}", MIN_FPS, value))
 }
 
-fn new<'a>(payload: Vec<u8>) -> Frame {
-    let mut frame = Frame {
-        header : [0x5a, 0x77, 0xff],
-        size : payload.len() as u16,
-        payload : payload.into(),
-        checksum : 0,
+// a number with ms, s, m or h after it, seconds without
+fn duration(value : &str) -> Result<Duration, String>
+{
+    let (number, unit) = match value.find(|c : char| c.is_ascii_alphabetic())
+    {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let seconds = match unit
+    {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("invalid unit {}, expected ms, s, m or h", unit)),
     };
-    frame.checksum = frame.calculate_checksum().unwrap();
-    frame
+    positive(number).map(|number| Duration::from_secs_f64(number * seconds))
 }
 
-impl Frame
+// what args of a subcommand doesn't take is left as it is
+fn configure(args : &ArgMatches, pipeline : &mut Pipeline, device : &mut device::Settings) -> Result<(), String>
 {
+    let one = |name : &str| args.try_get_one::<String>(name).ok().flatten();
+    let strings = |name : &str| args.try_get_many::<String>(name).ok().flatten().into_iter().flatten();
+
+    // the config's device first, --device over it, then the single options over both
+    if let Some(path) = one("config")
+    {
+        let watcher = Watcher::new(path);
+        let config = Config::load(path).map_err(|msg| format!("Error loading config {}!, {}", path, msg))?;
+        let (filters, analyzers, publishers, settings) = Ok((config.filters()?, config.analyzers()?, config.publishers()?, config.device()?))
+            .map_err(|msg : String| format!("Error loading config {}!, {}", path, msg))?;
+        pipeline.reload = Some(Reload
+        {
+            watcher,
+            filters : 0..filters.len(),
+            analyzers : analyzers.len(),
+            publishers : config.publish.clone(),
+            device : config.device.clone(),
+            decimate : None,
+        });
+        pipeline.filters.extend(filters);
+        pipeline.analyzers.extend(analyzers);
+        pipeline.publishers.extend(publishers);
+        *device = settings.unwrap_or(device.clone());
+    }
+    if let Some(spec) = one("device")
+    {
+        *device = device::Settings::from_spec(spec, device).map_err(|msg| format!("Error in device {}!, {}", spec, msg))?;
+    }
+    if let Some(path) = one("port")
+    {
+        device.path = path.clone();
+    }
+    if let Ok(Some(baud_rate)) = args.try_get_one::<u32>("baud")
+    {
+        device.baud_rate = *baud_rate;
+    }
+    if let Ok(Some(mode)) = args.try_get_one::<Mode>("mode")
+    {
+        device.mode = *mode;
+    }
+    if let Ok(Some(timeout)) = args.try_get_one::<u64>("timeout")
+    {
+        device.timeout = Duration::from_millis(*timeout);
+    }
+    if let Ok(Some(watchdog)) = args.try_get_one::<u64>("watchdog")
+    {
+        device.watchdog = Some(Duration::from_millis(*watchdog));
+    }
+
+    for url in strings("publish")
+    {
+        pipeline.publishers.push(publish::open(url).map_err(|msg| format!("Error opening publisher {}!, {}", url, msg))?);
+    }
+    for target in strings("bridge")
+    {
+        pipeline.publishers.push(publish::open(&format!("bridge://{}", target)).map_err(|msg| format!("Error opening bridge {}!, {}", target, msg))?);
+    }
+    for spec in strings("filter")
+    {
+        pipeline.filters.push(filters::open(spec).map_err(|msg| format!("Error setting up filter {}!, {}", spec, msg))?);
+    }
+    for command in strings("script")
+    {
+        pipeline.scripts.push(Script::spawn(command).map_err(|msg| format!("Error starting script {}!, {}", command, msg))?);
+    }
+    for spec in strings("analyze")
+    {
+        pipeline.analyzers.push(analysis::open(spec).map_err(|msg| format!("Error setting up analyzer {}!, {}", spec, msg))?);
+    }
+    if args.try_get_one::<bool>("latency").ok().flatten() == Some(&true)
+    {
+        STATS.start_tracing();
+        pipeline.quiet = true;
+    }
+    pipeline.limit = args.try_get_one::<u64>("frames").ok().flatten().copied();
+    pipeline.duration = args.try_get_one::<Duration>("duration").ok().flatten().copied();
+    pipeline.stats_interval = args.try_get_one::<Duration>("stats-interval").ok().flatten().copied();
+    if let Ok(Some(true)) = args.try_get_one::<bool>("daemon")
+    {
+        pipeline.daemon = true;
+        pipeline.quiet = true;
+        pipeline.notifier = Notifier::from_env();
+    }
+
+    // publishers with their own decimate keep it, the ones added for subcommands see every frame
+    let decimate = args.try_get_one::<u64>("decimate").ok().flatten().copied();
+    for output in pipeline.publishers.iter_mut()
+    {
+        output.throttle.decimate = output.throttle.decimate.or(decimate);
+    }
+    if let Some(reload) = pipeline.reload.as_mut()
+    {
+        reload.decimate = decimate;
+    }
 
-fn calculate_checksum(&self) -> Result<u8, ()>
+    let range = Range
+    {
+        min_mm : args.try_get_one::<u16>("min-range").ok().flatten().copied().unwrap_or(0),
+        max_mm : args.try_get_one::<u16>("max-range").ok().flatten().copied().unwrap_or(u16::MAX),
+    };
+    if range.min_mm > 0 || range.max_mm < u16::MAX
+    {
+        pipeline.set_range(range);
+    }
+    Ok(())
+}
+
+const DEFAULT_VIEW_ADDRESS : &str = "127.0.0.1:8080";
+const DEFAULT_TUI_RECORDING : &str = "recording_{date}_{time}_{seq}.rlv";
+
+fn main()
 {
-    let mut sum = 0;
-    let mut bytes = match bincode::serialize(&self)
+    let matches = cli().get_matches();
+    // -v and -q move from --log-level, or the default
+    let level = matches.get_one::<LevelFilter>("log-level").copied().unwrap_or(logging::DEFAULT_LEVEL);
+    let (verbose, quiet) = (matches.get_count("verbose") as usize, matches.get_count("quiet") as usize);
+    let level = LevelFilter::iter().nth((level as usize + verbose).saturating_sub(quiet)).unwrap_or(LevelFilter::max());
+    let _ = logging::init(level, matches.get_one::<logging::Format>("log-format").copied().unwrap_or(logging::Format::Text));
+    let (command, args) = matches.subcommand().unwrap_or(("stream", &matches));
+    let one = |name : &str| args.try_get_one::<String>(name).ok().flatten().cloned();
+    let mut pipeline = Pipeline::default();
+    let mut device = device::Settings::default();
+    if let Err(msg) = configure(args, &mut pipeline, &mut device)
     {
-        Ok(bytes) => bytes,
-        Err(msg) =>
+        error!("{}", msg); return ;
+    }
+
+    // what the subcommand adds to the pipeline, and the file or directory it writes
+    let written = match command { "record" => one("recording"), "tui" => None, _ => one("output") }.map(in_output_dir);
+    let written = match written.map(|path| template::expand(&path, if command == "snapshot" { ".png" } else { "" })).transpose()
+    {
+        Ok(written) => written,
+        Err(msg) => { error!("Error setting up {}!, {}", command, msg); return ; },
+    };
+    let averaged = || *args.try_get_one::<u64>("frames").ok().flatten().unwrap_or(&(snapshot::DEFAULT_FRAMES as u64)) as usize;
+    let added = match command
+    {
+        "info" => { run_info(&device); return ; },
+        "doctor" => { run_doctor(&device); return ; },
+        "config" => { run_config(args); return ; },
+        "list-ports" =>
         {
-            println!("Failed to serialize {}", msg);
-            return Err(())
-        }
+            let paths = args.get_many::<String>("paths").into_iter().flatten().cloned().collect();
+            run_list_ports(paths, device.baud_rate, !args.get_flag("no-probe"));
+            return ;
+        },
+        "record" => publish::open(&format!("record://{}", written.clone().unwrap_or_default())).map(Some),
+        "export" =>
+        {
+            let format = one("format").unwrap_or("png".to_string());
+            publish::open(&format!("export://{}?format={}", written.clone().unwrap_or_default(), format)).map(Some)
+        },
+        "view" =>
+        {
+            let address = one("listen").unwrap_or(DEFAULT_VIEW_ADDRESS.to_string());
+            info!("Open http://{}/ to see the frames", address);
+            publish::open(&format!("http://{}", address)).map(Some)
+        },
+        "snapshot" =>
+        {
+            pipeline.limit = Some(averaged() as u64);
+            let path = written.clone().unwrap_or_else(|| in_output_dir("snapshot".to_string()));
+            Ok(Some(Output::new("snapshot", Box::new(SnapshotPublisher::new(&path, averaged())))))
+        },
+        "volume" =>
+        {
+            pipeline.limit = Some(averaged() as u64);
+            let roi = args.try_get_one::<[usize; 4]>("roi").ok().flatten().copied();
+            VolumePublisher::new(&one("reference").unwrap_or_default(), roi, averaged(), written.as_deref())
+            .map(|publisher| Some(Output::new("volume", Box::new(publisher))))
+        },
+        "tui" =>
+        {
+            pipeline.recordings = in_output_dir(one("output").unwrap_or(DEFAULT_TUI_RECORDING.to_string()));
+            Screen::start(&device, pipeline.range).map(|(screen, publisher)|
+            {
+                pipeline.screen = Some(screen);
+                Some(Output::new("tui", Box::new(publisher)))
+            })
+        },
+        _ => Ok(None),
     };
-    if self.size <= 7 // serializer adds 8 bytes of padding in certain cases ...
+    match added
     {
-        bytes.drain(5..(5 + 8));
+        Ok(Some(output)) => pipeline.publishers.push(output),
+        Ok(None) => (),
+        Err(msg) => { error!("Error setting up {}!, {}", command, msg); return ; },
     }
-    else    // another magic done by serializer for some larger structures
+    // these write files or show frames elsewhere, printing every frame is only in the way
+    if ["record", "export", "view", "tui"].contains(&command)
     {
-        bytes.drain(5..(5 + 2));
+        pipeline.quiet = true;
+    }
+
+    let running = &signals::RUNNING;
+    ctrlc::set_handler(move || {
+        running.store(false, Ordering::SeqCst);
+    }).expect("Error setting Ctrl-C handler");
+    if let Err(msg) = signals::install()
+    {
+        warn!("Failed to handle SIGTERM and SIGHUP, {}", msg);
+    }
+
+    let recording = one("recording").unwrap_or_default();
+    match command
+    {
+        "connect" => run_remote(&one("url").unwrap_or_default(), running, &mut pipeline),
+        "bench" => run_bench(&recording, running, &mut pipeline),
+        "play" =>
+        {
+            let start = args.get_one::<usize>("start").copied().unwrap_or(0);
+            let fps = args.get_one::<f64>("fps").copied().unwrap_or(DEFAULT_PLAY_FPS);
+            run_play(&recording, start, fps, running, &mut pipeline)
+        },
+        "export" => run_play(&recording, 0, f64::INFINITY, running, &mut pipeline),
+        _ => run_device(&device, running, &mut pipeline),
     }
-    let interesting_bytes = &bytes[3..bytes.len()-1]; // exclude header and checksum
-    for byte in interesting_bytes
+    // the terminal back, with what was logged meanwhile
+    pipeline.screen = None;
+    if let Some(notifier) = &pipeline.notifier
+    {
+        notifier.stopping(&format!("Stopped after {} frames", pipeline.frames));
+    }
+    // reading ended without being asked to, for systemd to restart the service
+    if pipeline.daemon && running.load(Ordering::SeqCst) && !pipeline.done()
+    {
+        std::process::exit(1);
+    }
+    match command
+    {
+        "record" => println!("Recorded {} frames to {}", pipeline.frames, written.unwrap_or_default()),
+        "export" => println!("Exported {} frames to {}", pipeline.frames, written.unwrap_or_default()),
+        _ => (),
+    }
+    if args.try_get_one::<bool>("latency").ok().flatten() == Some(&true)
+    {
+        println!("Time from a frame's header arriving to");
+        print!("{}", STATS.trace_report());
+    }
+}
+
+// relative paths written to go into RLV_OUTPUT_DIR when it is set
+fn in_output_dir(path : String) -> String
+{
+    match std::env::var_os("RLV_OUTPUT_DIR")
     {
-        sum ^= byte;
+        Some(directory) if Path::new(&path).is_relative() => Path::new(&directory).join(path).to_string_lossy().into_owned(),
+        _ => path,
     }
-    Ok(sum)
 }
 
-fn as_bytes(&self) -> Result<Vec<u8>, ()>
+// a line per problem as path:line:column: message, like compilers, exiting with 1 when
+// there was one, e.g. before restarting the service with the file
+fn run_config(args : &ArgMatches)
 {
-    let mut bytes = match bincode::serialize(&self)
+    match args.subcommand()
     {
-        Ok(bytes) => bytes,
-        Err(msg) =>
+        Some(("validate", args)) =>
+        {
+            let path = args.get_one::<String>("path").cloned().unwrap_or_default();
+            let problems = match config::validate(&path)
+            {
+                Ok(problems) => problems,
+                Err(msg) => { error!("{}", msg); std::process::exit(1) },
+            };
+            for problem in problems.iter()
             {
-                println!("Failed to serialize {}", msg);
-                return Err(())
+                println!("{}:{}:{}: {}", path, problem.line, problem.column, problem.message);
             }
+            if !problems.is_empty()
+            {
+                std::process::exit(1);
+            }
+            println!("{} is valid", path);
+        },
+        _ => println!("{}", serde_json::to_string_pretty(&schema::json_schema()).unwrap_or_default()),
+    }
+}
+
+// a line per check, exiting with 1 when one failed, for scripts
+fn run_doctor(settings : &device::Settings)
+{
+    let checks = doctor::diagnose(settings);
+    for check in checks.iter()
+    {
+        println!("{} {:<9} {}", if check.passed { "PASS" } else { "FAIL" }, check.name, check.detail);
+    }
+    if checks.iter().any(|check| !check.passed)
+    {
+        std::process::exit(1);
+    }
+    println!("All checks passed");
+}
+
+// the serial ports found and the ones given, with how they are attached and whether the
+// lidar answers on them, marked with a *
+fn run_list_ports(paths : Vec<String>, baud_rate : u32, probe : bool)
+{
+    let mut ports = match serialport::available_ports()
+    {
+        Ok(ports) => ports,
+        Err(msg) => { error!("Failed to list serial ports!, {}", msg); Vec::new() },
     };
-    bytes.drain(5..13);
-    Ok(bytes)
+    for path in paths
+    {
+        if ports.iter().all(|port| port.port_name != path)
+        {
+            ports.push(SerialPortInfo { port_name : path, port_type : SerialPortType::Unknown });
+        }
+    }
+    if ports.is_empty()
+    {
+        println!("No serial ports found"); return ;
+    }
+    for port in ports
+    {
+        let answer = if probe { device::probe(&port.port_name, baud_rate, device::PROBE_WAIT) } else { Ok(None) };
+        let (mark, answer) = match answer
+        {
+            Ok(Some(info)) => ("*", format!(", lidar with firmware {}, hardware {}", info.firmware, info.hardware)),
+            Ok(None) => (" ", String::new()),
+            Err(msg) => (" ", format!(", failed to probe, {}", msg)),
+        };
+        println!("{} {:<20} {}{}", mark, port.port_name, device::describe(&port.port_type), answer);
+    }
 }
 
+// what the device says about itself, without starting it
+fn run_info(settings : &device::Settings)
+{
+    let mut serial_port = match settings.open()
+    {
+        Ok(port) => { port },
+        Err(msg) => { error!("Error opening port!, {}", msg); return ; },
+    };
+    let device_info_read = match device::handshake(&mut serial_port, settings.timeout)
+    {
+        Ok(frame) => frame,
+        Err(msg) => { error!("Failed to read device info on {}!, {}", settings.path, msg); return ; },
+    };
+    match DeviceInfo::from_payload(&device_info_read.payload)
+    {
+        Some(info) => println!("{} at {} baud, firmware {}, hardware {}", settings.path, settings.baud_rate, info.firmware, info.hardware),
+        None => println!("{:?}", device_info_read),
+    }
 }
 
-fn read_frame(serial_port : &mut TTYPort, payload_size : u16) -> Result<Frame, ()>
+// recordings don't keep time, they are played at a fixed rate
+const DEFAULT_PLAY_FPS : f64 = 30.0;
+
+fn run_play(path : &str, start : usize, fps : f64, running : &AtomicBool, pipeline : &mut Pipeline)
 {
-    let mut frame = vec![0u8; (payload_size + 6) as usize];
+    let playback = match Playback::open(path)
+    {
+        Ok(playback) => playback,
+        Err(msg) => { error!("Error opening recording {}!, {}", path, msg); return ; },
+    };
+    if start >= playback.len()
+    {
+        error!("Recording {} has only {} frames", path, playback.len()); return ;
+    }
+    info!("Playing frames {} to {} of {}", start, playback.len() - 1, path);
+    let interval = Duration::from_secs_f64(1.0 / fps);
+    let mut frame = new(Vec::new());
+    let mut depth = DepthFrame::default();
+    let mut next_at = Instant::now();
+    let mut progress = Progress::new("frames", pipeline.limit.map_or(playback.len() - start, |limit| (limit as usize).min(playback.len() - start)));
+    pipeline.start();
+    for bytes in playback.frames().skip(start)
+    {
+        if !running.load(Ordering::SeqCst) || pipeline.done()
+        {
+            break;
+        }
+        progress.advance();
+        if parse_frame_into(bytes, &mut frame).is_ok()
+        {
+            depth.unpack(&frame.payload);
+            pipeline.process(&mut frame, &mut depth, Instant::now());
+        }
+        next_at += interval;
+        if let Some(wait) = next_at.checked_duration_since(Instant::now())
+        {
+            thread::sleep(wait);
+        }
+    }
+}
 
-    loop
+// plays a recording through the pipeline as fast as it goes, over and over for at least
+// BENCH_DURATION, and prints the frame rate it kept up
+const BENCH_DURATION : Duration = Duration::from_secs(5);
+
+fn run_bench(path : &str, running : &AtomicBool, pipeline : &mut Pipeline)
+{
+    let playback = match Playback::open(path)
     {
-        serial_port.set_timeout(Duration::from_millis(130)).expect("Couldn't set a tiemout");
-        match serial_port.read_exact(&mut frame)
+        Ok(playback) => playback,
+        Err(msg) => { error!("Error opening recording {}!, {}", path, msg); return ; },
+    };
+    let mut frame = new(Vec::new());
+    let mut depth = DepthFrame::default();
+    let mut times = Vec::new();
+    let started = Instant::now();
+    while running.load(Ordering::SeqCst) && started.elapsed() < BENCH_DURATION
+    {
+        for bytes in playback.frames()
         {
-            Ok(_) =>
+            let read_at = Instant::now();
+            if parse_frame_into(bytes, &mut frame).is_err()
             {
-                let frame_obj = new(frame[5..(payload_size+5) as usize].to_vec());
+                continue;
+            }
+            depth.unpack(&frame.payload);
+            let analyses = pipeline.analyze(&mut frame, &mut depth);
+            publish(&mut pipeline.publishers, &frame, &depth, &analyses, read_at);
+            times.push(read_at.elapsed());
+        }
+        if times.is_empty()
+        {
+            error!("No valid frames in recording {}", path); return ;
+        }
+    }
+    // interrupted before the first pass
+    if times.is_empty()
+    {
+        println!("No frames processed"); return ;
+    }
+    let elapsed = started.elapsed();
+    times.sort();
+    let percentile = |percentile : f64| times[((times.len() - 1) as f64 * percentile).round() as usize];
+    println!("Processed {} frames in {:.2?}, {:.1} frames/s, {:.2?} per frame at the median, {:.2?} at the 99th percentile",
+        times.len(), elapsed, times.len() as f64 / elapsed.as_secs_f64(), percentile(0.5), percentile(0.99));
+}
 
-                if frame_obj.header != &frame[0..3]
-                {
-                    println!("Failed to deserialize frame header"); return Err(())
-                }
-                let size : u16 = match bincode::deserialize(&frame[3..5])
-                {
-                    Ok(size) => size,
-                    Err(msg) => { println!("Failed to deserialize size of frame {}", msg); return Err(()) }
-                };
-                if size != payload_size
-                {
-                    println!("Failed to deserialize size of frame frame, size is not as expected ( {} )", payload_size); return Err(())
-                }
-                let checksum = frame[frame.len()-1];
-                if frame_obj.checksum != checksum
-                {
-                    println!("Failed to deserialize checksum, expected ( {} )", checksum); return Err(())
-                }
+// frames from the stream of another instance, see remote.rs
+fn run_remote(url : &str, running : &AtomicBool, pipeline : &mut Pipeline)
+{
+    let mut source = match RemoteSource::connect(url)
+    {
+        Ok(source) => source,
+        Err(msg) => { error!("Error connecting to {}!, {}", url, msg); return ; },
+    };
+    pipeline.start();
+    while running.load(Ordering::SeqCst) && !pipeline.done()
+    {
+        pipeline.log_stats();
+        match source.receive()
+        {
+            Ok(Received::DeviceInfo(info)) => pipeline.device_info(&info),
+            Ok(Received::Frame(mut frame, mut depth)) => pipeline.process(&mut frame, &mut depth, Instant::now()),
+            Err(msg) => { error!("Failed to read frame : {:?}", msg); break; }
+        }
+    }
+}
 
-                return Ok(frame_obj);
-            },
-            Err(msg) =>
+fn run_device(settings : &device::Settings, running : &AtomicBool, pipeline : &mut Pipeline)
+{
+    let mut serial_port = match settings.open()
+    {
+        Ok(port) => { port },
+        Err(msg) => { error!("Error opening port!, {}", msg); return ; },
+    };
+
+    info!("Opened serial port with baud {:?}", serial_port.baud_rate());
+
+    // an instance killed while reading leaves the device streaming into the handshake
+    if pipeline.daemon
+    {
+        let _ = device::stop(&mut serial_port);
+    }
+
+    let device_info_read = match device::handshake(&mut serial_port, settings.timeout)
+    {
+        Ok(frame) => frame,
+        Err(msg) => { error!("Failed to read device info on {}!, {}", settings.path, msg); return ; },
+    };
+    match DeviceInfo::from_payload(&device_info_read.payload)
+    {
+        Some(info) => pipeline.device_info(&info),
+        None => { warn!("Unexpected device info {:?}", device_info_read); },
+    };
+
+    if device::start(&mut serial_port, settings.mode).is_err()
+    {
+        return ;
+    }
+    let (reader, control) = match SerialReader::new(&serial_port, settings.timeout)
+    {
+        Ok(reader) => reader,
+        Err(msg) => { error!("Error waiting on port!, {}", msg); return ; },
+    };
+    info!("Started reading frames");
+    pipeline.start();
+    // set for read_stage to restart the device when it is told to stop
+    let restart = AtomicBool::new(false);
+
+    thread::scope(|scope|
+    {
+        let (raw_sender, raw) = sync_channel(STAGE_QUEUE_DEPTH);
+        let (parsed_sender, parsed) = sync_channel(STAGE_QUEUE_DEPTH);
+        let (processed_sender, processed) = sync_channel(STAGE_QUEUE_DEPTH);
+        let (changes_sender, changes) = channel();
+        let serial_port = &mut serial_port;
+        let restart = &restart;
+        scope.spawn(move || read_stage(serial_port, settings, reader, restart, raw_sender));
+        scope.spawn(move || parse_stage(raw, parsed_sender));
+        let publishers = mem::take(&mut pipeline.publishers);
+        let quiet = pipeline.quiet;
+        let sink = scope.spawn(move || sink_stage(publishers, quiet, processed, changes));
+
+        let mut last_frame = Instant::now();
+        while running.load(Ordering::SeqCst) && !pipeline.done()
+        {
+            for change in pipeline.reload().into_iter().chain(pipeline.control())
             {
-                if msg.kind() == TimedOut
-                {
-                    println!("Timed out reading from serial!");
-                    continue;
-                }
-                else
+                let _ = changes_sender.send(change);
+            }
+            pipeline.log_stats();
+            // counted again from the restart, so a device that doesn't answer is tried again
+            if let Some(watchdog) = settings.watchdog.filter(|watchdog| last_frame.elapsed() >= *watchdog)
+            {
+                warn!(watchdog_ms = watchdog.as_millis() as u64; "No valid frame for {} ms, restarting the stream", watchdog.as_millis());
+                restart.store(true, Ordering::SeqCst);
+                let _ = control.send(Command::Stop);
+                last_frame = Instant::now();
+            }
+            let mut next : Parsed = match parsed.recv_timeout(Duration::from_millis(100))
+            {
+                Ok(next) => next,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            last_frame = Instant::now();
+            let started = Instant::now();
+            let analyses = pipeline.analyze(&mut next.frame, &mut next.depth);
+            STATS.stage(Stage::Process).record(started.elapsed());
+            if let Err(TrySendError::Full(_)) = processed_sender.try_send(Processed { parsed : next, analyses })
+            {
+                Stats::count(&STATS.stage(Stage::Sink).dropped);
+            }
+        }
+        // the other stages stop as the queues close, reading when told to, without
+        // restarting for a watchdog Stop still queued
+        restart.store(false, Ordering::SeqCst);
+        let _ = control.send(Command::Stop);
+        drop(parsed);
+        drop(processed_sender);
+        pipeline.publishers = sink.join().unwrap_or_default();
+    });
+    if pipeline.done()
+    {
+        info!("Stopping after {} frames", pipeline.frames);
+    }
+
+    if device::stop(&mut serial_port).is_ok()
+    {
+        info!("Stopped reading frames");
+    }
+}
+
+// The device loop runs in stages connected by bounded queues: read takes frames off the
+// serial port, parse checks and unpacks them, process runs the pipeline without its
+// publishers, sink hands the results to the publishers, each fed from a queue of its own,
+// see sinks.rs. Each runs on a thread of its own, process on the main one, and a frame
+// meeting a full queue between stages is dropped rather than waited for, so a slow
+// publisher never holds up reading. Buffers come from the pools in pool.rs.
+const STAGE_QUEUE_DEPTH : usize = 4;
+
+struct Parsed
+{
+    frame : Pooled<Frame>,
+    depth : Pooled<DepthFrame>,
+    read_at : Instant,
+}
+
+struct Processed
+{
+    parsed : Parsed,
+    analyses : Vec<Analysis>,
+}
+
+// frames go on with when their first bytes arrived. Told to stop with restart set, the
+// device is restarted and reading goes on
+fn read_stage(serial_port : &mut TTYPort, settings : &device::Settings, mut reader : SerialReader, restart : &AtomicBool, raw : SyncSender<(Pooled<Vec<u8>>, Instant)>)
+{
+    settings.apply_to_thread();
+    let mut bytes = BYTES.get();
+    loop
+    {
+        let started = Instant::now();
+        let arrived = match reader.read_frame(serial_port, settings.mode.payload_sizes(), settings.chunk.unwrap_or(usize::MAX), &mut bytes)
+        {
+            Ok(Some(arrived)) => arrived,
+            Ok(None) if restart.swap(false, Ordering::SeqCst) =>
+            {
+                Stats::count(&STATS.restarts);
+                match device::restart(serial_port, settings.mode, device::PROBE_WAIT)
                 {
-                    println!("Error reading device info from serial!, {}", msg);
-                    return Err(());
+                    Ok(Some(info)) => { reader.clear(); info!(restarts = STATS.restarts.load(Ordering::Relaxed); "Restarted the stream, {:?}", info) },
+                    Ok(None) => warn!("The device didn't answer the info request, trying again"),
+                    Err(msg) => error!("Failed to restart the stream!, {}", msg),
                 }
+                continue;
             },
+            Ok(None) => break,
+            Err(msg) => { error!("Failed to read frame : {:?}", msg); break; },
+        };
+        STATS.stage(Stage::Read).record(started.elapsed());
+        STATS.trace(Milestone::Read, arrived.elapsed());
+        STATS.record_arrival(arrived);
+        match raw.try_send((bytes, arrived))
+        {
+            Ok(()) => bytes = BYTES.get(),
+            Err(TrySendError::Full((full, _))) => { Stats::count(&STATS.stage(Stage::Parse).dropped); bytes = full },
+            Err(TrySendError::Disconnected(_)) => break,
         }
     }
+}
 
+fn parse_stage(raw : Receiver<(Pooled<Vec<u8>>, Instant)>, parsed : SyncSender<Parsed>)
+{
+    for (bytes, arrived) in raw
+    {
+        let started = Instant::now();
+        let mut next = Parsed { frame : FRAMES.get(), depth : DEPTH_FRAMES.get(), read_at : arrived };
+        // counted and reported where it failed, the next one may be fine
+        if parse_frame_into(&bytes, &mut next.frame).is_err()
+        {
+            continue;
+        }
+        Stats::count(&STATS.frames);
+        STATS.bytes_read.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        next.depth.unpack(&next.frame.payload);
+        STATS.stage(Stage::Parse).record(started.elapsed());
+        STATS.trace(Milestone::Parsed, arrived.elapsed());
+        match parsed.try_send(next)
+        {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => Stats::count(&STATS.stage(Stage::Process).dropped),
+            Err(TrySendError::Disconnected(_)) => break,
+        }
+    }
 }
 
-fn main()
+// gives the publishers back once the queue closed and they are done
+// publishers the config no longer has are stopped and new ones started before the next
+// frame, the others go on as they were
+fn sink_stage(publishers : Vec<Output>, quiet : bool, processed : Receiver<Processed>, changes : Receiver<Change>) -> Vec<Output>
 {
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
+    let mut sinks = Sinks::start(publishers);
+    for Processed { parsed, analyses } in processed
+    {
+        while let Ok(Change { removed, added }) = changes.try_recv()
+        {
+            sinks.remove(&removed);
+            sinks.add(added);
+        }
+        let started = Instant::now();
+        if !quiet
+        {
+            print_frame(&parsed.depth, &analyses);
+        }
+        let Parsed { frame, depth, read_at } = parsed;
+        sinks.send(Published { frame, depth, analyses, read_at });
+        STATS.stage(Stage::Sink).record(started.elapsed());
+    }
+    sinks.stop()
+}
 
-    ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
-    }).expect("Error setting Ctrl-C handler");
+// Every frame goes through the filters, the scripts, then the analyzers, then to the publishers
+#[derive(Default)]
+struct Pipeline
+{
+    filters : Vec<Box<dyn Filter>>,
+    scripts : Vec<Script>,
+    analyzers : Vec<Box<dyn Analyzer>>,
+    publishers : Vec<Output>,
+    // stop after this many frames or this long after start
+    limit : Option<u64>,
+    frames : u64,
+    duration : Option<Duration>,
+    deadline : Option<Instant>,
+    // logging the link counters that often, with when they were last and what they were
+    stats_interval : Option<Duration>,
+    stats_logged : Option<(Instant, Link)>,
+    // no printing frames, for --latency
+    quiet : bool,
+    // run by systemd, see systemd.rs
+    daemon : bool,
+    notifier : Option<Notifier>,
+    reload : Option<Reload>,
+    // the range filter, first of the filters when set
+    range : Option<Range>,
+    // the tui subcommand's, with where its recordings go and the one being written
+    screen : Option<Screen>,
+    recordings : String,
+    recording : Option<String>,
+}
+
+// what of the pipeline came from the --config file, to be replaced when it changes
+struct Reload
+{
+    watcher : Watcher,
+    filters : std::ops::Range<usize>,
+    // the first ones
+    analyzers : usize,
+    publishers : Vec<String>,
+    device : Option<toml::Table>,
+    // --decimate, for publishers added
+    decimate : Option<u64>,
+}
+
+// publishers to stop, by url, and to start
+struct Change
+{
+    removed : Vec<String>,
+    added : Vec<Output>,
+}
+
+impl Pipeline
+{
 
-    let serial_port_builder = serialport::new("/dev/ttyUSB0", 3000000)
-    .data_bits(DataBits::Eight)
-    .parity(Parity::None)
-    .stop_bits(StopBits::One)
-    .flow_control(FlowControl::None)
-        ;
-    let mut serial_port = match serial_port_builder.open_native()
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    info!("{:?}", info);
+    for output in self.publishers.iter_mut()
     {
-        Ok(port) => { port },
-        Err(msg) => { println!("Error opening port!, {}", msg); return ; },
-    };
+        output.publisher.device_info(info);
+    }
+}
 
-    println!("Opened serial port with baud {:?}", serial_port.baud_rate());
+// when frames start coming, for the duration to count from
+fn start(&mut self)
+{
+    self.deadline = self.duration.map(|duration| Instant::now() + duration);
+    self.stats_logged = Some((Instant::now(), STATS.link()));
+    if let Some(notifier) = &self.notifier
+    {
+        notifier.ready("Reading frames");
+    }
+}
 
-    let baud_rate = new(vec![0x12, 0x55]);
-    match serial_port.write(&baud_rate.as_bytes().unwrap())
+// the link counters since they were last logged, once stats_interval went by
+fn log_stats(&mut self)
+{
+    let (logged, earlier) = match (self.stats_interval, self.stats_logged)
     {
-        Ok(_) => {  },
-        Err(msg) => { println!("Error writing baud info!, {}", msg); return ; },
+        (Some(interval), Some((logged, earlier))) if logged.elapsed() >= interval => (logged, earlier),
+        _ => return,
     };
+    let link = STATS.link();
+    let since = link.since(&earlier);
+    info!(frames = since.frames, checksum_errors = since.checksum_errors, resyncs = since.resyncs, short_reads = since.short_reads,
+        timeouts = since.timeouts; "Link {}", since.summary(logged.elapsed()));
+    self.stats_logged = Some((Instant::now(), link));
+}
+
+fn done(&self) -> bool
+{
+    self.limit.is_some_and(|limit| self.frames >= limit) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
 
-    let device_info = new(vec![0x10, 0x00]);
-    match serial_port.write(&device_info.as_bytes().unwrap())
+// filters, zones and publishers from the --config file again, once it changed. A config
+// that doesn't load, or a publisher that doesn't open, leaves everything as it was
+fn reload(&mut self) -> Option<Change>
+{
+    let reload = self.reload.as_mut()?;
+    if !reload.watcher.changed()
     {
-        Ok(_) => {  },
-        Err(msg) => { println!("Error writing dev info request!, {}", msg); return ; },
+        return None
+    }
+    let path = reload.watcher.path().to_string();
+    let loaded = Config::load(&path).and_then(|config|
+    {
+        // opened last, a config failing before that has bound no ports
+        let (filters, analyzers) = (config.filters()?, config.analyzers()?);
+        let removed : Vec<String> = reload.publishers.iter().filter(|url| !config.publish.contains(url)).cloned().collect();
+        let added = config.publish.iter().filter(|url| !reload.publishers.contains(url))
+            .map(|url| publish::open(url).map_err(|msg| format!("publisher {}, {}", url, msg)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok((filters, analyzers, removed, added, config))
+    });
+    let (filters, analyzers, removed, mut added, config) = match loaded
+    {
+        Ok(loaded) => loaded,
+        Err(msg) => { error!("Failed to reload config {}, keeping the one before, {}", path, msg); return None },
     };
+    if config.device != reload.device
+    {
+        warn!("Device settings in {} changed, they only apply on restart", path);
+    }
+    for output in added.iter_mut()
+    {
+        output.throttle.decimate = output.throttle.decimate.or(reload.decimate);
+    }
+    info!("Reloaded {}, {} filters, {} analyzers, {} publishers started, {} stopped", path, filters.len(), analyzers.len(), added.len(), removed.len());
+    let replaced = reload.filters.clone();
+    reload.filters = replaced.start..replaced.start + filters.len();
+    self.filters.splice(replaced, filters);
+    let replaced = mem::replace(&mut reload.analyzers, analyzers.len());
+    self.analyzers.splice(..replaced, analyzers);
+    reload.publishers = config.publish;
+    reload.device = config.device;
+    Some(Change { removed, added })
+}
 
-    let device_info_read = read_frame(&mut serial_port, 7).unwrap();
-    println!("{:?}", device_info_read);
-
-    serial_port.flush().unwrap();
+// the range filter goes first so the others don't work on distances that are dropped anyway
+fn set_range(&mut self, range : Range)
+{
+    if self.range.is_some()
+    {
+        self.filters[0] = Box::new(range);
+    }
+    else
+    {
+        self.filters.insert(0, Box::new(range));
+        if let Some(reload) = self.reload.as_mut()
+        {
+            reload.filters = reload.filters.start + 1..reload.filters.end + 1;
+        }
+    }
+    self.range = Some(range);
+}
 
-    thread::sleep(Duration::from_secs(1));
+// what the keys pressed on the tui screen since the last frame ask for, recordings
+// started or stopped are for the publishers
+fn control(&mut self) -> Option<Change>
+{
+    let controls = self.screen.as_ref()?.controls();
+    if controls.is_empty()
+    {
+        return None
+    }
+    let mut change = Change { removed : Vec::new(), added : Vec::new() };
+    for control in controls
+    {
+        match control
+        {
+            Control::Range(range) =>
+            {
+                match range.max_mm
+                {
+                    u16::MAX => info!("Keeping distances from {} mm", range.min_mm),
+                    max_mm => info!("Keeping distances from {} to {} mm", range.min_mm, max_mm),
+                }
+                self.set_range(range);
+            },
+            Control::Record => match self.recording.take()
+            {
+                Some(url) => { info!("Stopped recording to {}", url.trim_start_matches("record://")); change.removed.push(url) },
+                None => match template::expand(&self.recordings, "").and_then(|path| publish::open(&format!("record://{}", path)))
+                {
+                    Ok(output) => { self.recording = Some(output.name.clone()); change.added.push(output) },
+                    Err(msg) => error!("Failed to start recording, {}", msg),
+                },
+            },
+        }
+    }
+    if let Some(screen) = self.screen.as_ref()
+    {
+        screen.set_recording(self.recording.as_deref().map(|url| url.trim_start_matches("record://")));
+        if let Some(range) = self.range
+        {
+            screen.set_range(range);
+        }
+    }
+    if change.removed.is_empty() && change.added.is_empty() { None } else { Some(change) }
+}
 
-    let start_3d = new(vec![0x08, 0x00]);
-    match serial_port.write(&start_3d.as_bytes().unwrap())
+fn process(&mut self, frame : &mut Frame, depth : &mut DepthFrame, read_at : Instant)
+{
+    if let Some(Change { removed, added }) = self.reload()
     {
-        Ok(_) => { println!("Started reading frames"); },
-        Err(msg) => { println!("Error writing dev info request!, {}", msg); return ; },
-    };
-    thread::sleep(Duration::from_secs(1));
+        self.publishers.retain(|output| !removed.contains(&output.name));
+        self.publishers.extend(added);
+    }
+    let analyses = self.analyze(frame, depth);
+    publish(&mut self.publishers, frame, depth, &analyses, read_at);
+    if !self.quiet
+    {
+        print_frame(depth, &analyses);
+    }
+}
 
-    while running.load(Ordering::SeqCst)
+// filtered frames are packed again so publishers sending raw frames send them filtered too
+fn analyze(&mut self, frame : &mut Frame, depth : &mut DepthFrame) -> Vec<Analysis>
+{
+    let mut analyses = Vec::new();
+    if !self.filters.is_empty() || !self.scripts.is_empty()
     {
-        const point_cloud_3d_size : u16 = 160 * 60;
-        let frame_3d = match read_frame(&mut serial_port, ((point_cloud_3d_size*3)/2)+1)
+        for filter in self.filters.iter_mut()
         {
-            Ok(frame_3d) => frame_3d,
-            Err(msg) => { println!("Failed to read frame : {:?}", msg); break; }
-        };
-        let mut point_cloud_3d = [0u16;point_cloud_3d_size as usize];
-        let mut iter_frame: usize = 0;
-        let mut iter_point_cloud: usize = 0;
-        while iter_point_cloud < point_cloud_3d_size as usize && iter_frame < frame_3d.payload.len()-3
+            filter.apply(depth);
+        }
+        // a script that fails is left out from then on
+        self.scripts.retain_mut(|script| match script.run(depth)
         {
-            let first = frame_3d.payload[iter_frame]; iter_frame+=1;
-            let second = frame_3d.payload[iter_frame]; iter_frame+=1;
-            let third = frame_3d.payload[iter_frame]; iter_frame+=1;
+            Ok(outputs) => { analyses.extend(outputs); true },
+            Err(msg) => { warn!("Dropping script {}, {}", script.command(), msg); false },
+        });
+        depth.pack(&mut frame.payload);
+        frame.update();
+    }
+    analyses.extend(self.analyzers.iter_mut().filter_map(|analyzer| analyzer.analyze(depth)));
+    self.frames += 1;
+    if let Some(notifier) = self.notifier.as_mut()
+    {
+        notifier.frame();
+    }
+    analyses
+}
 
-            point_cloud_3d[iter_point_cloud] = first as u16;
-            point_cloud_3d[iter_point_cloud] |= ((second & 0xf) as u16) << 8;
-            iter_point_cloud+=1;
+}
 
-            point_cloud_3d[iter_point_cloud] = ((second & 0xf) >> 4) as u16;
-            point_cloud_3d[iter_point_cloud] |= (third << 4) as u16;
+// publishing in line, for everything but the device loop
+fn publish(publishers : &mut [Output], frame : &Frame, depth : &DepthFrame, analyses : &[Analysis], read_at : Instant)
+{
+    for output in publishers.iter_mut()
+    {
+        if output.throttle.admit(read_at)
+        {
+            output.publish(frame, depth, analyses);
         }
-        thread::sleep(Duration::from_millis(20));
-        println!("Read frame, its point cloud is {:?}", point_cloud_3d);
     }
+    let latency = read_at.elapsed();
+    STATS.record_latency(latency);
+    STATS.trace(Milestone::Delivered, latency);
+}
 
-    let stop = new(vec![0x02, 0x00, 0x00]);
-    match serial_port.write(&stop.as_bytes().unwrap())
+fn print_frame(depth : &DepthFrame, analyses : &[Analysis])
+{
+    trace!("Read frame, its point cloud is {:?}", depth.data);
+    for analysis in analyses.iter()
     {
-        Ok(_) => { println!("Stopped reading frames"); },
-        Err(msg) => { println!("Error writing dev info request!, {}", msg); return ; },
-    };
+        debug!("{} : {}", analysis.name(), serde_json::to_string(analysis).unwrap_or_default());
+    }
 }
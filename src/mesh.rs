@@ -0,0 +1,98 @@
+// Triangle meshes from point clouds. from_voxels puts the cloud into cubes of leaf meters
+// and emits the faces between occupied and empty cubes, two triangles each, which gives a
+// closed, blocky surface of everything scanned that opens directly in Blender and alike.
+use crate::cloud::PointCloud;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh
+{
+    pub vertices : Vec<[f32; 3]>,
+    // counter clockwise seen from outside
+    pub triangles : Vec<[u32; 3]>,
+}
+
+// the corners of the face towards each of the six neighbours, counter clockwise from outside
+const FACES : [([i32; 3], [[i32; 3]; 4]); 6] =
+[
+    ([1, 0, 0], [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 1]]),
+    ([-1, 0, 0], [[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]]),
+    ([0, 1, 0], [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]]),
+    ([0, -1, 0], [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]]),
+    ([0, 0, 1], [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]]),
+    ([0, 0, -1], [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 0, 0]]),
+];
+
+pub fn from_voxels(cloud : &PointCloud, leaf : f32) -> Mesh
+{
+    let voxels : HashSet<[i32; 3]> = cloud.points.iter().map(|point| point.map(|value| (value / leaf).floor() as i32)).collect();
+    let mut corners : HashMap<[i32; 3], u32> = HashMap::new();
+    let mut mesh = Mesh::default();
+    for voxel in voxels.iter()
+    {
+        for (direction, face) in FACES.iter()
+        {
+            let neighbour = [voxel[0] + direction[0], voxel[1] + direction[1], voxel[2] + direction[2]];
+            if voxels.contains(&neighbour)
+            {
+                continue;
+            }
+            let indices = face.map(|corner|
+            {
+                let corner = [voxel[0] + corner[0], voxel[1] + corner[1], voxel[2] + corner[2]];
+                *corners.entry(corner).or_insert_with(||
+                {
+                    mesh.vertices.push(corner.map(|value| value as f32 * leaf));
+                    (mesh.vertices.len() - 1) as u32
+                })
+            });
+            mesh.triangles.push([indices[0], indices[1], indices[2]]);
+            mesh.triangles.push([indices[0], indices[2], indices[3]]);
+        }
+    }
+    mesh
+}
+
+impl Mesh
+{
+
+pub fn write_obj<W : Write>(&self, mut writer : W) -> io::Result<()>
+{
+    for vertex in self.vertices.iter()
+    {
+        writeln!(writer, "v {} {} {}", vertex[0], vertex[1], vertex[2])?;
+    }
+    // obj indices start at 1
+    for triangle in self.triangles.iter()
+    {
+        writeln!(writer, "f {} {} {}", triangle[0] + 1, triangle[1] + 1, triangle[2] + 1)?;
+    }
+    writer.flush()
+}
+
+// binary little endian
+pub fn write_ply<W : Write>(&self, mut writer : W) -> io::Result<()>
+{
+    write!(writer, "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+        element face {}\nproperty list uchar uint vertex_indices\nend_header\n", self.vertices.len(), self.triangles.len())?;
+    for vertex in self.vertices.iter()
+    {
+        for value in vertex
+        {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    for triangle in self.triangles.iter()
+    {
+        writer.write_all(&[3])?;
+        for index in triangle
+        {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+    }
+    writer.flush()
+}
+
+}
@@ -0,0 +1,258 @@
+use crate::analysis::Analysis;
+use crate::frame::Frame;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::homeassistant::{self, HomeAssistant, State};
+use crate::publish::{Options, Publisher};
+
+use log::{error, info, warn};
+use rumqttc::{Client, LastWill, MqttOptions, QoS};
+use serde::Serialize;
+
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Topics, relative to the prefix:
+//   status   "online" / "offline" (retained, also set as last will)
+//   summary  json with frame size, valid point count and min / max / mean distance, every frame
+//   health   json with uptime and counters, every HEALTH_INTERVAL
+//   frame    frame bytes as read from the device, only with frames=true
+//   <name>   json results of each analyzer, e.g. obstacles
+//   state    nearest distance and presence for Home Assistant, only with homeassistant=true,
+//            when it changes and at least every STATE_INTERVAL
+const DEFAULT_PORT : u16 = 1883;
+const DEFAULT_PREFIX : &str = "rusty_lidar_viewer";
+const HEALTH_INTERVAL : Duration = Duration::from_secs(5);
+const STATE_INTERVAL : Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+struct Summary
+{
+    sequence : u64,
+    timestamp_ms : u128,
+    width : usize,
+    height : usize,
+    valid_points : usize,
+    min_mm : Option<u16>,
+    max_mm : Option<u16>,
+    mean_mm : Option<f32>,
+}
+
+#[derive(Serialize)]
+struct Health
+{
+    uptime_s : u64,
+    frames : u64,
+    dropped_messages : u64,
+}
+
+pub struct MqttPublisher
+{
+    client : Client,
+    prefix : String,
+    qos : QoS,
+    frames : bool,
+    sequence : u64,
+    dropped : u64,
+    started : Instant,
+    last_health : Option<Instant>,
+    homeassistant : Option<HomeAssistant>,
+    last_state : Option<(Instant, State)>,
+}
+
+impl MqttPublisher
+{
+
+// options: prefix=<topic prefix>, qos=0|1|2, frames=true|false, homeassistant=true|false,
+// discovery_prefix=<home assistant discovery prefix>, node_id=<home assistant node id>,
+// presence_mm=<distance below which presence is on>
+pub fn new(address : &str, options : &Options) -> Result<MqttPublisher, String>
+{
+    let mut prefix = DEFAULT_PREFIX.to_string();
+    let mut qos = QoS::AtMostOnce;
+    let mut frames = false;
+    let mut discovery = false;
+    let mut homeassistant = HomeAssistant
+    {
+        discovery_prefix : homeassistant::DEFAULT_DISCOVERY_PREFIX.to_string(),
+        node_id : homeassistant::DEFAULT_NODE_ID.to_string(),
+        presence_mm : homeassistant::DEFAULT_PRESENCE_MM,
+    };
+    for (name, value) in options
+    {
+        match *name
+        {
+            "prefix" => prefix = value.trim_end_matches('/').to_string(),
+            "qos" => qos = match *value
+            {
+                "0" => QoS::AtMostOnce,
+                "1" => QoS::AtLeastOnce,
+                "2" => QoS::ExactlyOnce,
+                _ => return Err(format!("invalid mqtt qos {}", value)),
+            },
+            "frames" => frames = value.parse().map_err(|_| format!("invalid value for frames {}", value))?,
+            "homeassistant" => discovery = value.parse().map_err(|_| format!("invalid value for homeassistant {}", value))?,
+            "discovery_prefix" => homeassistant.discovery_prefix = value.trim_end_matches('/').to_string(),
+            "node_id" => homeassistant.node_id = value.to_string(),
+            "presence_mm" => homeassistant.presence_mm = value.parse().map_err(|_| format!("invalid value for presence_mm {}", value))?,
+            _ => return Err(format!("mqtt publisher has no option {}", name)),
+        }
+    }
+    let (host, port) = match address.rsplit_once(':')
+    {
+        Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid mqtt port {}", port))?),
+        None => (address, DEFAULT_PORT),
+    };
+
+    let status = format!("{}/status", prefix);
+    let mut mqtt_options = MqttOptions::new(format!("rusty_lidar_viewer-{}", std::process::id()), host, port);
+    mqtt_options.set_keep_alive(Duration::from_secs(5))
+        .set_max_packet_size(1 << 20, 1 << 20)
+        .set_last_will(LastWill::new(status.clone(), "offline", QoS::AtLeastOnce, true));
+    let (client, mut connection) = Client::new(mqtt_options, 64);
+    thread::spawn(move ||
+    {
+        for event in connection.iter()
+        {
+            if let Err(msg) = event
+            {
+                warn!("Mqtt connection error, {}", msg);
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    });
+    if let Err(msg) = client.try_publish(status, QoS::AtLeastOnce, true, "online")
+    {
+        return Err(msg.to_string());
+    }
+    info!("Publishing frames to mqtt://{}:{}/{}", host, port, prefix);
+
+    let mut publisher = MqttPublisher
+    {
+        client, prefix, qos, frames,
+        sequence : 0,
+        dropped : 0,
+        started : Instant::now(),
+        last_health : None,
+        homeassistant : if discovery { Some(homeassistant) } else { None },
+        last_state : None,
+    };
+    publisher.announce(None)?;
+    Ok(publisher)
+}
+
+// (re)publishes the home assistant discovery configs, once more when the device info is known
+fn announce(&mut self, info : Option<&DeviceInfo>) -> Result<(), String>
+{
+    let configs = match &self.homeassistant
+    {
+        Some(homeassistant) => homeassistant.configs(&self.prefix, info).map_err(|msg| msg.to_string())?,
+        None => return Ok(()),
+    };
+    for (topic, payload) in configs
+    {
+        self.client.try_publish(topic, QoS::AtLeastOnce, true, payload).map_err(|msg| msg.to_string())?;
+    }
+    Ok(())
+}
+
+fn send<S : Serialize>(&mut self, topic : &str, retain : bool, message : &S) -> Result<(), ()>
+{
+    let payload = match serde_json::to_vec(message)
+    {
+        Ok(payload) => payload,
+        Err(msg) => { error!("Failed to serialize {} message {}", topic, msg); return Err(()) }
+    };
+    self.send_bytes(topic, retain, payload);
+    Ok(())
+}
+
+// the client queue being full means the broker can't keep up, drop rather than block the reader
+fn send_bytes(&mut self, topic : &str, retain : bool, payload : Vec<u8>)
+{
+    if self.client.try_publish(format!("{}/{}", self.prefix, topic), self.qos, retain, payload).is_err()
+    {
+        self.dropped += 1;
+    }
+}
+
+}
+
+impl Publisher for MqttPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    if let Err(msg) = self.announce(Some(info))
+    {
+        error!("Failed to publish home assistant discovery, {}", msg);
+    }
+}
+
+// urgent events are retained and sent at least once whatever qos is set to
+fn analysis(&mut self, analysis : &Analysis)
+{
+    if !analysis.is_urgent()
+    {
+        let _ = self.send(analysis.name(), false, analysis);
+        return
+    }
+    let qos = if self.qos == QoS::AtMostOnce { QoS::AtLeastOnce } else { self.qos };
+    match serde_json::to_vec(analysis)
+    {
+        Ok(payload) => if let Err(msg) = self.client.try_publish(format!("{}/{}", self.prefix, analysis.name()), qos, true, payload)
+        {
+            error!("Failed to publish {}, {}", analysis.name(), msg);
+        },
+        Err(msg) => error!("Failed to serialize {} message {}", analysis.name(), msg),
+    }
+}
+
+fn publish(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let stats = depth.summary();
+    let summary = Summary
+    {
+        sequence : self.sequence,
+        timestamp_ms : SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0),
+        width : depth.width,
+        height : depth.height,
+        valid_points : stats.valid_points,
+        min_mm : stats.min_mm,
+        max_mm : stats.max_mm,
+        mean_mm : stats.mean_mm,
+    };
+    self.sequence += 1;
+    self.send("summary", false, &summary)?;
+
+    if self.frames
+    {
+        let bytes = frame.as_bytes()?;
+        self.send_bytes("frame", false, bytes);
+    }
+
+    if let Some(homeassistant) = &self.homeassistant
+    {
+        let state = homeassistant.state(&stats);
+        let topic = homeassistant.state_topic(&self.prefix);
+        if self.last_state.as_ref().is_none_or(|(at, last)| *last != state || at.elapsed() >= STATE_INTERVAL)
+        {
+            match serde_json::to_vec(&state)
+            {
+                Ok(payload) => if self.client.try_publish(topic, self.qos, true, payload).is_err() { self.dropped += 1; },
+                Err(msg) => { error!("Failed to serialize home assistant state {}", msg); return Err(()) }
+            }
+            self.last_state = Some((Instant::now(), state));
+        }
+    }
+
+    if self.last_health.is_none_or(|last| last.elapsed() >= HEALTH_INTERVAL)
+    {
+        self.last_health = Some(Instant::now());
+        let health = Health { uptime_s : self.started.elapsed().as_secs(), frames : self.sequence, dropped_messages : self.dropped };
+        self.send("health", true, &health)?;
+    }
+    Ok(())
+}
+
+}
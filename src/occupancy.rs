@@ -0,0 +1,228 @@
+// 2D occupancy grid in the ground plane of the sensor, updated with log odds: every point
+// marks its cell occupied and the cells on the line from the sensor to it free. The grid
+// is square, size meters wide with the sensor in the middle, and is written as a map_server
+// map, <path>.pgm and <path>.yaml, every few frames and when the viewer stops.
+// Map coordinates follow ROS: x forward (z of cloud.rs), y left (-x of cloud.rs).
+use crate::cloud::{PointCloud, Projection};
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::{Options, Publisher};
+use crate::sinks::Backpressure;
+
+use log::{error, info};
+
+use std::fs;
+use std::io;
+
+const DEFAULT_RESOLUTION : f32 = 0.05;
+const DEFAULT_SIZE : f32 = 10.0;
+const DEFAULT_EVERY : u64 = 10;
+
+const LOG_ODDS_HIT : f32 = 0.85;
+const LOG_ODDS_MISS : f32 = -0.4;
+const LOG_ODDS_LIMIT : f32 = 5.0;
+// map_server defaults
+const OCCUPIED_THRESHOLD : f32 = 0.65;
+const FREE_THRESHOLD : f32 = 0.196;
+
+pub struct OccupancyGrid
+{
+    pub resolution : f32,
+    pub cells : usize,
+    // row major, row 0 at the lowest map y
+    pub log_odds : Vec<f32>,
+}
+
+impl OccupancyGrid
+{
+
+pub fn new(resolution : f32, size : f32) -> OccupancyGrid
+{
+    let cells = ((size / resolution).ceil() as usize).max(1);
+    OccupancyGrid { resolution, cells, log_odds : vec![0.0; cells * cells] }
+}
+
+fn cell(&self, x : f32, y : f32) -> Option<(usize, usize)>
+{
+    let half = self.cells as f32 / 2.0;
+    let column = (x / self.resolution + half).floor();
+    let row = (y / self.resolution + half).floor();
+    if column < 0.0 || row < 0.0 || column >= self.cells as f32 || row >= self.cells as f32
+    {
+        return None
+    }
+    Some((column as usize, row as usize))
+}
+
+fn add(&mut self, (column, row) : (usize, usize), log_odds : f32)
+{
+    let cell = &mut self.log_odds[row * self.cells + column];
+    *cell = (*cell + log_odds).clamp(-LOG_ODDS_LIMIT, LOG_ODDS_LIMIT);
+}
+
+// points with a height above the sensor (-y) outside min_height..max_height are ignored
+pub fn update(&mut self, cloud : &PointCloud, min_height : f32, max_height : f32)
+{
+    let origin = match self.cell(0.0, 0.0)
+    {
+        Some(origin) => origin,
+        None => return,
+    };
+    for point in cloud.points.iter()
+    {
+        if -point[1] < min_height || -point[1] > max_height
+        {
+            continue;
+        }
+        let hit = match self.cell(point[2], -point[0])
+        {
+            Some(hit) => hit,
+            None => continue,
+        };
+        for free in line(origin, hit)
+        {
+            self.add(free, LOG_ODDS_MISS);
+        }
+        self.add(hit, LOG_ODDS_HIT);
+    }
+}
+
+pub fn probability(&self, cell : usize) -> f32
+{
+    1.0 - 1.0 / (1.0 + self.log_odds[cell].exp())
+}
+
+// trinary map_server image: 0 occupied, 254 free, 205 unknown
+pub fn save(&self, path : &str) -> io::Result<()>
+{
+    let mut pgm = format!("P5\n{} {}\n255\n", self.cells, self.cells).into_bytes();
+    for row in (0..self.cells).rev()
+    {
+        for column in 0..self.cells
+        {
+            let probability = self.probability(row * self.cells + column);
+            pgm.push(if probability > OCCUPIED_THRESHOLD { 0 } else if probability < FREE_THRESHOLD { 254 } else { 205 });
+        }
+    }
+    let image = format!("{}.pgm", path);
+    fs::write(&image, pgm)?;
+    let origin = -(self.cells as f32) * self.resolution / 2.0;
+    let name = image.rsplit('/').next().unwrap_or(&image);
+    let yaml = format!("image: {}\nresolution: {}\norigin: [{}, {}, 0.0]\nnegate: 0\noccupied_thresh: {}\nfree_thresh: {}\n",
+        name, self.resolution, origin, origin, OCCUPIED_THRESHOLD, FREE_THRESHOLD);
+    fs::write(format!("{}.yaml", path), yaml)
+}
+
+}
+
+// the cells from start up to, not including, end (Bresenham)
+fn line(start : (usize, usize), end : (usize, usize)) -> impl Iterator<Item = (usize, usize)>
+{
+    let (mut x, mut y) = (start.0 as i64, start.1 as i64);
+    let (end_x, end_y) = (end.0 as i64, end.1 as i64);
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+    std::iter::from_fn(move ||
+    {
+        if x == end_x && y == end_y
+        {
+            return None
+        }
+        let cell = (x as usize, y as usize);
+        let doubled = 2 * error;
+        if doubled >= dy
+        {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx
+        {
+            error += dx;
+            y += step_y;
+        }
+        Some(cell)
+    })
+}
+
+pub struct MapPublisher
+{
+    path : String,
+    every : u64,
+    min_height : f32,
+    max_height : f32,
+    frames : u64,
+    grid : OccupancyGrid,
+    projection : Projection,
+}
+
+impl MapPublisher
+{
+
+// options: resolution=<meters per cell>, size=<meters>, every=<frames between saves>,
+// min_height=<meters>, max_height=<meters>
+pub fn new(path : &str, options : &Options) -> Result<MapPublisher, String>
+{
+    let mut resolution = DEFAULT_RESOLUTION;
+    let mut size = DEFAULT_SIZE;
+    let mut every = DEFAULT_EVERY;
+    let mut min_height = f32::MIN;
+    let mut max_height = f32::MAX;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "resolution" => resolution = value.parse().ok().filter(|resolution| *resolution > 0.0).ok_or(format!("invalid resolution {}", value))?,
+            "size" => size = value.parse().ok().filter(|size| *size > 0.0).ok_or(format!("invalid size {}", value))?,
+            "every" => every = value.parse().ok().filter(|every| *every > 0).ok_or(format!("invalid value for every {}", value))?,
+            "min_height" => min_height = value.parse().map_err(|_| format!("invalid value for min_height {}", value))?,
+            "max_height" => max_height = value.parse().map_err(|_| format!("invalid value for max_height {}", value))?,
+            _ => return Err(format!("map publisher has no option {}", name)),
+        }
+    }
+    let grid = OccupancyGrid::new(resolution, size);
+    grid.save(path).map_err(|msg| format!("failed to write map {}, {}", path, msg))?;
+    info!("Writing occupancy grid to {}.pgm", path);
+    Ok(MapPublisher { path : path.to_string(), every, min_height, max_height, frames : 0, grid, projection : Projection::default() })
+}
+
+}
+
+impl Publisher for MapPublisher
+{
+
+// the map is built up from every frame
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    self.grid.update(&self.projection.project_pooled(depth), self.min_height, self.max_height);
+    self.frames += 1;
+    if self.frames.is_multiple_of(self.every)
+    {
+        if let Err(msg) = self.grid.save(&self.path)
+        {
+            error!("Failed to write map {}, {}", self.path, msg);
+            return Err(())
+        }
+    }
+    Ok(())
+}
+
+}
+
+impl Drop for MapPublisher
+{
+    fn drop(&mut self)
+    {
+        if let Err(msg) = self.grid.save(&self.path)
+        {
+            error!("Failed to write map {}, {}", self.path, msg);
+        }
+    }
+}
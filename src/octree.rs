@@ -0,0 +1,294 @@
+// Octree over a growing cloud, as used for scanning. Space is split down to cubes of leaf
+// meters, the voxels, and points falling into the same voxel are merged into their mean
+// as they are inserted, so the tree holds one point per voxel however many frames went
+// into it. The root grows by doubling towards points outside it. Region and frustum
+// queries only descend into the cubes that overlap what is asked for.
+use crate::cloud::PointCloud;
+use crate::icp::Transform;
+
+const NONE : u32 = u32::MAX;
+
+enum Node
+{
+    Branch([u32; 8]),
+    Leaf(u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Voxel
+{
+    // mean of the points merged into the voxel
+    pub point : [f32; 3],
+    pub count : u32,
+    // of the first point, see PointCloud
+    pub pixel : usize,
+}
+
+pub struct Octree
+{
+    leaf : f32,
+    // min corner of the root cube, which is leaf * 2^depth wide
+    origin : [f32; 3],
+    depth : u32,
+    root : u32,
+    nodes : Vec<Node>,
+    voxels : Vec<Voxel>,
+}
+
+impl Octree
+{
+
+pub fn new(leaf : f32) -> Octree
+{
+    Octree { leaf, origin : [0.0; 3], depth : 0, root : NONE, nodes : Vec::new(), voxels : Vec::new() }
+}
+
+pub fn leaf(&self) -> f32
+{
+    self.leaf
+}
+
+pub fn len(&self) -> usize
+{
+    self.voxels.len()
+}
+
+pub fn is_empty(&self) -> bool
+{
+    self.voxels.is_empty()
+}
+
+pub fn voxels(&self) -> &[Voxel]
+{
+    &self.voxels
+}
+
+fn size(&self) -> f32
+{
+    self.leaf * (1u64 << self.depth) as f32
+}
+
+fn contains(&self, point : [f32; 3]) -> bool
+{
+    let size = self.size();
+    (0..3).all(|axis| point[axis] >= self.origin[axis] && point[axis] < self.origin[axis] + size)
+}
+
+fn leaf_node(&mut self, point : [f32; 3], pixel : usize) -> u32
+{
+    self.voxels.push(Voxel { point, count : 1, pixel });
+    self.nodes.push(Node::Leaf(self.voxels.len() as u32 - 1));
+    self.nodes.len() as u32 - 1
+}
+
+// doubles the root towards point, the old root becomes one of the eight children
+fn grow(&mut self, point : [f32; 3])
+{
+    let size = self.size();
+    let mut octant = 0;
+    for (axis, (value, origin)) in point.iter().zip(self.origin.iter_mut()).enumerate()
+    {
+        if *value < *origin
+        {
+            *origin -= size;
+            octant |= 1 << axis;
+        }
+    }
+    let mut children = [NONE; 8];
+    children[octant] = self.root;
+    self.nodes.push(Node::Branch(children));
+    self.root = self.nodes.len() as u32 - 1;
+    self.depth += 1;
+}
+
+// points that aren't finite are left out
+pub fn insert(&mut self, point : [f32; 3], pixel : usize)
+{
+    if !point.iter().all(|value| value.is_finite())
+    {
+        return
+    }
+    if self.root == NONE
+    {
+        self.origin = point.map(|value| (value / self.leaf).floor() * self.leaf);
+        self.root = self.leaf_node(point, pixel);
+        return
+    }
+    while !self.contains(point)
+    {
+        self.grow(point);
+    }
+    let mut node = self.root;
+    let mut origin = self.origin;
+    let mut size = self.size();
+    for level in (0..self.depth).rev()
+    {
+        size /= 2.0;
+        let mut octant = 0;
+        for axis in 0..3
+        {
+            if point[axis] >= origin[axis] + size
+            {
+                origin[axis] += size;
+                octant |= 1 << axis;
+            }
+        }
+        let child = match &self.nodes[node as usize]
+        {
+            Node::Branch(children) => children[octant],
+            Node::Leaf(_) => unreachable!("leaves are only at level 0"),
+        };
+        if child != NONE
+        {
+            node = child;
+            continue;
+        }
+        let created = if level == 0
+        {
+            self.leaf_node(point, pixel)
+        }
+        else
+        {
+            self.nodes.push(Node::Branch([NONE; 8]));
+            self.nodes.len() as u32 - 1
+        };
+        if let Node::Branch(children) = &mut self.nodes[node as usize]
+        {
+            children[octant] = created;
+        }
+        if level == 0
+        {
+            return
+        }
+        node = created;
+    }
+    if let Node::Leaf(voxel) = self.nodes[node as usize]
+    {
+        let voxel = &mut self.voxels[voxel as usize];
+        voxel.count += 1;
+        let weight = 1.0 / voxel.count as f32;
+        for (mean, value) in voxel.point.iter_mut().zip(point)
+        {
+            *mean += (value - *mean) * weight;
+        }
+    }
+}
+
+// every point of cloud moved by pose
+pub fn insert_cloud(&mut self, cloud : &PointCloud, pose : &Transform)
+{
+    for (point, pixel) in cloud.points.iter().zip(&cloud.pixels)
+    {
+        self.insert(pose.apply(*point), *pixel);
+    }
+}
+
+// the voxel means as an unorganized cloud
+pub fn to_cloud(&self) -> PointCloud
+{
+    PointCloud
+    {
+        points : self.voxels.iter().map(|voxel| voxel.point).collect(),
+        pixels : self.voxels.iter().map(|voxel| voxel.pixel).collect(),
+        width : 0,
+    }
+}
+
+// indices into voxels of those with their point in the box from min to max
+pub fn within_box(&self, min : [f32; 3], max : [f32; 3], found : &mut Vec<usize>)
+{
+    found.clear();
+    let overlaps = |origin : [f32; 3], size : f32| (0..3).all(|axis| origin[axis] <= max[axis] && origin[axis] + size >= min[axis]);
+    let contains = |point : [f32; 3]| (0..3).all(|axis| point[axis] >= min[axis] && point[axis] <= max[axis]);
+    self.visit(self.root, self.origin, self.size(), &overlaps, &contains, found);
+}
+
+// indices into voxels of those with their point in frustum, e.g. for drawing what a
+// camera sees of the map
+pub fn in_frustum(&self, frustum : &Frustum, found : &mut Vec<usize>)
+{
+    found.clear();
+    self.visit(self.root, self.origin, self.size(), &|origin, size| frustum.overlaps(origin, size), &|point| frustum.contains(point), found);
+}
+
+fn visit(&self, node : u32, origin : [f32; 3], size : f32, overlaps : &dyn Fn([f32; 3], f32) -> bool, contains : &dyn Fn([f32; 3]) -> bool, found : &mut Vec<usize>)
+{
+    if node == NONE || !overlaps(origin, size)
+    {
+        return
+    }
+    match &self.nodes[node as usize]
+    {
+        Node::Leaf(voxel) =>
+        {
+            if contains(self.voxels[*voxel as usize].point)
+            {
+                found.push(*voxel as usize);
+            }
+        },
+        Node::Branch(children) =>
+        {
+            let half = size / 2.0;
+            for (octant, child) in children.iter().enumerate()
+            {
+                let origin = [0, 1, 2].map(|axis| if octant & 1 << axis != 0 { origin[axis] + half } else { origin[axis] });
+                self.visit(*child, origin, half, overlaps, contains, found);
+            }
+        },
+    }
+}
+
+}
+
+// The space a sensor or camera sees, as six planes (normal, offset) with the inside where
+// normal . point + offset >= 0
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum
+{
+    planes : [[f32; 4]; 6],
+}
+
+impl Frustum
+{
+
+// looking along z from pose, x to the right and y down as in cloud.rs, seeing from near
+// to far meters
+pub fn new(pose : &Transform, fov_h_deg : f32, fov_v_deg : f32, near : f32, far : f32) -> Frustum
+{
+    let (sin_h, cos_h) = (fov_h_deg.to_radians() / 2.0).sin_cos();
+    let (sin_v, cos_v) = (fov_v_deg.to_radians() / 2.0).sin_cos();
+    let planes = [
+        [0.0, 0.0, 1.0, -near],
+        [0.0, 0.0, -1.0, far],
+        [cos_h, 0.0, sin_h, 0.0],
+        [-cos_h, 0.0, sin_h, 0.0],
+        [0.0, cos_v, sin_v, 0.0],
+        [0.0, -cos_v, sin_v, 0.0],
+    ];
+    // from the sensor's coordinates into the map's, the rotation keeps normals unit length
+    Frustum { planes : planes.map(|[x, y, z, offset]|
+    {
+        let r = &pose.rotation;
+        let normal = [0, 1, 2].map(|row| r[row][0] * x + r[row][1] * y + r[row][2] * z);
+        let moved = normal[0] * pose.translation[0] + normal[1] * pose.translation[1] + normal[2] * pose.translation[2];
+        [normal[0], normal[1], normal[2], offset - moved]
+    }) }
+}
+
+pub fn contains(&self, point : [f32; 3]) -> bool
+{
+    self.planes.iter().all(|plane| plane[0] * point[0] + plane[1] * point[1] + plane[2] * point[2] + plane[3] >= 0.0)
+}
+
+// whether any of the cube from origin size wide may be inside, tested with the corner
+// furthest along each plane's normal
+pub fn overlaps(&self, origin : [f32; 3], size : f32) -> bool
+{
+    self.planes.iter().all(|plane|
+    {
+        let corner = [0, 1, 2].map(|axis| if plane[axis] > 0.0 { origin[axis] + size } else { origin[axis] });
+        plane[0] * corner[0] + plane[1] * corner[1] + plane[2] * corner[2] + plane[3] >= 0.0
+    })
+}
+
+}
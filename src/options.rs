@@ -0,0 +1,28 @@
+// Options of publish targets and filters, given as name?option=value&...
+pub type Options<'a> = Vec<(&'a str, &'a str)>;
+
+pub fn parse(query : &str) -> Result<Options<'_>, String>
+{
+    query.split('&').filter(|pair| !pair.is_empty()).map(|pair| match pair.split_once('=')
+    {
+        Some(option) => Ok(option),
+        None => Err(format!("option {} has no value", pair)),
+    }).collect()
+}
+
+// splits what comes before the ? from the options after it
+pub fn split(spec : &str) -> Result<(&str, Options<'_>), String>
+{
+    match spec.split_once('?')
+    {
+        Some((name, query)) => Ok((name, parse(query)?)),
+        None => Ok((spec, Vec::new())),
+    }
+}
+
+// a value holding exactly N comma separated numbers, e.g. a region x0,y0,x1,y1
+pub fn list<T : std::str::FromStr, const N : usize>(value : &str) -> Option<[T; N]>
+{
+    let values : Vec<T> = value.split(',').map(|value| value.trim().parse().ok()).collect::<Option<_>>()?;
+    values.try_into().ok()
+}
@@ -0,0 +1,146 @@
+use crate::frame::Frame;
+use crate::depth::DepthFrame;
+use crate::publish::{Options, Publisher};
+
+use log::warn;
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+// Every frame goes out as one OSC bundle, timetag "immediately", with two messages:
+//   <prefix>/frame  i sequence, i valid points, f min, f max, f mean distance
+//   <prefix>/grid   i cols, i rows, cols * rows f mean distance per block, row major
+// Distances are in meters, -1 where there are no valid pixels.
+const DEFAULT_PREFIX : &str = "/lidar";
+const DEFAULT_GRID : (usize, usize) = (16, 6);
+
+enum Argument
+{
+    Int(i32),
+    Float(f32),
+}
+
+fn pad(buffer : &mut Vec<u8>)
+{
+    while !buffer.len().is_multiple_of(4)
+    {
+        buffer.push(0);
+    }
+}
+
+fn push_string(buffer : &mut Vec<u8>, string : &str)
+{
+    buffer.extend_from_slice(string.as_bytes());
+    buffer.push(0);
+    pad(buffer);
+}
+
+fn message(address : &str, arguments : &[Argument]) -> Vec<u8>
+{
+    let mut buffer = Vec::with_capacity(address.len() + arguments.len() * 5 + 8);
+    push_string(&mut buffer, address);
+    let tags : String = std::iter::once(',').chain(arguments.iter().map(|argument| match argument
+    {
+        Argument::Int(_) => 'i',
+        Argument::Float(_) => 'f',
+    })).collect();
+    push_string(&mut buffer, &tags);
+    for argument in arguments
+    {
+        match argument
+        {
+            Argument::Int(value) => buffer.extend_from_slice(&value.to_be_bytes()),
+            Argument::Float(value) => buffer.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+    buffer
+}
+
+fn bundle(messages : &[Vec<u8>]) -> Vec<u8>
+{
+    let mut buffer = Vec::new();
+    push_string(&mut buffer, "#bundle");
+    buffer.extend_from_slice(&1u64.to_be_bytes());
+    for message in messages
+    {
+        buffer.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        buffer.extend_from_slice(message);
+    }
+    buffer
+}
+
+fn meters(distance : Option<f32>) -> Argument
+{
+    Argument::Float(distance.map_or(-1.0, |distance| distance / 1000.0))
+}
+
+pub struct OscPublisher
+{
+    socket : UdpSocket,
+    target : SocketAddr,
+    prefix : String,
+    grid : (usize, usize),
+    sequence : i32,
+}
+
+impl OscPublisher
+{
+
+// options: prefix=<address prefix>, grid=<cols>x<rows>
+pub fn new(address : &str, options : &Options) -> Result<OscPublisher, String>
+{
+    let mut prefix = DEFAULT_PREFIX.to_string();
+    let mut grid = DEFAULT_GRID;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "prefix" => prefix = format!("/{}", value.trim_matches('/')),
+            "grid" => grid = match value.split_once('x').map(|(cols, rows)| (cols.parse(), rows.parse()))
+            {
+                Some((Ok(cols), Ok(rows))) if cols > 0 && rows > 0 => (cols, rows),
+                _ => return Err(format!("invalid grid {}, expected <cols>x<rows>", value)),
+            },
+            _ => return Err(format!("osc publisher has no option {}", name)),
+        }
+    }
+    let target = match address.to_socket_addrs().map_err(|msg| msg.to_string())?.next()
+    {
+        Some(target) => target,
+        None => return Err(format!("{} did not resolve", address)),
+    };
+    let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local).map_err(|msg| msg.to_string())?;
+    Ok(OscPublisher { socket, target, prefix, grid, sequence : 0 })
+}
+
+}
+
+impl Publisher for OscPublisher
+{
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let summary = depth.summary();
+    let frame = message(&format!("{}/frame", self.prefix), &[
+        Argument::Int(self.sequence),
+        Argument::Int(summary.valid_points as i32),
+        meters(summary.min_mm.map(|min| min as f32)),
+        meters(summary.max_mm.map(|max| max as f32)),
+        meters(summary.mean_mm),
+    ]);
+    self.sequence = self.sequence.wrapping_add(1);
+
+    let (cols, rows) = self.grid;
+    let mut arguments = vec![Argument::Int(cols as i32), Argument::Int(rows as i32)];
+    arguments.extend(depth.downsample(cols, rows).into_iter().map(meters));
+    let grid = message(&format!("{}/grid", self.prefix), &arguments);
+
+    if let Err(msg) = self.socket.send_to(&bundle(&[frame, grid]), self.target)
+    {
+        warn!("Failed to send osc bundle to {}, {}", self.target, msg);
+        return Err(())
+    }
+    Ok(())
+}
+
+}
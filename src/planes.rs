@@ -0,0 +1,141 @@
+// RANSAC plane segmentation. Planes are found one after another, each from the points not
+// taken by the planes before it: the plane through three random points that has the most
+// points within threshold meters wins. The sampling is seeded so results are repeatable.
+use crate::cloud::PointCloud;
+
+pub const DEFAULT_THRESHOLD : f32 = 0.02;
+pub const DEFAULT_ITERATIONS : usize = 200;
+
+#[derive(Clone, Debug)]
+pub struct Plane
+{
+    // unit normal and offset, normal . p + d = 0 for points p on the plane
+    pub normal : [f32; 3],
+    pub d : f32,
+    // by point of the cloud given to segment_planes
+    pub inliers : Vec<bool>,
+}
+
+impl Plane
+{
+
+pub fn distance(&self, point : [f32; 3]) -> f32
+{
+    self.normal[0] * point[0] + self.normal[1] * point[1] + self.normal[2] * point[2] + self.d
+}
+
+pub fn inlier_count(&self) -> usize
+{
+    self.inliers.iter().filter(|inlier| **inlier).count()
+}
+
+}
+
+// up to max_planes planes with at least min_inliers points each, largest first
+pub fn segment_planes(cloud : &PointCloud, max_planes : usize, threshold : f32, iterations : usize, min_inliers : usize) -> Vec<Plane>
+{
+    let mut random = Random(0x2545f4914f6cdd1d);
+    let mut taken = vec![false; cloud.len()];
+    let mut planes = Vec::new();
+    while planes.len() < max_planes
+    {
+        let remaining : Vec<usize> = (0..cloud.len()).filter(|index| !taken[*index]).collect();
+        if remaining.len() < min_inliers.max(3)
+        {
+            break;
+        }
+        let best = ransac(cloud, &remaining, threshold, iterations, &mut random, |_| true);
+        let (normal, d) = match best
+        {
+            Some((normal, d, count)) if count >= min_inliers => (normal, d),
+            _ => break,
+        };
+        let mut inliers = vec![false; cloud.len()];
+        for index in remaining
+        {
+            if within(normal, d, cloud.points[index], threshold)
+            {
+                inliers[index] = true;
+                taken[index] = true;
+            }
+        }
+        planes.push(Plane { normal, d, inliers });
+    }
+    planes
+}
+
+// the ground is the largest plane whose normal is within max_tilt_deg of the y axis, the
+// returned normal points up (towards -y) so distance() is the height above it
+pub fn find_ground(cloud : &PointCloud, threshold : f32, iterations : usize, max_tilt_deg : f32) -> Option<Plane>
+{
+    let mut random = Random(0x2545f4914f6cdd1d);
+    let all : Vec<usize> = (0..cloud.len()).collect();
+    let min_y = max_tilt_deg.to_radians().cos();
+    let (normal, d, _) = ransac(cloud, &all, threshold, iterations, &mut random, |normal| normal[1].abs() >= min_y)?;
+    let (normal, d) = if normal[1] > 0.0 { ([-normal[0], -normal[1], -normal[2]], -d) } else { (normal, d) };
+    let inliers = cloud.points.iter().map(|point| within(normal, d, *point, threshold)).collect();
+    Some(Plane { normal, d, inliers })
+}
+
+// the plane through three of the candidates with the most candidates within threshold,
+// of those whose normal is accepted
+fn ransac(cloud : &PointCloud, candidates : &[usize], threshold : f32, iterations : usize, random : &mut Random,
+    accept : impl Fn([f32; 3]) -> bool) -> Option<([f32; 3], f32, usize)>
+{
+    if candidates.len() < 3
+    {
+        return None
+    }
+    let mut best : Option<([f32; 3], f32, usize)> = None;
+    for _ in 0..iterations
+    {
+        let sample = [0, 1, 2].map(|_| cloud.points[candidates[random.below(candidates.len())]]);
+        let (normal, d) = match plane_through(sample)
+        {
+            Some(plane) if accept(plane.0) => plane,
+            _ => continue,
+        };
+        let count = candidates.iter().filter(|index| within(normal, d, cloud.points[**index], threshold)).count();
+        if best.is_none_or(|(_, _, best)| count > best)
+        {
+            best = Some((normal, d, count));
+        }
+    }
+    best
+}
+
+fn plane_through(points : [[f32; 3]; 3]) -> Option<([f32; 3], f32)>
+{
+    let u = [points[1][0] - points[0][0], points[1][1] - points[0][1], points[1][2] - points[0][2]];
+    let v = [points[2][0] - points[0][0], points[2][1] - points[0][1], points[2][2] - points[0][2]];
+    let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if length < 1e-9
+    {
+        return None
+    }
+    let normal = [normal[0] / length, normal[1] / length, normal[2] / length];
+    let d = -(normal[0] * points[0][0] + normal[1] * points[0][1] + normal[2] * points[0][2]);
+    Some((normal, d))
+}
+
+fn within(normal : [f32; 3], d : f32, point : [f32; 3], threshold : f32) -> bool
+{
+    (normal[0] * point[0] + normal[1] * point[1] + normal[2] * point[2] + d).abs() <= threshold
+}
+
+// xorshift64, plenty for picking samples
+struct Random(u64);
+
+impl Random
+{
+
+fn below(&mut self, bound : usize) -> usize
+{
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    (self.0 % bound as u64) as usize
+}
+
+}
@@ -0,0 +1,182 @@
+// Filters from shared libraries in the plugins directory, see
+// include/rusty_lidar_viewer_plugin.h for the ABI. Each library exports
+// lidar_filter_plugin() returning a table with the ABI version, the filter name used as
+// type in [[pipeline.stage]] and the functions creating, applying and destroying a filter.
+// Libraries stay loaded as long as a filter made by them is alive.
+use crate::depth::DepthFrame;
+use crate::filters::{self, Filter};
+use crate::options::Options;
+
+use log::info;
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+pub const ABI_VERSION : u32 = 1;
+const ENTRY_POINT : &CStr = c"lidar_filter_plugin";
+const RTLD_NOW : c_int = 2;
+
+#[repr(C)]
+pub struct FilterPlugin
+{
+    pub abi_version : u32,
+    pub name : *const c_char,
+    pub create : unsafe extern "C" fn(options : *const c_char) -> *mut c_void,
+    pub apply : unsafe extern "C" fn(state : *mut c_void, data : *mut u16, width : u32, height : u32),
+    pub destroy : unsafe extern "C" fn(state : *mut c_void),
+}
+
+extern "C"
+{
+    fn dlopen(filename : *const c_char, flags : c_int) -> *mut c_void;
+    fn dlsym(handle : *mut c_void, symbol : *const c_char) -> *mut c_void;
+    fn dlclose(handle : *mut c_void) -> c_int;
+    fn dlerror() -> *const c_char;
+}
+
+fn last_error() -> String
+{
+    // SAFETY: dlerror returns NULL or a NUL terminated string
+    let error = unsafe { dlerror() };
+    if error.is_null() { "unknown error".to_string() } else { unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned() }
+}
+
+struct Library
+{
+    handle : *mut c_void,
+    plugin : *const FilterPlugin,
+}
+
+impl Library
+{
+
+fn open(path : &Path) -> Result<Library, String>
+{
+    let filename = CString::new(path.to_string_lossy().as_bytes()).map_err(|msg| msg.to_string())?;
+    // SAFETY: loading a library runs its initializers, plugins are trusted like the binary
+    let handle = unsafe { dlopen(filename.as_ptr(), RTLD_NOW) };
+    if handle.is_null()
+    {
+        return Err(last_error())
+    }
+    // closes the library again on the errors below
+    let mut library = Library { handle, plugin : std::ptr::null() };
+    let entry = unsafe { dlsym(handle, ENTRY_POINT.as_ptr()) };
+    if entry.is_null()
+    {
+        return Err(format!("no {} in {}", ENTRY_POINT.to_string_lossy(), path.display()))
+    }
+    // SAFETY: the ABI says the entry point has this signature
+    let entry : unsafe extern "C" fn() -> *const FilterPlugin = unsafe { std::mem::transmute(entry) };
+    let plugin = unsafe { entry() };
+    if plugin.is_null()
+    {
+        return Err(format!("{} returned no plugin", path.display()))
+    }
+    let version = unsafe { (*plugin).abi_version };
+    if version != ABI_VERSION || unsafe { (*plugin).name }.is_null()
+    {
+        return Err(format!("{} has plugin ABI version {}, not {}", path.display(), version, ABI_VERSION))
+    }
+    library.plugin = plugin;
+    Ok(library)
+}
+
+fn name(&self) -> String
+{
+    // SAFETY: checked to be non NULL in open, the table lives as long as the library
+    unsafe { CStr::from_ptr((*self.plugin).name) }.to_string_lossy().into_owned()
+}
+
+}
+
+impl Drop for Library
+{
+    fn drop(&mut self)
+    {
+        unsafe { dlclose(self.handle) };
+    }
+}
+
+pub struct Plugins
+{
+    libraries : Vec<(String, Rc<Library>)>,
+}
+
+impl Plugins
+{
+
+// every .so and .dylib in directory, none if there is no such directory
+pub fn load(directory : &str) -> Result<Plugins, String>
+{
+    if !Path::new(directory).is_dir()
+    {
+        return Ok(Plugins { libraries : Vec::new() })
+    }
+    let entries = fs::read_dir(directory).map_err(|msg| format!("failed to read plugins directory {}, {}", directory, msg))?;
+    let mut libraries : Vec<(String, Rc<Library>)> = Vec::new();
+    for entry in entries
+    {
+        let path = entry.map_err(|msg| msg.to_string())?.path();
+        if !path.extension().is_some_and(|extension| extension == "so" || extension == "dylib")
+        {
+            continue;
+        }
+        let library = Library::open(&path).map_err(|msg| format!("failed to load plugin {}, {}", path.display(), msg))?;
+        let name = library.name();
+        if filters::NAMES.contains(&name.as_str()) || libraries.iter().any(|(loaded, _)| *loaded == name)
+        {
+            return Err(format!("plugin {} from {} is already a filter", name, path.display()))
+        }
+        info!("Loaded filter plugin {} from {}", name, path.display());
+        libraries.push((name, Rc::new(library)));
+    }
+    Ok(Plugins { libraries })
+}
+
+pub fn create(&self, name : &str, options : &Options) -> Result<Box<dyn Filter>, String>
+{
+    let library = match self.libraries.iter().find(|(loaded, _)| loaded == name)
+    {
+        Some((_, library)) => library.clone(),
+        None => return Err(format!("unknown filter {}", name)),
+    };
+    let joined = options.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&");
+    let joined = CString::new(joined).map_err(|msg| msg.to_string())?;
+    // SAFETY: the table was checked when loading and joined is NUL terminated
+    let state = unsafe { ((*library.plugin).create)(joined.as_ptr()) };
+    if state.is_null()
+    {
+        return Err(format!("plugin {} rejected options {}", name, joined.to_string_lossy()))
+    }
+    Ok(Box::new(PluginFilter { library, state }))
+}
+
+}
+
+struct PluginFilter
+{
+    library : Rc<Library>,
+    state : *mut c_void,
+}
+
+impl Filter for PluginFilter
+{
+
+fn apply(&mut self, depth : &mut DepthFrame)
+{
+    // SAFETY: state came from create of the same plugin, data holds width * height distances
+    unsafe { ((*self.library.plugin).apply)(self.state, depth.data.as_mut_ptr(), depth.width as u32, depth.height as u32) };
+}
+
+}
+
+impl Drop for PluginFilter
+{
+    fn drop(&mut self)
+    {
+        unsafe { ((*self.library.plugin).destroy)(self.state) };
+    }
+}
@@ -0,0 +1,92 @@
+// Pools of frame sized buffers, so the per frame work doesn't allocate once running. A
+// buffer taken from a pool goes back when its Pooled handle is dropped, wherever that is,
+// and comes out again holding whatever it held last, with its capacity. Pools keep at
+// most MAX_FREE buffers, the rest are freed.
+use crate::cloud::PointCloud;
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+const MAX_FREE : usize = 16;
+
+pub static FRAMES : FramePool<Frame> = FramePool::new();
+pub static DEPTH_FRAMES : FramePool<DepthFrame> = FramePool::new();
+pub static CLOUDS : FramePool<PointCloud> = FramePool::new();
+pub static BYTES : FramePool<Vec<u8>> = FramePool::new();
+
+pub struct FramePool<T>
+{
+    free : Mutex<Vec<T>>,
+}
+
+impl<T> Default for FramePool<T>
+{
+    fn default() -> Self
+    {
+        FramePool::new()
+    }
+}
+
+impl<T> FramePool<T>
+{
+
+pub const fn new() -> FramePool<T>
+{
+    FramePool { free : Mutex::new(Vec::new()) }
+}
+
+pub fn get(&'static self) -> Pooled<T>
+    where T : Default
+{
+    let value = self.free.lock().unwrap().pop().unwrap_or_default();
+    Pooled { value : Some(value), pool : self }
+}
+
+fn put(&self, value : T)
+{
+    let mut free = self.free.lock().unwrap();
+    if free.len() < MAX_FREE
+    {
+        free.push(value);
+    }
+}
+
+}
+
+pub struct Pooled<T : 'static>
+{
+    // only None while being dropped
+    value : Option<T>,
+    pool : &'static FramePool<T>,
+}
+
+impl<T> Deref for Pooled<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Pooled<T>
+{
+    fn deref_mut(&mut self) -> &mut T
+    {
+        self.value.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Pooled<T>
+{
+    fn drop(&mut self)
+    {
+        if let Some(value) = self.value.take()
+        {
+            self.pool.put(value);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// How far along a long run through a recording is, e.g. export, on stderr: a bar redrawn
+// in place at most every REDRAW_INTERVAL when stderr is a terminal, a log line every
+// tenth of the way when it isn't, as in a service's journal. Nothing is shown below the
+// info log level, with -q. Log lines written meanwhile clear the bar first, the next
+// redraw puts it back.
+use log::{info, LevelFilter};
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const REDRAW_INTERVAL : Duration = Duration::from_millis(100);
+const WIDTH : usize = 30;
+
+// whether a bar is on the last line of stderr, for the logger
+static SHOWN : AtomicBool = AtomicBool::new(false);
+
+pub struct Progress
+{
+    what : &'static str,
+    total : usize,
+    done : usize,
+    started : Instant,
+    drawn : Option<Instant>,
+    terminal : bool,
+    shown : bool,
+}
+
+impl Progress
+{
+
+// what is counted, e.g. frames
+pub fn new(what : &'static str, total : usize) -> Progress
+{
+    Progress
+    {
+        what,
+        total,
+        done : 0,
+        started : Instant::now(),
+        drawn : None,
+        terminal : io::stderr().is_terminal(),
+        shown : log::max_level() >= LevelFilter::Info,
+    }
+}
+
+pub fn advance(&mut self)
+{
+    self.done += 1;
+    if !self.shown
+    {
+        return
+    }
+    if !self.terminal
+    {
+        let tenth = |done : usize| done * 10 / self.total.max(1);
+        if tenth(self.done) != tenth(self.done - 1)
+        {
+            info!("{} of {} {}, {:.0}%", self.done, self.total, self.what, self.percent());
+        }
+        return
+    }
+    if self.drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL) || self.done == self.total
+    {
+        self.drawn = Some(Instant::now());
+        self.draw();
+    }
+}
+
+fn percent(&self) -> f64
+{
+    100.0 * self.done as f64 / self.total.max(1) as f64
+}
+
+fn draw(&self)
+{
+    let filled = (WIDTH * self.done / self.total.max(1)).min(WIDTH);
+    let elapsed = self.started.elapsed().as_secs_f64();
+    let rate = self.done as f64 / elapsed.max(f64::EPSILON);
+    let left = self.total.saturating_sub(self.done) as f64 / rate.max(f64::EPSILON);
+    let _ = write!(io::stderr().lock(), "\r[{}{}] {:3.0}% {}/{} {}, {:.1}/s, {:.0} s left\x1b[K",
+        "#".repeat(filled), ".".repeat(WIDTH - filled), self.percent(), self.done, self.total, self.what, rate, left);
+    SHOWN.store(true, Ordering::SeqCst);
+}
+
+}
+
+// the bar stays up with where it ended
+impl Drop for Progress
+{
+    fn drop(&mut self)
+    {
+        if SHOWN.swap(false, Ordering::SeqCst)
+        {
+            let _ = writeln!(io::stderr().lock());
+        }
+    }
+}
+
+// clears the bar for a line written to stderr
+pub fn clear()
+{
+    if SHOWN.swap(false, Ordering::SeqCst)
+    {
+        let _ = write!(io::stderr().lock(), "\r\x1b[K");
+    }
+}
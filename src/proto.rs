@@ -0,0 +1,189 @@
+use crate::analysis::Analysis;
+use crate::analysis::motion::MotionKind;
+use crate::analysis::zones::ZoneEvent;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+
+use prost::Message as _;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// generated from proto/rusty_lidar_viewer.proto
+pub mod messages
+{
+    include!(concat!(env!("OUT_DIR"), "/rusty_lidar_viewer.rs"));
+}
+
+use messages::message::Body;
+
+const STATS_INTERVAL : Duration = Duration::from_secs(1);
+
+pub struct Encoder
+{
+    sequence : u64,
+    started : Instant,
+    last_stats : Option<Instant>,
+}
+
+impl Default for Encoder
+{
+    fn default() -> Self
+    {
+        Encoder::new()
+    }
+}
+
+impl Encoder
+{
+
+pub fn new() -> Encoder
+{
+    Encoder { sequence : 0, started : Instant::now(), last_stats : None }
+}
+
+pub fn device_info(&self, info : &DeviceInfo) -> Vec<u8>
+{
+    encode(Body::DeviceInfo(messages::DeviceInfo { firmware : info.firmware.clone(), hardware : info.hardware.clone() }))
+}
+
+// the depth frame, preceded by a stats message once every STATS_INTERVAL
+pub fn frame(&mut self, depth : &DepthFrame) -> Vec<Vec<u8>>
+{
+    let mut encoded = Vec::with_capacity(2);
+    if self.last_stats.is_none_or(|last| last.elapsed() >= STATS_INTERVAL)
+    {
+        self.last_stats = Some(Instant::now());
+        encoded.push(encode(Body::Stats(messages::Stats { uptime_s : self.started.elapsed().as_secs(), frames : self.sequence })));
+    }
+    encoded.push(encode(Body::DepthFrame(messages::DepthFrame
+    {
+        sequence : self.sequence,
+        timestamp_ms : SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0),
+        width : depth.width as u32,
+        height : depth.height as u32,
+        depth_mm : depth.data.iter().map(|distance| *distance as u32).collect(),
+    })));
+    self.sequence += 1;
+    encoded
+}
+
+// results for the frame sent last
+pub fn analysis(&self, analysis : &Analysis) -> Vec<u8>
+{
+    let sequence = self.sequence.saturating_sub(1);
+    match analysis
+    {
+        Analysis::Obstacles(obstacles) => encode(Body::Obstacles(messages::Obstacles
+        {
+            sequence,
+            obstacles : obstacles.iter().map(|obstacle| messages::Obstacle
+            {
+                centroid : obstacle.centroid.to_vec(),
+                bbox_min : obstacle.bbox[0].to_vec(),
+                bbox_max : obstacle.bbox[1].to_vec(),
+                point_count : obstacle.point_count as u32,
+            }).collect(),
+        })),
+        Analysis::Sectors(nearest) => encode(Body::Sectors(messages::Sectors
+        {
+            sequence,
+            nearest_mm : nearest.iter().map(|nearest| nearest.unwrap_or(0) as u32).collect(),
+        })),
+        Analysis::Foreground(foreground) => encode(Body::Foreground(messages::Foreground
+        {
+            sequence,
+            width : foreground.width as u32,
+            height : foreground.height as u32,
+            foreground_pixels : foreground.foreground_pixels as u32,
+            mask : foreground.mask.clone(),
+        })),
+        Analysis::Odometry(odometry) => encode(Body::Odometry(messages::Odometry
+        {
+            sequence,
+            pose_rotation : odometry.pose.rotation.concat(),
+            pose_translation : odometry.pose.translation.to_vec(),
+            step_rotation : odometry.step.rotation.concat(),
+            step_translation : odometry.step.translation.to_vec(),
+            rmse : odometry.rmse,
+            pairs : odometry.pairs as u32,
+        })),
+        Analysis::Motion(motion) => encode(Body::Motion(messages::Motion
+        {
+            sequence,
+            kind : match motion.kind
+            {
+                MotionKind::MotionStarted => messages::motion::Kind::MotionStarted,
+                MotionKind::MotionStopped => messages::motion::Kind::MotionStopped,
+            } as i32,
+            column_min : motion.region.column_min as u32,
+            row_min : motion.region.row_min as u32,
+            column_max : motion.region.column_max as u32,
+            row_max : motion.region.row_max as u32,
+            magnitude : motion.magnitude,
+            frames : motion.frames,
+        })),
+        Analysis::Script(outputs) => encode(Body::Script(messages::Script
+        {
+            sequence,
+            json : outputs.to_string(),
+        })),
+        Analysis::Zones(reports) => encode(Body::Zones(messages::Zones
+        {
+            sequence,
+            zones : reports.iter().map(|report| messages::Zone
+            {
+                name : report.name.clone(),
+                points : report.points as u32,
+                min_distance : report.min_distance.unwrap_or(0.0),
+                occupied : report.occupied,
+                event : match report.event
+                {
+                    None => messages::zone::Event::None,
+                    Some(ZoneEvent::Entered) => messages::zone::Event::Entered,
+                    Some(ZoneEvent::Left) => messages::zone::Event::Left,
+                } as i32,
+            }).collect(),
+        })),
+        Analysis::People(count) => encode(Body::People(messages::People
+        {
+            sequence,
+            tracked : count.tracked as u32,
+            in_total : count.in_total,
+            out_total : count.out_total,
+            crossed_in : count.crossed_in,
+            crossed_out : count.crossed_out,
+        })),
+        Analysis::Fall(fall) => encode(Body::Fall(messages::Fall
+        {
+            sequence,
+            track : fall.track,
+            position : fall.position.to_vec(),
+            height : fall.height,
+            fall_frames : fall.fall_frames,
+        })),
+        Analysis::Dimensions(dimensions) => encode(Body::Dimensions(messages::BoxDimensions
+        {
+            sequence,
+            length : dimensions.length,
+            width : dimensions.width,
+            height : dimensions.height,
+            tolerance : dimensions.tolerance.to_vec(),
+            center : dimensions.center.to_vec(),
+            point_count : dimensions.point_count as u32,
+        })),
+        Analysis::Level(level) => encode(Body::Level(messages::FillLevel
+        {
+            sequence,
+            percent : level.percent,
+            distance_mm : level.distance_mm as u32,
+            pixels : level.pixels as u32,
+        })),
+    }
+}
+
+}
+
+fn encode(body : Body) -> Vec<u8>
+{
+    messages::Message { body : Some(body) }.encode_to_vec()
+}
@@ -0,0 +1,268 @@
+use crate::analysis::Analysis;
+use crate::bridge::BridgePublisher;
+use crate::frame::Frame;
+use crate::delta;
+use crate::depth::{DepthFrame, HEIGHT_3D, WIDTH_3D};
+use crate::device::DeviceInfo;
+use crate::events::EventsPublisher;
+use crate::export::ExportPublisher;
+use crate::http::HttpPublisher;
+use crate::mqtt::MqttPublisher;
+use crate::occupancy::MapPublisher;
+use crate::options;
+use crate::osc::OscPublisher;
+use crate::proto;
+use crate::recording::RecordPublisher;
+use crate::rvl;
+use crate::scan::ScanPublisher;
+use crate::shm::ShmPublisher;
+use crate::sinks::{Backpressure, Throttle, MIN_FPS};
+use crate::stats::{Stats, STATS};
+use crate::tcp::TcpPublisher;
+use crate::udp::UdpPublisher;
+use crate::webhook::WebhookPublisher;
+use crate::ws::WsPublisher;
+
+use log::warn;
+
+pub use crate::options::Options;
+
+pub trait Publisher : Send
+{
+    fn device_info(&mut self, _info : &DeviceInfo) {}
+
+    // what the analyzers found in the frame published last
+    fn analysis(&mut self, _analysis : &Analysis) {}
+
+    fn publish(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<(), ()>;
+
+    // what to do when frames come faster than this publisher takes them, see sinks.rs
+    fn backpressure(&self) -> Backpressure
+    {
+        Backpressure::DropOldest
+    }
+}
+
+// frames an output's queue holds unless given queue=
+pub const DEFAULT_SINK_QUEUE : usize = 4;
+// frames a second sent to a preview unless given max_fps=
+pub const DEFAULT_PREVIEW_FPS : f64 = 2.0;
+
+// preview=<width>x<height> on udp, tcp and ws: the publisher is sent frames downsampled
+// to that size, at most WIDTH_3D x HEIGHT_3D, for watching over a slow link, next to publishers sending the full ones
+pub struct Preview
+{
+    pub width : usize,
+    pub height : usize,
+    depth : DepthFrame,
+}
+
+impl Preview
+{
+
+pub fn new(width : usize, height : usize) -> Preview
+{
+    Preview { width, height, depth : DepthFrame::default() }
+}
+
+}
+
+// A publisher with how the device loop feeds it
+pub struct Output
+{
+    pub name : String,
+    pub publisher : Box<dyn Publisher>,
+    pub backpressure : Backpressure,
+    pub queue : usize,
+    pub throttle : Throttle,
+    pub preview : Option<Preview>,
+}
+
+impl Output
+{
+
+pub fn new(name : &str, publisher : Box<dyn Publisher>) -> Output
+{
+    let backpressure = publisher.backpressure();
+    Output { name : name.to_string(), publisher, backpressure, queue : DEFAULT_SINK_QUEUE, throttle : Throttle::default(), preview : None }
+}
+
+pub fn publish(&mut self, frame : &Frame, depth : &DepthFrame, analyses : &[Analysis])
+{
+    // previews go with formats carrying their size, which don't send the frame as read
+    let depth = match self.preview.as_mut()
+    {
+        Some(preview) => { depth.preview_into(preview.width, preview.height, &mut preview.depth); &preview.depth },
+        None => depth,
+    };
+    if self.publisher.publish(frame, depth).is_err()
+    {
+        Stats::count(&STATS.publish_errors);
+        warn!(output = self.name.as_str(); "Failed to publish frame");
+    }
+    for analysis in analyses.iter()
+    {
+        self.publisher.analysis(analysis);
+    }
+}
+
+}
+
+// Targets are given as scheme://address[?option=value&...], e.g. udp://192.168.1.10:7777.
+// Besides their own options all take backpressure=block|drop_oldest|drop_newest and
+// queue=<frames>, for how the device loop feeds them, and decimate=<every nth frame> and
+// max_fps=<frames a second> to be sent fewer frames. udp, tcp and ws also take preview
+pub fn open(url : &str) -> Result<Output, String>
+{
+    let (scheme, target) = match url.split_once("://")
+    {
+        Some(parts) => parts,
+        None => return Err(format!("missing scheme in publish target {}", url)),
+    };
+    let (address, mut options) = options::split(target)?;
+    let mut backpressure = None;
+    let mut queue = DEFAULT_SINK_QUEUE;
+    let mut throttle = Throttle::default();
+    let mut preview = None;
+    for (name, value) in options.iter()
+    {
+        match *name
+        {
+            "backpressure" => backpressure = Some(Backpressure::parse(value)?),
+            "queue" => queue = value.parse().ok().filter(|queue| *queue > 0).ok_or_else(|| format!("invalid value for queue {}", value))?,
+            "decimate" => throttle.decimate = Some(value.parse().ok().filter(|decimate| *decimate > 0).ok_or_else(|| format!("invalid value for decimate {}", value))?),
+            "max_fps" => throttle.max_fps = Some(value.parse().ok().filter(|max_fps : &f64| *max_fps >= MIN_FPS).ok_or_else(|| format!("invalid value for max_fps {}, expected at least {}", value, MIN_FPS))?),
+            "preview" => preview = match value.split_once('x').map(|(width, height)| (width.parse(), height.parse()))
+            {
+                // no bigger than the sensor's frames, which it downsamples
+                Some((Ok(width), Ok(height))) if (1..=WIDTH_3D).contains(&width) && (1..=HEIGHT_3D).contains(&height) => Some(Preview::new(width, height)),
+                _ => return Err(format!("invalid preview {}, expected <width>x<height> up to {}x{}", value, WIDTH_3D, HEIGHT_3D)),
+            },
+            _ => (),
+        }
+    }
+    if preview.is_some()
+    {
+        if !["udp", "tcp", "ws"].contains(&scheme)
+        {
+            return Err(format!("{} publisher has no option preview", scheme))
+        }
+        // raw frames are always the size of the sensor's
+        if options.iter().all(|(name, value)| *name != "format" || *value == "raw")
+        {
+            return Err("preview needs format=proto, rvl or delta".to_string())
+        }
+        throttle.max_fps = throttle.max_fps.or(Some(DEFAULT_PREVIEW_FPS));
+    }
+    options.retain(|(name, _)| !["backpressure", "queue", "decimate", "max_fps", "preview"].contains(name));
+    let publisher : Box<dyn Publisher> = match scheme
+    {
+        "udp" => Box::new(UdpPublisher::new(address, Encoding::from_options(scheme, &options)?).map_err(|msg| msg.to_string())?),
+        "tcp" => Box::new(TcpPublisher::new(address, Encoding::from_options(scheme, &options)?).map_err(|msg| msg.to_string())?),
+        "ws" => Box::new(WsPublisher::new(address, Encoding::from_options(scheme, &options)?).map_err(|msg| msg.to_string())?),
+        "http" => Box::new(HttpPublisher::new(address, &options)?),
+        "shm" => Box::new(ShmPublisher::new(address, &options)?),
+        "bridge" => Box::new(BridgePublisher::new(address, &options)?),
+        "osc" => Box::new(OscPublisher::new(address, &options)?),
+        "mqtt" => Box::new(MqttPublisher::new(address, &options)?),
+        "map" => Box::new(MapPublisher::new(address, &options)?),
+        "scan" => Box::new(ScanPublisher::new(address, &options)?),
+        "events" => Box::new(EventsPublisher::new(address, &options)?),
+        "webhook" => Box::new(WebhookPublisher::new(address, &options)?),
+        "record" => Box::new(RecordPublisher::new(address, &options)?),
+        "export" => Box::new(ExportPublisher::new(address, &options)?),
+        _ => return Err(format!("unsupported publish target {}", url)),
+    };
+    let mut output = Output::new(url, publisher);
+    output.backpressure = backpressure.unwrap_or(output.backpressure);
+    output.queue = queue;
+    output.throttle = throttle;
+    output.preview = preview;
+    Ok(output)
+}
+
+// How the udp, tcp and ws publishers put frames on the wire: raw sends the frame as
+// read from the device, proto sends messages from proto/rusty_lidar_viewer.proto, rvl
+// sends RVL compressed distances with a small header, see rvl.rs, delta sends zstd
+// compressed differences to the previous frame with periodic keyframes, see delta.rs
+pub enum Encoding
+{
+    Raw,
+    Proto(proto::Encoder),
+    Rvl { sequence : u32 },
+    Delta(delta::Encoder),
+}
+
+impl Encoding
+{
+
+// options: format=raw|proto|rvl|delta, and with format=delta keyframe=<frames between
+// keyframes> and level=<zstd level>
+pub fn from_options(scheme : &str, options : &Options) -> Result<Encoding, String>
+{
+    let mut format = "raw";
+    let mut keyframe = None;
+    let mut level = None;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "format" => format = value,
+            "keyframe" => keyframe = Some(value.parse().map_err(|_| format!("invalid value for keyframe {}", value))?),
+            "level" => level = Some(value.parse().map_err(|_| format!("invalid value for level {}", value))?),
+            _ => return Err(format!("{} publisher has no option {}", scheme, name)),
+        }
+    }
+    if format != "delta" && (keyframe.is_some() || level.is_some())
+    {
+        return Err("keyframe and level only apply to format=delta".to_string())
+    }
+    match format
+    {
+        "raw" => Ok(Encoding::Raw),
+        "proto" => Ok(Encoding::Proto(proto::Encoder::new())),
+        "rvl" => Ok(Encoding::Rvl { sequence : 0 }),
+        "delta" => Ok(Encoding::Delta(delta::Encoder::new(
+            keyframe.unwrap_or(delta::DEFAULT_KEYFRAME_INTERVAL),
+            level.unwrap_or(delta::DEFAULT_LEVEL)))),
+        _ => Err(format!("unknown format {}", format)),
+    }
+}
+
+pub fn device_info(&self, info : &DeviceInfo) -> Option<Vec<u8>>
+{
+    match self
+    {
+        Encoding::Raw | Encoding::Rvl { .. } | Encoding::Delta(_) => None,
+        Encoding::Proto(encoder) => Some(encoder.device_info(info)),
+    }
+}
+
+// only protobuf has messages for analysis results
+pub fn analysis(&self, analysis : &Analysis) -> Option<Vec<u8>>
+{
+    match self
+    {
+        Encoding::Proto(encoder) => Some(encoder.analysis(analysis)),
+        _ => None,
+    }
+}
+
+// messages to send for this frame, in order
+pub fn frame(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<Vec<Vec<u8>>, ()>
+{
+    match self
+    {
+        Encoding::Raw => Ok(vec![frame.as_bytes()?]),
+        Encoding::Proto(encoder) => Ok(encoder.frame(depth)),
+        Encoding::Rvl { sequence } =>
+        {
+            let message = rvl::encode_frame(depth, *sequence);
+            *sequence = sequence.wrapping_add(1);
+            Ok(vec![message])
+        },
+        Encoding::Delta(encoder) => Ok(vec![encoder.frame(depth)?]),
+    }
+}
+
+}
@@ -0,0 +1,241 @@
+// Reads frames off the serial port as the port becomes readable, rather than in reads
+// with a timeout, waiting with mio on the port and on a control channel at once. Commands
+// sent over the channel wake the reader wherever it waits: Write sends bytes to the device
+// between reads, Stop ends reading. A port staying quiet for a while is counted as a timeout
+// and reported once, the reader keeps waiting without spinning. The port is read in
+// chunks as big as what arrived, into a FrameRing that frames are then taken from, rather
+// than a frame at a time, for fewer system calls at 3 Mbaud.
+use crate::frame::FrameRing;
+use crate::stats::{Stats, STATS};
+use crate::transport::Transport;
+
+use log::{error, warn};
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::ErrorKind::{Interrupted, TimedOut, WouldBlock};
+use std::io::{IoSliceMut, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PORT : Token = Token(0);
+const CONTROL : Token = Token(1);
+// room for the largest frames, twice over
+const RING_SIZE : usize = 1 << 17;
+
+pub enum Command
+{
+    // bytes to send to the device, e.g. a command frame from frame::new
+    Write(Vec<u8>),
+    Stop,
+}
+
+// Sends commands to a SerialReader from any thread
+#[derive(Clone)]
+pub struct Control
+{
+    commands : Sender<Command>,
+    wake : Arc<UnixStream>,
+}
+
+impl Control
+{
+
+pub fn send(&self, command : Command) -> Result<(), ()>
+{
+    self.commands.send(command).map_err(|_| ())?;
+    // with the pipe full the reader is being woken already
+    let _ = (&*self.wake).write(&[0]);
+    Ok(())
+}
+
+}
+
+pub struct SerialReader
+{
+    poll : Poll,
+    events : Events,
+    commands : Receiver<Command>,
+    // taken off commands with their wake bytes drained and not run yet, those after a Stop
+    pending : VecDeque<Command>,
+    woken : UnixStream,
+    // the port's own timeout, for writes, reads don't wait
+    timeout : Duration,
+    quiet : Duration,
+    ring : FrameRing,
+    // where each read into the ring ended and when it was, for when frames arrived
+    reads : VecDeque<(usize, Instant)>,
+}
+
+impl SerialReader
+{
+
+// a port staying quiet for quiet is counted as a timeout, see device::Settings
+pub fn new(serial_port : &dyn Transport, quiet : Duration) -> io::Result<(SerialReader, Control)>
+{
+    let poll = Poll::new()?;
+    let (wake, woken) = UnixStream::pair()?;
+    wake.set_nonblocking(true)?;
+    woken.set_nonblocking(true)?;
+    poll.registry().register(&mut SourceFd(&serial_port.as_raw_fd()), PORT, Interest::READABLE)?;
+    poll.registry().register(&mut SourceFd(&woken.as_raw_fd()), CONTROL, Interest::READABLE)?;
+    let (sender, commands) = channel();
+    let reader = SerialReader
+    {
+        poll,
+        events : Events::with_capacity(4),
+        commands,
+        pending : VecDeque::new(),
+        woken,
+        timeout : serial_port.timeout(),
+        quiet,
+        ring : FrameRing::new(RING_SIZE),
+        reads : VecDeque::new(),
+    };
+    Ok((reader, Control { commands : sender, wake : Arc::new(wake) }))
+}
+
+// the bytes of the next frame with a payload of one of payload_sizes, unchecked, for
+// parse_frame_into, reading at most chunk bytes at a time. Returns when the first of them
+// arrived, None once told to stop
+pub fn read_frame(&mut self, serial_port : &mut dyn Transport, payload_sizes : &[u16], chunk : usize, buffer : &mut Vec<u8>) -> Result<Option<Instant>, ()>
+{
+    self.timeout = serial_port.timeout();
+    // a read with no timeout takes what is there and fails with TimedOut if nothing is
+    let _ = serial_port.set_timeout(Duration::ZERO);
+    let read = self.fill(serial_port, payload_sizes, chunk, buffer);
+    let _ = serial_port.set_timeout(self.timeout);
+    read
+}
+
+fn fill(&mut self, serial_port : &mut dyn Transport, payload_sizes : &[u16], chunk : usize, buffer : &mut Vec<u8>) -> Result<Option<Instant>, ()>
+{
+    loop
+    {
+        if let Some(position) = self.ring.next_frame(payload_sizes, buffer)
+        {
+            // the read the frame started in is the first to end after its start
+            while self.reads.front().is_some_and(|(end, _)| *end <= position)
+            {
+                self.reads.pop_front();
+            }
+            return Ok(Some(self.reads.front().map_or_else(Instant::now, |(_, arrived)| *arrived)))
+        }
+        let [first, second] = self.ring.free_mut();
+        let first_len = first.len().min(chunk);
+        let second_len = second.len().min(chunk - first_len);
+        match serial_port.read_vectored(&mut [IoSliceMut::new(&mut first[..first_len]), IoSliceMut::new(&mut second[..second_len])])
+        {
+            Ok(0) =>
+            {
+                Stats::count(&STATS.read_errors);
+                error!("Error reading from serial!, end of file");
+                return Err(());
+            },
+            Ok(count) =>
+            {
+                self.ring.filled(count);
+                STATS.bytes_received.fetch_add(count as u64, Ordering::Relaxed);
+                self.reads.push_back((self.ring.end(), Instant::now()));
+                continue;
+            },
+            Err(msg) if msg.kind() == Interrupted => continue,
+            // mio only wakes on new data, so the port is read until there is none first
+            Err(msg) if msg.kind() == TimedOut || msg.kind() == WouldBlock => (),
+            Err(msg) =>
+            {
+                Stats::count(&STATS.read_errors);
+                error!("Error reading from serial!, {}", msg);
+                return Err(());
+            },
+        }
+        if !self.wait(serial_port)?
+        {
+            return Ok(None)
+        }
+    }
+}
+
+// drops the bytes read and not taken out as a frame yet
+pub fn clear(&mut self)
+{
+    self.ring.clear();
+    self.reads.clear();
+}
+
+// until the port is readable, false once told to stop
+fn wait(&mut self, serial_port : &mut dyn Transport) -> Result<bool, ()>
+{
+    // their wake bytes are gone, so poll wouldn't say
+    if !self.pending.is_empty() && !self.run_commands(serial_port)
+    {
+        return Ok(false)
+    }
+    let mut quiet = false;
+    loop
+    {
+        match self.poll.poll(&mut self.events, Some(self.quiet))
+        {
+            Ok(()) => (),
+            Err(msg) if msg.kind() == Interrupted => continue,
+            Err(msg) => { error!("Failed to wait for serial!, {}", msg); return Err(()) },
+        }
+        if self.events.is_empty()
+        {
+            Stats::count(&STATS.timeouts);
+            if !quiet
+            {
+                // part of a frame in, the rest didn't come
+                if !self.ring.is_empty()
+                {
+                    Stats::count(&STATS.short_reads);
+                }
+                warn!(quiet_ms = self.quiet.as_millis() as u64; "Nothing from serial");
+                quiet = true;
+            }
+            continue;
+        }
+        let readable = self.events.iter().any(|event| event.token() == PORT);
+        if self.events.iter().any(|event| event.token() == CONTROL) && !self.run_commands(serial_port)
+        {
+            return Ok(false)
+        }
+        if readable
+        {
+            return Ok(true)
+        }
+    }
+}
+
+// false on Stop
+fn run_commands(&mut self, serial_port : &mut dyn Transport) -> bool
+{
+    let mut drained = [0u8; 64];
+    while matches!((&self.woken).read(&mut drained), Ok(count) if count > 0) {}
+    self.pending.extend(self.commands.try_iter());
+    while let Some(command) = self.pending.pop_front()
+    {
+        match command
+        {
+            Command::Write(bytes) =>
+            {
+                let _ = serial_port.set_timeout(self.timeout);
+                if let Err(msg) = serial_port.write_all(&bytes)
+                {
+                    error!("Error writing to serial!, {}", msg);
+                }
+                let _ = serial_port.set_timeout(Duration::ZERO);
+            },
+            Command::Stop => return false,
+        }
+    }
+    true
+}
+
+}
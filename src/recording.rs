@@ -0,0 +1,131 @@
+// Recordings are the frames as read from the device, back to back. Frames carry their
+// size, so they are found again by walking from one header to the next. record://<path>
+// writes one, Playback maps one into memory and finds its frames once, after that any
+// frame is a slice of the map, read from disk only when it is looked at. The play
+// subcommand plays one back, bench as fast as the pipeline goes.
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::{Options, Publisher};
+use crate::sinks::Backpressure;
+use crate::template;
+
+use log::{error, info};
+use memmap2::Mmap;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+
+// header, 2 bytes of size, payload, checksum
+const FRAME_OVERHEAD : usize = 6;
+
+// where the frames of a recording are. A frame cut off at the end, as when recording was
+// interrupted, is left out
+fn spans(bytes : &[u8]) -> impl Iterator<Item = Range<usize>> + '_
+{
+    let mut offset = 0;
+    std::iter::from_fn(move ||
+    {
+        let size = bytes.get(offset + 3..offset + 5)?;
+        let end = offset + u16::from_le_bytes([size[0], size[1]]) as usize + FRAME_OVERHEAD;
+        if end > bytes.len()
+        {
+            return None
+        }
+        let span = offset..end;
+        offset = end;
+        Some(span)
+    })
+}
+
+pub struct Playback
+{
+    map : Mmap,
+    // where each frame starts, and the end of the last one
+    offsets : Vec<usize>,
+}
+
+impl Playback
+{
+
+pub fn open(path : &str) -> Result<Playback, String>
+{
+    let file = File::open(path).map_err(|msg| msg.to_string())?;
+    // the recording isn't expected to change while it is played, a truncated file ends
+    // the playback with a bus error
+    let map = unsafe { Mmap::map(&file) }.map_err(|msg| msg.to_string())?;
+    let mut offsets = vec![0];
+    offsets.extend(spans(&map).map(|span| span.end));
+    if offsets.len() == 1
+    {
+        return Err("no frames in recording".to_string())
+    }
+    Ok(Playback { map, offsets })
+}
+
+pub fn len(&self) -> usize
+{
+    self.offsets.len() - 1
+}
+
+pub fn is_empty(&self) -> bool
+{
+    self.len() == 0
+}
+
+pub fn frame(&self, index : usize) -> Option<&[u8]>
+{
+    self.map.get(*self.offsets.get(index)?..*self.offsets.get(index + 1)?)
+}
+
+// the frames as read from the device, unchecked, for parse_frame
+pub fn frames(&self) -> impl Iterator<Item = &[u8]>
+{
+    self.offsets.windows(2).map(|span| &self.map[span[0]..span[1]])
+}
+
+}
+
+pub struct RecordPublisher
+{
+    path : String,
+    file : BufWriter<File>,
+}
+
+impl RecordPublisher
+{
+
+pub fn new(path : &str, options : &Options) -> Result<RecordPublisher, String>
+{
+    if let Some((name, _)) = options.first()
+    {
+        return Err(format!("record publisher has no option {}", name))
+    }
+    let path = template::expand(path, "")?;
+    let file = File::create(&path).map_err(|msg| format!("failed to create recording {}, {}", path, msg))?;
+    info!("Recording frames to {}", path);
+    Ok(RecordPublisher { path, file : BufWriter::new(file) })
+}
+
+}
+
+impl Publisher for RecordPublisher
+{
+
+// a recording with frames missing isn't one
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, frame : &Frame, _depth : &DepthFrame) -> Result<(), ()>
+{
+    if let Err(msg) = self.file.write_all(&frame.as_bytes()?)
+    {
+        error!("Failed to write to recording {}, {}", self.path, msg);
+        return Err(())
+    }
+    Ok(())
+}
+
+}
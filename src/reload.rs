@@ -0,0 +1,55 @@
+// Tells when the --config file should be read again, on SIGHUP or once it was written
+// since it was last read, looking at its modification time at most every CHECK_INTERVAL.
+// What is read again is up to the caller, see Pipeline::reload in main.rs.
+use crate::signals;
+
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const CHECK_INTERVAL : Duration = Duration::from_secs(1);
+
+pub struct Watcher
+{
+    path : String,
+    modified : Option<SystemTime>,
+    checked : Instant,
+}
+
+impl Watcher
+{
+
+// the file as it is now counts as read
+pub fn new(path : &str) -> Watcher
+{
+    Watcher { path : path.to_string(), modified : modified(path), checked : Instant::now() }
+}
+
+pub fn path(&self) -> &str
+{
+    &self.path
+}
+
+pub fn changed(&mut self) -> bool
+{
+    let hangup = signals::hangup();
+    if !hangup && self.checked.elapsed() < CHECK_INTERVAL
+    {
+        return false
+    }
+    self.checked = Instant::now();
+    let modified = modified(&self.path);
+    // a file being replaced is missing for a moment, that is no change yet
+    let written = modified.is_some() && modified != self.modified;
+    if written || hangup
+    {
+        self.modified = modified.or(self.modified);
+    }
+    written || hangup
+}
+
+}
+
+fn modified(path : &str) -> Option<SystemTime>
+{
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,150 @@
+// Client for the tcp publisher, so frames read on one machine can be republished, shown
+// and recorded on another. The stream is taken as tcp://host:port[?format=raw|proto|rvl|delta],
+// the format has to match the one the publisher was started with.
+use crate::delta;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::frame::{new, parse_frame, Frame};
+use crate::proto::messages;
+use crate::proto::messages::message::Body;
+use crate::rvl;
+use crate::stats::{Stats, STATS};
+
+use log::{error, info};
+use prost::Message as _;
+
+use std::io::{BufReader, Read};
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+
+// well above the largest message the publisher sends, a raw frame
+const MAX_MESSAGE_SIZE : usize = 1 << 20;
+
+enum Format
+{
+    Raw,
+    Proto,
+    Rvl,
+    Delta(delta::Decoder),
+}
+
+pub enum Received
+{
+    DeviceInfo(DeviceInfo),
+    Frame(Frame, DepthFrame),
+}
+
+pub struct RemoteSource
+{
+    stream : BufReader<TcpStream>,
+    format : Format,
+}
+
+impl RemoteSource
+{
+
+pub fn connect(url : &str) -> Result<RemoteSource, String>
+{
+    let target = match url.split_once("://")
+    {
+        Some(("tcp", target)) => target,
+        _ => return Err(format!("unsupported stream {}, expected tcp://host:port", url)),
+    };
+    let (address, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut format = Format::Raw;
+    for pair in query.split('&').filter(|pair| !pair.is_empty())
+    {
+        format = match pair.split_once('=')
+        {
+            Some(("format", "raw")) => Format::Raw,
+            Some(("format", "proto")) => Format::Proto,
+            Some(("format", "rvl")) => Format::Rvl,
+            Some(("format", "delta")) => Format::Delta(delta::Decoder::new()),
+            _ => return Err(format!("unknown stream option {}", pair)),
+        };
+    }
+    let stream = TcpStream::connect(address).map_err(|msg| format!("failed to connect to {}, {}", address, msg))?;
+    info!("Reading frames from tcp://{}", address);
+    Ok(RemoteSource { stream : BufReader::new(stream), format })
+}
+
+// the next frame or device info, skipping messages that don't carry either
+pub fn receive(&mut self) -> Result<Received, ()>
+{
+    loop
+    {
+        let message = self.read_message()?;
+        STATS.bytes_read.fetch_add(4 + message.len() as u64, Ordering::Relaxed);
+        let depth = match &mut self.format
+        {
+            Format::Raw =>
+            {
+                let frame = parse_frame(&message)?;
+                let depth = DepthFrame::from_payload(&frame.payload);
+                Stats::count(&STATS.frames);
+                return Ok(Received::Frame(frame, depth))
+            },
+            Format::Proto => match messages::Message::decode(message.as_slice())
+            {
+                Ok(messages::Message { body : Some(Body::DeviceInfo(info)) }) =>
+                    return Ok(Received::DeviceInfo(DeviceInfo { firmware : info.firmware, hardware : info.hardware })),
+                Ok(messages::Message { body : Some(Body::DepthFrame(depth)) }) => DepthFrame
+                {
+                    width : depth.width as usize,
+                    height : depth.height as usize,
+                    data : depth.depth_mm.iter().map(|distance| *distance as u16).collect(),
+                },
+                Ok(_) => continue,
+                Err(msg) => { error!("Failed to decode protobuf message, {}", msg); return Err(()) }
+            },
+            Format::Rvl => match rvl::decode_frame(&message)
+            {
+                Some((_, depth)) => depth,
+                None => { error!("Failed to decode rvl frame"); return Err(()) }
+            },
+            // without a keyframe yet there is nothing to apply deltas to, wait for one
+            Format::Delta(decoder) => match decoder.decode(&message)
+            {
+                Some((_, depth)) => depth.clone(),
+                None => continue,
+            },
+        };
+        // filters and analyzers index by width and height
+        if depth.width.checked_mul(depth.height) != Some(depth.data.len())
+        {
+            Stats::count(&STATS.size_errors);
+            error!("Frame of {} distances doesn't match its size {}x{}", depth.data.len(), depth.width, depth.height);
+            return Err(())
+        }
+        Stats::count(&STATS.frames);
+        return Ok(Received::Frame(new(depth.to_payload()), depth))
+    }
+}
+
+fn read_message(&mut self) -> Result<Vec<u8>, ()>
+{
+    let mut length = [0u8; 4];
+    if let Err(msg) = self.stream.read_exact(&mut length)
+    {
+        Stats::count(&STATS.read_errors);
+        error!("Error reading from stream!, {}", msg);
+        return Err(())
+    }
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE
+    {
+        Stats::count(&STATS.size_errors);
+        error!("Stream message of {} bytes is too large", length);
+        return Err(())
+    }
+    let mut message = vec![0u8; length];
+    if let Err(msg) = self.stream.read_exact(&mut message)
+    {
+        Stats::count(&STATS.read_errors);
+        error!("Error reading from stream!, {}", msg);
+        return Err(())
+    }
+    Ok(message)
+}
+
+}
@@ -0,0 +1,178 @@
+// RVL depth compression (A. Wilson, "Fast Lossless Depth Image Compression", 2017).
+// Runs of zero and non zero pixels are coded as counts, non zero pixels as zigzag deltas
+// to the previous one, all in variable length nibbles of 3 data bits and a continuation
+// bit, packed eight to a little endian u32 word. Zero means "no data", so callers map
+// invalid pixels to zero before encoding.
+use crate::depth::{is_valid, DepthFrame, MAX_PIXELS};
+
+// header of a frame message: width u16, height u16, sequence u32, little endian
+const FRAME_HEADER : usize = 8;
+
+struct Writer
+{
+    bytes : Vec<u8>,
+    word : u32,
+    nibbles : u32,
+}
+
+impl Writer
+{
+
+fn push(&mut self, mut value : u32)
+{
+    loop
+    {
+        let mut nibble = value & 0x7;
+        value >>= 3;
+        if value != 0
+        {
+            nibble |= 0x8;
+        }
+        self.word = (self.word << 4) | nibble;
+        self.nibbles += 1;
+        if self.nibbles == 8
+        {
+            self.bytes.extend_from_slice(&self.word.to_le_bytes());
+            self.word = 0;
+            self.nibbles = 0;
+        }
+        if value == 0
+        {
+            break;
+        }
+    }
+}
+
+fn finish(mut self) -> Vec<u8>
+{
+    if self.nibbles != 0
+    {
+        self.word <<= 4 * (8 - self.nibbles);
+        self.bytes.extend_from_slice(&self.word.to_le_bytes());
+    }
+    self.bytes
+}
+
+}
+
+struct Reader<'a>
+{
+    words : std::slice::ChunksExact<'a, u8>,
+    word : u32,
+    nibbles : u32,
+}
+
+impl Reader<'_>
+{
+
+fn next(&mut self) -> Option<u32>
+{
+    let mut value : u32 = 0;
+    let mut shift = 0;
+    loop
+    {
+        if self.nibbles == 0
+        {
+            self.word = u32::from_le_bytes(self.words.next()?.try_into().unwrap());
+            self.nibbles = 8;
+        }
+        let nibble = self.word >> 28;
+        self.word <<= 4;
+        self.nibbles -= 1;
+        if shift > 30
+        {
+            return None
+        }
+        value |= (nibble & 0x7) << shift;
+        shift += 3;
+        if nibble & 0x8 == 0
+        {
+            return Some(value)
+        }
+    }
+}
+
+}
+
+pub fn encode(depth : &[u16]) -> Vec<u8>
+{
+    let mut writer = Writer { bytes : Vec::with_capacity(depth.len()), word : 0, nibbles : 0 };
+    let mut previous : i32 = 0;
+    let mut index = 0;
+    while index < depth.len()
+    {
+        let zeros = depth[index..].iter().take_while(|value| **value == 0).count();
+        writer.push(zeros as u32);
+        index += zeros;
+        let nonzeros = depth[index..].iter().take_while(|value| **value != 0).count();
+        writer.push(nonzeros as u32);
+        for value in &depth[index..index + nonzeros]
+        {
+            let delta = *value as i32 - previous;
+            writer.push(((delta << 1) ^ (delta >> 31)) as u32);
+            previous = *value as i32;
+        }
+        index += nonzeros;
+    }
+    writer.finish()
+}
+
+// None if the data is malformed or doesn't decode to exactly pixels values
+pub fn decode(bytes : &[u8], pixels : usize) -> Option<Vec<u16>>
+{
+    let mut reader = Reader { words : bytes.chunks_exact(4), word : 0, nibbles : 0 };
+    let mut depth = Vec::with_capacity(pixels);
+    let mut previous : i32 = 0;
+    while depth.len() < pixels
+    {
+        let zeros = reader.next()? as usize;
+        if zeros > pixels - depth.len()
+        {
+            return None
+        }
+        depth.resize(depth.len() + zeros, 0);
+        let nonzeros = reader.next()? as usize;
+        if nonzeros > pixels - depth.len()
+        {
+            return None
+        }
+        for _ in 0..nonzeros
+        {
+            let positive = reader.next()? as i32;
+            let current = previous + ((positive >> 1) ^ -(positive & 1));
+            depth.push(u16::try_from(current).ok()?);
+            previous = current;
+        }
+    }
+    Some(depth)
+}
+
+// A frame message as sent with format=rvl, invalid pixels become zero
+pub fn encode_frame(depth : &DepthFrame, sequence : u32) -> Vec<u8>
+{
+    let cleaned : Vec<u16> = depth.data.iter().map(|distance| if is_valid(*distance) { *distance } else { 0 }).collect();
+    let mut message = Vec::with_capacity(FRAME_HEADER + depth.data.len());
+    message.extend_from_slice(&(depth.width as u16).to_le_bytes());
+    message.extend_from_slice(&(depth.height as u16).to_le_bytes());
+    message.extend_from_slice(&sequence.to_le_bytes());
+    message.extend_from_slice(&encode(&cleaned));
+    message
+}
+
+pub fn decode_frame(message : &[u8]) -> Option<(u32, DepthFrame)>
+{
+    if message.len() < FRAME_HEADER
+    {
+        return None
+    }
+    let width = u16::from_le_bytes([message[0], message[1]]) as usize;
+    let height = u16::from_le_bytes([message[2], message[3]]) as usize;
+    let sequence = u32::from_le_bytes(message[4..8].try_into().unwrap());
+    // a few bytes of zero run would otherwise have any size the header says filled in
+    if width * height > MAX_PIXELS
+    {
+        return None
+    }
+    let data = decode(&message[FRAME_HEADER..], width * height)?;
+    Some((sequence, DepthFrame { width, height, data }))
+}
@@ -0,0 +1,158 @@
+// Scanning: every frame is projected, moved into the coordinates of the first frame and
+// added to an octree that merges its points into voxels as they come, see octree.rs,
+// written as PLY when the viewer stops, with mesh=<path.obj|path.ply> also as a mesh, see
+// mesh.rs. Poses come from ICP against the previous frame or, with
+// trajectory=<file>, from a file with one pose per frame in TUM format
+// (timestamp tx ty tz qx qy qz qw, lines starting with # are skipped).
+use crate::cloud::{PointCloud, Projection};
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::mesh;
+use crate::icp::{align, Transform, DEFAULT_ITERATIONS, DEFAULT_LEAF, DEFAULT_MAX_DISTANCE};
+use crate::octree::Octree;
+use crate::pool::Pooled;
+use crate::publish::{Options, Publisher};
+use crate::sinks::Backpressure;
+
+use log::{error, info, warn};
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+
+const DEFAULT_MAP_LEAF : f32 = 0.02;
+
+pub struct ScanPublisher
+{
+    path : String,
+    trajectory : Option<Vec<Transform>>,
+    mesh : Option<String>,
+    frames : u64,
+    pose : Transform,
+    previous : Option<Pooled<PointCloud>>,
+    map : Octree,
+    projection : Projection,
+}
+
+fn read_trajectory(path : &str) -> Result<Vec<Transform>, String>
+{
+    let text = fs::read_to_string(path).map_err(|msg| format!("failed to read trajectory {}, {}", path, msg))?;
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(|line|
+    {
+        let values : Vec<f32> = line.split_whitespace().map(|value| value.parse()).collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid trajectory line {}", line))?;
+        match values.as_slice()
+        {
+            [_, tx, ty, tz, qx, qy, qz, qw] => Ok(Transform::from_quaternion([*tx, *ty, *tz], [*qx, *qy, *qz, *qw])),
+            _ => Err(format!("trajectory line needs 8 values, {}", line)),
+        }
+    }).collect()
+}
+
+impl ScanPublisher
+{
+
+// options: leaf=<map voxel meters>, trajectory=<file>, mesh=<file>
+pub fn new(path : &str, options : &Options) -> Result<ScanPublisher, String>
+{
+    let mut leaf = DEFAULT_MAP_LEAF;
+    let mut trajectory = None;
+    let mut mesh = None;
+    for (name, value) in options
+    {
+        match *name
+        {
+            "leaf" => leaf = value.parse().ok().filter(|leaf| *leaf > 0.0).ok_or(format!("invalid value for leaf {}", value))?,
+            "trajectory" => trajectory = Some(read_trajectory(value)?),
+            "mesh" if value.ends_with(".obj") || value.ends_with(".ply") => mesh = Some(value.to_string()),
+            "mesh" => return Err(format!("mesh has to be an .obj or .ply file, not {}", value)),
+            _ => return Err(format!("scan publisher has no option {}", name)),
+        }
+    }
+    File::create(path).map_err(|msg| format!("failed to create {}, {}", path, msg))?;
+    info!("Scanning into {}", path);
+    Ok(ScanPublisher
+    {
+        path : path.to_string(), trajectory, mesh,
+        frames : 0,
+        pose : Transform::default(),
+        previous : None,
+        map : Octree::new(leaf),
+        projection : Projection::default(),
+    })
+}
+
+pub fn map(&self) -> &Octree
+{
+    &self.map
+}
+
+fn save(&self) -> Result<(), String>
+{
+    let cloud = self.map.to_cloud();
+    let file = File::create(&self.path).map_err(|msg| msg.to_string())?;
+    cloud.write_ply(BufWriter::new(file)).map_err(|msg| msg.to_string())?;
+    if let Some(path) = &self.mesh
+    {
+        let mesh = mesh::from_voxels(&cloud, self.map.leaf());
+        let file = BufWriter::new(File::create(path).map_err(|msg| msg.to_string())?);
+        if path.ends_with(".obj") { mesh.write_obj(file) } else { mesh.write_ply(file) }.map_err(|msg| msg.to_string())?;
+        info!("Wrote a mesh of {} triangles to {}", mesh.triangles.len(), path);
+    }
+    Ok(())
+}
+
+}
+
+impl Publisher for ScanPublisher
+{
+
+// frames left out leave gaps between the scans being matched
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let cloud = self.projection.project_pooled(depth);
+    match &self.trajectory
+    {
+        Some(trajectory) => match trajectory.get(self.frames as usize)
+        {
+            Some(pose) => self.pose = *pose,
+            None => return Ok(()),
+        },
+        None =>
+        {
+            if let Some(previous) = &self.previous
+            {
+                match align(previous, &cloud, DEFAULT_LEAF, DEFAULT_MAX_DISTANCE, DEFAULT_ITERATIONS)
+                {
+                    Some(alignment) => self.pose = self.pose.then(&alignment.transform),
+                    None => warn!("Failed to align frame {}, keeping the last pose", self.frames),
+                }
+            }
+        },
+    }
+    self.map.insert_cloud(&cloud, &self.pose);
+    if self.trajectory.is_none()
+    {
+        self.previous = Some(cloud);
+    }
+    self.frames += 1;
+    Ok(())
+}
+
+}
+
+impl Drop for ScanPublisher
+{
+    fn drop(&mut self)
+    {
+        match self.save()
+        {
+            Ok(_) => info!("Wrote {} points to {}", self.map.len(), self.path),
+            Err(msg) => error!("Failed to write scan {}, {}", self.path, msg),
+        }
+    }
+}
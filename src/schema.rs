@@ -0,0 +1,305 @@
+// Every option the --config file takes, for config validate and config schema: the
+// filters of [[pipeline.stage]], the publishers of publish and their options, [device],
+// [[zone]] and [plugins]. config schema prints it as a JSON Schema, which editors with a
+// TOML language server check the file against as it is written. Publishers are only
+// checked against it, since opening one binds its port or creates its file; filters,
+// zones and [device] are also built, see config.rs.
+use crate::options;
+
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Kind
+{
+    Bool,
+    Integer,
+    Number,
+    Text,
+    Choice(&'static [&'static str]),
+    // <width>x<height>
+    Size,
+    // [x, y, z] in meters
+    Point,
+    // [[x, z], ...] in meters
+    Polygon,
+}
+
+pub struct Opt
+{
+    pub name : &'static str,
+    pub kind : Kind,
+    pub help : &'static str,
+}
+
+pub struct Section
+{
+    pub name : &'static str,
+    pub help : &'static str,
+    pub options : &'static [Opt],
+}
+
+const fn opt(name : &'static str, kind : Kind, help : &'static str) -> Opt
+{
+    Opt { name, kind, help }
+}
+
+const ENCODING : [Opt; 3] =
+[
+    opt("format", Kind::Choice(&["raw", "proto", "rvl", "delta"]), "how frames are sent, raw by default"),
+    opt("keyframe", Kind::Integer, "with format=delta, frames between full ones"),
+    opt("level", Kind::Integer, "with format=delta, zstd level"),
+];
+
+pub const FILTERS : &[Section] =
+&[
+    Section { name : "bilateral", help : "smooths surfaces without blurring edges", options : &[
+        opt("spatial_sigma", Kind::Number, "weight by distance in the image, pixels"),
+        opt("range_sigma", Kind::Number, "weight by difference in distance, mm"),
+    ] },
+    Section { name : "flying", help : "drops the flying pixels along object edges", options : &[
+        opt("threshold_mm", Kind::Integer, "difference from a neighbour that counts"),
+        opt("fraction", Kind::Number, "of the neighbours that have to differ"),
+    ] },
+    Section { name : "ground", help : "drops the floor, the largest level plane", options : &[
+        opt("tolerance", Kind::Number, "meters above the floor dropped too"),
+        opt("max_tilt", Kind::Number, "degrees the floor may be tilted"),
+    ] },
+    Section { name : "median", help : "median of the valid pixels around each one", options : &[
+        opt("size", Kind::Choice(&["3", "5"]), "window size"),
+        opt("kernel", Kind::Choice(&["3", "5"]), "window size, as size"),
+    ] },
+    Section { name : "outliers", help : "drops points far from their neighbours", options : &[
+        opt("k", Kind::Integer, "neighbours looked at"),
+        opt("std_ratio", Kind::Number, "standard deviations above average that are too far"),
+    ] },
+    Section { name : "range", help : "drops distances outside of a range", options : &[
+        opt("min_mm", Kind::Integer, "nearest distance kept"),
+        opt("max_mm", Kind::Integer, "farthest distance kept"),
+    ] },
+    Section { name : "temporal", help : "smooths every pixel over frames", options : &[
+        opt("mode", Kind::Choice(&["ema", "median"]), "moving average or median"),
+        opt("alpha", Kind::Number, "weight of the newest frame, ema"),
+        opt("frames", Kind::Integer, "frames the median is of"),
+    ] },
+];
+
+// taken by every publisher, see publish::open
+pub const PUBLISH_OPTIONS : &[Opt] =
+&[
+    opt("backpressure", Kind::Choice(&["block", "drop_oldest", "drop_newest"]), "what to do when frames come faster than taken"),
+    opt("queue", Kind::Integer, "frames queued for the publisher"),
+    opt("decimate", Kind::Integer, "every Nth frame only"),
+    opt("max_fps", Kind::Number, "frames a second at most"),
+    opt("preview", Kind::Size, "frames downsampled to this size, udp, tcp and ws with format other than raw"),
+];
+
+// by scheme, help is what comes after ://
+pub const PUBLISHERS : &[Section] =
+&[
+    Section { name : "udp", help : "host:port", options : &ENCODING },
+    Section { name : "tcp", help : "address to listen on", options : &ENCODING },
+    Section { name : "ws", help : "address to listen on", options : &ENCODING },
+    Section { name : "http", help : "address to listen on", options : &[
+        opt("min_mm", Kind::Integer, "distance at the near end of the colormap"),
+        opt("max_mm", Kind::Integer, "distance at the far end of the colormap"),
+    ] },
+    Section { name : "shm", help : "shared memory name", options : &[
+        opt("slots", Kind::Integer, "frames in the ring"),
+    ] },
+    Section { name : "bridge", help : "host:port", options : &[
+        opt("endian", Kind::Choice(&["little", "big"]), "byte order, little by default"),
+    ] },
+    Section { name : "osc", help : "host:port", options : &[
+        opt("prefix", Kind::Text, "address prefix"),
+        opt("grid", Kind::Size, "cells the frame is averaged into, <cols>x<rows>"),
+    ] },
+    Section { name : "mqtt", help : "host:port of the broker", options : &[
+        opt("prefix", Kind::Text, "topic prefix"),
+        opt("qos", Kind::Choice(&["0", "1", "2"]), "quality of service"),
+        opt("frames", Kind::Bool, "publish frames too, not only analyses"),
+        opt("homeassistant", Kind::Bool, "announce sensors to Home Assistant"),
+        opt("discovery_prefix", Kind::Text, "Home Assistant discovery prefix"),
+        opt("node_id", Kind::Text, "Home Assistant node id"),
+        opt("presence_mm", Kind::Integer, "nearest distance that counts as presence"),
+    ] },
+    Section { name : "map", help : "path of the map written", options : &[
+        opt("resolution", Kind::Number, "meters a cell"),
+        opt("size", Kind::Number, "meters a side"),
+        opt("every", Kind::Integer, "frames between writing the map"),
+        opt("min_height", Kind::Number, "lowest point that is an obstacle, meters"),
+        opt("max_height", Kind::Number, "highest point that is an obstacle, meters"),
+    ] },
+    Section { name : "scan", help : "path of the point cloud written", options : &[
+        opt("leaf", Kind::Number, "voxel size, meters"),
+        opt("trajectory", Kind::Text, "file with a pose per frame"),
+        opt("mesh", Kind::Text, ".obj or .ply mesh written too"),
+    ] },
+    Section { name : "events", help : "path of the json lines written", options : &[] },
+    Section { name : "webhook", help : "http url posted to", options : &[] },
+    Section { name : "record", help : "path of the recording", options : &[] },
+    Section { name : "export", help : "directory of the frames", options : &[
+        opt("format", Kind::Choice(&["png", "ply", "csv"]), "file format, png by default"),
+    ] },
+];
+
+pub const DEVICE : &[Opt] =
+&[
+    opt("port", Kind::Text, "serial port, /dev/ttyUSB0 by default"),
+    opt("baud", Kind::Integer, "baud rate, 3000000 by default"),
+    opt("low_latency", Kind::Bool, "ask the driver to pass bytes on at once"),
+    opt("latency_timer", Kind::Integer, "FTDI latency timer, 1 to 255 ms"),
+    opt("chunk", Kind::Integer, "bytes per read"),
+    opt("exclusive", Kind::Bool, "open the port exclusively, true by default"),
+    opt("cpu", Kind::Integer, "core the thread reading the port is pinned to"),
+    opt("priority", Kind::Integer, "SCHED_FIFO priority of that thread, 1 to 99"),
+    opt("mode", Kind::Choice(&["2d", "3d", "dual"]), "what the device streams"),
+    opt("timeout", Kind::Integer, "ms the device may stay quiet before that is reported"),
+    opt("watchdog", Kind::Integer, "ms without a valid frame after which the stream is restarted"),
+];
+
+pub const ZONE : &[Opt] =
+&[
+    opt("name", Kind::Text, "reported with the zone"),
+    opt("min", Kind::Point, "corner of a box, with max"),
+    opt("max", Kind::Point, "opposite corner of the box"),
+    opt("polygon", Kind::Polygon, "outline on the ground, instead of a box"),
+    opt("min_height", Kind::Number, "lowest point of a polygon zone above the sensor"),
+    opt("max_height", Kind::Number, "highest point of a polygon zone above the sensor"),
+    opt("min_points", Kind::Integer, "points from which the zone is occupied, 10 by default"),
+];
+
+pub const PLUGINS : &[Opt] =
+&[
+    opt("directory", Kind::Text, "where filter plugins are loaded from, plugins by default"),
+];
+
+pub fn section(sections : &'static [Section], name : &str) -> Option<&'static Section>
+{
+    sections.iter().find(|section| section.name == name)
+}
+
+// whether value, as given in a url, is one of kind
+pub fn check(option : &Opt, value : &str) -> Result<(), String>
+{
+    let valid = match option.kind
+    {
+        Kind::Bool => value.parse::<bool>().is_ok(),
+        Kind::Integer => value.parse::<i64>().is_ok(),
+        Kind::Number => value.parse::<f64>().is_ok(),
+        Kind::Text => true,
+        Kind::Choice(choices) => choices.contains(&value),
+        Kind::Size => value.split_once('x').is_some_and(|(width, height)| width.parse::<usize>().is_ok() && height.parse::<usize>().is_ok()),
+        Kind::Point | Kind::Polygon => false,
+    };
+    if valid { Ok(()) } else { Err(format!("invalid value for {} {}, expected {}", option.name, value, expected(option.kind))) }
+}
+
+// a publish url without opening it, failing with where in it the problem starts
+pub fn check_url(url : &str) -> Result<(), (usize, String)>
+{
+    let (scheme, target) = url.split_once("://").ok_or((0, format!("missing scheme in publish target {}", url)))?;
+    let publisher = section(PUBLISHERS, scheme).ok_or_else(||
+    {
+        let schemes : Vec<&str> = PUBLISHERS.iter().map(|publisher| publisher.name).collect();
+        (0, format!("unsupported publish target {}, expected one of {}", scheme, schemes.join(", ")))
+    })?;
+    let (_, options) = options::split(target).map_err(|msg| (scheme.len() + 3, msg))?;
+    for (name, value) in options
+    {
+        let at = url.find(&format!("{}=", name)).unwrap_or(0);
+        let option = publisher.options.iter().chain(PUBLISH_OPTIONS).find(|option| option.name == name)
+            .ok_or((at, format!("{} publisher has no option {}", scheme, name)))?;
+        check(option, value).map_err(|msg| (at, msg))?;
+    }
+    Ok(())
+}
+
+fn expected(kind : Kind) -> String
+{
+    match kind
+    {
+        Kind::Bool => "true or false".to_string(),
+        Kind::Integer => "a whole number".to_string(),
+        Kind::Number => "a number".to_string(),
+        Kind::Text => "text".to_string(),
+        Kind::Choice(choices) => choices.join(", "),
+        Kind::Size => "<width>x<height>".to_string(),
+        Kind::Point => "[x, y, z]".to_string(),
+        Kind::Polygon => "[[x, z], ...]".to_string(),
+    }
+}
+
+// values in tables may also be strings, as options in urls are
+fn property(option : &Opt) -> Value
+{
+    let number = |kind : &str| json!({ "type" : [kind, "string"] });
+    let mut property = match option.kind
+    {
+        Kind::Bool => number("boolean"),
+        Kind::Integer => number("integer"),
+        Kind::Number => number("number"),
+        Kind::Text | Kind::Size => json!({ "type" : "string" }),
+        Kind::Choice(choices) => json!({ "enum" : choices.iter().flat_map(|choice| match choice.parse::<i64>()
+        {
+            Ok(number) => vec![json!(choice), json!(number)],
+            Err(_) => vec![json!(choice)],
+        }).collect::<Vec<_>>() }),
+        Kind::Point => json!({ "type" : "array", "items" : { "type" : "number" }, "minItems" : 3, "maxItems" : 3 }),
+        Kind::Polygon => json!({ "type" : "array", "minItems" : 3,
+            "items" : { "type" : "array", "items" : { "type" : "number" }, "minItems" : 2, "maxItems" : 2 } }),
+    };
+    property["description"] = json!(option.help);
+    property
+}
+
+fn object(options : &[Opt]) -> Value
+{
+    let properties : Map<String, Value> = options.iter().map(|option| (option.name.to_string(), property(option))).collect();
+    json!({ "type" : "object", "properties" : properties, "additionalProperties" : false })
+}
+
+// a --config file, as JSON Schema draft 2020-12
+pub fn json_schema() -> Value
+{
+    // a stage with a type that isn't a filter is a plugin's, its options aren't known
+    let mut stages : Vec<Value> = FILTERS.iter().map(|filter|
+    {
+        let mut stage = object(filter.options);
+        stage["properties"]["type"] = json!({ "const" : filter.name });
+        stage["required"] = json!(["type"]);
+        stage["description"] = json!(filter.help);
+        stage
+    }).collect();
+    let names : Vec<&str> = FILTERS.iter().map(|filter| filter.name).collect();
+    stages.push(json!({ "type" : "object", "required" : ["type"], "description" : "a plugin's filter",
+        "properties" : { "type" : { "type" : "string", "not" : { "enum" : names } } } }));
+    let schemes : Vec<&str> = PUBLISHERS.iter().map(|publisher| publisher.name).collect();
+    let publishers : Vec<String> = PUBLISHERS.iter().map(|publisher|
+    {
+        let options : Vec<&str> = publisher.options.iter().chain(PUBLISH_OPTIONS).map(|option| option.name).collect();
+        format!("{}://{}?{}", publisher.name, publisher.help, options.join("&"))
+    }).collect();
+    let mut zone = object(ZONE);
+    zone["required"] = json!(["name"]);
+    json!({
+        "$schema" : "https://json-schema.org/draft/2020-12/schema",
+        "title" : "rusty_lidar_viewer --config",
+        "type" : "object",
+        "additionalProperties" : false,
+        "properties" : {
+            "publish" : {
+                "type" : "array",
+                "description" : publishers.join("\n"),
+                "items" : { "type" : "string", "pattern" : format!("^({})://", schemes.join("|")) },
+            },
+            "pipeline" : {
+                "type" : "object",
+                "additionalProperties" : false,
+                "properties" : { "stage" : { "type" : "array", "items" : { "oneOf" : stages } } },
+            },
+            "zone" : { "type" : "array", "items" : zone },
+            "device" : object(DEVICE),
+            "plugins" : object(PLUGINS),
+        },
+    })
+}
@@ -0,0 +1,144 @@
+// Per frame hook for site specific logic, run as a child process so it can be written in
+// any language: `sh -c <command>` gets one json line per frame on stdin and has to answer
+// each with one json line on stdout, see docs/script.md. The answer may replace the
+// distances and may carry outputs, which are published like analyzer results. Answers are
+// read on a thread of their own so a script that hangs is dropped after REPLY_TIMEOUT.
+use crate::analysis::Analysis;
+use crate::depth::DepthFrame;
+use crate::stats::{Stats, STATS};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// how long a script gets to answer a frame
+pub const REPLY_TIMEOUT : Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+struct Request<'a>
+{
+    sequence : u64,
+    width : usize,
+    height : usize,
+    valid_points : usize,
+    min_mm : Option<u16>,
+    max_mm : Option<u16>,
+    mean_mm : Option<f32>,
+    depth : &'a [u16],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Reply
+{
+    #[serde(default)]
+    depth : Option<Vec<u16>>,
+    #[serde(default)]
+    outputs : Option<serde_json::Value>,
+}
+
+pub struct Script
+{
+    command : String,
+    child : Child,
+    stdin : ChildStdin,
+    // lines from stdout, closed when the script exits
+    replies : Receiver<io::Result<String>>,
+    sequence : u64,
+}
+
+impl Script
+{
+
+pub fn spawn(command : &str) -> Result<Script, String>
+{
+    let mut child = Command::new("sh").arg("-c").arg(command).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()
+        .map_err(|msg| format!("failed to start {}, {}", command, msg))?;
+    let stdin = child.stdin.take().ok_or("no stdin")?;
+    let mut stdout = BufReader::new(child.stdout.take().ok_or("no stdout")?);
+    let (sender, replies) = mpsc::channel();
+    thread::Builder::new().name("script".to_string()).spawn(move ||
+    {
+        loop
+        {
+            let mut line = String::new();
+            let read = match stdout.read_line(&mut line)
+            {
+                Ok(0) => break,
+                Ok(_) => Ok(line),
+                Err(msg) => Err(msg),
+            };
+            let failed = read.is_err();
+            if sender.send(read).is_err() || failed
+            {
+                break;
+            }
+        }
+    }).map_err(|msg| format!("failed to start reading from {}, {}", command, msg))?;
+    info!("Running frames through {}", command);
+    Ok(Script { command : command.to_string(), child, stdin, replies, sequence : 0 })
+}
+
+pub fn command(&self) -> &str
+{
+    &self.command
+}
+
+// waits up to REPLY_TIMEOUT for the answer, so the script sees every frame and frames stay in order
+pub fn run(&mut self, depth : &mut DepthFrame) -> Result<Option<Analysis>, String>
+{
+    let summary = depth.summary();
+    let request = Request
+    {
+        sequence : self.sequence,
+        width : depth.width,
+        height : depth.height,
+        valid_points : summary.valid_points,
+        min_mm : summary.min_mm,
+        max_mm : summary.max_mm,
+        mean_mm : summary.mean_mm,
+        depth : &depth.data,
+    };
+    self.sequence += 1;
+    let mut json = serde_json::to_vec(&request).map_err(|msg| msg.to_string())?;
+    json.push(b'\n');
+    self.stdin.write_all(&json).and_then(|_| self.stdin.flush()).map_err(|msg| format!("failed to write to script, {}", msg))?;
+
+    let line = match self.replies.recv_timeout(REPLY_TIMEOUT)
+    {
+        Ok(Ok(line)) => line,
+        Ok(Err(msg)) => return Err(format!("failed to read from script, {}", msg)),
+        Err(RecvTimeoutError::Disconnected) => return Err("script exited".to_string()),
+        Err(RecvTimeoutError::Timeout) =>
+        {
+            Stats::count(&STATS.script_timeouts);
+            return Err(format!("script didn't answer within {:?}", REPLY_TIMEOUT))
+        },
+    };
+    let reply : Reply = serde_json::from_str(&line).map_err(|msg| format!("invalid answer from script, {}", msg))?;
+    if let Some(data) = reply.depth
+    {
+        if data.len() != depth.data.len()
+        {
+            return Err(format!("script answered with {} distances instead of {}", data.len(), depth.data.len()))
+        }
+        depth.data = data;
+    }
+    Ok(reply.outputs.map(Analysis::Script))
+}
+
+}
+
+impl Drop for Script
+{
+    fn drop(&mut self)
+    {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,207 @@
+use crate::frame::Frame;
+use crate::depth::{DepthFrame, HEIGHT_3D, WIDTH_3D};
+use crate::publish::{Options, Publisher};
+
+use log::{info, warn};
+use memmap2::{Mmap, MmapMut};
+
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Frames go into a ring of slots in /dev/shm/<name>, native endian:
+//   header : magic u32 ("RLV1"), slot count u32, slot size u32, width u32, height u32,
+//            padding to 32 bytes, frames written u64
+//   slot   : lock u64, timestamp_ms u64, sequence u64, width * height distances u16
+// The lock of a slot is odd while it is written. Readers take slot (frames written - 1)
+// % slot count, read its lock, copy, read the lock again and retry if it was odd or
+// changed.
+const MAGIC : u32 = u32::from_le_bytes(*b"RLV1");
+const HEADER_SIZE : usize = 64;
+const FRAMES_WRITTEN : usize = 32;
+const SLOT_HEADER : usize = 24;
+const DEFAULT_SLOTS : usize = 4;
+
+fn shm_path(name : &str) -> PathBuf
+{
+    PathBuf::from("/dev/shm").join(name.trim_start_matches('/'))
+}
+
+// the lock or frames written counter at offset of a map that starts at base and is len
+// bytes long, for as long as 'a, which the caller ties to the map. Those 8 bytes are only
+// ever accessed atomically, by this and the other processes mapping the file
+unsafe fn atomic<'a>(base : *const u8, len : usize, offset : usize) -> &'a AtomicU64
+{
+    assert!(offset.is_multiple_of(8) && offset + 8 <= len);
+    // SAFETY: the map starts on a page and offset is a multiple of 8, so the pointer is
+    // aligned for an AtomicU64, and the 8 bytes are within the map, checked above
+    unsafe { &*(base.add(offset) as *const AtomicU64) }
+}
+
+fn read_u32(map : &[u8], offset : usize) -> u32
+{
+    u32::from_ne_bytes(map[offset..offset + 4].try_into().unwrap())
+}
+
+pub struct ShmPublisher
+{
+    map : MmapMut,
+    path : PathBuf,
+    slots : usize,
+    slot_size : usize,
+    sequence : u64,
+}
+
+impl ShmPublisher
+{
+
+// options: slots=<ring length>
+pub fn new(name : &str, options : &Options) -> Result<ShmPublisher, String>
+{
+    let mut slots = DEFAULT_SLOTS;
+    for (option, value) in options
+    {
+        match *option
+        {
+            "slots" => slots = value.parse().ok().filter(|slots| *slots > 0).ok_or(format!("invalid slot count {}", value))?,
+            _ => return Err(format!("shm publisher has no option {}", option)),
+        }
+    }
+    let slot_size = (SLOT_HEADER + WIDTH_3D * HEIGHT_3D * 2).next_multiple_of(8);
+    let path = shm_path(name);
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).map_err(|msg| msg.to_string())?;
+    file.set_len((HEADER_SIZE + slots * slot_size) as u64).map_err(|msg| msg.to_string())?;
+    let mut map = unsafe { MmapMut::map_mut(&file) }.map_err(|msg| msg.to_string())?;
+    for (index, value) in [MAGIC, slots as u32, slot_size as u32, WIDTH_3D as u32, HEIGHT_3D as u32].iter().enumerate()
+    {
+        map[index * 4..index * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+    info!("Publishing frames in {}", path.display());
+    Ok(ShmPublisher { map, path, slots, slot_size, sequence : 0 })
+}
+
+fn atomic(&mut self, offset : usize) -> &AtomicU64
+{
+    // SAFETY: the counter borrows self, so it can't outlive the map
+    unsafe { atomic(self.map.as_mut_ptr(), self.map.len(), offset) }
+}
+
+}
+
+impl Drop for ShmPublisher
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Publisher for ShmPublisher
+{
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    if depth.data.len() != WIDTH_3D * HEIGHT_3D
+    {
+        warn!("Frame of {} points doesn't fit the shared memory slots", depth.data.len());
+        return Err(())
+    }
+    let offset = HEADER_SIZE + (self.sequence as usize % self.slots) * self.slot_size;
+    let sequence = self.sequence;
+    self.atomic(offset).store(sequence * 2 + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0);
+    self.map[offset + 8..offset + 16].copy_from_slice(&timestamp_ms.to_ne_bytes());
+    self.map[offset + 16..offset + 24].copy_from_slice(&self.sequence.to_ne_bytes());
+    unsafe
+    {
+        ptr::copy_nonoverlapping(depth.data.as_ptr() as *const u8, self.map.as_mut_ptr().add(offset + SLOT_HEADER), depth.data.len() * 2);
+    }
+
+    self.atomic(offset).store(sequence * 2 + 2, Ordering::Release);
+    self.sequence += 1;
+    self.atomic(FRAMES_WRITTEN).store(sequence + 1, Ordering::Release);
+    Ok(())
+}
+
+}
+
+// Reading side for consumers written in rust
+pub struct ShmReader
+{
+    map : Mmap,
+    slots : usize,
+    slot_size : usize,
+    pub width : usize,
+    pub height : usize,
+}
+
+impl ShmReader
+{
+
+pub fn open(name : &str) -> Result<ShmReader, String>
+{
+    let file = File::open(shm_path(name)).map_err(|msg| msg.to_string())?;
+    let map = unsafe { Mmap::map(&file) }.map_err(|msg| msg.to_string())?;
+    if map.len() < HEADER_SIZE || read_u32(&map, 0) != MAGIC
+    {
+        return Err(format!("{} is not a frame ring", name));
+    }
+    let slots = read_u32(&map, 4) as usize;
+    let slot_size = read_u32(&map, 8) as usize;
+    let width = read_u32(&map, 12) as usize;
+    let height = read_u32(&map, 16) as usize;
+    // slots a multiple of 8 long keep their locks aligned
+    let ring = slots.checked_mul(slot_size).and_then(|ring| ring.checked_add(HEADER_SIZE));
+    let slot = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(2)).and_then(|data| data.checked_add(SLOT_HEADER));
+    if slots == 0 || !slot_size.is_multiple_of(8) || ring.is_none_or(|ring| map.len() < ring) || slot.is_none_or(|slot| slot_size < slot)
+    {
+        return Err(format!("{} has an inconsistent header", name));
+    }
+    Ok(ShmReader { map, slots, slot_size, width, height })
+}
+
+fn atomic(&self, offset : usize) -> &AtomicU64
+{
+    // SAFETY: the counter borrows self, so it can't outlive the map
+    unsafe { atomic(self.map.as_ptr(), self.map.len(), offset) }
+}
+
+// copies the newest complete frame into depth, returns its sequence and timestamp_ms,
+// None while nothing has been written
+pub fn latest(&self, depth : &mut Vec<u16>) -> Option<(u64, u64)>
+{
+    depth.resize(self.width * self.height, 0);
+    loop
+    {
+        let written = self.atomic(FRAMES_WRITTEN).load(Ordering::Acquire);
+        if written == 0
+        {
+            return None
+        }
+        let offset = HEADER_SIZE + ((written - 1) as usize % self.slots) * self.slot_size;
+        let lock = self.atomic(offset);
+        let before = lock.load(Ordering::Acquire);
+        if before % 2 == 1
+        {
+            std::hint::spin_loop();
+            continue;
+        }
+        let timestamp_ms = u64::from_ne_bytes(self.map[offset + 8..offset + 16].try_into().unwrap());
+        let sequence = u64::from_ne_bytes(self.map[offset + 16..offset + 24].try_into().unwrap());
+        unsafe
+        {
+            ptr::copy_nonoverlapping(self.map.as_ptr().add(offset + SLOT_HEADER), depth.as_mut_ptr() as *mut u8, depth.len() * 2);
+        }
+        fence(Ordering::Acquire);
+        if lock.load(Ordering::Relaxed) == before
+        {
+            return Some((sequence, timestamp_ms))
+        }
+    }
+}
+
+}
@@ -0,0 +1,48 @@
+// The signals a service is sent: SIGTERM stops reading like Ctrl-C does, clearing
+// RUNNING, and SIGHUP asks for the --config file to be read again, see reload.rs. The
+// handlers only set flags, the loops look at them between frames.
+use std::ffi::c_int;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// cleared to stop, also by the Ctrl-C handler
+pub static RUNNING : AtomicBool = AtomicBool::new(true);
+static HANGUP : AtomicBool = AtomicBool::new(false);
+
+const SIGHUP : c_int = 1;
+const SIGTERM : c_int = 15;
+const SIG_ERR : usize = usize::MAX;
+
+extern "C"
+{
+    fn signal(signum : c_int, handler : usize) -> usize;
+}
+
+extern "C" fn handle(signum : c_int)
+{
+    match signum
+    {
+        SIGTERM => RUNNING.store(false, Ordering::SeqCst),
+        SIGHUP => HANGUP.store(true, Ordering::SeqCst),
+        _ => (),
+    }
+}
+
+pub fn install() -> io::Result<()>
+{
+    for signum in [SIGTERM, SIGHUP]
+    {
+        // SAFETY: handle only stores to atomics, which is safe in a signal handler
+        if unsafe { signal(signum, handle as extern "C" fn(c_int) as usize) } == SIG_ERR
+        {
+            return Err(io::Error::last_os_error())
+        }
+    }
+    Ok(())
+}
+
+// whether SIGHUP came since the last call
+pub fn hangup() -> bool
+{
+    HANGUP.swap(false, Ordering::SeqCst)
+}
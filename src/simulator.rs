@@ -0,0 +1,471 @@
+// The device side of the protocol, for developing and demoing without a lidar, see the
+// rusty_lidar_simulator binary. It answers the info request with its versions and
+// streams what the start request asks for until the stop request, like the device, which
+// acknowledges requests only by doing what they ask. Frames are rendered from a scene
+// each time: a wall in front of the sensor, a room around it, or the room with a person
+// walking across it, with gaussian noise on the distances, pixels dropped as unmeasured
+// and frames sent with a wrong byte, each as often as Settings says. Streams are written
+// to without blocking, frames are dropped while the host hasn't taken the one before.
+use crate::cloud::{Projection, FOV_H_DEG};
+use crate::depth::{DepthFrame, HEIGHT_3D, INVALID_DEPTH, WIDTH_2D, WIDTH_3D};
+use crate::device::Mode;
+use crate::frame::{new, parse_frame, HEADER};
+
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::pty::openpty;
+use nix::sys::termios::{self, SetArg};
+use nix::unistd::ttyname;
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// meters, x to the right, y down and z ahead as in cloud.rs, the sensor at the origin
+const ROOM_HALF_WIDTH : f32 = 1.5;
+const FLOOR : f32 = 1.0;
+const CEILING : f32 = -1.5;
+const PERSON_RADIUS : f32 = 0.25;
+const PERSON_HEAD : f32 = FLOOR - 1.75;
+// how far to either side the person walks and how long there and back takes
+const WALK : f32 = 1.0;
+const WALK_PERIOD : f32 = 8.0;
+// requests are a few bytes, a bigger size is no request
+const MAX_REQUEST : u16 = 16;
+// how long a stream that isn't streaming is waited on at a time
+const IDLE_WAIT : Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scene
+{
+    Wall,
+    Room,
+    Person,
+}
+
+impl Scene
+{
+
+pub fn parse(value : &str) -> Result<Scene, String>
+{
+    match value
+    {
+        "wall" => Ok(Scene::Wall),
+        "room" => Ok(Scene::Room),
+        "person" => Ok(Scene::Person),
+        _ => Err(format!("invalid scene {}, expected wall, room or person", value)),
+    }
+}
+
+}
+
+// distance is from the sensor to the wall ahead in the scene, noise the standard deviation
+// of the distances in millimeters, dropout and corrupt the fractions of pixels left
+// unmeasured and of frames with a wrong byte
+#[derive(Clone, Debug)]
+pub struct Settings
+{
+    pub scene : Scene,
+    pub distance : f32,
+    pub noise : f32,
+    pub dropout : f32,
+    pub corrupt : f32,
+    pub fps : f64,
+    pub firmware : [u8; 3],
+    pub hardware : [u8; 3],
+    pub seed : u64,
+}
+
+impl Default for Settings
+{
+    fn default() -> Self
+    {
+        Settings
+        {
+            scene : Scene::Person,
+            distance : 3.0,
+            noise : 10.0,
+            dropout : 0.01,
+            corrupt : 0.0,
+            fps : 30.0,
+            firmware : [1, 0, 0],
+            hardware : [1, 0, 0],
+            seed : 1,
+        }
+    }
+}
+
+// xorshift64*, the noise only has to look like noise
+struct Random
+{
+    state : u64,
+}
+
+impl Random
+{
+
+fn uniform(&mut self) -> f32
+{
+    self.state ^= self.state >> 12;
+    self.state ^= self.state << 25;
+    self.state ^= self.state >> 27;
+    (self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// Box-Muller, standard deviation 1
+fn gaussian(&mut self) -> f32
+{
+    let (u1, u2) = (self.uniform().max(f32::MIN_POSITIVE), self.uniform());
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+}
+
+pub struct Simulator
+{
+    settings : Settings,
+    depth_rays : Projection,
+    scan_rays : Projection,
+    random : Random,
+    started : Instant,
+    frame : DepthFrame,
+    payload : Vec<u8>,
+    // what was read and isn't a request yet, and what was sent and isn't written yet
+    input : Vec<u8>,
+    output : Vec<u8>,
+    mode : Option<Mode>,
+    dropping : bool,
+}
+
+impl Simulator
+{
+
+pub fn new(settings : Settings) -> Simulator
+{
+    let random = Random { state : settings.seed.max(1) };
+    Simulator
+    {
+        settings,
+        depth_rays : Projection::default(),
+        // the scan is the row straight ahead
+        scan_rays : Projection::new(WIDTH_2D, 1, FOV_H_DEG, 0.0),
+        random,
+        started : Instant::now(),
+        frame : DepthFrame::default(),
+        payload : Vec::new(),
+        input : Vec::new(),
+        output : Vec::new(),
+        mode : None,
+        dropping : false,
+    }
+}
+
+// The device on stream, which has to be nonblocking, until running is cleared or the host
+// closes it. Every stream starts with the device stopped
+pub fn serve<S : Read + Write + AsFd>(&mut self, stream : &mut S, running : &AtomicBool) -> io::Result<()>
+{
+    self.input.clear();
+    self.output.clear();
+    self.mode = None;
+    let interval = Duration::from_secs_f64(1.0 / self.settings.fps);
+    let mut next = Instant::now();
+    while running.load(Ordering::SeqCst)
+    {
+        let wait = match self.mode
+        {
+            Some(_) => next.saturating_duration_since(Instant::now()),
+            None => IDLE_WAIT,
+        };
+        let events = match self.output.is_empty()
+        {
+            true => PollFlags::POLLIN,
+            false => PollFlags::POLLIN | PollFlags::POLLOUT,
+        };
+        let mut fds = [PollFd::new(stream.as_fd(), events)];
+        match poll(&mut fds, PollTimeout::try_from(wait).unwrap_or(PollTimeout::MAX))
+        {
+            Ok(_) => (),
+            // a signal, e.g. the one that cleared running
+            Err(Errno::EINTR) => continue,
+            Err(msg) => return Err(io::Error::from(msg)),
+        }
+        let ready = fds[0].revents().unwrap_or(PollFlags::empty());
+        if ready.intersects(PollFlags::POLLIN | PollFlags::POLLHUP) && !self.read(stream)?
+        {
+            info!("Host closed the connection");
+            return Ok(())
+        }
+        if ready.contains(PollFlags::POLLOUT)
+        {
+            self.flush(stream)?;
+        }
+        let mode = match self.mode
+        {
+            Some(mode) if Instant::now() >= next => mode,
+            Some(_) => continue,
+            None => { next = Instant::now(); continue },
+        };
+        let seconds = self.started.elapsed().as_secs_f32();
+        if mode != Mode::Depth
+        {
+            self.render(seconds, false);
+            self.send(stream, true)?;
+        }
+        if mode != Mode::Scan
+        {
+            self.render(seconds, true);
+            self.send(stream, true)?;
+        }
+        // a host that fell behind gets the next frame an interval from now, not a burst
+        next = (next + interval).max(Instant::now());
+    }
+    Ok(())
+}
+
+// what the host sent, answering the requests in it, false once it closed the stream
+fn read<S : Read + Write>(&mut self, stream : &mut S) -> io::Result<bool>
+{
+    let mut read = [0u8; 256];
+    loop
+    {
+        match stream.read(&mut read)
+        {
+            Ok(0) => return Ok(false),
+            Ok(count) => self.input.extend_from_slice(&read[..count]),
+            Err(msg) if msg.kind() == ErrorKind::WouldBlock => break,
+            Err(msg) if msg.kind() == ErrorKind::Interrupted => continue,
+            Err(msg) => return Err(msg),
+        }
+    }
+    while let Some(request) = self.request()
+    {
+        self.answer(stream, &request)?;
+    }
+    Ok(true)
+}
+
+// the payload of the next whole request in input, skipping what isn't one
+fn request(&mut self) -> Option<Vec<u8>>
+{
+    loop
+    {
+        match self.input.windows(HEADER.len()).position(|window| window == HEADER)
+        {
+            Some(start) => { self.input.drain(..start); },
+            None =>
+            {
+                // the start of a header may be at the end
+                self.input.drain(..self.input.len().saturating_sub(HEADER.len() - 1));
+                return None
+            },
+        }
+        if self.input.len() < HEADER.len() + 2
+        {
+            return None
+        }
+        let size = u16::from_le_bytes([self.input[3], self.input[4]]);
+        let length = size as usize + 6;
+        if size > MAX_REQUEST
+        {
+            warn!("Skipping a request of {} bytes", size);
+            self.input.drain(..1);
+            continue;
+        }
+        if self.input.len() < length
+        {
+            return None
+        }
+        match parse_frame(&self.input[..length])
+        {
+            Ok(frame) => { self.input.drain(..length); return Some(frame.payload) },
+            Err(()) => { warn!("Skipping a request with a wrong checksum"); self.input.drain(..1); },
+        }
+    }
+}
+
+fn answer<S : Read + Write>(&mut self, stream : &mut S, request : &[u8]) -> io::Result<()>
+{
+    let mode = [Mode::Scan, Mode::Depth, Mode::Dual].into_iter().find(|mode| request == [mode.command(), 0x00]);
+    match request
+    {
+        [0x12, 0x55] => debug!("Baud rate request"),
+        [0x10, 0x00] =>
+        {
+            info!("Sending device info");
+            self.payload.clear();
+            self.payload.push(0x10);
+            self.payload.extend_from_slice(&self.settings.firmware);
+            self.payload.extend_from_slice(&self.settings.hardware);
+            self.send(stream, false)?;
+        },
+        [0x02, 0x00, 0x00] =>
+        {
+            info!("Stopping");
+            self.mode = None;
+            self.output.clear();
+        },
+        _ if mode.is_some() =>
+        {
+            info!("Streaming {:?}", mode.unwrap());
+            self.mode = mode;
+        },
+        _ => warn!("Unknown request {:02x?}", request),
+    }
+    Ok(())
+}
+
+// a depth frame or a scan of the scene as seconds in into payload
+fn render(&mut self, seconds : f32, depth : bool)
+{
+    let (rays, width, height) = match depth
+    {
+        true => (self.depth_rays.rays(), WIDTH_3D, HEIGHT_3D),
+        false => (self.scan_rays.rays(), WIDTH_2D, 1),
+    };
+    self.frame.width = width;
+    self.frame.height = height;
+    self.frame.data.clear();
+    for ray in rays
+    {
+        let distance = distance(&self.settings, *ray, seconds).map(|meters| meters * 1000.0 + self.settings.noise * self.random.gaussian());
+        let measured = match distance
+        {
+            Some(mm) if mm >= 1.0 && mm < INVALID_DEPTH as f32 && self.random.uniform() >= self.settings.dropout => mm.round() as u16,
+            _ => INVALID_DEPTH,
+        };
+        self.frame.data.push(measured);
+    }
+    self.frame.pack(&mut self.payload);
+}
+
+// payload as a frame after what is still going out, or dropped then if it can be
+fn send<S : Write>(&mut self, stream : &mut S, droppable : bool) -> io::Result<()>
+{
+    if droppable && !self.output.is_empty()
+    {
+        if !self.dropping
+        {
+            warn!("The host isn't reading, dropping frames");
+            self.dropping = true;
+        }
+        return Ok(())
+    }
+    let start = self.output.len();
+    self.output.extend(new(self.payload.clone()).as_bytes().map_err(|_| io::Error::new(ErrorKind::InvalidInput, "payload too big"))?);
+    if droppable && self.random.uniform() < self.settings.corrupt
+    {
+        let at = ((self.random.uniform() * self.payload.len() as f32) as usize).min(self.payload.len() - 1);
+        self.output[start + HEADER.len() + 2 + at] ^= 0xff;
+    }
+    if droppable && self.dropping
+    {
+        info!("The host is reading again");
+        self.dropping = false;
+    }
+    self.flush(stream)
+}
+
+// as much of output as the stream takes
+fn flush<S : Write>(&mut self, stream : &mut S) -> io::Result<()>
+{
+    let mut written = 0;
+    while written < self.output.len()
+    {
+        match stream.write(&self.output[written..])
+        {
+            Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+            Ok(count) => written += count,
+            Err(msg) if msg.kind() == ErrorKind::Interrupted => continue,
+            Err(msg) if msg.kind() == ErrorKind::WouldBlock => break,
+            Err(msg) => return Err(msg),
+        }
+    }
+    self.output.drain(..written);
+    Ok(())
+}
+
+}
+
+// meters along ray to the nearest surface of the scene, None when nothing is in the way
+fn distance(settings : &Settings, ray : [f32; 3], seconds : f32) -> Option<f32>
+{
+    let closer = |a : Option<f32>, b : Option<f32>| match (a, b)
+    {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    };
+    let mut nearest = plane(ray, 2, settings.distance);
+    if settings.scene == Scene::Wall
+    {
+        return nearest
+    }
+    for (axis, at) in [(0, -ROOM_HALF_WIDTH), (0, ROOM_HALF_WIDTH), (1, FLOOR), (1, CEILING)]
+    {
+        nearest = closer(nearest, plane(ray, axis, at));
+    }
+    if settings.scene == Scene::Person
+    {
+        let center = [WALK * (2.0 * PI * seconds / WALK_PERIOD).sin(), settings.distance * 0.6];
+        nearest = closer(nearest, person(ray, center));
+    }
+    nearest
+}
+
+// the plane where coordinate axis is at
+fn plane(ray : [f32; 3], axis : usize, at : f32) -> Option<f32>
+{
+    let meters = at / ray[axis];
+    (meters.is_finite() && meters > 0.0).then_some(meters)
+}
+
+// a standing cylinder on the floor at center, x and z
+fn person(ray : [f32; 3], center : [f32; 2]) -> Option<f32>
+{
+    let a = ray[0] * ray[0] + ray[2] * ray[2];
+    let b = -2.0 * (ray[0] * center[0] + ray[2] * center[1]);
+    let c = center[0] * center[0] + center[1] * center[1] - PERSON_RADIUS * PERSON_RADIUS;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 || a == 0.0
+    {
+        return None
+    }
+    let meters = (-b - discriminant.sqrt()) / (2.0 * a);
+    let height = ray[1] * meters;
+    (meters > 0.0 && (PERSON_HEAD..=FLOOR).contains(&height)).then_some(meters)
+}
+
+// A pseudo terminal to serve the device on, for the viewer to open like a port. The
+// slave end stays open here too, so the host can close and open it again, and the
+// master end is nonblocking for serve
+pub struct Pty
+{
+    pub master : File,
+    _slave : OwnedFd,
+    pub path : PathBuf,
+}
+
+impl Pty
+{
+
+pub fn open() -> io::Result<Pty>
+{
+    let pty = openpty(None, None).map_err(io::Error::from)?;
+    for fd in [&pty.master, &pty.slave]
+    {
+        let mut raw = termios::tcgetattr(fd).map_err(io::Error::from)?;
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(fd, SetArg::TCSANOW, &raw).map_err(io::Error::from)?;
+    }
+    let flags = OFlag::from_bits_truncate(fcntl(&pty.master, FcntlArg::F_GETFL).map_err(io::Error::from)?);
+    fcntl(&pty.master, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK)).map_err(io::Error::from)?;
+    let path = ttyname(&pty.slave).map_err(io::Error::from)?;
+    Ok(Pty { master : File::from(pty.master), _slave : pty.slave, path })
+}
+
+}
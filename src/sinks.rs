@@ -0,0 +1,249 @@
+// Every output of the device loop gets a queue and a thread of its own, so outputs only
+// wait for themselves. What happens when an output's queue is full is up to its
+// Backpressure: Block waits for room, holding up the other outputs and in the end costing
+// frames at the sink stage, DropOldest makes room by dropping the oldest frame waiting,
+// DropNewest drops the frame that didn't fit. Outputs that have to see every frame, like
+// recordings, block by default, the others drop the oldest. Dropped frames are counted
+// by output in the stats. An output's Throttle passes it only some of the frames, every
+// Nth or at most so many a second, before they are queued; frames left out that way aren't
+// dropped ones.
+use crate::analysis::Analysis;
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::pool::Pooled;
+use crate::publish::Output;
+use crate::stats::{Milestone, Stats, STATS};
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backpressure
+{
+    Block,
+    DropOldest,
+    DropNewest,
+}
+
+impl Backpressure
+{
+
+pub fn parse(value : &str) -> Result<Backpressure, String>
+{
+    match value
+    {
+        "block" => Ok(Backpressure::Block),
+        "drop_oldest" => Ok(Backpressure::DropOldest),
+        "drop_newest" => Ok(Backpressure::DropNewest),
+        _ => Err(format!("invalid backpressure {}, expected block, drop_oldest or drop_newest", value)),
+    }
+}
+
+}
+
+// fewer frames a second than this leaves intervals too long for a Duration
+pub const MIN_FPS : f64 = 0.001;
+
+#[derive(Clone, Debug, Default)]
+pub struct Throttle
+{
+    // every decimate-th frame, the first included, default all
+    pub decimate : Option<u64>,
+    pub max_fps : Option<f64>,
+    frames : u64,
+    last : Option<Instant>,
+}
+
+impl Throttle
+{
+
+// whether the frame read at read_at goes to the output
+pub fn admit(&mut self, read_at : Instant) -> bool
+{
+    let index = self.frames;
+    self.frames += 1;
+    if !index.is_multiple_of(self.decimate.unwrap_or(1))
+    {
+        return false
+    }
+    if let (Some(max_fps), Some(last)) = (self.max_fps, self.last)
+    {
+        if read_at.saturating_duration_since(last) < Duration::from_secs_f64(1.0 / max_fps)
+        {
+            return false
+        }
+    }
+    self.last = Some(read_at);
+    true
+}
+
+}
+
+// a frame with what was found in it, on its way to the outputs. Once the last output is
+// done with it its buffers go back to their pools and the time it took is recorded
+pub struct Published
+{
+    pub frame : Pooled<Frame>,
+    pub depth : Pooled<DepthFrame>,
+    pub analyses : Vec<Analysis>,
+    pub read_at : Instant,
+}
+
+impl Drop for Published
+{
+    fn drop(&mut self)
+    {
+        let latency = self.read_at.elapsed();
+        STATS.record_latency(latency);
+        STATS.trace(Milestone::Delivered, latency);
+    }
+}
+
+struct Queue
+{
+    state : Mutex<QueueState>,
+    changed : Condvar,
+    capacity : usize,
+    backpressure : Backpressure,
+    dropped : Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct QueueState
+{
+    waiting : VecDeque<Arc<Published>>,
+    closed : bool,
+}
+
+impl Queue
+{
+
+fn push(&self, published : Arc<Published>)
+{
+    let mut state = self.state.lock().unwrap();
+    while state.waiting.len() >= self.capacity
+    {
+        match self.backpressure
+        {
+            Backpressure::Block => state = self.changed.wait(state).unwrap(),
+            Backpressure::DropOldest => { state.waiting.pop_front(); Stats::count(&self.dropped); },
+            Backpressure::DropNewest => { Stats::count(&self.dropped); return },
+        }
+    }
+    state.waiting.push_back(published);
+    self.changed.notify_all();
+}
+
+// None once closed and empty
+fn pop(&self) -> Option<Arc<Published>>
+{
+    let mut state = self.state.lock().unwrap();
+    loop
+    {
+        if let Some(published) = state.waiting.pop_front()
+        {
+            self.changed.notify_all();
+            return Some(published)
+        }
+        if state.closed
+        {
+            return None
+        }
+        state = self.changed.wait(state).unwrap();
+    }
+}
+
+fn close(&self)
+{
+    self.state.lock().unwrap().closed = true;
+    self.changed.notify_all();
+}
+
+}
+
+struct Sink
+{
+    name : String,
+    throttle : Throttle,
+    queue : Arc<Queue>,
+    thread : JoinHandle<Output>,
+}
+
+pub struct Sinks
+{
+    sinks : Vec<Sink>,
+}
+
+impl Sinks
+{
+
+pub fn start(outputs : Vec<Output>) -> Sinks
+{
+    Sinks { sinks : outputs.into_iter().map(Sinks::spawn).collect() }
+}
+
+fn spawn(mut output : Output) -> Sink
+{
+    let queue = Arc::new(Queue
+    {
+        state : Mutex::new(QueueState::default()),
+        changed : Condvar::new(),
+        capacity : output.queue,
+        backpressure : output.backpressure,
+        dropped : STATS.sink_dropped(&output.name),
+    });
+    let name = output.name.clone();
+    let throttle = output.throttle.clone();
+    let fed = queue.clone();
+    let thread = thread::spawn(move ||
+    {
+        while let Some(published) = fed.pop()
+        {
+            output.publish(&published.frame, &published.depth, &published.analyses);
+        }
+        output
+    });
+    Sink { name, throttle, queue, thread }
+}
+
+// outputs added while running see the frames sent from then on
+pub fn add(&mut self, outputs : Vec<Output>)
+{
+    self.sinks.extend(outputs.into_iter().map(Sinks::spawn));
+}
+
+// the outputs with these names, once they finished what is queued
+pub fn remove(&mut self, names : &[String]) -> Vec<Output>
+{
+    let (removed, kept) = mem::take(&mut self.sinks).into_iter().partition(|sink| names.contains(&sink.name));
+    self.sinks = kept;
+    Sinks { sinks : removed }.stop()
+}
+
+pub fn send(&mut self, published : Published)
+{
+    let published = Arc::new(published);
+    for sink in self.sinks.iter_mut()
+    {
+        if sink.throttle.admit(published.read_at)
+        {
+            sink.queue.push(published.clone());
+        }
+    }
+}
+
+// lets the outputs finish what is queued and gives them back
+pub fn stop(self) -> Vec<Output>
+{
+    for sink in self.sinks.iter()
+    {
+        sink.queue.close();
+    }
+    self.sinks.into_iter().filter_map(|sink| sink.thread.join().ok()).collect()
+}
+
+}
@@ -0,0 +1,157 @@
+// Averages a number of frames of a static scene into one: per pixel the valid samples are
+// averaged, samples more than OUTLIER_SIGMAS standard deviations from the median are left
+// out, and pixels valid in less than half the frames are invalid. The result is written as
+// <path>.png (16 bit, mean distance in mm) and <path>.json (mean, standard deviation and
+// sample count per pixel), the usual way to characterize the noise of a ToF sensor.
+use crate::depth::{is_valid, DepthFrame};
+use crate::frame::Frame;
+use crate::publish::Publisher;
+use crate::sinks::Backpressure;
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+
+pub const DEFAULT_FRAMES : usize = 50;
+const OUTLIER_SIGMAS : f32 = 2.5;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot
+{
+    pub width : usize,
+    pub height : usize,
+    pub frames : usize,
+    // 0 for invalid pixels
+    pub mean_mm : Vec<f32>,
+    pub std_mm : Vec<f32>,
+    pub samples : Vec<u32>,
+}
+
+impl Snapshot
+{
+
+pub fn from_frames(frames : &[DepthFrame]) -> Option<Snapshot>
+{
+    let first = frames.first()?;
+    let (width, height) = (first.width, first.height);
+    let pixels = width * height;
+    let mut snapshot = Snapshot { width, height, frames : frames.len(), mean_mm : vec![0.0; pixels], std_mm : vec![0.0; pixels], samples : vec![0; pixels] };
+    let mut values = Vec::with_capacity(frames.len());
+    for pixel in 0..pixels
+    {
+        values.clear();
+        values.extend(frames.iter().filter_map(|frame| frame.data.get(pixel).copied()).filter(|distance| is_valid(*distance)).map(f32::from));
+        if values.len() * 2 < frames.len() || values.is_empty()
+        {
+            continue;
+        }
+        values.sort_unstable_by(f32::total_cmp);
+        let median = values[values.len() / 2];
+        let (_, std) = mean_std(&values);
+        values.retain(|value| (value - median).abs() <= OUTLIER_SIGMAS * std.max(1.0));
+        let (mean, std) = mean_std(&values);
+        snapshot.mean_mm[pixel] = mean;
+        snapshot.std_mm[pixel] = std;
+        snapshot.samples[pixel] = values.len() as u32;
+    }
+    Some(snapshot)
+}
+
+// a <path>.json written by the snapshot command
+pub fn load(path : &str) -> Result<Snapshot, String>
+{
+    let json = fs::read(path).map_err(|msg| format!("failed to read {}, {}", path, msg))?;
+    let snapshot : Snapshot = serde_json::from_slice(&json).map_err(|msg| format!("invalid snapshot {}, {}", path, msg))?;
+    let pixels = snapshot.width * snapshot.height;
+    if snapshot.mean_mm.len() != pixels || snapshot.std_mm.len() != pixels || snapshot.samples.len() != pixels
+    {
+        return Err(format!("snapshot {} doesn't have {} pixels", path, pixels))
+    }
+    Ok(snapshot)
+}
+
+pub fn depth(&self) -> DepthFrame
+{
+    DepthFrame { width : self.width, height : self.height, data : self.mean_mm.iter().map(|mean| mean.round() as u16).collect() }
+}
+
+// mean of the standard deviations of the valid pixels
+pub fn noise_mm(&self) -> Option<f32>
+{
+    let valid : Vec<f32> = self.std_mm.iter().zip(&self.samples).filter(|(_, samples)| **samples > 0).map(|(std, _)| *std).collect();
+    if valid.is_empty() { None } else { Some(valid.iter().sum::<f32>() / valid.len() as f32) }
+}
+
+}
+
+fn mean_std(values : &[f32]) -> (f32, f32)
+{
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f32>() / values.len() as f32;
+    (mean, variance.sqrt())
+}
+
+// collects frames and writes the snapshot once it has enough
+pub struct SnapshotPublisher
+{
+    path : String,
+    count : usize,
+    frames : Vec<DepthFrame>,
+}
+
+impl SnapshotPublisher
+{
+
+pub fn new(path : &str, count : usize) -> SnapshotPublisher
+{
+    info!("Averaging {} frames into {}.png", count, path);
+    SnapshotPublisher { path : path.to_string(), count, frames : Vec::with_capacity(count) }
+}
+
+fn save(&self, snapshot : &Snapshot) -> Result<(), String>
+{
+    let png = snapshot.depth().to_png().map_err(|msg| msg.to_string())?;
+    fs::write(format!("{}.png", self.path), png).map_err(|msg| msg.to_string())?;
+    let json = serde_json::to_vec(snapshot).map_err(|msg| msg.to_string())?;
+    fs::write(format!("{}.json", self.path), json).map_err(|msg| msg.to_string())
+}
+
+}
+
+impl Publisher for SnapshotPublisher
+{
+
+// the snapshot averages every frame it was asked for
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    if self.frames.len() >= self.count
+    {
+        return Ok(())
+    }
+    self.frames.push(depth.clone());
+    if self.frames.len() < self.count
+    {
+        return Ok(())
+    }
+    let snapshot = match Snapshot::from_frames(&self.frames)
+    {
+        Some(snapshot) => snapshot,
+        None => return Err(()),
+    };
+    if let Err(msg) = self.save(&snapshot)
+    {
+        error!("Failed to write snapshot {}, {}", self.path, msg);
+        return Err(())
+    }
+    let valid = snapshot.samples.iter().filter(|samples| **samples > 0).count();
+    info!("Wrote snapshot of {} frames to {}.png, {} valid pixels, mean noise {:?} mm", self.count, self.path, valid, snapshot.noise_mm());
+    Ok(())
+}
+
+}
@@ -0,0 +1,523 @@
+use serde::Serialize;
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Process wide counters, bumped where things happen and read by the metrics endpoint
+pub static STATS : Stats = Stats::new();
+
+const LATENCY_SAMPLES : usize = 512;
+
+// upper bounds of the frame interval histogram buckets, in seconds
+const INTERVAL_BUCKETS : [f64; 11] = [0.01, 0.02, 0.03, 0.035, 0.04, 0.05, 0.075, 0.1, 0.25, 0.5, 1.0];
+
+pub struct Stats
+{
+    pub frames : AtomicU64,
+    pub bytes_read : AtomicU64,
+    // every byte off the port, in frames or not
+    pub bytes_received : AtomicU64,
+    pub timeouts : AtomicU64,
+    // times the device went quiet partway through a frame
+    pub short_reads : AtomicU64,
+    pub read_errors : AtomicU64,
+    pub header_errors : AtomicU64,
+    pub size_errors : AtomicU64,
+    pub checksum_errors : AtomicU64,
+    // times frame sync was lost and the bytes up to the next header skipped
+    pub resyncs : AtomicU64,
+    pub publish_errors : AtomicU64,
+    // scripts dropped for not answering a frame in time, see script.rs
+    pub script_timeouts : AtomicU64,
+    // streams restarted for bringing no valid frame, see device::Settings
+    pub restarts : AtomicU64,
+    // messages waiting in tcp / ws client queues, and clients dropped for being slow
+    pub queued_messages : AtomicU64,
+    pub dropped_clients : AtomicU64,
+    // time from a frame being read to all publishers having it, newest last
+    latencies : Mutex<VecDeque<Duration>>,
+    // by Stage
+    stages : [StageStats; 4],
+    // frames dropped by each output of the device loop, see sinks.rs
+    sinks : Mutex<Vec<(String, Arc<AtomicU64>)>>,
+    // with --latency, every frame's time from its header arriving to each Milestone
+    tracing : AtomicBool,
+    traces : Mutex<[Vec<Duration>; 3]>,
+    // time between frames arriving from the device
+    intervals : Mutex<Intervals>,
+}
+
+struct Intervals
+{
+    last : Option<Instant>,
+    // by INTERVAL_BUCKETS, not cumulative, the last one for longer intervals
+    buckets : [u64; 12],
+    count : u64,
+    sum : Duration,
+    max : Duration,
+    // newest last, for the jitter
+    recent : VecDeque<Duration>,
+}
+
+// Frame intervals over the last LATENCY_SAMPLES frames. The jitter is their standard
+// deviation, the max gap is the longest interval since starting
+#[derive(Clone, Copy, Debug)]
+pub struct Jitter
+{
+    pub mean : Duration,
+    pub jitter : Duration,
+    pub max_gap : Duration,
+}
+
+// The link counters at one point, from Stats::link, for /stats, the viewer and the lines
+// --stats-interval prints
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Link
+{
+    pub frames : u64,
+    pub bytes_read : u64,
+    pub bytes_received : u64,
+    pub checksum_errors : u64,
+    pub header_errors : u64,
+    pub size_errors : u64,
+    pub resyncs : u64,
+    pub short_reads : u64,
+    pub timeouts : u64,
+    pub read_errors : u64,
+    pub restarts : u64,
+}
+
+impl Link
+{
+
+// what was counted after earlier
+pub fn since(&self, earlier : &Link) -> Link
+{
+    Link
+    {
+        frames : self.frames.saturating_sub(earlier.frames),
+        bytes_read : self.bytes_read.saturating_sub(earlier.bytes_read),
+        bytes_received : self.bytes_received.saturating_sub(earlier.bytes_received),
+        checksum_errors : self.checksum_errors.saturating_sub(earlier.checksum_errors),
+        header_errors : self.header_errors.saturating_sub(earlier.header_errors),
+        size_errors : self.size_errors.saturating_sub(earlier.size_errors),
+        resyncs : self.resyncs.saturating_sub(earlier.resyncs),
+        short_reads : self.short_reads.saturating_sub(earlier.short_reads),
+        timeouts : self.timeouts.saturating_sub(earlier.timeouts),
+        read_errors : self.read_errors.saturating_sub(earlier.read_errors),
+        restarts : self.restarts.saturating_sub(earlier.restarts),
+    }
+}
+
+// frames that came in bad for every one that came in at all, 0 without frames
+pub fn error_rate(&self) -> f64
+{
+    let bad = self.checksum_errors + self.header_errors + self.size_errors;
+    bad as f64 / (self.frames + bad).max(1) as f64
+}
+
+// one line for counters over the time since the last one
+pub fn summary(&self, over : Duration) -> String
+{
+    let seconds = over.as_secs_f64().max(f64::EPSILON);
+    format!("{} frames, {:.1} frames/s, {:.1} kB/s, {:.2} % bad, {} checksum errors, {} resyncs, {} short reads, {} timeouts, {} restarts over {:.1?}",
+        self.frames, self.frames as f64 / seconds, self.bytes_received as f64 / seconds / 1000.0, self.error_rate() * 100.0,
+        self.checksum_errors, self.resyncs, self.short_reads, self.timeouts, self.restarts, over)
+}
+
+}
+
+// how far a frame got, for --latency
+#[derive(Clone, Copy, Debug)]
+pub enum Milestone
+{
+    // all of it read off the serial port
+    Read,
+    // checked and unpacked
+    Parsed,
+    // all publishers had it
+    Delivered,
+}
+
+impl Milestone
+{
+
+pub const ALL : [Milestone; 3] = [Milestone::Read, Milestone::Parsed, Milestone::Delivered];
+
+pub fn name(&self) -> &'static str
+{
+    match self
+    {
+        Milestone::Read => "read",
+        Milestone::Parsed => "parsed",
+        Milestone::Delivered => "delivered",
+    }
+}
+
+}
+
+// the stages of the device loop, see run_device in main.rs
+#[derive(Clone, Copy, Debug)]
+pub enum Stage
+{
+    Read,
+    Parse,
+    Process,
+    Sink,
+}
+
+impl Stage
+{
+
+pub const ALL : [Stage; 4] = [Stage::Read, Stage::Parse, Stage::Process, Stage::Sink];
+
+pub fn name(&self) -> &'static str
+{
+    match self
+    {
+        Stage::Read => "read",
+        Stage::Parse => "parse",
+        Stage::Process => "process",
+        Stage::Sink => "sink",
+    }
+}
+
+}
+
+pub struct StageStats
+{
+    pub frames : AtomicU64,
+    pub busy_ns : AtomicU64,
+    // frames dropped because the queue into the stage was full
+    pub dropped : AtomicU64,
+}
+
+impl StageStats
+{
+
+const fn new() -> StageStats
+{
+    StageStats { frames : AtomicU64::new(0), busy_ns : AtomicU64::new(0), dropped : AtomicU64::new(0) }
+}
+
+// a frame that took busy in the stage
+pub fn record(&self, busy : Duration)
+{
+    Stats::count(&self.frames);
+    self.busy_ns.fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+}
+
+}
+
+impl Default for Stats
+{
+    fn default() -> Self
+    {
+        Stats::new()
+    }
+}
+
+impl Stats
+{
+
+pub const fn new() -> Stats
+{
+    Stats
+    {
+        frames : AtomicU64::new(0),
+        bytes_read : AtomicU64::new(0),
+        bytes_received : AtomicU64::new(0),
+        timeouts : AtomicU64::new(0),
+        short_reads : AtomicU64::new(0),
+        read_errors : AtomicU64::new(0),
+        header_errors : AtomicU64::new(0),
+        size_errors : AtomicU64::new(0),
+        checksum_errors : AtomicU64::new(0),
+        resyncs : AtomicU64::new(0),
+        publish_errors : AtomicU64::new(0),
+        script_timeouts : AtomicU64::new(0),
+        restarts : AtomicU64::new(0),
+        queued_messages : AtomicU64::new(0),
+        dropped_clients : AtomicU64::new(0),
+        latencies : Mutex::new(VecDeque::new()),
+        stages : [StageStats::new(), StageStats::new(), StageStats::new(), StageStats::new()],
+        sinks : Mutex::new(Vec::new()),
+        tracing : AtomicBool::new(false),
+        traces : Mutex::new([Vec::new(), Vec::new(), Vec::new()]),
+        intervals : Mutex::new(Intervals
+        {
+            last : None,
+            buckets : [0; 12],
+            count : 0,
+            sum : Duration::ZERO,
+            max : Duration::ZERO,
+            recent : VecDeque::new(),
+        }),
+    }
+}
+
+pub fn stage(&self, stage : Stage) -> &StageStats
+{
+    &self.stages[stage as usize]
+}
+
+// the dropped frames counter of an output, shared by outputs of the same name
+pub fn sink_dropped(&self, name : &str) -> Arc<AtomicU64>
+{
+    let mut sinks = self.sinks.lock().unwrap();
+    if let Some((_, dropped)) = sinks.iter().find(|(sink, _)| sink == name)
+    {
+        return dropped.clone()
+    }
+    let dropped = Arc::new(AtomicU64::new(0));
+    sinks.push((name.to_string(), dropped.clone()));
+    dropped
+}
+
+pub fn link(&self) -> Link
+{
+    let load = |counter : &AtomicU64| counter.load(Ordering::Relaxed);
+    Link
+    {
+        frames : load(&self.frames),
+        bytes_read : load(&self.bytes_read),
+        bytes_received : load(&self.bytes_received),
+        checksum_errors : load(&self.checksum_errors),
+        header_errors : load(&self.header_errors),
+        size_errors : load(&self.size_errors),
+        resyncs : load(&self.resyncs),
+        short_reads : load(&self.short_reads),
+        timeouts : load(&self.timeouts),
+        read_errors : load(&self.read_errors),
+        restarts : load(&self.restarts),
+    }
+}
+
+pub fn count(counter : &AtomicU64)
+{
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// a frame started arriving from the device
+pub fn record_arrival(&self, arrived : Instant)
+{
+    let mut intervals = self.intervals.lock().unwrap();
+    if let Some(interval) = intervals.last.map(|last| arrived.saturating_duration_since(last))
+    {
+        let bucket = INTERVAL_BUCKETS.iter().position(|bound| interval.as_secs_f64() <= *bound).unwrap_or(INTERVAL_BUCKETS.len());
+        intervals.buckets[bucket] += 1;
+        intervals.count += 1;
+        intervals.sum += interval;
+        intervals.max = intervals.max.max(interval);
+        if intervals.recent.len() == LATENCY_SAMPLES
+        {
+            intervals.recent.pop_front();
+        }
+        intervals.recent.push_back(interval);
+    }
+    intervals.last = Some(arrived);
+}
+
+// None before the second frame
+pub fn jitter(&self) -> Option<Jitter>
+{
+    let intervals = self.intervals.lock().unwrap();
+    if intervals.recent.is_empty()
+    {
+        return None
+    }
+    let seconds = || intervals.recent.iter().map(Duration::as_secs_f64);
+    let mean = seconds().sum::<f64>() / intervals.recent.len() as f64;
+    let variance = seconds().map(|interval| (interval - mean).powi(2)).sum::<f64>() / intervals.recent.len() as f64;
+    Some(Jitter { mean : Duration::from_secs_f64(mean), jitter : Duration::from_secs_f64(variance.sqrt()), max_gap : intervals.max })
+}
+
+pub fn record_latency(&self, latency : Duration)
+{
+    let mut latencies = self.latencies.lock().unwrap();
+    if latencies.len() == LATENCY_SAMPLES
+    {
+        latencies.pop_front();
+    }
+    latencies.push_back(latency);
+}
+
+// latency percentiles over the last LATENCY_SAMPLES frames, None before the first frame
+pub fn latency_percentiles(&self, percentiles : &[f64]) -> Option<Vec<Duration>>
+{
+    let latencies : Vec<Duration> = self.latencies.lock().unwrap().iter().copied().collect();
+    percentiles_of(latencies, percentiles)
+}
+
+// keeps the latency of every frame from here on, for trace_report
+pub fn start_tracing(&self)
+{
+    self.tracing.store(true, Ordering::Relaxed);
+}
+
+pub fn trace(&self, milestone : Milestone, latency : Duration)
+{
+    if self.tracing.load(Ordering::Relaxed)
+    {
+        self.traces.lock().unwrap()[milestone as usize].push(latency);
+    }
+}
+
+// p50, p95 and p99 of the time to each milestone, since start_tracing
+pub fn trace_report(&self) -> String
+{
+    let traces = self.traces.lock().unwrap();
+    let mut report = String::new();
+    for milestone in Milestone::ALL
+    {
+        let latencies = &traces[milestone as usize];
+        match percentiles_of(latencies.clone(), &[0.5, 0.95, 0.99])
+        {
+            Some(percentiles) => { let _ = writeln!(report, "{:<9} {:>8.2?} p50 {:>8.2?} p95 {:>8.2?} p99 over {} frames",
+                milestone.name(), percentiles[0], percentiles[1], percentiles[2], latencies.len()); },
+            None => { let _ = writeln!(report, "{:<9} no frames", milestone.name()); },
+        }
+    }
+    report
+}
+
+// prometheus text exposition format
+pub fn prometheus(&self) -> String
+{
+    let mut text = String::new();
+    let counters = [
+        ("frames_total", "Frames read from the device", &self.frames),
+        ("bytes_read_total", "Bytes of valid frames read from the device", &self.bytes_read),
+        ("bytes_received_total", "Bytes read from the device, in frames or not", &self.bytes_received),
+        ("timeouts_total", "Serial reads that timed out", &self.timeouts),
+        ("short_reads_total", "Times the device went quiet partway through a frame", &self.short_reads),
+        ("read_errors_total", "Serial reads that failed", &self.read_errors),
+        ("header_errors_total", "Frames with a bad header", &self.header_errors),
+        ("size_errors_total", "Frames with an unexpected size", &self.size_errors),
+        ("checksum_errors_total", "Frames with a bad checksum", &self.checksum_errors),
+        ("resyncs_total", "Times frame sync was lost and bytes skipped to the next header", &self.resyncs),
+        ("publish_errors_total", "Frames a publisher failed to send", &self.publish_errors),
+        ("script_timeouts_total", "Scripts dropped for not answering in time", &self.script_timeouts),
+        ("restarts_total", "Streams the watchdog restarted", &self.restarts),
+        ("dropped_clients_total", "Network clients dropped for being too slow", &self.dropped_clients),
+    ];
+    for (name, help, counter) in counters
+    {
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_{} counter", name);
+        let _ = writeln!(text, "rusty_lidar_viewer_{} {}", name, counter.load(Ordering::Relaxed));
+    }
+    let _ = writeln!(text, "# HELP rusty_lidar_viewer_queued_messages Messages waiting in network client queues");
+    let _ = writeln!(text, "# TYPE rusty_lidar_viewer_queued_messages gauge");
+    let _ = writeln!(text, "rusty_lidar_viewer_queued_messages {}", self.queued_messages.load(Ordering::Relaxed));
+
+    let stage_metrics = [
+        ("stage_frames_total", "Frames through each stage of the device loop"),
+        ("stage_seconds_total", "Time spent in each stage of the device loop"),
+        ("stage_dropped_total", "Frames dropped because the queue into a stage was full"),
+    ];
+    for (metric, (name, help)) in stage_metrics.iter().enumerate()
+    {
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_{} counter", name);
+        for stage in Stage::ALL
+        {
+            let stats = self.stage(stage);
+            let value = match metric
+            {
+                0 => stats.frames.load(Ordering::Relaxed) as f64,
+                1 => stats.busy_ns.load(Ordering::Relaxed) as f64 / 1e9,
+                _ => stats.dropped.load(Ordering::Relaxed) as f64,
+            };
+            let _ = writeln!(text, "rusty_lidar_viewer_{}{{stage=\"{}\"}} {}", name, stage.name(), value);
+        }
+    }
+
+    let sinks = self.sinks.lock().unwrap();
+    if !sinks.is_empty()
+    {
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_sink_dropped_total Frames an output dropped because its queue was full");
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_sink_dropped_total counter");
+        for (name, dropped) in sinks.iter()
+        {
+            let label = name.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = writeln!(text, "rusty_lidar_viewer_sink_dropped_total{{sink=\"{}\"}} {}", label, dropped.load(Ordering::Relaxed));
+        }
+    }
+    drop(sinks);
+
+    let intervals = self.intervals.lock().unwrap();
+    let _ = writeln!(text, "# HELP rusty_lidar_viewer_frame_interval_seconds Time between frames arriving from the device");
+    let _ = writeln!(text, "# TYPE rusty_lidar_viewer_frame_interval_seconds histogram");
+    let mut cumulative = 0;
+    for (bound, count) in INTERVAL_BUCKETS.iter().zip(intervals.buckets.iter())
+    {
+        cumulative += count;
+        let _ = writeln!(text, "rusty_lidar_viewer_frame_interval_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+    }
+    let _ = writeln!(text, "rusty_lidar_viewer_frame_interval_seconds_bucket{{le=\"+Inf\"}} {}", intervals.count);
+    let _ = writeln!(text, "rusty_lidar_viewer_frame_interval_seconds_sum {}", intervals.sum.as_secs_f64());
+    let _ = writeln!(text, "rusty_lidar_viewer_frame_interval_seconds_count {}", intervals.count);
+    drop(intervals);
+    if let Some(jitter) = self.jitter()
+    {
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_frame_jitter_seconds Standard deviation of recent frame intervals");
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_frame_jitter_seconds gauge");
+        let _ = writeln!(text, "rusty_lidar_viewer_frame_jitter_seconds {}", jitter.jitter.as_secs_f64());
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_frame_max_gap_seconds Longest time between two frames since starting");
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_frame_max_gap_seconds gauge");
+        let _ = writeln!(text, "rusty_lidar_viewer_frame_max_gap_seconds {}", jitter.max_gap.as_secs_f64());
+    }
+
+    let quantiles = [0.5, 0.95, 0.99];
+    if let Some(latencies) = self.latency_percentiles(&quantiles)
+    {
+        let _ = writeln!(text, "# HELP rusty_lidar_viewer_pipeline_latency_seconds Time from reading a frame to all publishers having it");
+        let _ = writeln!(text, "# TYPE rusty_lidar_viewer_pipeline_latency_seconds summary");
+        for (quantile, latency) in quantiles.iter().zip(latencies)
+        {
+            let _ = writeln!(text, "rusty_lidar_viewer_pipeline_latency_seconds{{quantile=\"{}\"}} {}", quantile, latency.as_secs_f64());
+        }
+    }
+    text
+}
+
+}
+
+fn percentiles_of(mut latencies : Vec<Duration>, percentiles : &[f64]) -> Option<Vec<Duration>>
+{
+    if latencies.is_empty()
+    {
+        return None
+    }
+    latencies.sort();
+    Some(percentiles.iter().map(|percentile|
+    {
+        let index = ((latencies.len() - 1) as f64 * percentile).round() as usize;
+        latencies[index]
+    }).collect())
+}
+
+// A message sitting in a network client queue, counted in queued_messages for as long
+// as it lives, whether it ends up sent, rejected or dropped with the queue
+pub struct Queued<T>(pub T);
+
+impl<T> Queued<T>
+{
+    pub fn new(message : T) -> Queued<T>
+    {
+        Stats::count(&STATS.queued_messages);
+        Queued(message)
+    }
+}
+
+impl<T> Drop for Queued<T>
+{
+    fn drop(&mut self)
+    {
+        STATS.queued_messages.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,79 @@
+// Tells systemd how the service is doing over the socket in NOTIFY_SOCKET, as sd_notify
+// does, for --daemon under a Type=notify unit: READY=1 once frames are read, WATCHDOG=1
+// as they keep coming, at most every half of WATCHDOG_USEC, so a device gone quiet gets
+// the service restarted, and STOPPING=1 on the way out. Without NOTIFY_SOCKET, e.g. run
+// by hand, nothing is sent.
+use log::{debug, warn};
+
+use std::env;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+
+pub struct Notifier
+{
+    socket : UnixDatagram,
+    address : SocketAddr,
+    watchdog : Option<Duration>,
+    petted : Instant,
+}
+
+impl Notifier
+{
+
+// None when not run by systemd with Type=notify
+pub fn from_env() -> Option<Notifier>
+{
+    let path = env::var("NOTIFY_SOCKET").ok()?;
+    // names starting with @ are in the abstract namespace
+    let address = match path.strip_prefix('@')
+    {
+        #[cfg(target_os = "linux")]
+        Some(name) => SocketAddr::from_abstract_name(name),
+        _ => SocketAddr::from_pathname(&path),
+    };
+    let (socket, address) = match UnixDatagram::unbound().and_then(|socket| Ok((socket, address?)))
+    {
+        Ok(bound) => bound,
+        Err(msg) => { warn!("Failed to open NOTIFY_SOCKET {}, {}", path, msg); return None },
+    };
+    // the watchdog is for this process only if WATCHDOG_PID, when there, says so
+    let own = env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+    let watchdog = env::var("WATCHDOG_USEC").ok()
+        .and_then(|usec| usec.parse().ok())
+        .filter(|usec| *usec > 0 && own)
+        .map(|usec| Duration::from_micros(usec) / 2);
+    debug!("Notifying systemd at {}, watchdog every {:?}", path, watchdog);
+    Some(Notifier { socket, address, watchdog, petted : Instant::now() })
+}
+
+pub fn notify(&self, state : &str)
+{
+    if let Err(msg) = self.socket.send_to_addr(state.as_bytes(), &self.address)
+    {
+        warn!("Failed to notify systemd of {}, {}", state.trim_end(), msg);
+    }
+}
+
+pub fn ready(&self, status : &str)
+{
+    self.notify(&format!("READY=1\nSTATUS={}\n", status));
+}
+
+// for every frame, sending only once half the watchdog interval went by
+pub fn frame(&mut self)
+{
+    if self.watchdog.is_some_and(|interval| self.petted.elapsed() >= interval)
+    {
+        self.petted = Instant::now();
+        self.notify("WATCHDOG=1\n");
+    }
+}
+
+pub fn stopping(&self, status : &str)
+{
+    self.notify(&format!("STOPPING=1\nSTATUS={}\n", status));
+}
+
+}
@@ -0,0 +1,190 @@
+use crate::analysis::Analysis;
+use crate::frame::Frame;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::publish::{Encoding, Publisher};
+use crate::stats::{Queued, Stats, STATS};
+
+use log::{error, info, warn};
+
+use std::io;
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Every message is a u32 little endian length followed by the frame as read from the
+// device, or a protobuf message with format=proto. Each client gets its own writer
+// thread and a bounded queue; a client whose queue is full is considered too slow and
+// gets disconnected. Dropping the publisher stops accepting, closes the listener and
+// disconnects the clients.
+const CLIENT_QUEUE_DEPTH : usize = 8;
+
+struct Client
+{
+    address : SocketAddr,
+    sender : SyncSender<Queued<Arc<Vec<u8>>>>,
+}
+
+#[derive(Default)]
+struct Clients
+{
+    connected : Vec<Client>,
+    // sent to every client first, the device info with format=proto
+    greeting : Option<Arc<Vec<u8>>>,
+    // the publisher was dropped, the next accepted connection ends accepting
+    stopped : bool,
+}
+
+pub struct TcpPublisher
+{
+    clients : Arc<Mutex<Clients>>,
+    encoding : Encoding,
+    address : SocketAddr,
+    accepting : Option<JoinHandle<()>>,
+}
+
+impl TcpPublisher
+{
+
+pub fn new(address : &str, encoding : Encoding) -> io::Result<TcpPublisher>
+{
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    info!("Publishing frames on tcp://{}", address);
+    let clients = Arc::new(Mutex::new(Clients::default()));
+    let accepted = clients.clone();
+    let accepting = Some(thread::spawn(move || accept_clients(listener, accepted)));
+    Ok(TcpPublisher { clients, encoding, address, accepting })
+}
+
+fn broadcast(&mut self, messages : Vec<Arc<Vec<u8>>>)
+{
+    let mut clients = self.clients.lock().unwrap();
+    clients.connected.retain(|client| messages.iter().all(|message| match client.sender.try_send(Queued::new(message.clone()))
+    {
+        Ok(_) => true,
+        Err(TrySendError::Full(_)) => { Stats::count(&STATS.dropped_clients); warn!(client:% = client.address; "Dropping slow tcp client"); false },
+        Err(TrySendError::Disconnected(_)) => { info!(client:% = client.address; "Tcp client disconnected"); false },
+    }));
+}
+
+}
+
+impl Drop for TcpPublisher
+{
+    fn drop(&mut self)
+    {
+        {
+            // the writers end with their senders, closing the connections
+            let mut clients = self.clients.lock().unwrap();
+            clients.stopped = true;
+            clients.connected.clear();
+        }
+        wake(self.address, self.accepting.take());
+    }
+}
+
+// connects to a listener at address so its accept returns and the accepting thread, having
+// been told to stop, ends and closes the listener. Without a connection there is nothing
+// to wait for, the thread is left to itself
+pub(crate) fn wake(address : SocketAddr, accepting : Option<JoinHandle<()>>)
+{
+    let address = match address
+    {
+        SocketAddr::V4(v4) if v4.ip().is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), v4.port()),
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), v6.port()),
+        address => address,
+    };
+    match TcpStream::connect_timeout(&address, Duration::from_secs(1))
+    {
+        Ok(stream) => { drop(stream); if let Some(accepting) = accepting { let _ = accepting.join(); } },
+        Err(msg) => warn!("Failed to stop accepting on {}, {}", address, msg),
+    }
+}
+
+fn accept_clients(listener : TcpListener, clients : Arc<Mutex<Clients>>)
+{
+    for stream in listener.incoming()
+    {
+        if clients.lock().unwrap().stopped
+        {
+            return
+        }
+        let stream = match stream
+        {
+            Ok(stream) => stream,
+            Err(msg) => { error!("Failed to accept tcp client, {}", msg); continue; }
+        };
+        let address = match stream.peer_addr()
+        {
+            Ok(address) => address,
+            Err(_) => continue,
+        };
+        let _ = stream.set_nodelay(true);
+        let (sender, receiver) = sync_channel::<Queued<Arc<Vec<u8>>>>(CLIENT_QUEUE_DEPTH);
+        thread::spawn(move || write_client(stream, receiver));
+        info!(client:% = address; "Tcp client connected");
+        let mut clients = clients.lock().unwrap();
+        if let Some(greeting) = &clients.greeting
+        {
+            let _ = sender.try_send(Queued::new(greeting.clone()));
+        }
+        clients.connected.push(Client { address, sender });
+    }
+}
+
+fn write_client(mut stream : TcpStream, messages : Receiver<Queued<Arc<Vec<u8>>>>)
+{
+    for message in messages
+    {
+        if stream.write_all(&message.0).is_err()
+        {
+            break;
+        }
+    }
+}
+
+fn length_prefixed(bytes : &[u8]) -> Arc<Vec<u8>>
+{
+    let mut message = Vec::with_capacity(4 + bytes.len());
+    message.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    message.extend_from_slice(bytes);
+    Arc::new(message)
+}
+
+impl Publisher for TcpPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    if let Some(message) = self.encoding.device_info(info)
+    {
+        let message = length_prefixed(&message);
+        let mut clients = self.clients.lock().unwrap();
+        for client in clients.connected.iter()
+        {
+            let _ = client.sender.try_send(Queued::new(message.clone()));
+        }
+        clients.greeting = Some(message);
+    }
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    if let Some(message) = self.encoding.analysis(analysis)
+    {
+        self.broadcast(vec![length_prefixed(&message)]);
+    }
+}
+
+fn publish(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let messages = self.encoding.frame(frame, depth)?.iter().map(|message| length_prefixed(message)).collect();
+    self.broadcast(messages);
+    Ok(())
+}
+
+}
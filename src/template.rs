@@ -0,0 +1,48 @@
+// Paths of what is written with placeholders filled in when it is opened, so captures
+// left running unattended don't overwrite each other: {date} is the day as 2026-10-14,
+// {time} the time of day as 09-30-05, both UTC, and {seq} the lowest number from 0000 up
+// that makes a path not there yet. The directories the path goes through are created.
+use crate::clock::Utc;
+
+use std::fs;
+use std::path::Path;
+
+// suffix is added to the path when looking for one there, e.g. .png for a snapshot,
+// written to path.png
+pub fn expand(template : &str, suffix : &str) -> Result<String, String>
+{
+    if !template.contains('{')
+    {
+        return Ok(template.to_string())
+    }
+    let now = Utc::now();
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{')
+    {
+        let end = rest[start..].find('}').ok_or(format!("unclosed {{ in {}", template))? + start;
+        expanded += &rest[..start];
+        match &rest[start + 1..end]
+        {
+            "date" => expanded += &now.date(),
+            "time" => expanded += &now.time(),
+            // filled in below, once the rest is known
+            "seq" => expanded += "{seq}",
+            name => return Err(format!("unknown placeholder {{{}}} in {}, expected date, time or seq", name, template)),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded += rest;
+    if expanded.contains("{seq}")
+    {
+        expanded = (0..)
+            .map(|seq| expanded.replace("{seq}", &format!("{:04}", seq)))
+            .find(|path| !Path::new(&format!("{}{}", path, suffix)).exists())
+            .unwrap_or_default();
+    }
+    if let Some(parent) = Path::new(&expanded).parent().filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|msg| format!("failed to create {}, {}", parent.display(), msg))?;
+    }
+    Ok(expanded)
+}
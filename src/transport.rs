@@ -0,0 +1,254 @@
+// The bytes to and from the device, behind Transport so the protocol code in device.rs,
+// frame.rs and reader.rs runs over anything that reads, writes and can be waited on: the
+// serial port, or a MockTransport. A MockTransport plays the device from a script of
+// exchanges, each a request expected from the host and the response the device sends to
+// it, for testing the handshake, resyncing and the error paths without hardware. What the
+// host sends that isn't the next request fails its write. Responses go out over a unix
+// socket pair from a thread of their own, so the host end can be polled like a port and
+// responses of any size don't hold up the writes that trigger them.
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub trait Transport : Read + Write + AsRawFd
+{
+    // for reads, zero is no waiting at all
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout : Duration) -> io::Result<()>;
+
+    // drops the bytes received and not read yet
+    fn clear_input(&mut self) -> io::Result<()>;
+}
+
+impl Transport for TTYPort
+{
+
+fn timeout(&self) -> Duration
+{
+    SerialPort::timeout(self)
+}
+
+fn set_timeout(&mut self, timeout : Duration) -> io::Result<()>
+{
+    SerialPort::set_timeout(self, timeout).map_err(io::Error::from)
+}
+
+fn clear_input(&mut self) -> io::Result<()>
+{
+    self.clear(ClearBuffer::Input).map_err(io::Error::from)
+}
+
+}
+
+pub struct Exchange
+{
+    pub request : Vec<u8>,
+    pub response : Vec<u8>,
+}
+
+// how many bytes the thread writing responses wrote
+#[derive(Default)]
+struct Sent
+{
+    written : Mutex<usize>,
+    changed : Condvar,
+}
+
+pub struct MockTransport
+{
+    host : UnixStream,
+    responses : Option<Sender<Vec<u8>>>,
+    sent : Arc<Sent>,
+    queued : usize,
+    exchanges : VecDeque<Exchange>,
+    // of the next request, and everything the host wrote
+    pending : Vec<u8>,
+    written : Vec<u8>,
+    timeout : Duration,
+}
+
+impl MockTransport
+{
+
+pub fn new() -> io::Result<MockTransport>
+{
+    let (host, mut device) = UnixStream::pair()?;
+    let (responses, queue) = channel::<Vec<u8>>();
+    let sent = Arc::new(Sent::default());
+    let counted = sent.clone();
+    // the device end closes once the mock is closed or dropped, which the host reads as
+    // the end of file
+    thread::spawn(move ||
+    {
+        for response in queue
+        {
+            if device.write_all(&response).is_err()
+            {
+                break
+            }
+            *counted.written.lock().unwrap() += response.len();
+            counted.changed.notify_all();
+        }
+    });
+    host.set_nonblocking(true)?;
+    Ok(MockTransport
+    {
+        host,
+        responses : Some(responses),
+        sent,
+        queued : 0,
+        exchanges : VecDeque::new(),
+        pending : Vec::new(),
+        written : Vec::new(),
+        timeout : Duration::ZERO,
+    })
+}
+
+// response once the host sent request, after the exchanges before
+pub fn expect(&mut self, request : &[u8], response : &[u8])
+{
+    self.exchanges.push_back(Exchange { request : request.to_vec(), response : response.to_vec() });
+}
+
+// bytes the device sends without being asked, e.g. left over from before or a stream
+pub fn send(&mut self, bytes : &[u8])
+{
+    if let Some(responses) = &self.responses
+    {
+        self.queued += bytes.len();
+        let _ = responses.send(bytes.to_vec());
+    }
+}
+
+// the device gone, reads end once what was sent before is read
+pub fn close(&mut self)
+{
+    self.responses = None;
+}
+
+// everything the host wrote
+pub fn written(&self) -> &[u8]
+{
+    &self.written
+}
+
+// whether every exchange took place
+pub fn finished(&self) -> bool
+{
+    self.exchanges.is_empty()
+}
+
+// until the thread wrote what was queued, reading it off meanwhile when drop says so
+fn settle(&mut self, drop : bool) -> io::Result<()>
+{
+    let started = Instant::now();
+    loop
+    {
+        if drop
+        {
+            self.host.set_nonblocking(true)?;
+            let mut dropped = [0u8; 4096];
+            while matches!(self.host.read(&mut dropped), Ok(count) if count > 0) {}
+            self.set_timeout(self.timeout)?;
+        }
+        let written = self.sent.written.lock().unwrap();
+        if *written >= self.queued
+        {
+            return Ok(())
+        }
+        if started.elapsed() > Duration::from_secs(5)
+        {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "responses not written"))
+        }
+        let _ = self.sent.changed.wait_timeout(written, Duration::from_millis(10));
+    }
+}
+
+}
+
+impl Read for MockTransport
+{
+    // like a serial port, a read that waited for nothing has timed out
+    fn read(&mut self, buffer : &mut [u8]) -> io::Result<usize>
+    {
+        match self.host.read(buffer)
+        {
+            Err(msg) if msg.kind() == io::ErrorKind::WouldBlock => Err(io::Error::new(io::ErrorKind::TimedOut, msg)),
+            read => read,
+        }
+    }
+}
+
+impl Write for MockTransport
+{
+    fn write(&mut self, bytes : &[u8]) -> io::Result<usize>
+    {
+        self.written.extend_from_slice(bytes);
+        self.pending.extend_from_slice(bytes);
+        while let Some(exchange) = self.exchanges.front()
+        {
+            let compared = self.pending.len().min(exchange.request.len());
+            if self.pending[..compared] != exchange.request[..compared]
+            {
+                let msg = format!("expected {:02x?}, got {:02x?}", exchange.request, self.pending);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+            if compared < exchange.request.len()
+            {
+                break
+            }
+            self.pending.drain(..compared);
+            let exchange = self.exchanges.pop_front().unwrap();
+            self.send(&exchange.response);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.settle(false)
+    }
+}
+
+impl AsRawFd for MockTransport
+{
+    fn as_raw_fd(&self) -> RawFd
+    {
+        self.host.as_raw_fd()
+    }
+}
+
+impl Transport for MockTransport
+{
+
+fn timeout(&self) -> Duration
+{
+    self.timeout
+}
+
+fn set_timeout(&mut self, timeout : Duration) -> io::Result<()>
+{
+    self.timeout = timeout;
+    if timeout.is_zero()
+    {
+        return self.host.set_nonblocking(true)
+    }
+    self.host.set_nonblocking(false)?;
+    self.host.set_read_timeout(Some(timeout))
+}
+
+// what was sent so far arrived, as it has on a port, before it is dropped
+fn clear_input(&mut self) -> io::Result<()>
+{
+    self.settle(true)
+}
+
+}
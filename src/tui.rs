@@ -0,0 +1,341 @@
+// A status screen in the terminal for the tui subcommand, for robots without a display
+// reached over ssh: the device and its settings, frames and errors from stats.rs, the
+// last frame's distances, the range filter, the recording being written, the zones,
+// what the analyzers found last, the latest events and the log, redrawn every
+// REDRAW_INTERVAL. Keys are read without waiting for enter: r starts and stops
+// recording, [ and ] move the nearest distance kept, - and + the farthest, by RANGE_STEP,
+// q quits like Ctrl-C. What a key changes is up to the main loop, it gets them as
+// Controls and says what came of them with set_recording and set_range. Log lines go to
+// the screen while it is up and to stderr again after, all of them, so nothing logged is
+// lost.
+use crate::analysis::zones::ZoneReport;
+use crate::analysis::Analysis;
+use crate::depth::{DepthFrame, Summary, INVALID_DEPTH};
+use crate::device::{DeviceInfo, Settings};
+use crate::filters::range::Range;
+use crate::frame::Frame;
+use crate::logging;
+use crate::publish::Publisher;
+use crate::signals;
+use crate::stats::{Stage, STATS};
+
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::os::fd::AsFd;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::ffi::{c_int, c_ulong};
+
+pub const REDRAW_INTERVAL : Duration = Duration::from_millis(200);
+pub const RANGE_STEP : u16 = 100;
+const EVENTS : usize = 5;
+// kept for stderr once the screen is gone, the newest of them are shown
+const LOG_LINES : usize = 1000;
+
+// what a key asks the main loop for
+#[derive(Clone, Copy, Debug)]
+pub enum Control
+{
+    Record,
+    Range(Range),
+}
+
+#[derive(Default)]
+struct State
+{
+    settings : String,
+    info : Option<DeviceInfo>,
+    summary : Summary,
+    points : usize,
+    zones : Vec<ZoneReport>,
+    // the last of each, as json
+    analyses : Vec<(&'static str, String)>,
+    events : VecDeque<String>,
+    log : VecDeque<String>,
+    range : Option<Range>,
+    recording : Option<(String, Instant)>,
+}
+
+// the publisher feeding the screen, see Screen::start
+pub struct TuiPublisher
+{
+    state : Arc<Mutex<State>>,
+    started : Instant,
+}
+
+impl Publisher for TuiPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    self.state.lock().unwrap().info = Some(info.clone());
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    let mut state = self.state.lock().unwrap();
+    if let Some(events) = analysis.events()
+    {
+        if state.events.len() == EVENTS
+        {
+            state.events.pop_front();
+        }
+        let line = format!("{:9.1} s {} {}", self.started.elapsed().as_secs_f64(), events.name(), serde_json::to_string(&events).unwrap_or_default());
+        state.events.push_back(line);
+    }
+    if let Analysis::Zones(reports) = analysis
+    {
+        state.zones = reports.clone();
+        return
+    }
+    let json = serde_json::to_string(analysis).unwrap_or_default();
+    match state.analyses.iter_mut().find(|(name, _)| *name == analysis.name())
+    {
+        Some((_, last)) => *last = json,
+        None => state.analyses.push((analysis.name(), json)),
+    }
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let mut state = self.state.lock().unwrap();
+    state.summary = depth.summary();
+    state.points = depth.data.len();
+    Ok(())
+}
+
+}
+
+pub struct Screen
+{
+    state : Arc<Mutex<State>>,
+    controls : Receiver<Control>,
+    stop : Sender<()>,
+    thread : Option<JoinHandle<()>>,
+    restore : Termios,
+}
+
+impl Screen
+{
+
+// takes over the terminal until dropped
+pub fn start(settings : &Settings, range : Option<Range>) -> Result<(Screen, TuiPublisher), String>
+{
+    let restore = termios::tcgetattr(io::stdin().as_fd()).map_err(|msg| format!("stdin is not a terminal, {}", msg))?;
+    // keys one at a time and not echoed, Ctrl-C still stops
+    let mut raw = restore.clone();
+    raw.local_flags.remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    termios::tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &raw).map_err(|msg| msg.to_string())?;
+    let state = Arc::new(Mutex::new(State
+    {
+        settings : format!("{} at {} baud, {:?} mode", settings.path, settings.baud_rate, settings.mode),
+        range,
+        ..State::default()
+    }));
+    let lines = state.clone();
+    logging::redirect(Some(Box::new(move |line : &str|
+    {
+        let mut state = lines.lock().unwrap();
+        if state.log.len() == LOG_LINES
+        {
+            state.log.pop_front();
+        }
+        state.log.push_back(line.to_string());
+    })));
+    let _ = write!(io::stdout().lock(), "\x1b[?1049h\x1b[?25l");
+    let (control, controls) = channel();
+    let (stop, stopped) = channel();
+    let shown = state.clone();
+    let thread = thread::spawn(move || run(shown, control, stopped));
+    let publisher = TuiPublisher { state : state.clone(), started : Instant::now() };
+    Ok((Screen { state, controls, stop, thread : Some(thread), restore }, publisher))
+}
+
+// the keys pressed since the last call
+pub fn controls(&self) -> Vec<Control>
+{
+    self.controls.try_iter().collect()
+}
+
+pub fn set_recording(&self, path : Option<&str>)
+{
+    self.state.lock().unwrap().recording = path.map(|path| (path.to_string(), Instant::now()));
+}
+
+pub fn set_range(&self, range : Range)
+{
+    self.state.lock().unwrap().range = Some(range);
+}
+
+}
+
+impl Drop for Screen
+{
+    fn drop(&mut self)
+    {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take()
+        {
+            let _ = thread.join();
+        }
+        let _ = write!(io::stdout().lock(), "\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = termios::tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &self.restore);
+        logging::redirect(None);
+        let mut stderr = io::stderr().lock();
+        for line in self.state.lock().unwrap().log.iter()
+        {
+            let _ = writeln!(stderr, "{}", line);
+        }
+    }
+}
+
+// keys and redraws until the screen is dropped
+fn run(state : Arc<Mutex<State>>, control : Sender<Control>, stopped : Receiver<()>)
+{
+    let mut redrawn = Instant::now() - REDRAW_INTERVAL;
+    while stopped.try_recv().is_err()
+    {
+        let wait = REDRAW_INTERVAL.saturating_sub(redrawn.elapsed());
+        let stdin = io::stdin();
+        let mut fds = [PollFd::new(stdin.as_fd(), PollFlags::POLLIN)];
+        let timeout = PollTimeout::try_from(wait).unwrap_or(PollTimeout::ZERO);
+        if poll(&mut fds, timeout).unwrap_or(0) > 0
+        {
+            let mut keys = [0; 16];
+            let read = stdin.lock().read(&mut keys).unwrap_or(0);
+            for key in keys[..read].iter()
+            {
+                if let Some(pressed) = key_control(&state, *key)
+                {
+                    let _ = control.send(pressed);
+                }
+            }
+        }
+        if redrawn.elapsed() >= REDRAW_INTERVAL
+        {
+            redrawn = Instant::now();
+            let lines = draw(&state.lock().unwrap());
+            let (columns, rows) = size();
+            let mut stdout = io::stdout().lock();
+            let _ = write!(stdout, "\x1b[H");
+            for line in lines.iter().take(rows)
+            {
+                let line : String = line.chars().take(columns).collect();
+                let _ = write!(stdout, "{}\x1b[K\r\n", line);
+            }
+            let _ = write!(stdout, "\x1b[J");
+            let _ = stdout.flush();
+        }
+    }
+}
+
+fn key_control(state : &Mutex<State>, key : u8) -> Option<Control>
+{
+    let mut state = state.lock().unwrap();
+    let range = state.range.unwrap_or(Range { min_mm : 0, max_mm : u16::MAX });
+    // without a farthest distance the step down is from the farthest one measured
+    let max_mm = range.max_mm.min(INVALID_DEPTH / RANGE_STEP * RANGE_STEP);
+    let range = match key
+    {
+        b'q' => { signals::RUNNING.store(false, Ordering::SeqCst); return None },
+        b'r' => return Some(Control::Record),
+        b'[' => Range { min_mm : range.min_mm.saturating_sub(RANGE_STEP), ..range },
+        b']' => Range { min_mm : (range.min_mm + RANGE_STEP).min(max_mm.saturating_sub(RANGE_STEP)), ..range },
+        b'-' => Range { max_mm : max_mm.saturating_sub(RANGE_STEP).max(range.min_mm + RANGE_STEP), ..range },
+        b'+' | b'=' => Range { max_mm : if range.max_mm >= max_mm { u16::MAX } else { max_mm + RANGE_STEP }, ..range },
+        _ => return None,
+    };
+    // shown right away, and the next key goes on from it
+    state.range = Some(range);
+    Some(Control::Range(range))
+}
+
+fn draw(state : &State) -> Vec<String>
+{
+    let mut lines = vec!["rusty_lidar_viewer    q quit, r record, [ ] nearest kept, - + farthest kept".to_string(), String::new()];
+    let info = state.info.as_ref().map_or(String::new(), |info| format!(", firmware {}, hardware {}", info.firmware, info.hardware));
+    lines.push(format!("Device     {}{}", state.settings, info));
+    let frames = STATS.frames.load(Ordering::Relaxed);
+    lines.push(match STATS.jitter()
+    {
+        Some(jitter) => format!("Frames     {}, {:.1} frames/s, jitter {:.1?}, longest gap {:.1?}", frames, 1.0 / jitter.mean.as_secs_f64().max(f64::EPSILON), jitter.jitter, jitter.max_gap),
+        None => format!("Frames     {}", frames),
+    });
+    let count = |counter : &std::sync::atomic::AtomicU64| counter.load(Ordering::Relaxed);
+    let dropped : u64 = Stage::ALL.iter().map(|stage| count(&STATS.stage(*stage).dropped)).sum();
+    lines.push(format!("Errors     {} timeouts, {} read, {} header, {} size, {} checksum, {} publish, {} frames dropped, {} restarts",
+        count(&STATS.timeouts), count(&STATS.read_errors), count(&STATS.header_errors), count(&STATS.size_errors),
+        count(&STATS.checksum_errors), count(&STATS.publish_errors), dropped, count(&STATS.restarts)));
+    let link = STATS.link();
+    lines.push(format!("Link       {} bytes in, {} in valid frames, {:.2} % frames bad, {} resyncs, {} short reads",
+        link.bytes_received, link.bytes_read, link.error_rate() * 100.0, link.resyncs, link.short_reads));
+    let mm = |mm : Option<u16>| mm.map_or("-".to_string(), |mm| format!("{} mm", mm));
+    let summary = &state.summary;
+    lines.push(format!("Distances  {} of {} points valid, nearest {}, mean {}, farthest {}", summary.valid_points, state.points,
+        mm(summary.min_mm), mm(summary.mean_mm.map(|mean| mean.round() as u16)), mm(summary.max_mm)));
+    lines.push(match state.range
+    {
+        Some(range) if range.max_mm < u16::MAX => format!("Range      {} to {} mm", range.min_mm, range.max_mm),
+        Some(range) => format!("Range      from {} mm", range.min_mm),
+        None => "Range      all".to_string(),
+    });
+    lines.push(match &state.recording
+    {
+        Some((path, since)) => format!("Recording  to {} for {} s", path, since.elapsed().as_secs()),
+        None => "Recording  off".to_string(),
+    });
+    lines.push(String::new());
+    lines.push("Zones".to_string());
+    for zone in state.zones.iter()
+    {
+        let nearest = zone.min_distance.map_or("-".to_string(), |meters| format!("{:.0} mm", meters * 1000.0));
+        lines.push(format!("  {:<16} {:<9} {} points, nearest {}", zone.name, if zone.occupied { "OCCUPIED" } else { "empty" }, zone.points, nearest));
+    }
+    lines.push("Analyses".to_string());
+    for (name, json) in state.analyses.iter()
+    {
+        lines.push(format!("  {:<16} {}", name, json));
+    }
+    lines.push("Events".to_string());
+    lines.extend(state.events.iter().map(|event| format!("  {}", event)));
+    lines.push("Log".to_string());
+    let (_, rows) = size();
+    // the rest of the screen, newest last
+    let shown = rows.saturating_sub(lines.len()).max(1);
+    lines.extend(state.log.iter().skip(state.log.len().saturating_sub(shown)).map(|line| format!("  {}", line)));
+    lines
+}
+
+#[cfg(target_os = "linux")]
+extern "C"
+{
+    fn ioctl(fd : c_int, request : c_ulong, ...) -> c_int;
+}
+
+// columns and rows of the terminal, 80 by 24 when it doesn't say
+#[cfg(target_os = "linux")]
+fn size() -> (usize, usize)
+{
+    const TIOCGWINSZ : c_ulong = 0x5413;
+    let mut size = [0u16; 4];
+    // SAFETY: TIOCGWINSZ fills a struct winsize, four unsigned shorts, rows first
+    if unsafe { ioctl(1, TIOCGWINSZ, size.as_mut_ptr()) } == 0 && size[0] > 0 && size[1] > 0
+    {
+        return (size[1] as usize, size[0] as usize)
+    }
+    (80, 24)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn size() -> (usize, usize)
+{
+    (80, 24)
+}
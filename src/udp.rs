@@ -0,0 +1,93 @@
+use crate::analysis::Analysis;
+use crate::frame::Frame;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::publish::{Encoding, Publisher};
+
+use log::warn;
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+// Every message (a frame as read from the device, or a protobuf message with
+// format=proto) is split into datagrams of at most MAX_CHUNK bytes, each prefixed with
+//   sequence : u32, chunk index : u16, chunk count : u16   (little endian)
+// so consumers can reassemble messages and detect drops.
+const MAX_CHUNK : usize = 1400;
+const DATAGRAM_HEADER : usize = 8;
+
+pub struct UdpPublisher
+{
+    socket : UdpSocket,
+    target : SocketAddr,
+    encoding : Encoding,
+    sequence : u32,
+}
+
+impl UdpPublisher
+{
+
+pub fn new(address : &str, encoding : Encoding) -> io::Result<UdpPublisher>
+{
+    let target = match address.to_socket_addrs()?.next()
+    {
+        Some(target) => target,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve")),
+    };
+    let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local)?;
+    Ok(UdpPublisher { socket, target, encoding, sequence : 0 })
+}
+
+fn send(&mut self, message : &[u8]) -> Result<(), ()>
+{
+    let count = message.len().div_ceil(MAX_CHUNK) as u16;
+    let mut datagram = Vec::with_capacity(DATAGRAM_HEADER + MAX_CHUNK);
+    for (index, chunk) in message.chunks(MAX_CHUNK).enumerate()
+    {
+        datagram.clear();
+        datagram.extend_from_slice(&self.sequence.to_le_bytes());
+        datagram.extend_from_slice(&(index as u16).to_le_bytes());
+        datagram.extend_from_slice(&count.to_le_bytes());
+        datagram.extend_from_slice(chunk);
+        if let Err(msg) = self.socket.send_to(&datagram, self.target)
+        {
+            warn!(target:% = self.target, error:% = msg; "Failed to send frame");
+            return Err(())
+        }
+    }
+    self.sequence = self.sequence.wrapping_add(1);
+    Ok(())
+}
+
+}
+
+impl Publisher for UdpPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    if let Some(message) = self.encoding.device_info(info)
+    {
+        let _ = self.send(&message);
+    }
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    if let Some(message) = self.encoding.analysis(analysis)
+    {
+        let _ = self.send(&message);
+    }
+}
+
+fn publish(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    for message in self.encoding.frame(frame, depth)?
+    {
+        self.send(&message)?;
+    }
+    Ok(())
+}
+
+}
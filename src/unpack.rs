@@ -0,0 +1,96 @@
+// Unpacking of the 12 bit distances of a 3D payload, every 3 bytes carry two of them. The
+// vector versions shuffle 12 bytes into eight 16 bit lanes, each holding the two bytes its
+// distance is in, then mask the even lanes and shift the odd ones. SSSE3 is detected at
+// runtime, NEON is always there on aarch64, anything else uses the scalar loop.
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+
+// the bytes of each 16 bit lane, little endian
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const SHUFFLE : [u8; 16] = [0, 1, 1, 2, 3, 4, 4, 5, 6, 7, 7, 8, 9, 10, 10, 11];
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const EVEN : [u16; 8] = [0xfff, 0, 0xfff, 0, 0xfff, 0, 0xfff, 0];
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const ODD : [u16; 8] = [0, 0xfff, 0, 0xfff, 0, 0xfff, 0, 0xfff];
+
+// fills as much of out as packed has distances for, the rest of out is left as it is
+pub fn unpack_12bit(packed : &[u8], out : &mut [u16])
+{
+    let done = unpack_vector(packed, out);
+    unpack_12bit_scalar(&packed[done / 2 * 3..], &mut out[done..])
+}
+
+pub fn unpack_12bit_scalar(packed : &[u8], out : &mut [u16])
+{
+    for (points, bytes) in out.chunks_exact_mut(2).zip(packed.chunks_exact(3))
+    {
+        points[0] = bytes[0] as u16 | ((bytes[1] & 0xf) as u16) << 8;
+        points[1] = (bytes[1] >> 4) as u16 | (bytes[2] as u16) << 4;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn unpack_vector(packed : &[u8], out : &mut [u16]) -> usize
+{
+    if !is_x86_feature_detected!("ssse3")
+    {
+        return 0
+    }
+    // SAFETY: ssse3 was detected just now
+    unsafe { unpack_ssse3(packed, out) }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn unpack_vector(packed : &[u8], out : &mut [u16]) -> usize
+{
+    // SAFETY: neon is part of every aarch64 target
+    unsafe { unpack_neon(packed, out) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn unpack_vector(_packed : &[u8], _out : &mut [u16]) -> usize
+{
+    0
+}
+
+// the number of distances written, always a multiple of 8. Every step loads 16 bytes for
+// the 12 it uses, so it stops while there are 16 left
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn unpack_ssse3(packed : &[u8], out : &mut [u16]) -> usize
+{
+    let shuffle = _mm_loadu_si128(SHUFFLE.as_ptr() as *const __m128i);
+    let even = _mm_loadu_si128(EVEN.as_ptr() as *const __m128i);
+    let odd = _mm_loadu_si128(ODD.as_ptr() as *const __m128i);
+    let mut done = 0;
+    while done / 2 * 3 + 16 <= packed.len() && done + 8 <= out.len()
+    {
+        let bytes = _mm_loadu_si128(packed.as_ptr().add(done / 2 * 3) as *const __m128i);
+        let lanes = _mm_shuffle_epi8(bytes, shuffle);
+        let points = _mm_or_si128(_mm_and_si128(lanes, even), _mm_and_si128(_mm_srli_epi16(lanes, 4), odd));
+        _mm_storeu_si128(out.as_mut_ptr().add(done) as *mut __m128i, points);
+        done += 8;
+    }
+    done
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn unpack_neon(packed : &[u8], out : &mut [u16]) -> usize
+{
+    let shuffle = vld1q_u8(SHUFFLE.as_ptr());
+    let even = vld1q_u16(EVEN.as_ptr());
+    let odd = vld1q_u16(ODD.as_ptr());
+    let mut done = 0;
+    while done / 2 * 3 + 16 <= packed.len() && done + 8 <= out.len()
+    {
+        let bytes = vld1q_u8(packed.as_ptr().add(done / 2 * 3));
+        let lanes = vreinterpretq_u16_u8(vqtbl1q_u8(bytes, shuffle));
+        let points = vorrq_u16(vandq_u16(lanes, even), vandq_u16(vshrq_n_u16::<4>(lanes), odd));
+        vst1q_u16(out.as_mut_ptr().add(done), points);
+        done += 8;
+    }
+    done
+}
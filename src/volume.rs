@@ -0,0 +1,130 @@
+// Volume of what was added to a scene, e.g. a pile or the contents of a bin, against a
+// reference snapshot of it empty. Both are averaged snapshots, see snapshot.rs. Every
+// pixel sees a thin cone of its solid angle, and the material between the reference and
+// the current distance along it fills solid_angle * (reference^3 - current^3) / 3; the
+// sum over the region of interest is the volume, negative where material was taken away.
+// The uncertainty propagates the standard error of each pixel's mean distance, so it
+// covers sensor noise but not calibration errors.
+use crate::cloud::{Projection, FOV_H_DEG, FOV_V_DEG};
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::Publisher;
+use crate::sinks::Backpressure;
+use crate::snapshot::Snapshot;
+
+use log::{error, info};
+use serde::Serialize;
+
+use std::fs;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Volume
+{
+    // cubic meters
+    pub volume : f32,
+    // one standard deviation, cubic meters
+    pub uncertainty : f32,
+    // pixels valid in both snapshots that went into the sum
+    pub pixels : usize,
+    pub roi : [usize; 4],
+}
+
+// roi is x0, y0, x1, y1 in pixels, x1 and y1 exclusive
+pub fn estimate(reference : &Snapshot, current : &Snapshot, roi : [usize; 4]) -> Result<Volume, String>
+{
+    if (reference.width, reference.height) != (current.width, current.height)
+    {
+        return Err(format!("reference is {}x{}, the scene {}x{}", reference.width, reference.height, current.width, current.height))
+    }
+    let [x0, y0, x1, y1] = roi;
+    if x0 >= x1 || y0 >= y1 || x1 > current.width || y1 > current.height
+    {
+        return Err(format!("roi {},{},{},{} is outside the {}x{} frame", x0, y0, x1, y1, current.width, current.height))
+    }
+    let projection = Projection::new(current.width, current.height, FOV_H_DEG, FOV_V_DEG);
+    let (mut volume, mut variance, mut pixels) = (0.0f64, 0.0f64, 0);
+    for y in y0..y1
+    {
+        for pixel in y * current.width + x0..y * current.width + x1
+        {
+            if reference.samples[pixel] == 0 || current.samples[pixel] == 0
+            {
+                continue;
+            }
+            let solid_angle = projection.solid_angle(pixel) as f64;
+            let far = reference.mean_mm[pixel] as f64 / 1000.0;
+            let near = current.mean_mm[pixel] as f64 / 1000.0;
+            volume += solid_angle * (far.powi(3) - near.powi(3)) / 3.0;
+            // d volume / d distance is solid_angle * distance^2
+            let far_error = reference.std_mm[pixel] as f64 / 1000.0 / (reference.samples[pixel] as f64).sqrt();
+            let near_error = current.std_mm[pixel] as f64 / 1000.0 / (current.samples[pixel] as f64).sqrt();
+            variance += (solid_angle * far * far * far_error).powi(2) + (solid_angle * near * near * near_error).powi(2);
+            pixels += 1;
+        }
+    }
+    Ok(Volume { volume : volume as f32, uncertainty : variance.sqrt() as f32, pixels, roi })
+}
+
+// averages frames like the snapshot command and estimates the volume once it has enough
+pub struct VolumePublisher
+{
+    reference : Snapshot,
+    roi : Option<[usize; 4]>,
+    output : Option<String>,
+    count : usize,
+    frames : Vec<DepthFrame>,
+}
+
+impl VolumePublisher
+{
+
+pub fn new(reference : &str, roi : Option<[usize; 4]>, count : usize, output : Option<&str>) -> Result<VolumePublisher, String>
+{
+    let reference = Snapshot::load(reference)?;
+    info!("Averaging {} frames to measure the volume against a reference of {} frames", count, reference.frames);
+    Ok(VolumePublisher { reference, roi, output : output.map(str::to_string), count, frames : Vec::with_capacity(count) })
+}
+
+}
+
+impl Publisher for VolumePublisher
+{
+
+// the volume is measured over every frame it was asked for
+fn backpressure(&self) -> Backpressure
+{
+    Backpressure::Block
+}
+
+fn publish(&mut self, _frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    if self.frames.len() >= self.count
+    {
+        return Ok(())
+    }
+    self.frames.push(depth.clone());
+    if self.frames.len() < self.count
+    {
+        return Ok(())
+    }
+    let current = Snapshot::from_frames(&self.frames).ok_or(())?;
+    let roi = self.roi.unwrap_or([0, 0, current.width, current.height]);
+    let volume = match estimate(&self.reference, &current, roi)
+    {
+        Ok(volume) => volume,
+        Err(msg) => { error!("Failed to estimate volume, {}", msg); return Err(()) }
+    };
+    info!("Volume {:.4} +- {:.4} m^3 over {} pixels", volume.volume, volume.uncertainty, volume.pixels);
+    if let Some(output) = &self.output
+    {
+        let written = serde_json::to_vec(&volume).map_err(|msg| msg.to_string()).and_then(|json| fs::write(output, json).map_err(|msg| msg.to_string()));
+        if let Err(msg) = written
+        {
+            error!("Failed to write {}, {}", output, msg);
+            return Err(())
+        }
+    }
+    Ok(())
+}
+
+}
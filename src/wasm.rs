@@ -0,0 +1,23 @@
+use crate::colormap;
+use crate::depth::DepthFrame;
+use crate::frame::parse_frame;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Takes a 3D frame as sent by the device (and by the tcp / ws publishers) and returns
+// its distances in millimeters, row major, or nothing if the frame doesn't check out.
+#[wasm_bindgen]
+pub fn decode_frame(bytes : &[u8]) -> Option<Vec<u16>>
+{
+    let frame = parse_frame(bytes).ok()?;
+    Some(DepthFrame::from_payload(&frame.payload).data)
+}
+
+// RGBA pixels ready for an ImageData of the given size
+#[wasm_bindgen]
+pub fn colorize(depth : &[u16], width : usize, height : usize, min_mm : u16, max_mm : u16) -> Vec<u8>
+{
+    let depth = DepthFrame { width, height, data : depth.to_vec() };
+    let rgb = colormap::colorize(&depth, min_mm, max_mm);
+    rgb.chunks_exact(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect()
+}
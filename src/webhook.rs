@@ -0,0 +1,108 @@
+// POSTs events, see Analysis::events, as json to an HTTP endpoint, e.g. zones being
+// entered or left, for alerting systems. Requests go out one after another on a thread of
+// their own so a slow endpoint doesn't hold up frames; events beyond QUEUE_DEPTH waiting
+// are dropped. Only plain http, put a proxy in front for https.
+use crate::analysis::Analysis;
+use crate::depth::DepthFrame;
+use crate::frame::Frame;
+use crate::publish::{Options, Publisher};
+
+use log::{error, info, warn};
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+const QUEUE_DEPTH : usize = 16;
+const TIMEOUT : Duration = Duration::from_secs(5);
+
+pub struct WebhookPublisher
+{
+    sender : SyncSender<String>,
+}
+
+impl WebhookPublisher
+{
+
+// target is host[:port][/path]
+pub fn new(target : &str, options : &Options) -> Result<WebhookPublisher, String>
+{
+    if let Some((name, _)) = options.first()
+    {
+        return Err(format!("webhook publisher has no option {}", name))
+    }
+    let (host, path) = match target.find('/')
+    {
+        Some(index) => (&target[..index], &target[index..]),
+        None => (target, "/"),
+    };
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let (sender, receiver) = sync_channel(QUEUE_DEPTH);
+    let (host, path) = (host.to_string(), path.to_string());
+    info!("Posting events to http://{}{}", host, path);
+    thread::spawn(move || post_events(&address, &host, &path, receiver));
+    Ok(WebhookPublisher { sender })
+}
+
+}
+
+fn post_events(address : &str, host : &str, path : &str, events : Receiver<String>)
+{
+    for body in events
+    {
+        if let Err(msg) = post(address, host, path, &body)
+        {
+            warn!("Failed to post event to http://{}{}, {}", host, path, msg);
+        }
+    }
+}
+
+fn post(address : &str, host : &str, path : &str, body : &str) -> Result<(), String>
+{
+    let address = address.to_socket_addrs().map_err(|msg| msg.to_string())?.next().ok_or("no address")?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|msg| msg.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|msg| msg.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|msg| msg.to_string())?;
+    write!(stream, "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, host, body.len(), body).map_err(|msg| msg.to_string())?;
+    // the status line is all we look at
+    let mut response = [0; 64];
+    let read = stream.read(&mut response).map_err(|msg| msg.to_string())?;
+    let status = String::from_utf8_lossy(&response[..read]);
+    match status.split(' ').nth(1)
+    {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(code) => Err(format!("status {}", code)),
+        None => Err("no response".to_string()),
+    }
+}
+
+impl Publisher for WebhookPublisher
+{
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    let event = match analysis.events()
+    {
+        Some(event) => event,
+        None => return,
+    };
+    let body = match serde_json::to_string(&event)
+    {
+        Ok(body) => body,
+        Err(msg) => { error!("Failed to serialize {} {}", event.name(), msg); return }
+    };
+    if let Err(TrySendError::Full(_)) = self.sender.try_send(body)
+    {
+        warn!("Dropping {} event, webhook is behind", event.name());
+    }
+}
+
+fn publish(&mut self, _frame : &Frame, _depth : &DepthFrame) -> Result<(), ()>
+{
+    Ok(())
+}
+
+}
@@ -0,0 +1,239 @@
+use crate::analysis::Analysis;
+use crate::frame::Frame;
+use crate::depth::DepthFrame;
+use crate::device::DeviceInfo;
+use crate::publish::{Encoding, Publisher};
+use crate::tcp::wake;
+use crate::stats::{Link, Queued, Stats, STATS};
+
+use log::{error, info, warn};
+use serde::Serialize;
+
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tungstenite::{Bytes, Message, Utf8Bytes, WebSocket};
+
+// With the raw format each frame is sent as a text message with json metadata followed
+// by a binary message holding the frame as read from the device. With format=proto,
+// rvl or delta every message is a binary message of its own. Analysis results follow the
+// frame, as json text with the raw format and as protobuf with format=proto. Slow clients
+// are dropped, and dropping the publisher stops it, like in tcp.rs.
+const CLIENT_QUEUE_DEPTH : usize = 8;
+
+#[derive(Serialize)]
+struct Metadata
+{
+    sequence : u64,
+    timestamp_ms : u128,
+    payload_size : u16,
+    // frame intervals from the stats, none before the second frame from a device
+    interval_ms : Option<f64>,
+    jitter_ms : Option<f64>,
+    max_gap_ms : Option<f64>,
+    link : Link,
+}
+
+type Update = Arc<Vec<Message>>;
+
+struct Client
+{
+    address : SocketAddr,
+    sender : SyncSender<Queued<Update>>,
+}
+
+#[derive(Default)]
+struct Clients
+{
+    connected : Vec<Client>,
+    greeting : Option<Update>,
+    stopped : bool,
+}
+
+pub struct WsPublisher
+{
+    clients : Arc<Mutex<Clients>>,
+    encoding : Encoding,
+    sequence : u64,
+    address : SocketAddr,
+    accepting : Option<JoinHandle<()>>,
+}
+
+impl WsPublisher
+{
+
+pub fn new(address : &str, encoding : Encoding) -> io::Result<WsPublisher>
+{
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    info!("Publishing frames on ws://{}", address);
+    let clients = Arc::new(Mutex::new(Clients::default()));
+    let accepted = clients.clone();
+    let accepting = Some(thread::spawn(move || accept_clients(listener, accepted)));
+    Ok(WsPublisher { clients, encoding, sequence : 0, address, accepting })
+}
+
+fn raw_update(&mut self, frame : &Frame) -> Result<Update, ()>
+{
+    let jitter = STATS.jitter();
+    let metadata = Metadata
+    {
+        sequence : self.sequence,
+        timestamp_ms : SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0),
+        payload_size : frame.size,
+        interval_ms : jitter.map(|jitter| jitter.mean.as_secs_f64() * 1000.0),
+        jitter_ms : jitter.map(|jitter| jitter.jitter.as_secs_f64() * 1000.0),
+        max_gap_ms : jitter.map(|jitter| jitter.max_gap.as_secs_f64() * 1000.0),
+        link : STATS.link(),
+    };
+    self.sequence += 1;
+    let metadata = match serde_json::to_string(&metadata)
+    {
+        Ok(metadata) => Utf8Bytes::from(metadata),
+        Err(msg) => { error!("Failed to serialize frame metadata {}", msg); return Err(()) }
+    };
+    Ok(Arc::new(vec![Message::Text(metadata), Message::Binary(Bytes::from(frame.as_bytes()?))]))
+}
+
+fn broadcast(&mut self, update : Update)
+{
+    let mut clients = self.clients.lock().unwrap();
+    clients.connected.retain(|client| match client.sender.try_send(Queued::new(update.clone()))
+    {
+        Ok(_) => true,
+        Err(TrySendError::Full(_)) => { Stats::count(&STATS.dropped_clients); warn!(client:% = client.address; "Dropping slow websocket client"); false },
+        Err(TrySendError::Disconnected(_)) => { info!(client:% = client.address; "Websocket client disconnected"); false },
+    });
+}
+
+}
+
+impl Drop for WsPublisher
+{
+    fn drop(&mut self)
+    {
+        {
+            let mut clients = self.clients.lock().unwrap();
+            clients.stopped = true;
+            clients.connected.clear();
+        }
+        wake(self.address, self.accepting.take());
+    }
+}
+
+fn accept_clients(listener : TcpListener, clients : Arc<Mutex<Clients>>)
+{
+    for stream in listener.incoming()
+    {
+        if clients.lock().unwrap().stopped
+        {
+            return
+        }
+        let stream = match stream
+        {
+            Ok(stream) => stream,
+            Err(msg) => { error!("Failed to accept websocket client, {}", msg); continue; }
+        };
+        let address = match stream.peer_addr()
+        {
+            Ok(address) => address,
+            Err(_) => continue,
+        };
+        let _ = stream.set_nodelay(true);
+        let clients = clients.clone();
+        // handshake on its own thread so a stalled client can't block accepting others
+        thread::spawn(move ||
+        {
+            let socket = match tungstenite::accept(stream)
+            {
+                Ok(socket) => socket,
+                Err(msg) => { warn!(client:% = address, error:% = msg; "Websocket handshake failed"); return; }
+            };
+            let (sender, receiver) = sync_channel::<Queued<Update>>(CLIENT_QUEUE_DEPTH);
+            info!(client:% = address; "Websocket client connected");
+            {
+                let mut clients = clients.lock().unwrap();
+                // stopped during the handshake
+                if clients.stopped
+                {
+                    return
+                }
+                if let Some(greeting) = &clients.greeting
+                {
+                    let _ = sender.try_send(Queued::new(greeting.clone()));
+                }
+                clients.connected.push(Client { address, sender });
+            }
+            write_client(socket, receiver);
+        });
+    }
+}
+
+fn write_client(mut socket : WebSocket<TcpStream>, updates : Receiver<Queued<Update>>)
+{
+    for update in updates
+    {
+        if update.0.iter().any(|message| socket.send(message.clone()).is_err())
+        {
+            break;
+        }
+    }
+    let _ = socket.close(None);
+}
+
+fn binary(messages : Vec<Vec<u8>>) -> Update
+{
+    Arc::new(messages.into_iter().map(|message| Message::Binary(Bytes::from(message))).collect())
+}
+
+impl Publisher for WsPublisher
+{
+
+fn device_info(&mut self, info : &DeviceInfo)
+{
+    if let Some(message) = self.encoding.device_info(info)
+    {
+        let update = binary(vec![message]);
+        let mut clients = self.clients.lock().unwrap();
+        for client in clients.connected.iter()
+        {
+            let _ = client.sender.try_send(Queued::new(update.clone()));
+        }
+        clients.greeting = Some(update);
+    }
+}
+
+fn analysis(&mut self, analysis : &Analysis)
+{
+    let update = match self.encoding
+    {
+        Encoding::Raw => match serde_json::to_string(analysis)
+        {
+            Ok(json) => Arc::new(vec![Message::Text(Utf8Bytes::from(json))]),
+            Err(msg) => { error!("Failed to serialize {} {}", analysis.name(), msg); return }
+        },
+        _ => match self.encoding.analysis(analysis)
+        {
+            Some(message) => binary(vec![message]),
+            None => return,
+        },
+    };
+    self.broadcast(update);
+}
+
+fn publish(&mut self, frame : &Frame, depth : &DepthFrame) -> Result<(), ()>
+{
+    let update = match self.encoding
+    {
+        Encoding::Raw => self.raw_update(frame)?,
+        _ => binary(self.encoding.frame(frame, depth)?),
+    };
+    self.broadcast(update);
+    Ok(())
+}
+
+}
@@ -0,0 +1,157 @@
+// Properties of the framing, each checked over CASES payloads from a seeded generator:
+// what new(..).as_bytes() encodes, parse_frame decodes to the same frame, the checksum is
+// the parity of every bit over the size and payload as the device computes it, one bit off
+// anywhere gets a frame rejected, and distances come back out of a frame as they went in.
+// A failing case reports its seed, Cases::new(seed) generates it again.
+use rusty_lidar_viewer::depth::{DepthFrame, HEIGHT_3D, WIDTH_2D, WIDTH_3D};
+use rusty_lidar_viewer::frame::{new, parse_frame, HEADER};
+
+const CASES : u64 = 256;
+
+// xorshift64*, lengths at the edges come up more often than they would at random
+struct Cases
+{
+    state : u64,
+}
+
+impl Cases
+{
+
+fn new(seed : u64) -> Cases
+{
+    Cases { state : seed.wrapping_mul(0x9e3779b97f4a7c15) | 1 }
+}
+
+fn next(&mut self) -> u64
+{
+    self.state ^= self.state >> 12;
+    self.state ^= self.state << 25;
+    self.state ^= self.state >> 27;
+    self.state.wrapping_mul(0x2545f4914f6cdd1d)
+}
+
+fn below(&mut self, bound : usize) -> usize
+{
+    (self.next() % bound as u64) as usize
+}
+
+fn length(&mut self, max : usize) -> usize
+{
+    match self.below(8)
+    {
+        0 => 0,
+        1 => 1,
+        2 => max,
+        3 => self.below(max + 1),
+        _ => self.below(64.min(max) + 1),
+    }
+}
+
+fn payload(&mut self, max : usize) -> Vec<u8>
+{
+    let length = self.length(max);
+    (0..length).map(|_| self.next() as u8).collect()
+}
+
+}
+
+fn check(property : impl Fn(&mut Cases, u64))
+{
+    for seed in 0..CASES
+    {
+        property(&mut Cases::new(seed), seed);
+    }
+}
+
+// bit by bit: each bit of the checksum is the parity of that bit over the size, low byte
+// first, and the payload
+fn reference_checksum(payload : &[u8]) -> u8
+{
+    let size = (payload.len() as u16).to_le_bytes();
+    let bytes : Vec<u8> = size.iter().chain(payload).copied().collect();
+    (0..8).fold(0, |checksum, bit| checksum | (bytes.iter().filter(|byte| *byte >> bit & 1 == 1).count() as u8 & 1) << bit)
+}
+
+#[test]
+fn frames_decode_to_what_was_encoded()
+{
+    check(|cases, seed|
+    {
+        let payload = cases.payload(u16::MAX as usize);
+        let frame = new(payload.clone());
+        let bytes = frame.as_bytes().unwrap();
+        let decoded = parse_frame(&bytes).unwrap_or_else(|_| panic!("seed {}", seed));
+        assert_eq!((decoded.header, decoded.size, &decoded.payload, decoded.checksum), (HEADER, payload.len() as u16, &payload, frame.checksum), "seed {}", seed);
+        assert_eq!(decoded.as_bytes().unwrap(), bytes, "seed {}", seed);
+    });
+}
+
+#[test]
+fn frames_are_laid_out_as_the_device_sends_them()
+{
+    check(|cases, seed|
+    {
+        let payload = cases.payload(u16::MAX as usize);
+        let size = (payload.len() as u16).to_le_bytes();
+        let expected = [&HEADER[..], &size, &payload, &[reference_checksum(&payload)]].concat();
+        assert_eq!(new(payload).as_bytes().unwrap(), expected, "seed {}", seed);
+    });
+}
+
+#[test]
+fn checksum_matches_the_reference()
+{
+    check(|cases, seed|
+    {
+        let payload = cases.payload(u16::MAX as usize);
+        assert_eq!(new(payload.clone()).checksum, reference_checksum(&payload), "seed {}", seed);
+    });
+}
+
+#[test]
+fn a_bit_off_is_rejected()
+{
+    check(|cases, seed|
+    {
+        let bytes = new(cases.payload(300)).as_bytes().unwrap();
+        // every bit of short frames, some of the others
+        let bits : Vec<usize> = match bytes.len() <= 64
+        {
+            true => (0..bytes.len() * 8).collect(),
+            false => (0..64).map(|_| cases.below(bytes.len() * 8)).collect(),
+        };
+        for bit in bits
+        {
+            let mut flipped = bytes.clone();
+            flipped[bit / 8] ^= 1 << (bit % 8);
+            assert!(parse_frame(&flipped).is_err(), "seed {}, bit {}", seed, bit);
+        }
+    });
+}
+
+#[test]
+fn payloads_too_long_for_a_size_are_not_encoded()
+{
+    assert!(new(vec![0; u16::MAX as usize + 1]).as_bytes().is_err());
+    let mut frame = new(vec![1, 2, 3]);
+    frame.payload.push(4);
+    assert!(frame.as_bytes().is_err());
+}
+
+#[test]
+fn distances_come_out_as_they_went_in()
+{
+    check(|cases, seed|
+    {
+        let (width, height, max) = match cases.below(2)
+        {
+            0 => (WIDTH_3D, HEIGHT_3D, 0xfff),
+            _ => (WIDTH_2D, 1, u16::MAX as u64),
+        };
+        let data = (0..width * height).map(|_| (cases.next() % (max + 1)) as u16).collect();
+        let depth = DepthFrame { width, height, data };
+        let bytes = new(depth.to_payload()).as_bytes().unwrap();
+        let decoded = DepthFrame::from_payload(&parse_frame(&bytes).unwrap().payload);
+        assert_eq!((decoded.width, decoded.height, decoded.data), (width, height, depth.data), "seed {}", seed);
+    });
+}
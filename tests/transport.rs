@@ -0,0 +1,218 @@
+// The device protocol against a MockTransport playing the device: the handshake, the
+// start and stop requests, frames found again after garbage, the watchdog's restart, and
+// what happens when the device answers the wrong request, stays quiet or goes away.
+use rusty_lidar_viewer::depth::{PAYLOAD_2D_HEADER, PAYLOAD_2D_SIZE, PAYLOAD_3D_HEADER, PAYLOAD_3D_SIZE};
+use rusty_lidar_viewer::device::{self, DeviceInfo, Mode};
+use rusty_lidar_viewer::frame::{new, parse_frame};
+use rusty_lidar_viewer::reader::{Command, SerialReader};
+use rusty_lidar_viewer::transport::MockTransport;
+
+use std::thread;
+use std::time::Duration;
+
+const QUIET : Duration = Duration::from_millis(50);
+
+fn command(payload : &[u8]) -> Vec<u8>
+{
+    new(payload.to_vec()).as_bytes().unwrap()
+}
+
+fn info_request() -> Vec<u8>
+{
+    [command(&[0x12, 0x55]), command(&[0x10, 0x00])].concat()
+}
+
+// firmware 1.2.3, hardware 4.5.6
+fn info_frame() -> Vec<u8>
+{
+    command(&[0x10, 1, 2, 3, 4, 5, 6])
+}
+
+// a payload of size bytes after the header, filled with seed
+fn frame(header : u8, size : u16, seed : u8) -> Vec<u8>
+{
+    let mut payload = vec![seed; size as usize];
+    payload[0] = header;
+    command(&payload)
+}
+
+fn depth_frame(seed : u8) -> Vec<u8>
+{
+    frame(PAYLOAD_3D_HEADER, PAYLOAD_3D_SIZE, seed)
+}
+
+fn read(reader : &mut SerialReader, mock : &mut MockTransport, mode : Mode) -> Result<Option<Vec<u8>>, ()>
+{
+    let mut bytes = Vec::new();
+    Ok(reader.read_frame(mock, mode.payload_sizes(), usize::MAX, &mut bytes)?.map(|_| bytes))
+}
+
+#[test]
+fn handshake_reads_device_info()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.expect(&info_request(), &info_frame());
+    let frame = device::handshake(&mut mock, QUIET).unwrap();
+    let info = DeviceInfo::from_payload(&frame.payload).unwrap();
+    assert_eq!((info.firmware.as_str(), info.hardware.as_str()), ("1.2.3", "4.5.6"));
+    assert!(mock.finished());
+}
+
+#[test]
+fn handshake_drops_bytes_sent_before()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.send(&depth_frame(7)[..1000]);
+    mock.expect(&info_request(), &info_frame());
+    let frame = device::handshake(&mut mock, QUIET).unwrap();
+    assert_eq!(frame.payload, info_frame()[5..12]);
+}
+
+#[test]
+fn handshake_fails_on_another_request()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.expect(&command(&[0x08, 0x00]), &[]);
+    assert!(device::handshake(&mut mock, QUIET).is_err());
+    assert!(!mock.finished());
+}
+
+#[test]
+fn handshake_fails_when_the_device_goes_away()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.expect(&info_request(), &info_frame()[..4]);
+    mock.close();
+    assert!(device::handshake(&mut mock, QUIET).is_err());
+}
+
+#[test]
+fn start_and_stop_send_their_requests()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.expect(&command(&[0x08, 0x00]), &[]);
+    mock.expect(&command(&[0x02, 0x00, 0x00]), &[]);
+    device::start(&mut mock, Mode::Depth).unwrap();
+    device::stop(&mut mock).unwrap();
+    assert!(mock.finished());
+}
+
+#[test]
+fn reader_finds_frames_again_after_garbage()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let (first, second, third) = (depth_frame(1), depth_frame(2), depth_frame(3));
+    // noise, a header with a size of no frame and a frame cut off, which takes the bytes
+    // after it as its own and fails its checksum instead
+    let stream = [&[0x00, 0x5a, 0x77][..], &first, &[0x5a, 0x77, 0xff, 0x01, 0x00], &second[..100], &second, &third].concat();
+    mock.expect(&command(&[0x08, 0x00]), &stream);
+    device::start(&mut mock, Mode::Depth).unwrap();
+    let (mut reader, _control) = SerialReader::new(&mock, QUIET).unwrap();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap(), first);
+    assert!(parse_frame(&read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap()).is_err());
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap(), third);
+}
+
+#[test]
+fn reader_takes_the_frames_of_its_mode()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let scan = frame(PAYLOAD_2D_HEADER, PAYLOAD_2D_SIZE, 3);
+    mock.send(&[scan.clone(), depth_frame(4), scan.clone()].concat());
+    let (mut reader, _control) = SerialReader::new(&mock, QUIET).unwrap();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Scan).unwrap().unwrap(), scan);
+    assert_eq!(read(&mut reader, &mut mock, Mode::Scan).unwrap().unwrap(), scan);
+    mock.send(&[scan.clone(), depth_frame(5)].concat());
+    assert_eq!(read(&mut reader, &mut mock, Mode::Dual).unwrap().unwrap(), scan);
+    assert!(parse_frame(&read(&mut reader, &mut mock, Mode::Dual).unwrap().unwrap()).is_ok());
+}
+
+#[test]
+fn reader_fails_at_the_end_of_the_stream()
+{
+    let mut mock = MockTransport::new().unwrap();
+    mock.send(&depth_frame(6));
+    mock.close();
+    let (mut reader, _control) = SerialReader::new(&mock, QUIET).unwrap();
+    assert!(read(&mut reader, &mut mock, Mode::Depth).unwrap().is_some());
+    assert!(read(&mut reader, &mut mock, Mode::Depth).is_err());
+}
+
+#[test]
+fn reader_writes_and_stops_when_told_while_the_device_is_quiet()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let stop = command(&[0x02, 0x00, 0x00]);
+    mock.expect(&stop, &[]);
+    let (mut reader, control) = SerialReader::new(&mock, QUIET).unwrap();
+    let told = thread::spawn(move ||
+    {
+        // past the quiet time, which is only reported
+        thread::sleep(QUIET * 3);
+        control.send(Command::Write(stop)).unwrap();
+        control.send(Command::Stop).unwrap();
+    });
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth), Ok(None));
+    told.join().unwrap();
+    assert!(mock.finished());
+}
+
+#[test]
+fn watchdog_restart_gets_the_stream_going_again()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let (stop, start) = (command(&[0x02, 0x00, 0x00]), command(&[0x08, 0x00]));
+    mock.expect(&start, &depth_frame(1));
+    mock.expect(&stop, &[]);
+    mock.expect(&info_request(), &info_frame());
+    mock.expect(&start, &depth_frame(2));
+    device::start(&mut mock, Mode::Depth).unwrap();
+    let (mut reader, control) = SerialReader::new(&mock, QUIET).unwrap();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap(), depth_frame(1));
+    // the device goes quiet until the watchdog tells the reader to stop, as run_device does
+    let watchdog = thread::spawn(move ||
+    {
+        thread::sleep(QUIET * 3);
+        control.send(Command::Stop).unwrap();
+    });
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth), Ok(None));
+    watchdog.join().unwrap();
+    let info = device::restart(&mut mock, Mode::Depth, QUIET).unwrap().unwrap();
+    assert_eq!((info.firmware.as_str(), info.hardware.as_str()), ("1.2.3", "4.5.6"));
+    assert!(mock.finished());
+    assert_eq!(mock.written(), [&start[..], &stop, &info_request(), &start].concat());
+    reader.clear();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap(), depth_frame(2));
+}
+
+#[test]
+fn reader_stops_again_after_a_restart_when_both_stops_were_queued()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let (stop, start) = (command(&[0x02, 0x00, 0x00]), command(&[0x08, 0x00]));
+    mock.expect(&stop, &[]);
+    mock.expect(&info_request(), &info_frame());
+    mock.expect(&start, &depth_frame(1));
+    let (mut reader, control) = SerialReader::new(&mock, QUIET).unwrap();
+    // the watchdog's and the shutdown's, both woken for at once
+    control.send(Command::Stop).unwrap();
+    control.send(Command::Stop).unwrap();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth), Ok(None));
+    device::restart(&mut mock, Mode::Depth, QUIET).unwrap().unwrap();
+    reader.clear();
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth).unwrap().unwrap(), depth_frame(1));
+    assert_eq!(read(&mut reader, &mut mock, Mode::Depth), Ok(None));
+    assert!(mock.finished());
+}
+
+#[test]
+fn watchdog_restart_doesnt_start_a_device_that_doesnt_answer()
+{
+    let mut mock = MockTransport::new().unwrap();
+    let stop = command(&[0x02, 0x00, 0x00]);
+    mock.expect(&stop, &[]);
+    mock.expect(&info_request(), &[]);
+    assert!(device::restart(&mut mock, Mode::Depth, QUIET).unwrap().is_none());
+    assert!(mock.finished());
+    assert_eq!(mock.written(), [stop, info_request()].concat());
+}